@@ -0,0 +1,66 @@
+//! Benchmarks for the tree-walk interpreter.
+//!
+//! Every program runs on a fresh `Lox` with `print` output discarded, and
+//! only the run is timed, not setting the interpreter up. Criterion compares
+//! each run with the last one saved under `target/criterion`. Pass names to
+//! only run some of them:
+//!
+//! ```sh
+//! cargo bench --bench interpreter -- fib closures
+//! ```
+
+use std::io;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use lox_rs::interpreter::{
+    diagnostic::{ColorChoice, DiagnosticRenderer, ErrorFormat},
+    interpreter::InterpreterConfig,
+    Lox,
+};
+
+const PROGRAMS: &[(&str, &str)] = &[
+    ("fib", include_str!("programs/fib.lox")),
+    ("loops", include_str!("programs/loops.lox")),
+    ("strings", include_str!("programs/strings.lox")),
+    ("methods", include_str!("programs/methods.lox")),
+    ("instances", include_str!("programs/instances.lox")),
+    ("closures", include_str!("programs/closures.lox")),
+];
+
+fn new_lox() -> Lox {
+    let mut lox = Lox::new(
+        InterpreterConfig::default(),
+        DiagnosticRenderer::new(ColorChoice::Never, ErrorFormat::Human),
+    );
+    lox.interpreter.set_output(Box::new(io::sink()));
+
+    lox
+}
+
+fn programs(c: &mut Criterion) {
+    for (name, source) in PROGRAMS {
+        c.bench_function(name, |b| {
+            b.iter_batched(
+                new_lox,
+                |mut lox| {
+                    lox.run(source.to_string());
+                    assert!(
+                        !lox.had_error && !lox.had_runtime_error,
+                        "benchmark program failed"
+                    );
+                    lox
+                },
+                BatchSize::SmallInput,
+            )
+        });
+    }
+}
+
+criterion_group! {
+    name = benches;
+    // The programs take tens of milliseconds each, so fewer samples than
+    // criterion's default of 100 keep a full run to a few minutes
+    config = Criterion::default().sample_size(20);
+    targets = programs
+}
+criterion_main!(benches);
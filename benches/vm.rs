@@ -0,0 +1,70 @@
+//! Benchmarks for the bytecode VM.
+//!
+//! Runs an arithmetic-heavy chunk as assembled and after the peephole pass
+//! has fused it into superinstructions, on one VM with its output discarded.
+//! Criterion compares each run with the last one saved under
+//! `target/criterion`.
+//!
+//! ```sh
+//! cargo bench --bench vm
+//! ```
+
+use std::io;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use lox_rs::bytecode::{
+    chunk::{Chunk, ChunkBuilder, OpCode},
+    peephole::Peephole,
+    vm::{Vm, VmConfig},
+};
+
+/// Folds 250 constants into a running total with alternating operators
+fn arithmetic() -> Chunk {
+    let operators = [
+        OpCode::Add,
+        OpCode::Multiply,
+        OpCode::Subtract,
+        OpCode::Divide,
+    ];
+
+    (0..250)
+        .fold(ChunkBuilder::new().constant(1.0), |builder, i| {
+            builder
+                .constant(1.0 + i as f64 / 1000.0)
+                .op(operators[i % operators.len()])
+        })
+        .op(OpCode::Return)
+        .build()
+        .expect("benchmark chunk has too many constants")
+}
+
+fn chunks(c: &mut Criterion) {
+    let chunk = arithmetic();
+    let fused = Peephole::new(&chunk)
+        .optimize()
+        .expect("benchmark chunk failed to optimize");
+
+    let mut vm = Vm::new(VmConfig {
+        debug: false,
+        trace_json: None,
+        stats: false,
+    })
+    .expect("failed to create VM");
+    vm.set_output(Box::new(io::sink()));
+
+    let mut group = c.benchmark_group("arithmetic");
+    for (name, chunk) in [("plain", &chunk), ("fused", &fused)] {
+        // Running takes the chunk, so each run gets a copy made untimed
+        group.bench_function(name, |b| {
+            b.iter_batched(
+                || chunk.clone(),
+                |chunk| vm.run_chunk(chunk).expect("benchmark chunk failed"),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, chunks);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+//! A codemod built on the rewriter: turns every `print value;` statement into
+//! a `log(value);` call and prints the rewritten file.
+//!
+//! ```sh
+//! cargo run --example print_to_log -- path/to/file.lox
+//! ```
+
+use std::{env, fs, process};
+
+use lox_rs::interpreter::{
+    rewriter::{rewrite, Rewriter, Transform},
+    span::Span,
+    stmt::Stmt,
+    token_kind::TokenKind,
+};
+
+struct PrintToLog;
+
+impl Transform for PrintToLog {
+    fn statement(&mut self, statement: &Stmt, rewriter: &mut Rewriter) {
+        let keyword = match statement {
+            Stmt::Print(keyword, _) => keyword,
+            _ => return,
+        };
+
+        let value = rewriter.next_token(keyword).map(|token| token.span.start);
+        let semicolon = rewriter.find_after(keyword, TokenKind::Semicolon).cloned();
+        if let (Some(value), Some(semicolon)) = (value, semicolon) {
+            rewriter.replace(Span::new(keyword.span.start, value), "log(");
+            rewriter.insert_before(&semicolon, ")");
+        }
+    }
+}
+
+fn main() {
+    let path = match env::args().nth(1) {
+        Some(path) => path,
+        None => {
+            eprintln!("Usage: print_to_log <file>");
+            process::exit(64);
+        }
+    };
+
+    let source = match fs::read_to_string(&path) {
+        Ok(source) => source,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            process::exit(66);
+        }
+    };
+
+    match rewrite(&source, &mut PrintToLog) {
+        Ok(rewritten) => print!("{}", rewritten),
+        Err(error) => {
+            eprintln!("Error[{}]: {}", error.code(), error);
+            process::exit(65);
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+use lox_rs::{
+    bytecode::{
+        assembler::Assembler,
+        chunk::{Chunk, ChunkBuilder, OpCode},
+        debug::Disassembler,
+        eliminator::DeadCodeEliminator,
+        error::{LoxError, Result},
+        folder::ConstantFolder,
+        peephole::Peephole,
+        vm::{Vm, VmConfig},
+    },
+    cli::DiagnosticOptions,
+    interpreter::diagnostic::{DiagnosticRenderer, Severity},
+};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "blox")]
+struct CommandOptions {
+    /// Print the stack and each instruction as it's executed
+    #[structopt(short, long, global = true)]
+    debug: bool,
+
+    #[structopt(flatten)]
+    diagnostics: DiagnosticOptions,
+
+    /// Runs a built-in test chunk when omitted
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Assemble and run chunks written in the textual assembly format. Each
+    /// file is run in order on the same VM.
+    #[structopt(alias = "asm")]
+    Run {
+        #[structopt(parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+
+        /// Print each disassembled chunk before running it
+        #[structopt(long)]
+        disassemble: bool,
+
+        /// Print the source lines above the instructions assembled from them
+        /// when disassembling
+        #[structopt(long)]
+        source: bool,
+
+        /// Write a JSON trace of every executed instruction to this file
+        #[structopt(long, parse(from_os_str))]
+        trace_json: Option<PathBuf>,
+
+        /// Print instruction and stack operation counts to stderr when done
+        #[structopt(long)]
+        stats: bool,
+
+        /// Fold constant arithmetic, fuse common instruction pairs into
+        /// superinstructions and remove dead code and constants before running
+        #[structopt(short = "O", long)]
+        optimize: bool,
+
+        /// Leave constant arithmetic to run time when optimizing
+        #[structopt(long)]
+        no_fold: bool,
+    },
+
+    /// Assemble a chunk and report errors without running it
+    Compile {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+    },
+
+    /// Print the disassembled chunk without running it
+    Disasm {
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+
+        /// Print the source lines above the instructions assembled from them
+        #[structopt(long)]
+        source: bool,
+    },
+}
+
+fn main() {
+    let command_options = CommandOptions::from_args();
+    let diagnostics = command_options.diagnostics.renderer();
+
+    if let Err(error) = run(command_options) {
+        report(diagnostics, error);
+    }
+}
+
+fn run(command_options: CommandOptions) -> Result<()> {
+    let (chunks, disassemble, trace_json, stats) = match command_options.command {
+        Some(Command::Run {
+            files,
+            disassemble,
+            source: show_source,
+            trace_json,
+            stats,
+            optimize,
+            no_fold,
+        }) => {
+            let chunks = files
+                .iter()
+                .map(|file| {
+                    let (chunk, source) = assemble(file)?;
+                    let source = Some(source).filter(|_| show_source);
+                    match optimize {
+                        true => Ok((optimize_chunk(chunk, !no_fold)?, source)),
+                        false => Ok((chunk, source)),
+                    }
+                })
+                .collect::<Result<Vec<_>>>()?;
+
+            (chunks, disassemble, trace_json, stats)
+        }
+        Some(Command::Compile { file }) => {
+            assemble(&file)?;
+            return Ok(());
+        }
+        Some(Command::Disasm {
+            file,
+            source: show_source,
+        }) => {
+            let (chunk, source) = assemble(&file)?;
+            return disassemble_chunk(&chunk, Some(source.as_str()).filter(|_| show_source));
+        }
+        None => (vec![(test_chunk()?, None)], true, None, false),
+    };
+
+    let mut vm = Vm::new(VmConfig {
+        debug: command_options.debug,
+        trace_json,
+        stats,
+    })?;
+
+    for (chunk, source) in chunks {
+        if disassemble {
+            disassemble_chunk(&chunk, source.as_deref())?;
+        }
+
+        vm.run_chunk(chunk)?;
+    }
+
+    if let Some(stats) = vm.stats() {
+        eprintln!("{}", stats);
+    }
+
+    Ok(())
+}
+
+/// Prints `error` as `file:line: message`, or as JSON with
+/// `--error-format json`, and exits with the status `ilox` uses for the same
+/// kind of error
+fn report(mut diagnostics: DiagnosticRenderer, error: LoxError) -> ! {
+    let (file, line, message, status) = match &error {
+        LoxError::AssemblerError {
+            file,
+            line,
+            message,
+        } => (file.as_deref(), Some(*line), message.clone(), 65),
+        LoxError::RuntimeError { message, location } | LoxError::Internal { message, location } => {
+            (location.file.as_deref(), location.line, message.clone(), 70)
+        }
+        LoxError::TooManyConstants => (None, None, error.to_string(), 65),
+        LoxError::OpCodeConversionError => (None, None, error.to_string(), 70),
+        LoxError::Io(_) => (None, None, error.to_string(), 66),
+    };
+
+    diagnostics.set_source(file.map(str::to_string));
+    eprintln!(
+        "{}",
+        diagnostics.render_line(Severity::Error, line, &message)
+    );
+
+    process::exit(status);
+}
+
+fn disassemble_chunk(chunk: &Chunk, source: Option<&str>) -> Result<()> {
+    match source {
+        Some(source) => Disassembler::new(chunk).with_source(source).process_chunk(),
+        None => Disassembler::new(chunk).process_chunk(),
+    }
+}
+
+/// Assembles `file`, returning the chunk along with the source it came from
+fn assemble(file: &Path) -> Result<(Chunk, String)> {
+    let source = fs::read_to_string(file)?;
+    let name = file.display().to_string();
+    let chunk = Assembler::new(&source).with_file(&name).assemble()?;
+
+    Ok((chunk, source))
+}
+
+fn optimize_chunk(chunk: Chunk, fold: bool) -> Result<Chunk> {
+    let chunk = match fold {
+        true => ConstantFolder::new(&chunk).fold()?,
+        false => chunk,
+    };
+
+    let chunk = Peephole::new(&chunk).optimize()?;
+    DeadCodeEliminator::new(&chunk).eliminate()
+}
+
+fn test_chunk() -> Result<Chunk> {
+    ChunkBuilder::new()
+        .name("test chunk")
+        .line(123)
+        .constant(1.2)
+        .constant(3.4)
+        .op(OpCode::Add)
+        .constant(5.6)
+        .op(OpCode::Divide)
+        .op(OpCode::Negate)
+        .op(OpCode::Return)
+        .build()
+}
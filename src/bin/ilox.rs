@@ -0,0 +1,726 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    io::{self, IsTerminal},
+    panic,
+    path::{Path, PathBuf},
+    process, thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use anyhow::anyhow;
+use lox_rs::{
+    cli::DiagnosticOptions,
+    interpreter::{
+        annotate::annotate_resolved,
+        crash,
+        diagnostic::{DiagnosticRenderer, Severity},
+        doc_examples::DocExample,
+        error::{LoxError, Result, ScannerErrorDetails},
+        formatter::Formatter,
+        inputs::Inputs,
+        interpreter::InterpreterConfig,
+        keywords::Keywords,
+        manifest::{Manifest, MANIFEST_NAME},
+        observer::PhaseTimings,
+        output::Buffering,
+        rename::rename,
+        repl::{Repl, ReplConfig},
+        resolver::ResolutionMismatch,
+        source::read_source,
+        strictness::Strictness,
+        value::Value,
+        Lox,
+    },
+};
+use structopt::StructOpt;
+
+#[derive(StructOpt, Debug)]
+#[structopt(name = "ilox")]
+struct CommandOptions {
+    /// Print the parsed program before running it, and enable debugging
+    /// natives like `locals()`
+    #[structopt(short, long, global = true)]
+    debug: bool,
+
+    #[structopt(flatten)]
+    diagnostics: DiagnosticOptions,
+
+    /// Print how long each phase of running the program took
+    #[structopt(long, global = true)]
+    timings: bool,
+
+    /// Scripts run before the program or REPL session, like a personal
+    /// library of helpers. Can be given more than once.
+    #[structopt(long, global = true, number_of_values = 1, parse(from_os_str))]
+    preload: Vec<PathBuf>,
+
+    /// Expressions to report the value of on stderr whenever a statement
+    /// changes it. Can be given more than once.
+    #[structopt(long, global = true, number_of_values = 1)]
+    watch: Vec<String>,
+
+    /// Most parameters a function can declare and arguments a call can pass
+    /// [default: 255]
+    #[structopt(long, global = true)]
+    max_arguments: Option<usize>,
+
+    /// Most Lox function calls that can be in progress at once [default: 256]
+    #[structopt(long, global = true)]
+    max_call_depth: Option<usize>,
+
+    /// Stop the program after this many loop iterations in total
+    #[structopt(long, global = true)]
+    max_loop_iterations: Option<u64>,
+
+    /// Only allow fields and methods starting with `_` to be accessed through
+    /// `this` inside their class
+    #[structopt(long, global = true)]
+    private_fields: bool,
+
+    /// Report `==` and `!=` between values of different types as a warning or
+    /// an error
+    #[structopt(long, global = true, possible_values = &["warning", "error"])]
+    strict_equality: Option<Severity>,
+
+    /// Check type annotations before running, reporting mismatches as
+    /// warnings or errors
+    #[structopt(long, global = true, possible_values = &["warning", "error"])]
+    type_check: Option<Severity>,
+
+    /// Turn on every stricter rule: warnings are errors, `==` between types
+    /// is an error, the REPL doesn't define globals on assignment, variables
+    /// can't be read before they're assigned and bodies need braces
+    #[structopt(long, global = true)]
+    strict: bool,
+
+    /// Replace invalid UTF-8 in scripts with U+FFFD instead of refusing to run
+    /// them
+    #[structopt(long, global = true)]
+    lossy: bool,
+
+    /// File of keyword aliases, one `alias = keyword` per line, like
+    /// `función = fun`
+    #[structopt(long, global = true, parse(from_os_str))]
+    keywords: Option<PathBuf>,
+
+    /// Don't load the prelude of utility functions like `min` and `range`
+    #[structopt(long, global = true)]
+    no_prelude: bool,
+
+    /// Write each line `print` outputs straight away instead of collecting
+    /// them in a buffer, which is slower but shows progress as it's made
+    #[structopt(long, global = true)]
+    unbuffered: bool,
+
+    /// What to do when a local variable isn't where the resolver put it, a
+    /// bug in the interpreter: look it up by name with a warning, or fail
+    /// straight away, for CI
+    #[structopt(long, global = true, default_value = "fallback", possible_values = &["fallback", "fail"])]
+    resolution_mismatch: ResolutionMismatch,
+
+    /// Starts a REPL when omitted
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt, Debug)]
+enum Command {
+    /// Run a script
+    Run {
+        /// Script to run, or `-` to read it from stdin. Without one, the entry
+        /// script of the nearest lox.toml is run with its settings.
+        #[structopt(parse(from_os_str))]
+        script: Option<PathBuf>,
+
+        /// Run this code instead of a script, printing the value of a
+        /// trailing expression like the REPL does, as in `--expr '1 + 2'`
+        #[structopt(short, long, conflicts_with_all = &["script", "print-import-graph"])]
+        expr: Option<String>,
+
+        /// Print the script's imports, and those of the modules it imports,
+        /// as a graph in the DOT language instead of running it
+        #[structopt(long)]
+        print_import_graph: bool,
+
+        /// Write the results of `clock`, `random` and `readLine` to this file,
+        /// so the run can be reproduced with `--replay`
+        #[structopt(long, parse(from_os_str))]
+        record: Option<PathBuf>,
+
+        /// Return the results recorded to this file with `--record` from
+        /// `clock`, `random` and `readLine` instead of reading them
+        #[structopt(long, parse(from_os_str), conflicts_with = "record")]
+        replay: Option<PathBuf>,
+
+        /// Print how often the interpreter's runtime shortcuts, like the
+        /// inline caches of property accesses, paid off to stderr afterwards
+        #[structopt(long)]
+        profile: bool,
+
+        #[structopt(flatten)]
+        optimizations: OptimizationOptions,
+    },
+
+    /// Run a script again every time it or a module it imports changes
+    Watch {
+        /// Script to run
+        #[structopt(parse(from_os_str))]
+        script: PathBuf,
+
+        #[structopt(flatten)]
+        optimizations: OptimizationOptions,
+    },
+
+    /// Start an interactive session
+    Repl(ReplOptions),
+
+    /// Report errors in a script without running it
+    Check {
+        /// Script to check, or `-` to read it from stdin
+        #[structopt(parse(from_os_str))]
+        script: PathBuf,
+
+        /// Then print the script with the resolved scope depth of every
+        /// variable reference after it, like `x/*d=1*/`
+        #[structopt(long)]
+        emit_resolved: bool,
+    },
+
+    /// Run the ```lox code blocks of markdown files, checking what they print
+    /// against their `// expect: ` comments
+    Doctest {
+        /// Markdown files to check
+        #[structopt(parse(from_os_str), required = true)]
+        files: Vec<PathBuf>,
+    },
+
+    /// Reindent a script and print the result
+    Fmt {
+        /// Script to format, or `-` to read it from stdin
+        #[structopt(parse(from_os_str))]
+        script: PathBuf,
+
+        /// Overwrite the script instead of printing it
+        #[structopt(short, long)]
+        write: bool,
+
+        /// Exit with status 1 if the script isn't formatted, without printing it
+        #[structopt(long, conflicts_with = "write")]
+        check: bool,
+    },
+
+    /// Rename a variable, function or class and every reference to it, and
+    /// print the result
+    Rename {
+        /// Current name
+        old: String,
+
+        /// New name
+        new: String,
+
+        /// Where the name is declared or used, as `file:line:column`
+        #[structopt(long)]
+        at: String,
+
+        /// Overwrite the script instead of printing it
+        #[structopt(short, long)]
+        write: bool,
+    },
+}
+
+#[derive(StructOpt, Debug)]
+struct ReplOptions {
+    /// Don't print the REPL banner
+    #[structopt(short, long)]
+    quiet: bool,
+
+    /// Require `;` at the end of REPL statements like in files
+    #[structopt(long)]
+    strict_semicolons: bool,
+
+    /// Make assigning to an undeclared variable an error like in files,
+    /// instead of defining a global with a warning
+    #[structopt(long)]
+    strict_globals: bool,
+
+    /// Prompt displayed by the REPL
+    #[structopt(long, env = "LOX_PROMPT")]
+    prompt: Option<String>,
+
+    /// Print how long each REPL line took to evaluate
+    #[structopt(long)]
+    timing: bool,
+
+    #[structopt(flatten)]
+    optimizations: OptimizationOptions,
+}
+
+#[derive(StructOpt, Debug)]
+struct OptimizationOptions {
+    /// Enable optimizations like caching constant expressions in loops and
+    /// inlining tiny functions
+    #[structopt(short = "O", long)]
+    optimize: bool,
+}
+
+/// Stack of the thread programs run on. Every phase recurses once per level of
+/// nesting, and debug builds need several times the main thread's stack to
+/// reach the default `max_nesting_depth`.
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+fn main() {
+    let interpreter = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run_reporting_crashes);
+
+    match interpreter.map(|interpreter| interpreter.join()) {
+        Ok(Ok(())) => (),
+        // Panics are caught and reported on the thread, this is one while
+        // reporting
+        Ok(Err(_)) => process::exit(70),
+        Err(error) => {
+            eprintln!("Couldn't start the interpreter: {}", error);
+            process::exit(70);
+        }
+    }
+}
+
+fn run_reporting_crashes() {
+    // A panic is a bug in the interpreter, reported with a crash report to
+    // make it easier to track down
+    crash::install();
+    let result = panic::catch_unwind(|| run(CommandOptions::from_args())).unwrap_or_else(|_| {
+        Err(LoxError::Panic {
+            message: crash::panic_message().unwrap_or_default(),
+        })
+    });
+
+    if let Err(error) = result {
+        eprintln!("Error[{}]: {}", error.code(), error);
+        match error {
+            LoxError::InvalidUtf8 { .. } => {
+                eprintln!("Pass --lossy to run it with invalid sequences replaced.")
+            }
+            LoxError::Panic { .. } => {
+                let directory = crash::write_report(None);
+                eprintln!("{}", crash::notice(directory.as_deref()));
+                process::exit(70);
+            }
+            _ => (),
+        }
+
+        process::exit(66);
+    }
+}
+
+fn run(command_options: CommandOptions) -> Result<()> {
+    let renderer = command_options.diagnostics.renderer();
+
+    // Without a subcommand, start a REPL with its default options
+    let command = command_options
+        .command
+        .unwrap_or_else(|| Command::Repl(ReplOptions::from_iter(["repl"])));
+
+    let manifest = match &command {
+        Command::Run {
+            script: None,
+            expr: None,
+            ..
+        } => Some(
+            Manifest::find_from_current_dir()?
+                .ok_or_else(|| anyhow!("No script given and no {} found.", MANIFEST_NAME))?,
+        ),
+        _ => None,
+    };
+
+    let new_lox = |mut config: InterpreterConfig| {
+        if command_options.unbuffered {
+            config.buffering = Buffering::Line;
+        }
+
+        let mut lox = Lox::new(config, renderer.clone());
+        // The prelude and preloads follow the same rules as the program, with
+        // command line options taking precedence over the manifest
+        let config = &mut lox.interpreter.config;
+        if let Some(manifest) = &manifest {
+            manifest.configure(config);
+        }
+
+        config.private_fields |= command_options.private_fields;
+        config.strict_equality = command_options.strict_equality.or(config.strict_equality);
+        config.type_check = command_options.type_check.or(config.type_check);
+        config.debug = command_options.debug;
+        config.resolution_mismatch = command_options.resolution_mismatch;
+        if let Some(max_call_depth) = command_options.max_call_depth {
+            config.limits.max_call_depth = max_call_depth;
+        }
+        config.limits.max_loop_iterations = command_options
+            .max_loop_iterations
+            .or(config.limits.max_loop_iterations);
+        lox.lossy = command_options.lossy;
+
+        // Imports not found next to the importing file are looked for in the
+        // directories of LOX_PATH, then the manifest's
+        let lox_path = env::var_os("LOX_PATH").unwrap_or_default();
+        for directory in env::split_paths(&lox_path)
+            .filter(|directory| !directory.as_os_str().is_empty())
+            .chain(manifest.iter().flat_map(Manifest::include_paths))
+        {
+            lox.add_search_path(directory);
+        }
+
+        if !command_options.no_prelude {
+            lox.load_prelude();
+        }
+
+        // The prelude is parsed with the default limit, keywords and
+        // strictness, and can use every native
+        let max_arguments = command_options.max_arguments.or_else(|| {
+            manifest
+                .as_ref()
+                .and_then(|manifest| manifest.max_arguments)
+        });
+        if let Some(max_arguments) = max_arguments {
+            lox.parser_config.max_arguments = max_arguments;
+        }
+
+        for native in manifest
+            .iter()
+            .flat_map(|manifest| &manifest.disabled_natives)
+        {
+            lox.interpreter.remove_native(native)?;
+        }
+        if let Some(keywords) = &command_options.keywords {
+            lox.keywords = Keywords::default().parse(&read_source(keywords, false)?)?;
+        }
+        if command_options.strict {
+            lox.set_strictness(Strictness::all());
+        }
+
+        for preload in &command_options.preload {
+            lox.set_source_name(Some(preload.display().to_string()));
+            lox.run_file(preload)?;
+
+            if lox.had_error {
+                process::exit(65);
+            }
+
+            if lox.had_runtime_error {
+                process::exit(70);
+            }
+        }
+        lox.set_source_name(None);
+
+        for watch in &command_options.watch {
+            lox.watch(watch);
+        }
+
+        if lox.had_error {
+            process::exit(65);
+        }
+
+        lox.debug = command_options.debug;
+        if command_options.timings {
+            lox.set_observer(Box::new(PhaseTimings::new()));
+        }
+
+        Ok::<_, LoxError>(lox)
+    };
+
+    match command {
+        Command::Run {
+            script,
+            expr,
+            print_import_graph,
+            record,
+            replay,
+            profile,
+            optimizations,
+        } => {
+            let script = match (script, &expr) {
+                (None, None) => Some(
+                    manifest
+                        .as_ref()
+                        .and_then(Manifest::entry_path)
+                        .ok_or_else(|| {
+                            anyhow!("{} doesn't declare an entry script.", MANIFEST_NAME)
+                        })?,
+                ),
+                (script, _) => script,
+            };
+
+            let mut config = InterpreterConfig::from(optimizations);
+            config.profile = profile;
+
+            let mut lox = new_lox(config)?;
+            if let (true, Some(script)) = (print_import_graph, &script) {
+                println!("{}", lox.import_graph(script));
+                return Ok(());
+            }
+
+            if let Some(log) = record {
+                lox.interpreter.set_inputs(Inputs::record(&log)?);
+            }
+            if let Some(log) = replay {
+                lox.interpreter.set_inputs(Inputs::replay(&log)?);
+            }
+
+            // `--expr` can't be given along with a script
+            match (script, expr) {
+                (Some(script), _) => lox.run_file(script)?,
+                (None, expr) => run_expr(&mut lox, expr.unwrap_or_default()),
+            }
+
+            if let Some(profile) = lox.interpreter.profile() {
+                eprintln!("{}", profile);
+            }
+
+            if lox.had_error {
+                process::exit(65);
+            }
+
+            if lox.had_runtime_error {
+                process::exit(70);
+            }
+        }
+        Command::Watch {
+            script,
+            optimizations,
+        } => {
+            let lox = new_lox(optimizations.into())?;
+            watch_file(&lox, &script)?;
+        }
+        Command::Repl(options) => {
+            let mut lox = new_lox(options.optimizations.into())?;
+            let interactive = io::stdin().is_terminal();
+            Repl::new(ReplConfig {
+                quiet: options.quiet,
+                interactive,
+                prompt: options.prompt,
+                timing: options.timing,
+                lenient_semicolons: !options.strict_semicolons,
+                implicit_globals: !options.strict_globals,
+            })
+            .run(&mut lox)?;
+
+            // Piped input is treated like a script
+            if !interactive && lox.had_error {
+                process::exit(65);
+            }
+            if !interactive && lox.had_runtime_error {
+                process::exit(70);
+            }
+        }
+        Command::Check {
+            script,
+            emit_resolved,
+        } => {
+            let mut lox = new_lox(InterpreterConfig::default())?;
+            lox.check_file(&script)?;
+
+            if lox.had_error {
+                process::exit(65);
+            }
+
+            if emit_resolved {
+                print!("{}", annotate_resolved(&read_source(&script, false)?)?);
+            }
+        }
+        Command::Doctest { files } => {
+            let lox = new_lox(InterpreterConfig::default())?;
+            if !run_doc_examples(&lox, &files)? {
+                process::exit(1);
+            }
+        }
+        Command::Fmt {
+            script,
+            write,
+            check,
+        } => format_file(script, write, check, renderer)?,
+        Command::Rename {
+            old,
+            new,
+            at,
+            write,
+        } => rename_in_file(&old, &new, &at, write)?,
+    }
+
+    Ok(())
+}
+
+/// How often `watch` checks whether the script or its modules changed
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Runs `script` in a fork of `lox` whenever the modification time of it or
+/// of a module it imports changes, printing a one line summary of each run on
+/// stderr. Runs until interrupted.
+fn watch_file(lox: &Lox, script: &Path) -> Result<()> {
+    let modified = |file: &Path| fs::metadata(file).and_then(|metadata| metadata.modified());
+    // Files of the last run, with their modification times then
+    let mut files = vec![script.to_path_buf()];
+    let mut last_run: HashMap<PathBuf, SystemTime> = HashMap::new();
+
+    loop {
+        let mut changed = false;
+        // The script comes first, followed by its modules
+        for (index, file) in files.iter().enumerate() {
+            match modified(file) {
+                Ok(current) => changed |= last_run.get(file) != Some(&current),
+                // Editors can replace a file on save, so it can briefly be
+                // missing, and a missing module is the run's error to report
+                Err(_) if !last_run.is_empty() || index > 0 => {}
+                Err(error) => return Err(error.into()),
+            }
+        }
+
+        if changed {
+            let mut run = lox.fork();
+            // Imports can change between runs, so the files to watch are found
+            // again each time, before running so their times match what ran
+            files = run.module_files(script);
+            last_run = files
+                .iter()
+                .filter_map(|file| Some((file.clone(), modified(file).ok()?)))
+                .collect();
+
+            let start = Instant::now();
+            let summary = match run.run_file(script) {
+                Err(error) => format!("Error[{}]: {}", error.code(), error),
+                Ok(()) if run.had_error => "failed to compile".into(),
+                Ok(()) if run.had_runtime_error => "runtime error".into(),
+                Ok(()) => "ok".into(),
+            };
+
+            eprintln!(
+                "[watch] {}: {} in {:.1?}, waiting for changes",
+                script.display(),
+                summary,
+                start.elapsed()
+            );
+        }
+
+        thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Runs the examples of every file, reporting failures on stderr. Returns
+/// whether they all passed.
+fn run_doc_examples(lox: &Lox, files: &[PathBuf]) -> Result<bool> {
+    let mut count = 0;
+    let mut failed = 0;
+
+    for file in files {
+        let markdown = read_source(file, lox.lossy)?;
+        let mut lox = lox.fork();
+        lox.set_source_name(Some(file.display().to_string()));
+
+        for example in DocExample::extract(&markdown) {
+            count += 1;
+            if let Err(message) = example.run(&lox) {
+                failed += 1;
+                eprintln!(
+                    "{}:{}: example failed: {}",
+                    file.display(),
+                    example.line,
+                    message
+                );
+            }
+        }
+    }
+
+    println!("{} examples, {} failed", count, failed);
+
+    Ok(failed == 0)
+}
+
+fn format_file(
+    script: PathBuf,
+    write: bool,
+    check: bool,
+    renderer: DiagnosticRenderer,
+) -> Result<()> {
+    // Always strict, so formatting can't write replacement characters back
+    let source = read_source(&script, false)?;
+
+    let formatted = match Formatter::new(&source).format() {
+        Ok(formatted) => formatted,
+        Err(LoxError::ScanningError {
+            line_index,
+            details,
+            ..
+        }) => {
+            for detail in details {
+                let location = line_index.line_column(detail.span.start);
+                eprintln!(
+                    "{}",
+                    renderer.render(
+                        Severity::Error,
+                        Some(ScannerErrorDetails::CODE),
+                        location,
+                        "",
+                        &detail.message
+                    )
+                );
+            }
+
+            process::exit(65);
+        }
+        Err(error) => return Err(error),
+    };
+
+    match (write, check) {
+        (_, true) if formatted != source => process::exit(1),
+        (_, true) => {}
+        (true, _) if script.to_str() != Some("-") => fs::write(&script, formatted)?,
+        (true, _) => print!("{}", formatted),
+        (false, _) => print!("{}", formatted),
+    }
+
+    Ok(())
+}
+
+/// Runs the code of `--expr`, printing the value of a trailing expression
+/// unless it's `nil`, and allowing the `;` after it to be left out
+fn run_expr(lox: &mut Lox, expr: String) {
+    lox.set_source_name(Some("--expr".into()));
+    lox.parser_config.lenient_semicolons = true;
+
+    if let Some(value) = lox
+        .run_line(expr)
+        .filter(|value| !matches!(value, Value::Nil))
+    {
+        println!("{}", value);
+    }
+}
+
+/// Renames `old` to `new` in the file of `at`, a `file:line:column` position
+fn rename_in_file(old: &str, new: &str, at: &str, write: bool) -> Result<()> {
+    let position = at.rsplitn(3, ':').collect::<Vec<_>>();
+    let (script, line, column) = match position.as_slice() {
+        [column, line, script] => match (line.parse(), column.parse()) {
+            (Ok(line), Ok(column)) => (PathBuf::from(script), line, column),
+            _ => return Err(anyhow!("Invalid line or column in '{}'.", at).into()),
+        },
+        _ => return Err(anyhow!("Expected a position like 'main.lox:3:5', got '{}'.", at).into()),
+    };
+
+    let source = read_source(&script, false)?;
+    let renamed = rename(&source, (line, column), old, new)?;
+
+    match write {
+        true => fs::write(&script, renamed)?,
+        false => print!("{}", renamed),
+    }
+
+    Ok(())
+}
+
+impl From<OptimizationOptions> for InterpreterConfig {
+    fn from(options: OptimizationOptions) -> Self {
+        Self {
+            optimize: options.optimize,
+            ..Self::default()
+        }
+    }
+}
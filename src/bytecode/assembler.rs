@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+use crate::bytecode::{
+    chunk::{Chunk, ChunkBuilder, OpCode},
+    error::{LoxError, Result},
+    natives::standard_native_index,
+};
+
+/// Assembles the textual chunk format into a `Chunk`.
+///
+/// Each non-empty line holds one instruction, written with the same name the
+/// disassembler prints, followed by its operand if it has one:
+///
+/// ```text
+/// ; comments start with a semicolon
+/// OP_CONSTANT 1.2
+/// OP_CONSTANT 3.4
+/// OP_ADD
+/// OP_RETURN
+/// ```
+///
+/// `OP_GET_NATIVE` takes the name of a standard native like `clock` or the
+/// index of one defined on the VM, and `OP_CALL` the number of arguments.
+///
+/// `OP_JUMP` and `OP_PUSH_HANDLER` take a label, defined further on by a line
+/// holding its name followed by a colon:
+///
+/// ```text
+/// OP_PUSH_HANDLER failed
+/// OP_GET_NATIVE clock
+/// OP_NEGATE           ; a runtime error, as clock isn't a number
+/// OP_POP_HANDLER
+/// OP_RETURN
+/// failed:
+/// OP_CONSTANT -1
+/// OP_RETURN
+/// ```
+///
+/// Instructions are recorded with the line of the assembly file they were
+/// written on.
+pub struct Assembler<'a> {
+    source: &'a str,
+    file: Option<&'a str>,
+}
+
+impl<'a> Assembler<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self { source, file: None }
+    }
+
+    /// Records `file` as the source of the assembled chunk
+    pub fn with_file(mut self, file: &'a str) -> Self {
+        self.file = Some(file);
+        self
+    }
+
+    pub fn assemble(&self) -> Result<Chunk> {
+        let mut builder = ChunkBuilder::new();
+        if let Some(file) = self.file {
+            builder = builder.file(file);
+        }
+
+        let mut labels = HashMap::new();
+        // Offsets of the jumps to patch once their labels are defined, with
+        // the label and the line they're on
+        let mut jumps = vec![];
+
+        for (index, text) in self.source.lines().enumerate() {
+            let line = index + 1;
+            let text = match text.find(';') {
+                Some(comment) => &text[..comment],
+                None => text,
+            };
+
+            if let Some(label) = text.trim().strip_suffix(':') {
+                if labels.insert(label, builder.offset()).is_some() {
+                    return Err(self.error(line, format!("Label '{}' is already defined.", label)));
+                }
+                continue;
+            }
+
+            let mut parts = text.split_whitespace();
+            let name = match parts.next() {
+                Some(name) => name,
+                None => continue,
+            };
+
+            let code = name
+                .to_uppercase()
+                .parse::<OpCode>()
+                .map_err(|_| self.error(line, format!("Unknown instruction '{}'.", name)))?;
+
+            builder = builder.line(line);
+            builder = match code {
+                code if code.has_constant_operand() => {
+                    let operand = self.operand(line, code, parts.next())?;
+
+                    let value = operand.parse::<f64>().map_err(|_| {
+                        self.error(line, format!("Invalid constant '{}'.", operand))
+                    })?;
+
+                    builder.constant_op(code, value)
+                }
+                OpCode::GetNative => {
+                    let operand = self.operand(line, code, parts.next())?;
+                    let index = match operand.parse::<u8>() {
+                        Ok(index) => index as usize,
+                        Err(_) => standard_native_index(operand).ok_or_else(|| {
+                            self.error(line, format!("Unknown native '{}'.", operand))
+                        })?,
+                    };
+
+                    builder.op_with(code, index as u8)
+                }
+                OpCode::Call => {
+                    let operand = self.operand(line, code, parts.next())?;
+                    let count = operand.parse::<u8>().map_err(|_| {
+                        self.error(line, format!("Invalid argument count '{}'.", operand))
+                    })?;
+
+                    builder.op_with(code, count)
+                }
+                code if code.is_jump() => {
+                    let label = self.operand(line, code, parts.next())?;
+                    jumps.push((builder.offset(), label, line));
+
+                    builder.op_with(code, 0)
+                }
+                code => builder.op(code),
+            };
+
+            if let Some(extra) = parts.next() {
+                return Err(self.error(line, format!("Unexpected operand '{}'.", extra)));
+            }
+        }
+
+        let mut chunk = builder.build()?;
+        for (offset, label, line) in jumps {
+            let target = *labels
+                .get(label)
+                .ok_or_else(|| self.error(line, format!("Undefined label '{}'.", label)))?;
+            let distance = target
+                .checked_sub(offset + 2)
+                .ok_or_else(|| self.error(line, format!("Can't jump back to '{}'.", label)))?;
+            let distance = u8::try_from(distance)
+                .map_err(|_| self.error(line, format!("Label '{}' is too far away.", label)))?;
+
+            chunk.patch(offset + 1, distance);
+        }
+
+        Ok(chunk)
+    }
+
+    fn operand<'b>(&self, line: usize, code: OpCode, operand: Option<&'b str>) -> Result<&'b str> {
+        operand.ok_or_else(|| self.error(line, format!("Expected operand for {}.", code)))
+    }
+
+    fn error(&self, line: usize, message: String) -> LoxError {
+        LoxError::AssemblerError {
+            file: self.file.map(str::to_string),
+            line,
+            message,
+        }
+    }
+}
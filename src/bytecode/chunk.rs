@@ -1,8 +1,13 @@
-use strum::{AsRefStr, Display, FromRepr};
+use std::collections::BTreeMap;
 
-use crate::{error::LoxError, value::Value};
+use strum::{AsRefStr, Display, EnumString, FromRepr};
 
-#[derive(FromRepr, Display, AsRefStr, Clone, Copy, PartialEq, Eq)]
+use crate::bytecode::{
+    error::{Location, LoxError},
+    value::Value,
+};
+
+#[derive(FromRepr, Display, AsRefStr, EnumString, Clone, Copy, PartialEq, Eq, Hash, Debug)]
 #[repr(u8)]
 pub enum OpCode {
     #[strum(to_string = "OP_CONSTANT")]
@@ -20,11 +25,108 @@ pub enum OpCode {
     #[strum(to_string = "OP_DIVIDE")]
     Divide,
 
-    #[strum(to_string = "OP_MEGATE")]
+    #[strum(to_string = "OP_NEGATE")]
     Negate,
 
+    #[strum(to_string = "OP_NIL")]
+    Nil,
+
+    #[strum(to_string = "OP_TRUE")]
+    True,
+
+    #[strum(to_string = "OP_FALSE")]
+    False,
+
+    #[strum(to_string = "OP_NOT")]
+    Not,
+
+    #[strum(to_string = "OP_EQUAL")]
+    Equal,
+
+    #[strum(to_string = "OP_GREATER")]
+    Greater,
+
+    #[strum(to_string = "OP_LESS")]
+    Less,
+
     #[strum(to_string = "OP_RETURN")]
     Return,
+
+    #[strum(to_string = "OP_GET_NATIVE")]
+    GetNative,
+
+    #[strum(to_string = "OP_CALL")]
+    Call,
+
+    // Control flow. Jump operands are the distance in bytes from the end of
+    // the instruction to its target, which is always further on.
+    #[strum(to_string = "OP_JUMP")]
+    Jump,
+
+    #[strum(to_string = "OP_PUSH_HANDLER")]
+    PushHandler,
+
+    #[strum(to_string = "OP_POP_HANDLER")]
+    PopHandler,
+
+    // Superinstructions fusing `OP_CONSTANT` with the instruction after it,
+    // written by `Peephole`
+    #[strum(to_string = "OP_ADD_CONSTANT")]
+    AddConstant,
+
+    #[strum(to_string = "OP_SUBTRACT_CONSTANT")]
+    SubtractConstant,
+
+    #[strum(to_string = "OP_MULTIPLY_CONSTANT")]
+    MultiplyConstant,
+
+    #[strum(to_string = "OP_DIVIDE_CONSTANT")]
+    DivideConstant,
+
+    #[strum(to_string = "OP_CONSTANT_RETURN")]
+    ConstantReturn,
+}
+
+impl OpCode {
+    /// Number of operand bytes following the opcode
+    pub fn operand_count(&self) -> usize {
+        match self {
+            OpCode::GetNative | OpCode::Call => 1,
+            _ if self.is_jump() => 1,
+            _ if self.has_constant_operand() => 1,
+            _ => 0,
+        }
+    }
+
+    /// Whether the operand is an index into the constant table
+    pub fn has_constant_operand(&self) -> bool {
+        matches!(
+            self,
+            OpCode::Constant
+                | OpCode::AddConstant
+                | OpCode::SubtractConstant
+                | OpCode::MultiplyConstant
+                | OpCode::DivideConstant
+                | OpCode::ConstantReturn
+        )
+    }
+
+    /// Whether the operand is a forward distance to another instruction
+    pub fn is_jump(&self) -> bool {
+        matches!(self, OpCode::Jump | OpCode::PushHandler)
+    }
+
+    /// The superinstruction doing the same as `OP_CONSTANT` followed by `self`
+    pub fn with_constant(&self) -> Option<OpCode> {
+        match self {
+            OpCode::Add => Some(OpCode::AddConstant),
+            OpCode::Subtract => Some(OpCode::SubtractConstant),
+            OpCode::Multiply => Some(OpCode::MultiplyConstant),
+            OpCode::Divide => Some(OpCode::DivideConstant),
+            OpCode::Return => Some(OpCode::ConstantReturn),
+            _ => None,
+        }
+    }
 }
 
 impl TryFrom<u8> for OpCode {
@@ -41,10 +143,37 @@ impl From<OpCode> for u8 {
     }
 }
 
+/// A decoded instruction, for passes that rewrite chunks
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Instruction {
+    pub code: OpCode,
+    pub operand: Option<u8>,
+    pub line: usize,
+    /// Offset the instruction was decoded from. An instruction replacing
+    /// others takes the offset of the first, so jumps to it still land on it.
+    pub offset: usize,
+}
+
+impl Instruction {
+    /// Offset of the instruction a jump goes to, in the chunk it was decoded
+    /// from
+    pub fn target(&self) -> Option<usize> {
+        match self.operand {
+            Some(distance) if self.code.is_jump() => Some(self.offset + 2 + distance as usize),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Chunk {
     code: Vec<u8>,
     constants: Vec<Value>,
     pub lines: Vec<usize>,
+    /// Function the chunk was compiled from, or `None` for top-level code
+    pub name: Option<String>,
+    /// Source file the chunk was compiled or assembled from
+    pub file: Option<String>,
 }
 
 impl Chunk {
@@ -53,6 +182,27 @@ impl Chunk {
             code: vec![],
             constants: vec![],
             lines: vec![],
+            name: None,
+            file: None,
+        }
+    }
+
+    /// Label for the chunk in disassembly headers: the function name, falling
+    /// back to the file it came from
+    pub fn label(&self) -> &str {
+        self.name
+            .as_deref()
+            .or(self.file.as_deref())
+            .unwrap_or("<script>")
+    }
+
+    /// Where the instruction at `offset` came from
+    pub fn location(&self, offset: usize) -> Location {
+        Location {
+            function: self.name.clone(),
+            file: self.file.clone(),
+            // Past the end, the line of the last instruction is the closest
+            line: self.lines.get(offset).or(self.lines.last()).copied(),
         }
     }
 
@@ -69,6 +219,17 @@ impl Chunk {
         self.code[index]
     }
 
+    /// Overwrites the byte at `index`, like the operand of a jump once its
+    /// target is known
+    pub fn patch(&mut self, index: usize, byte: u8) {
+        self.code[index] = byte;
+    }
+
+    /// Offset of the instruction the jump at `offset` goes to
+    pub fn jump_target(&self, offset: usize) -> usize {
+        offset + 2 + self.code[offset + 1] as usize
+    }
+
     pub fn add_constant(&mut self, value: Value) -> usize {
         self.constants.push(value);
         self.constants.len() - 1
@@ -78,7 +239,211 @@ impl Chunk {
         self.constants[index]
     }
 
+    pub fn constants(&self) -> &[Value] {
+        &self.constants
+    }
+
     pub fn get_line(&self, index: usize) -> usize {
         self.lines[index]
     }
+
+    /// Decodes the code into one `Instruction` per opcode, failing if an
+    /// operand is cut off or loads a constant the table doesn't have
+    pub fn instructions(&self) -> Result<Vec<Instruction>, LoxError> {
+        let mut instructions = vec![];
+        let mut offset = 0;
+        while offset < self.count() {
+            let code = OpCode::try_from(self.get_code(offset))?;
+            let operand = match code.operand_count() {
+                0 => None,
+                _ => match self.code.get(offset + 1) {
+                    Some(operand) => Some(*operand),
+                    None => {
+                        return Err(
+                            self.internal_error(offset, format!("Missing operand of {}.", code))
+                        )
+                    }
+                },
+            };
+
+            if let Some(index) = operand.filter(|_| code.has_constant_operand()) {
+                if index as usize >= self.constants.len() {
+                    return Err(
+                        self.internal_error(offset, format!("Undefined constant {}.", index))
+                    );
+                }
+            }
+
+            instructions.push(Instruction {
+                code,
+                operand,
+                line: self.get_line(offset),
+                offset,
+            });
+            offset += 1 + code.operand_count();
+        }
+
+        Ok(instructions)
+    }
+
+    fn internal_error(&self, offset: usize, message: String) -> LoxError {
+        LoxError::Internal {
+            message,
+            location: self.location(offset),
+        }
+    }
+
+    /// Replaces the code with `instructions`, keeping the constant table.
+    ///
+    /// Jumps are pointed back at the instructions they went to before, found
+    /// by their `offset`. A jump to an instruction that was removed goes to
+    /// the next one kept after it, or to the end of the chunk.
+    pub fn rewrite(&mut self, instructions: &[Instruction]) -> Result<(), LoxError> {
+        // New offset of each instruction, by the offset it was decoded from
+        let mut offsets = BTreeMap::new();
+        let mut end = 0;
+        for instruction in instructions {
+            offsets.insert(instruction.offset, end);
+            end += 1 + instruction.code.operand_count();
+        }
+
+        self.code.clear();
+        self.lines.clear();
+        for instruction in instructions {
+            let offset = self.count();
+            let operand = match instruction.target() {
+                Some(target) => {
+                    let target = offsets.range(target..).next().map_or(end, |(_, new)| *new);
+                    let distance = target
+                        .checked_sub(offset + 2)
+                        .and_then(|distance| u8::try_from(distance).ok())
+                        .ok_or_else(|| {
+                            self.internal_error(
+                                offset,
+                                format!("Can't remap {}.", instruction.code),
+                            )
+                        })?;
+                    Some(distance)
+                }
+                None => instruction.operand,
+            };
+
+            self.write(instruction.code.into(), instruction.line);
+            if let Some(operand) = operand {
+                self.write(operand, instruction.line);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Fluent builder for assembling chunks by hand:
+///
+/// ```
+/// use lox_rs::bytecode::chunk::{ChunkBuilder, OpCode};
+///
+/// let chunk = ChunkBuilder::new()
+///     .line(3)
+///     .constant(1.2)
+///     .constant(3.4)
+///     .op(OpCode::Add)
+///     .op(OpCode::Return)
+///     .build()?;
+///
+/// assert_eq!(chunk.count(), 6);
+/// assert_eq!(chunk.get_line(0), 3);
+/// # Ok::<(), lox_rs::bytecode::error::LoxError>(())
+/// ```
+pub struct ChunkBuilder {
+    chunk: Chunk,
+    line: usize,
+    error: Option<LoxError>,
+}
+
+impl ChunkBuilder {
+    pub fn new() -> Self {
+        Self {
+            chunk: Chunk::new(),
+            line: 1,
+            error: None,
+        }
+    }
+
+    /// Names the function the chunk holds the code for
+    pub fn name(mut self, name: &str) -> Self {
+        self.chunk.name = Some(name.to_string());
+        self
+    }
+
+    /// Sets the source file the chunk is reported as coming from
+    pub fn file(mut self, file: &str) -> Self {
+        self.chunk.file = Some(file.to_string());
+        self
+    }
+
+    /// Sets the source line recorded for the instructions written after it
+    pub fn line(mut self, line: usize) -> Self {
+        self.line = line;
+        self
+    }
+
+    pub fn op(mut self, code: OpCode) -> Self {
+        self.chunk.write(code.into(), self.line);
+        self
+    }
+
+    /// Offset the next instruction will be written at
+    pub fn offset(&self) -> usize {
+        self.chunk.count()
+    }
+
+    /// Writes `code` followed by a one byte operand, like the argument count of
+    /// `OP_CALL`
+    pub fn op_with(mut self, code: OpCode, operand: u8) -> Self {
+        self.chunk.write(code.into(), self.line);
+        self.chunk.write(operand, self.line);
+        self
+    }
+
+    /// Adds `value` to the constant table and writes an `OP_CONSTANT` loading it
+    pub fn constant(self, value: impl Into<Value>) -> Self {
+        self.constant_op(OpCode::Constant, value)
+    }
+
+    /// Adds `value` to the constant table and writes `code` with its index as
+    /// the operand
+    pub fn constant_op(mut self, code: OpCode, value: impl Into<Value>) -> Self {
+        let index = self.chunk.add_constant(value.into());
+        match u8::try_from(index) {
+            Ok(index) => {
+                self.chunk.write(code.into(), self.line);
+                self.chunk.write(index, self.line);
+            }
+            Err(_) => {
+                self.error.get_or_insert(LoxError::TooManyConstants);
+            }
+        }
+
+        self
+    }
+
+    pub fn build(self) -> Result<Chunk, LoxError> {
+        match self.error {
+            Some(error) => Err(error),
+            None => Ok(self.chunk),
+        }
+    }
+}
+
+impl Default for ChunkBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -1,17 +1,30 @@
-use crate::chunk::{Chunk, OpCode};
-use crate::error::Result;
+use crate::bytecode::chunk::{Chunk, OpCode};
+use crate::bytecode::error::Result;
+use crate::bytecode::natives::standard_natives;
 
 pub struct Disassembler<'a> {
     chunk: &'a Chunk,
+    /// Source the chunk was built from, printed above its instructions
+    source: Option<Vec<&'a str>>,
 }
 
 impl<'a> Disassembler<'a> {
     pub fn new(chunk: &'a Chunk) -> Self {
-        Self { chunk }
+        Self {
+            chunk,
+            source: None,
+        }
+    }
+
+    /// Prints each line of `source` above the instructions recorded with it
+    /// by `process_chunk`
+    pub fn with_source(mut self, source: &'a str) -> Self {
+        self.source = Some(source.lines().collect());
+        self
     }
 
-    pub fn process_chunk(&self, name: &str) -> Result<()> {
-        println!("== {} ==", name);
+    pub fn process_chunk(&self) -> Result<()> {
+        println!("== {} ==", self.chunk.label());
 
         let mut offset = 0;
 
@@ -20,6 +33,7 @@ impl<'a> Disassembler<'a> {
                 return Ok(());
             }
 
+            self.process_source_line(offset);
             offset = self.process_instruction(offset)?;
         }
     }
@@ -29,14 +43,14 @@ impl<'a> Disassembler<'a> {
         print!("{:>4} ", self.get_line_label(offset));
 
         let instruction = self.chunk.get_code(offset);
-        Ok(match instruction.try_into() {
-            Ok(code @ OpCode::Return) => self.simple_instruction(code.as_ref(), offset),
-            Ok(code @ OpCode::Negate) => self.simple_instruction(code.as_ref(), offset),
-            Ok(code @ OpCode::Add) => self.simple_instruction(code.as_ref(), offset),
-            Ok(code @ OpCode::Subtract) => self.simple_instruction(code.as_ref(), offset),
-            Ok(code @ OpCode::Multiply) => self.simple_instruction(code.as_ref(), offset),
-            Ok(code @ OpCode::Divide) => self.simple_instruction(code.as_ref(), offset),
-            Ok(code @ OpCode::Constant) => self.constant_instruction(code.as_ref(), offset),
+        Ok(match OpCode::try_from(instruction) {
+            Ok(code) if code.has_constant_operand() => {
+                self.constant_instruction(code.as_ref(), offset)
+            }
+            Ok(code @ OpCode::GetNative) => self.native_instruction(code.as_ref(), offset),
+            Ok(code @ OpCode::Call) => self.byte_instruction(code.as_ref(), offset),
+            Ok(code) if code.is_jump() => self.jump_instruction(code.as_ref(), offset),
+            Ok(code) => self.simple_instruction(code.as_ref(), offset),
             Err(_) => {
                 println!("Unknown opcode {}", instruction);
                 offset + 1
@@ -44,15 +58,57 @@ impl<'a> Disassembler<'a> {
         })
     }
 
+    fn process_source_line(&self, offset: usize) {
+        let source = match &self.source {
+            Some(source) => source,
+            None => return,
+        };
+
+        let line = self.chunk.get_line(offset);
+        if offset > 0 && line == self.chunk.get_line(offset - 1) {
+            return;
+        }
+
+        if let Some(text) = line.checked_sub(1).and_then(|index| source.get(index)) {
+            println!("{:10}; {}", "", text.trim());
+        }
+    }
+
     fn simple_instruction(&self, name: &str, offset: usize) -> usize {
         println!("{}", name);
         offset + 1
     }
 
+    fn byte_instruction(&self, name: &str, offset: usize) -> usize {
+        let operand = self.chunk.get_code(offset + 1);
+        println!("{: <20} {:4}", name, operand);
+        offset + 2
+    }
+
+    fn jump_instruction(&self, name: &str, offset: usize) -> usize {
+        let distance = self.chunk.get_code(offset + 1);
+        println!(
+            "{: <20} {:4} -> {:04}",
+            name,
+            distance,
+            self.chunk.jump_target(offset)
+        );
+        offset + 2
+    }
+
+    fn native_instruction(&self, name: &str, offset: usize) -> usize {
+        let index = self.chunk.get_code(offset + 1);
+        match standard_natives().get(index as usize) {
+            Some(native) => println!("{: <20} {:4} '{}'", name, index, native.name),
+            None => println!("{: <20} {:4}", name, index),
+        }
+        offset + 2
+    }
+
     fn constant_instruction(&self, name: &str, offset: usize) -> usize {
         let constant_index = self.chunk.get_code(offset + 1) as usize;
         let constant = self.chunk.get_constant(constant_index);
-        println!("{: <16} {:4} '{}'", name, constant_index, constant);
+        println!("{: <20} {:4} '{}'", name, constant_index, constant);
         offset + 2
     }
 
@@ -0,0 +1,67 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::bytecode::{
+    chunk::{Chunk, OpCode},
+    error::Result,
+};
+
+/// Removes code that can never run and constants nothing loads.
+///
+/// Instructions after an `OP_RETURN`, `OP_CONSTANT_RETURN` or `OP_JUMP` are
+/// dropped up to the next one a kept jump or error handler goes to. The
+/// constant table is then rebuilt with only the constants the remaining
+/// instructions use, in the order they're first used, and their operands are
+/// renumbered to match.
+pub struct DeadCodeEliminator<'a> {
+    chunk: &'a Chunk,
+}
+
+impl<'a> DeadCodeEliminator<'a> {
+    pub fn new(chunk: &'a Chunk) -> Self {
+        Self { chunk }
+    }
+
+    pub fn eliminate(&self) -> Result<Chunk> {
+        // Jumps only go forward, so by the time an instruction comes up, every
+        // kept jump that can reach it has been seen
+        let mut targets = HashSet::new();
+        let mut falls_through = true;
+        let mut instructions = vec![];
+        for instruction in self.chunk.instructions()? {
+            if !falls_through && !targets.contains(&instruction.offset) {
+                continue;
+            }
+
+            targets.extend(instruction.target());
+            falls_through = !Self::ends_block(instruction.code);
+            instructions.push(instruction);
+        }
+
+        let mut output = Chunk::new();
+        output.name = self.chunk.name.clone();
+        output.file = self.chunk.file.clone();
+
+        let mut remapped: HashMap<u8, u8> = HashMap::new();
+        for instruction in &mut instructions {
+            let operand = match instruction.operand {
+                Some(operand) if instruction.code.has_constant_operand() => operand,
+                _ => continue,
+            };
+
+            // The table can only shrink, so new indexes always fit in a byte
+            let index = *remapped.entry(operand).or_insert_with(|| {
+                output.add_constant(self.chunk.get_constant(operand as usize)) as u8
+            });
+            instruction.operand = Some(index);
+        }
+
+        output.rewrite(&instructions)?;
+
+        Ok(output)
+    }
+
+    /// Whether the instruction after `code` only runs if something jumps to it
+    fn ends_block(code: OpCode) -> bool {
+        matches!(code, OpCode::Return | OpCode::ConstantReturn | OpCode::Jump)
+    }
+}
@@ -1,3 +1,5 @@
+use std::fmt::{self, Display};
+
 use thiserror::Error;
 
 pub type Result<T, E = LoxError> = std::result::Result<T, E>;
@@ -6,4 +8,52 @@ pub type Result<T, E = LoxError> = std::result::Result<T, E>;
 pub enum LoxError {
     #[error("Failed to convert value to OpCode")]
     OpCodeConversionError,
+
+    #[error("Too many constants in one chunk")]
+    TooManyConstants,
+
+    #[error("Runtime Error: {message} {location}")]
+    RuntimeError { message: String, location: Location },
+
+    /// A chunk broke an invariant the VM relies on, like popping an empty
+    /// stack, which a compiler bug would be to blame for
+    #[error("Internal Error: {message} {location}")]
+    Internal { message: String, location: Location },
+
+    #[error("[line {line}] Assembler Error: {message}")]
+    AssemblerError {
+        /// File being assembled, if the assembler was given one
+        file: Option<String>,
+        line: usize,
+        message: String,
+    },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Where an instruction came from, shown like `in function 'foo'
+/// (script.lox:12)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    /// Function the chunk was compiled from, or `None` for top-level code
+    pub function: Option<String>,
+    pub file: Option<String>,
+    /// `None` only for chunks without any code
+    pub line: Option<usize>,
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.function {
+            Some(name) => write!(f, "in function '{}'", name)?,
+            None => write!(f, "in script")?,
+        }
+
+        match (&self.file, self.line) {
+            (Some(file), Some(line)) => write!(f, " ({}:{})", file, line),
+            (None, Some(line)) => write!(f, " (line {})", line),
+            (_, None) => Ok(()),
+        }
+    }
 }
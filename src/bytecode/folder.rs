@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+
+use crate::bytecode::{
+    chunk::{Chunk, Instruction, OpCode},
+    error::Result,
+    value::Value,
+};
+
+/// Evaluates arithmetic on constants ahead of time.
+///
+/// An operator whose operands are all loaded by the instructions right before
+/// it is replaced by a single `OP_CONSTANT` with the result, which can in turn
+/// be folded into the operator after it:
+///
+/// ```text
+/// OP_CONSTANT 1.2
+/// OP_CONSTANT 3.4    =>  OP_CONSTANT 4.6
+/// OP_ADD
+/// ```
+///
+/// Results are computed with the same `f64` operations the VM uses, so
+/// division by zero and NaN behave as if the code had run. Folded constants
+/// are added to the end of the constant table and the ones they replace are
+/// left in place. A fold is skipped when the table is full, or when a jump
+/// lands past the first of the instructions it would replace, since those
+/// wouldn't always run together.
+pub struct ConstantFolder<'a> {
+    chunk: &'a Chunk,
+}
+
+impl<'a> ConstantFolder<'a> {
+    pub fn new(chunk: &'a Chunk) -> Self {
+        Self { chunk }
+    }
+
+    pub fn fold(&self) -> Result<Chunk> {
+        let decoded = self.chunk.instructions()?;
+        let targets: HashSet<usize> = decoded.iter().filter_map(Instruction::target).collect();
+
+        let mut output = self.chunk.clone();
+        let mut instructions: Vec<Instruction> = vec![];
+
+        for instruction in decoded {
+            let folded =
+                self.evaluate(&output, &instructions, instruction)
+                    .filter(|(replaced, _)| {
+                        Self::runs_together(&instructions, *replaced, &instruction, &targets)
+                    });
+            let index = u8::try_from(output.constants().len()).ok();
+
+            match (folded, index) {
+                (Some((replaced, value)), Some(index)) => {
+                    let start = instructions.len() - replaced;
+                    let first = instructions[start];
+                    instructions.truncate(start);
+
+                    output.add_constant(Value::Number(value));
+                    instructions.push(Instruction {
+                        code: OpCode::Constant,
+                        operand: Some(index),
+                        ..first
+                    });
+                }
+                _ => instructions.push(instruction),
+            }
+        }
+
+        output.rewrite(&instructions)?;
+
+        Ok(output)
+    }
+
+    /// The value of `instruction` if its operands are all constants loaded by
+    /// the end of `emitted`, along with how many of those loads it replaces
+    fn evaluate(
+        &self,
+        chunk: &Chunk,
+        emitted: &[Instruction],
+        instruction: Instruction,
+    ) -> Option<(usize, f64)> {
+        let code = instruction.code;
+        match code {
+            OpCode::Negate => Some((1, -Self::loaded(chunk, emitted, 1)?)),
+            OpCode::Add | OpCode::Subtract | OpCode::Multiply | OpCode::Divide => {
+                let a = Self::loaded(chunk, emitted, 2)?;
+                let b = Self::loaded(chunk, emitted, 1)?;
+                Some((2, Self::apply(code, a, b)?))
+            }
+            OpCode::AddConstant
+            | OpCode::SubtractConstant
+            | OpCode::MultiplyConstant
+            | OpCode::DivideConstant => {
+                let a = Self::loaded(chunk, emitted, 1)?;
+                let b = chunk
+                    .get_constant(instruction.operand? as usize)
+                    .as_number()?;
+                Some((1, Self::apply(code, a, b)?))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether no jump lands on `instruction` or on the `replaced` loads
+    /// before it, other than the first
+    fn runs_together(
+        emitted: &[Instruction],
+        replaced: usize,
+        instruction: &Instruction,
+        targets: &HashSet<usize>,
+    ) -> bool {
+        let start = emitted.len() - replaced;
+        emitted[start + 1..]
+            .iter()
+            .chain([instruction])
+            .all(|instruction| !targets.contains(&instruction.offset))
+    }
+
+    /// The number loaded by the instruction `distance` from the end of
+    /// `emitted`, if it's an `OP_CONSTANT`
+    fn loaded(chunk: &Chunk, emitted: &[Instruction], distance: usize) -> Option<f64> {
+        let instruction = emitted.get(emitted.len().checked_sub(distance)?)?;
+        match instruction.code {
+            OpCode::Constant => chunk
+                .get_constant(instruction.operand? as usize)
+                .as_number(),
+            _ => None,
+        }
+    }
+
+    fn apply(code: OpCode, a: f64, b: f64) -> Option<f64> {
+        match code {
+            OpCode::Add | OpCode::AddConstant => Some(a + b),
+            OpCode::Subtract | OpCode::SubtractConstant => Some(a - b),
+            OpCode::Multiply | OpCode::MultiplyConstant => Some(a * b),
+            OpCode::Divide | OpCode::DivideConstant => Some(a / b),
+            _ => None,
+        }
+    }
+}
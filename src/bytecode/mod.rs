@@ -0,0 +1,14 @@
+//! Bytecode VM, following Part 3 of Crafting Interpreters.
+
+pub mod assembler;
+pub mod chunk;
+pub mod debug;
+pub mod eliminator;
+pub mod error;
+pub mod folder;
+pub mod natives;
+pub mod peephole;
+pub mod stats;
+mod trace;
+pub mod value;
+pub mod vm;
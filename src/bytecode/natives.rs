@@ -0,0 +1,29 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bytecode::value::{ObjNative, Value};
+
+/// Natives every VM starts with, in the order of their `OP_GET_NATIVE`
+/// indexes. These are the natives of the tree-walk interpreter that only
+/// need values the VM can represent.
+pub fn standard_natives() -> Vec<ObjNative> {
+    vec![ObjNative {
+        name: "clock",
+        arity: 0,
+        function: clock,
+    }]
+}
+
+/// Index of the standard native called `name`, for the assembler
+pub fn standard_native_index(name: &str) -> Option<usize> {
+    standard_natives()
+        .iter()
+        .position(|native| native.name == name)
+}
+
+fn clock(_arguments: &[Value]) -> Result<Value, String> {
+    let elapsed = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|error| error.to_string())?;
+
+    Ok(Value::Number(elapsed.as_secs() as f64))
+}
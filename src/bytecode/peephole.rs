@@ -0,0 +1,64 @@
+use std::collections::HashSet;
+
+use crate::bytecode::{
+    chunk::{Chunk, Instruction, OpCode},
+    error::Result,
+};
+
+/// Rewrites common instruction pairs into single superinstructions.
+///
+/// An `OP_CONSTANT` followed by an arithmetic instruction or `OP_RETURN` is
+/// replaced by the fused instruction taking the constant as its operand, so
+/// the constant never goes through the stack:
+///
+/// ```text
+/// OP_CONSTANT 0      =>  OP_ADD_CONSTANT 0
+/// OP_ADD
+/// ```
+///
+/// The constant table is kept as is. Fused instructions take the line of the
+/// `OP_CONSTANT` they replace. A pair isn't fused when a jump lands on its
+/// second instruction, which then doesn't always run after the first.
+pub struct Peephole<'a> {
+    chunk: &'a Chunk,
+}
+
+impl<'a> Peephole<'a> {
+    pub fn new(chunk: &'a Chunk) -> Self {
+        Self { chunk }
+    }
+
+    pub fn optimize(&self) -> Result<Chunk> {
+        let decoded = self.chunk.instructions()?;
+        let targets: HashSet<usize> = decoded.iter().filter_map(Instruction::target).collect();
+
+        let mut instructions = vec![];
+        let mut decoded = decoded.into_iter().peekable();
+        while let Some(instruction) = decoded.next() {
+            let fused = match decoded.peek() {
+                Some(next)
+                    if instruction.code == OpCode::Constant && !targets.contains(&next.offset) =>
+                {
+                    next.code.with_constant()
+                }
+                _ => None,
+            };
+
+            match fused {
+                Some(code) => {
+                    decoded.next();
+                    instructions.push(Instruction {
+                        code,
+                        ..instruction
+                    });
+                }
+                None => instructions.push(instruction),
+            }
+        }
+
+        let mut output = self.chunk.clone();
+        output.rewrite(&instructions)?;
+
+        Ok(output)
+    }
+}
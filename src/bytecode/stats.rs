@@ -0,0 +1,52 @@
+use std::{collections::HashMap, fmt::Display};
+
+use crate::bytecode::chunk::OpCode;
+
+/// Counts of the work done by the VM, collected when `VmConfig::stats` is set
+#[derive(Debug, Default, Clone)]
+pub struct VmStats {
+    /// Number of times each opcode was executed
+    pub instructions: HashMap<OpCode, usize>,
+    pub pushes: usize,
+    pub pops: usize,
+    pub constants_loaded: usize,
+}
+
+impl VmStats {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Total number of instructions executed
+    pub fn instruction_count(&self) -> usize {
+        self.instructions.values().sum()
+    }
+
+    pub(crate) fn record_instruction(&mut self, code: OpCode) {
+        *self.instructions.entry(code).or_insert(0) += 1;
+    }
+}
+
+impl Display for VmStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut instructions = self.instructions.iter().collect::<Vec<_>>();
+        instructions.sort_by(|(a_code, a_count), (b_code, b_count)| {
+            b_count
+                .cmp(a_count)
+                .then(u8::from(**a_code).cmp(&u8::from(**b_code)))
+        });
+
+        writeln!(f, "{:<22} {:>10}", "instructions", self.instruction_count())?;
+        for (code, count) in instructions {
+            writeln!(f, "  {:<20} {:>10}", code.as_ref(), count)?;
+        }
+
+        writeln!(f, "{:<22} {:>10}", "pushes", self.pushes)?;
+        writeln!(f, "{:<22} {:>10}", "pops", self.pops)?;
+        write!(
+            f,
+            "{:<22} {:>10}",
+            "constants loaded", self.constants_loaded
+        )
+    }
+}
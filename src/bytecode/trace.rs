@@ -0,0 +1,74 @@
+use std::io::Write;
+
+use crate::bytecode::{
+    chunk::{Chunk, OpCode},
+    error::Result,
+    value::Value,
+};
+
+/// Writes one JSON object per executed instruction, one object per line:
+///
+/// ```text
+/// {"offset":0,"line":1,"opcode":"OP_CONSTANT","operands":[0],"constant":1.2,"stack":[]}
+/// ```
+///
+/// `stack` is the state of the stack _before_ the instruction executes.
+pub struct JsonTracer<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonTracer<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn trace(&mut self, chunk: &Chunk, offset: usize, stack: &[Value]) -> Result<()> {
+        let code: OpCode = chunk.get_code(offset).try_into()?;
+        let operands = (1..=code.operand_count())
+            .map(|i| chunk.get_code(offset + i))
+            .collect::<Vec<_>>();
+
+        write!(
+            self.writer,
+            "{{\"offset\":{},\"line\":{},\"opcode\":\"{}\",\"operands\":[{}]",
+            offset,
+            chunk.get_line(offset),
+            code,
+            join(operands.iter().map(|operand| operand.to_string())),
+        )?;
+
+        if code.has_constant_operand() {
+            let constant = chunk.get_constant(operands[0] as usize);
+            write!(self.writer, ",\"constant\":{}", json_number(constant))?;
+        }
+
+        writeln!(
+            self.writer,
+            ",\"stack\":[{}]}}",
+            join(stack.iter().map(|value| json_number(*value)))
+        )?;
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn join(values: impl Iterator<Item = String>) -> String {
+    values.collect::<Vec<_>>().join(",")
+}
+
+/// JSON has no representation for NaN, the infinities or natives, so those
+/// are written as strings
+fn json_number(value: Value) -> String {
+    match value {
+        Value::Nil => "null".into(),
+        Value::Boolean(boolean) => boolean.to_string(),
+        Value::Number(number) if number.is_finite() => number.to_string(),
+        Value::Native(native) => format!("\"<native fn {}>\"", native.name),
+        value => format!("\"{}\"", value),
+    }
+}
@@ -1 +1,76 @@
-pub type Value = f64;
+use std::fmt::Display;
+
+use crate::semantics::{self, Primitive};
+
+pub type NativeFn = fn(&[Value]) -> Result<Value, String>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    Native(ObjNative),
+}
+
+impl Value {
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(number) => Some(*number),
+            _ => None,
+        }
+    }
+
+    /// The value as the rules of `semantics` see it
+    pub fn primitive(&self) -> Primitive<'static> {
+        match self {
+            Value::Nil => Primitive::Nil,
+            Value::Boolean(boolean) => Primitive::Boolean(*boolean),
+            Value::Number(number) => Primitive::Number(*number),
+            Value::Native(_) => Primitive::Object("function"),
+        }
+    }
+
+    pub fn is_truthy(&self) -> bool {
+        semantics::is_truthy(self.primitive())
+    }
+
+    pub fn is_equal(&self, other: &Value) -> bool {
+        semantics::is_equal(
+            self.primitive(),
+            other.primitive(),
+            || matches!((self, other), (Value::Native(a), Value::Native(b)) if a == b),
+        )
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Self {
+        Value::Number(value)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Native(_) => write!(f, "<native fn>"),
+            value => write!(f, "{}", value.primitive()),
+        }
+    }
+}
+
+/// Function implemented in Rust, called with `OP_CALL` like any other value.
+///
+/// Natives are registered on the VM with `Vm::define_native` and loaded onto
+/// the stack with `OP_GET_NATIVE`.
+#[derive(Debug, Clone, Copy)]
+pub struct ObjNative {
+    pub name: &'static str,
+    pub arity: usize,
+    pub function: NativeFn,
+}
+
+impl PartialEq for ObjNative {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
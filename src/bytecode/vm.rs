@@ -1,10 +1,21 @@
-use std::fmt::Display;
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+};
 
 use crate::{
-    chunk::{Chunk, OpCode},
-    debug::Disassembler,
-    error::Result,
-    value::Value,
+    bytecode::{
+        chunk::{Chunk, OpCode},
+        debug::Disassembler,
+        error::{LoxError, Result},
+        natives::standard_natives,
+        stats::VmStats,
+        trace::JsonTracer,
+        value::{NativeFn, ObjNative, Value},
+    },
+    semantics::{self, Comparison},
 };
 
 const STACK_MAX: usize = 256;
@@ -19,19 +30,45 @@ struct Stack {
 impl Stack {
     pub fn new() -> Self {
         Self {
-            values: [0.0; STACK_MAX],
+            values: [Value::Nil; STACK_MAX],
             top: 0,
         }
     }
 
-    pub fn push(&mut self, value: Value) {
-        self.values[self.top] = value;
-        self.top += 1;
+    /// Pushes `value`, returning `false` if the stack is already full
+    pub fn push(&mut self, value: Value) -> bool {
+        match self.values.get_mut(self.top) {
+            Some(slot) => {
+                *slot = value;
+                self.top += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pops the top value, or returns `None` if the stack is empty
+    pub fn pop(&mut self) -> Option<Value> {
+        self.top = self.top.checked_sub(1)?;
+        Some(self.values[self.top])
+    }
+
+    /// The value `distance` slots down from the top
+    pub fn peek(&self, distance: usize) -> Value {
+        self.values[self.top - 1 - distance]
     }
 
-    pub fn pop(&mut self) -> Value {
-        self.top -= 1;
-        self.values[self.top]
+    pub fn reset(&mut self) {
+        self.top = 0;
+    }
+
+    /// Drops the values above the first `top`
+    pub fn truncate(&mut self, top: usize) {
+        self.top = self.top.min(top);
+    }
+
+    pub fn values(&self) -> &[Value] {
+        &self.values[..self.top]
     }
 }
 
@@ -47,8 +84,22 @@ impl Display for Stack {
     }
 }
 
+/// Where execution continues when a runtime error is raised, registered by
+/// `OP_PUSH_HANDLER`
+#[derive(Debug, Clone, Copy)]
+struct Handler {
+    /// Offset of the handler's first instruction
+    target: usize,
+    /// Stack size when the handler was pushed, which unwinding goes back to
+    depth: usize,
+}
+
 pub struct VmConfig {
     pub debug: bool,
+    /// File to write a JSON trace of every executed instruction to
+    pub trace_json: Option<PathBuf>,
+    /// Counts executed instructions and stack operations, see `Vm::stats`
+    pub stats: bool,
 }
 
 pub struct Vm {
@@ -57,63 +108,357 @@ pub struct Vm {
     /// Instruction Pointer: tracks the _next_ instruction to be executed
     ip: usize,
     stack: Stack,
+    tracer: Option<JsonTracer<BufWriter<File>>>,
+    stats: Option<VmStats>,
+    /// Where values returned by chunks are printed
+    output: Box<dyn Write>,
+    /// Natives loaded by `OP_GET_NATIVE`, by index
+    natives: Vec<ObjNative>,
+    /// Error handlers of the running chunk, innermost last
+    handlers: Vec<Handler>,
 }
 
 impl Vm {
-    pub fn interpret(code: Chunk, config: VmConfig) -> Result<()> {
-        let mut vm = Vm {
+    /// Creates a VM that can run any number of chunks, one after another
+    pub fn new(config: VmConfig) -> Result<Self> {
+        let tracer = match &config.trace_json {
+            Some(path) => Some(JsonTracer::new(BufWriter::new(File::create(path)?))),
+            None => None,
+        };
+
+        let stats = config.stats.then(VmStats::new);
+
+        Ok(Vm {
             config,
-            code,
+            code: Chunk::default(),
             ip: 0,
             stack: Stack::new(),
-        };
+            tracer,
+            stats,
+            output: Box::new(io::stdout()),
+            natives: standard_natives(),
+            handlers: vec![],
+        })
+    }
+
+    /// Registers a native after the standard ones, returning the index
+    /// `OP_GET_NATIVE` loads it with
+    pub fn define_native(&mut self, name: &'static str, arity: usize, function: NativeFn) -> usize {
+        self.natives.push(ObjNative {
+            name,
+            arity,
+            function,
+        });
+
+        self.natives.len() - 1
+    }
+
+    /// Redirects the values printed by `OP_RETURN`, which go to stdout by
+    /// default
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Counters for every chunk run so far, if `VmConfig::stats` is set
+    pub fn stats(&self) -> Option<&VmStats> {
+        self.stats.as_ref()
+    }
+
+    /// Runs a single chunk on a fresh VM
+    pub fn interpret(code: Chunk, config: VmConfig) -> Result<()> {
+        Vm::new(config)?.run_chunk(code)
+    }
+
+    /// Runs `code` to completion. The stack is emptied first, so nothing a
+    /// previous chunk left behind is visible to it, while the VM's own state,
+    /// like the trace file, carries over between runs.
+    pub fn run_chunk(&mut self, code: Chunk) -> Result<()> {
+        self.code = code;
+        self.ip = 0;
+        self.stack.reset();
+        self.handlers.clear();
+
+        let result = self.run();
 
-        vm.run()
+        if let Some(tracer) = &mut self.tracer {
+            tracer.flush()?;
+        }
+
+        result
     }
 
     fn run(&mut self) -> Result<()> {
         loop {
+            if self.ip >= self.code.count() {
+                return Err(self.internal_error(self.ip, "Ran off the end of the chunk."));
+            }
+
             if self.config.debug {
                 println!("          {}", self.stack);
                 Disassembler::new(&self.code).process_instruction(self.ip)?;
             }
 
-            let instruction = self.read_byte();
-            match instruction.try_into()? {
-                OpCode::Return => {
-                    println!("{}", self.stack.pop());
-                    return Ok(());
-                }
-                OpCode::Negate => {
-                    let value = -self.stack.pop();
-                    self.stack.push(value);
-                }
-                OpCode::Add => self.binary_op(|a, b| a + b),
-                OpCode::Subtract => self.binary_op(|a, b| a - b),
-                OpCode::Multiply => self.binary_op(|a, b| a * b),
-                OpCode::Divide => self.binary_op(|a, b| a / b),
-                OpCode::Constant => {
-                    let index = self.read_byte() as usize;
-                    let constant = self.code.get_constant(index);
-                    self.stack.push(constant);
+            if let Some(tracer) = &mut self.tracer {
+                tracer.trace(&self.code, self.ip, self.stack.values())?;
+            }
+
+            let offset = self.ip;
+            let instruction = self.read_byte(offset)?;
+            let code = instruction.try_into().map_err(|_| {
+                self.runtime_error(offset, format!("Unknown opcode {}", instruction))
+            })?;
+
+            if let Some(stats) = &mut self.stats {
+                stats.record_instruction(code);
+            }
+
+            match self.execute(offset, code) {
+                Ok(false) => {}
+                Ok(true) => return Ok(()),
+                Err(error @ LoxError::RuntimeError { .. }) => match self.handlers.pop() {
+                    Some(handler) => self.unwind(handler),
+                    None => return Err(error),
+                },
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Executes the instruction at `offset`, whose opcode has been read,
+    /// returning whether the chunk returned
+    fn execute(&mut self, offset: usize, code: OpCode) -> Result<bool> {
+        match code {
+            OpCode::Return => {
+                let value = self.pop(offset)?;
+                writeln!(self.output, "{}", value)?;
+                return Ok(true);
+            }
+            OpCode::Negate => {
+                let value = self.pop(offset)?;
+                let value = match value.as_number() {
+                    Some(value) => -value,
+                    None => {
+                        let message = semantics::number_operand_error(value.primitive());
+                        return Err(self.runtime_error(offset, message));
+                    }
+                };
+                self.push(offset, Value::Number(value))?;
+            }
+            OpCode::Nil => self.push(offset, Value::Nil)?,
+            OpCode::True => self.push(offset, Value::Boolean(true))?,
+            OpCode::False => self.push(offset, Value::Boolean(false))?,
+            OpCode::Not => {
+                let value = self.pop(offset)?;
+                self.push(offset, Value::Boolean(!value.is_truthy()))?;
+            }
+            OpCode::Equal => {
+                let b = self.pop(offset)?;
+                let a = self.pop(offset)?;
+                self.push(offset, Value::Boolean(a.is_equal(&b)))?;
+            }
+            OpCode::Greater => self.comparison(offset, Comparison::Greater)?,
+            OpCode::Less => self.comparison(offset, Comparison::Less)?,
+            OpCode::Add => self.binary_op(offset, |a, b| a + b)?,
+            OpCode::Subtract => self.binary_op(offset, |a, b| a - b)?,
+            OpCode::Multiply => self.binary_op(offset, |a, b| a * b)?,
+            OpCode::Divide => self.binary_op(offset, |a, b| a / b)?,
+            OpCode::Constant => {
+                let constant = self.read_constant(offset)?;
+                self.push(offset, constant)?;
+            }
+            OpCode::AddConstant => self.constant_op(offset, |a, b| a + b)?,
+            OpCode::SubtractConstant => self.constant_op(offset, |a, b| a - b)?,
+            OpCode::MultiplyConstant => self.constant_op(offset, |a, b| a * b)?,
+            OpCode::DivideConstant => self.constant_op(offset, |a, b| a / b)?,
+            OpCode::ConstantReturn => {
+                let value = self.read_constant(offset)?;
+                writeln!(self.output, "{}", value)?;
+                return Ok(true);
+            }
+            OpCode::GetNative => {
+                let index = self.read_byte(offset)? as usize;
+                let native = match self.natives.get(index) {
+                    Some(native) => *native,
+                    None => {
+                        return Err(
+                            self.runtime_error(offset, format!("Undefined native {}.", index))
+                        )
+                    }
+                };
+                self.push(offset, Value::Native(native))?;
+            }
+            OpCode::Call => {
+                let count = self.read_byte(offset)? as usize;
+                self.call(offset, count)?;
+            }
+            OpCode::Jump => {
+                let distance = self.read_byte(offset)? as usize;
+                self.ip += distance;
+            }
+            OpCode::PushHandler => {
+                let distance = self.read_byte(offset)? as usize;
+                self.handlers.push(Handler {
+                    target: self.ip + distance,
+                    depth: self.stack.values().len(),
+                });
+            }
+            OpCode::PopHandler => {
+                if self.handlers.pop().is_none() {
+                    return Err(self.runtime_error(offset, "No error handler to pop."));
                 }
             }
         }
+
+        Ok(false)
     }
 
-    fn read_byte(&mut self) -> u8 {
+    /// Continues at `handler` after a runtime error, dropping what the failed
+    /// code left on the stack. The VM has no strings or instances yet, so
+    /// unlike a `catch` clause the handler doesn't get the error. There are no
+    /// upvalues either, so restoring the stack is all unwinding has to do.
+    fn unwind(&mut self, handler: Handler) {
+        self.stack.truncate(handler.depth);
+        self.ip = handler.target;
+    }
+
+    /// Calls the value below the `count` arguments on top of the stack,
+    /// replacing them all with the result
+    fn call(&mut self, offset: usize, count: usize) -> Result<()> {
+        if count >= self.stack.values().len() {
+            return Err(self.runtime_error(offset, "Not enough values on the stack to call."));
+        }
+
+        let native = match self.stack.peek(count) {
+            Value::Native(native) => native,
+            _ => {
+                return Err(self.runtime_error(offset, "Can only call functions and classes."));
+            }
+        };
+
+        if native.arity != count {
+            return Err(self.runtime_error(
+                offset,
+                format!("Expected {} arguments but got {}.", native.arity, count),
+            ));
+        }
+
+        let values = self.stack.values();
+        let arguments = values[values.len() - count..].to_vec();
+        let result =
+            (native.function)(&arguments).map_err(|message| self.runtime_error(offset, message))?;
+
+        for _ in 0..=count {
+            self.pop(offset)?;
+        }
+        self.push(offset, result)?;
+
+        Ok(())
+    }
+
+    fn runtime_error(&self, offset: usize, message: impl Into<String>) -> LoxError {
+        LoxError::RuntimeError {
+            message: message.into(),
+            location: self.code.location(offset),
+        }
+    }
+
+    /// Errors for states a correct chunk can't get the VM into, which
+    /// handlers don't catch
+    fn internal_error(&self, offset: usize, message: impl Into<String>) -> LoxError {
+        LoxError::Internal {
+            message: message.into(),
+            location: self.code.location(offset),
+        }
+    }
+
+    /// Reads the next byte of the instruction at `offset`
+    fn read_byte(&mut self, offset: usize) -> Result<u8> {
+        if self.ip >= self.code.count() {
+            return Err(self.internal_error(offset, "Ran off the end of the chunk."));
+        }
+
         let byte = self.code.get_code(self.ip);
         self.ip += 1;
 
-        byte
+        Ok(byte)
+    }
+
+    fn read_constant(&mut self, offset: usize) -> Result<Value> {
+        let index = self.read_byte(offset)? as usize;
+        if let Some(stats) = &mut self.stats {
+            stats.constants_loaded += 1;
+        }
+
+        match self.code.constants().get(index) {
+            Some(constant) => Ok(*constant),
+            None => Err(self.internal_error(offset, format!("Undefined constant {}.", index))),
+        }
+    }
+
+    /// Applies `op` to the top of the stack and the constant in the operand,
+    /// without pushing the constant first
+    fn constant_op<F>(&mut self, offset: usize, op: F) -> Result<()>
+    where
+        F: FnOnce(f64, f64) -> f64,
+    {
+        let b = self.read_constant(offset)?;
+        let a = self.pop(offset)?;
+        self.arithmetic(offset, a, b, op)
+    }
+
+    fn binary_op<F>(&mut self, offset: usize, op: F) -> Result<()>
+    where
+        F: FnOnce(f64, f64) -> f64,
+    {
+        let b = self.pop(offset)?;
+        let a = self.pop(offset)?;
+        self.arithmetic(offset, a, b, op)
     }
 
-    fn binary_op<F>(&mut self, op: F)
+    fn arithmetic<F>(&mut self, offset: usize, a: Value, b: Value, op: F) -> Result<()>
     where
-        F: FnOnce(Value, Value) -> Value,
+        F: FnOnce(f64, f64) -> f64,
     {
-        let b = self.stack.pop();
-        let a = self.stack.pop();
-        self.stack.push(op(a, b));
+        match (a.as_number(), b.as_number()) {
+            (Some(a), Some(b)) => {
+                self.push(offset, Value::Number(op(a, b)))?;
+                Ok(())
+            }
+            _ => Err(self.runtime_error(
+                offset,
+                semantics::operand_error("Operands must be numbers", a.primitive(), b.primitive()),
+            )),
+        }
+    }
+
+    fn comparison(&mut self, offset: usize, comparison: Comparison) -> Result<()> {
+        let b = self.pop(offset)?;
+        let a = self.pop(offset)?;
+        let result = semantics::compare(comparison, a.primitive(), b.primitive())
+            .map_err(|message| self.runtime_error(offset, message))?;
+        self.push(offset, Value::Boolean(result))?;
+
+        Ok(())
+    }
+
+    fn push(&mut self, offset: usize, value: Value) -> Result<()> {
+        if let Some(stats) = &mut self.stats {
+            stats.pushes += 1;
+        }
+
+        match self.stack.push(value) {
+            true => Ok(()),
+            false => Err(self.runtime_error(offset, "Stack overflow.")),
+        }
+    }
+
+    fn pop(&mut self, offset: usize) -> Result<Value> {
+        if let Some(stats) = &mut self.stats {
+            stats.pops += 1;
+        }
+
+        self.stack
+            .pop()
+            .ok_or_else(|| self.internal_error(offset, "Popped an empty stack."))
     }
 }
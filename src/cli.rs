@@ -0,0 +1,32 @@
+//! Options shared by the `ilox` and `blox` command lines.
+
+use structopt::StructOpt;
+
+use crate::interpreter::diagnostic::{ColorChoice, DiagnosticRenderer, ErrorFormat};
+
+#[derive(StructOpt, Debug)]
+pub struct DiagnosticOptions {
+    /// When to color diagnostics: auto, always or never
+    #[structopt(
+        long,
+        global = true,
+        default_value = "auto",
+        possible_values = &["auto", "always", "never"]
+    )]
+    pub color: ColorChoice,
+
+    /// How diagnostics are printed: human or json
+    #[structopt(
+        long,
+        global = true,
+        default_value = "human",
+        possible_values = &["human", "json"]
+    )]
+    pub error_format: ErrorFormat,
+}
+
+impl DiagnosticOptions {
+    pub fn renderer(&self) -> DiagnosticRenderer {
+        DiagnosticRenderer::new(self.color, self.error_format)
+    }
+}
@@ -0,0 +1,82 @@
+use std::collections::HashSet;
+
+use crate::interpreter::{
+    error::Result,
+    expr::Expr,
+    interpreter::Interpreter,
+    parser::{Parser, ParserConfig},
+    resolver::Resolver,
+    rewriter::{rewrite_parsed, Rewriter, Transform},
+    scanner::Scanner,
+    stmt::Stmt,
+    token::Token,
+};
+
+/// Returns `source` with a comment after every variable reference saying what
+/// the resolver made of it: how many scopes out the variable is declared, or
+/// that it's a global.
+///
+/// ```text
+/// var a = 1;
+/// fun f(b) {
+///   return a/*global*/ + b/*d=0*/;
+/// }
+/// ```
+pub fn annotate_resolved(source: &str) -> Result<String> {
+    let tokens = Scanner::new(source.to_string()).scan_tokens()?;
+    let statements = Parser::new(tokens.clone(), ParserConfig::default()).parse()?;
+
+    let mut interpreter = Interpreter::new();
+    Resolver::new(&mut interpreter).resolve(&statements)?;
+
+    let mut annotate = ResolvedDepths {
+        interpreter: &interpreter,
+        seen: HashSet::new(),
+    };
+    rewrite_parsed(source, tokens, &statements, &mut annotate)
+}
+
+struct ResolvedDepths<'a> {
+    interpreter: &'a Interpreter,
+    /// Starts of the names already annotated or declared. Desugaring can
+    /// reuse a name's span, like the `close` call on the resource of a
+    /// `using` block, which shouldn't be annotated on its declaration.
+    seen: HashSet<usize>,
+}
+
+impl<'a> ResolvedDepths<'a> {
+    fn annotate(&mut self, name: &Token, rewriter: &mut Rewriter) {
+        if !self.seen.insert(name.span.start) || rewriter.text(name.span) != name.lexeme {
+            return;
+        }
+
+        let note = match self.interpreter.resolved_depth(name) {
+            Some(depth) => format!("/*d={}*/", depth),
+            None => "/*global*/".into(),
+        };
+        rewriter.insert_after(name, note);
+    }
+}
+
+impl<'a> Transform for ResolvedDepths<'a> {
+    fn statement(&mut self, statement: &Stmt, _rewriter: &mut Rewriter) {
+        if let Stmt::Var(name, ..) = statement {
+            self.seen.insert(name.span.start);
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr, rewriter: &mut Rewriter) {
+        match expr {
+            Expr::Variable(name)
+            | Expr::Assign(name, _)
+            | Expr::This(name)
+            | Expr::Super(name, _) => self.annotate(name, rewriter),
+            Expr::Destructure(_, names, _) => {
+                for name in names {
+                    self.annotate(name, rewriter);
+                }
+            }
+            _ => (),
+        }
+    }
+}
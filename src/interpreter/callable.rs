@@ -1,4 +1,4 @@
-use crate::{
+use crate::interpreter::{
     error::{LoxError, Result},
     interpreter::Interpreter,
     value::{LoxInstance, Value},
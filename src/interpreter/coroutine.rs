@@ -0,0 +1,432 @@
+use std::{cell::RefCell, mem, rc::Rc};
+
+use crate::interpreter::{
+    environment::Environment,
+    error::{LoxError, Result},
+    expr::Expr,
+    interpreter::Interpreter,
+    stmt::Stmt,
+    token::Token,
+    value::{LoxInstance, Value},
+};
+
+/// A Lox function running as a coroutine: `yield` suspends it and `resume`
+/// continues it from where it left off.
+///
+/// The interpreter keeps its place in a program on the Rust stack, which
+/// can't be suspended, so the statements that can reach a `yield` run here
+/// instead, keeping their place in a stack of frames. Everything else runs
+/// in the interpreter as usual, which means only the coroutine's own body
+/// can yield: a `yield` in a function it calls is an error.
+#[derive(Debug)]
+pub struct Coroutine {
+    function: Value,
+    state: State,
+}
+
+#[derive(Debug)]
+enum State {
+    /// Not resumed yet
+    Created,
+    /// Stopped at a `yield`, which stores the value it's resumed with in
+    /// `target`
+    Suspended {
+        frames: Vec<Frame>,
+        target: Target,
+    },
+    /// Between a `resume` and the next `yield`
+    Running,
+    Finished,
+}
+
+/// Where the value a suspended `yield` is resumed with goes
+#[derive(Debug)]
+enum Target {
+    /// `yield value;`
+    Discard,
+    /// `var name = yield value;`
+    Define(Token, Rc<RefCell<Environment>>),
+    /// `name = yield value;`
+    Assign(Token, Rc<RefCell<Environment>>),
+}
+
+/// A statement that can yield, in progress. Environments are created the way
+/// the interpreter creates them, since the resolver's distances depend on it.
+#[derive(Debug)]
+enum Frame {
+    Block {
+        statements: Vec<Stmt>,
+        index: usize,
+        environment: Rc<RefCell<Environment>>,
+    },
+    While {
+        condition: Expr,
+        body: Stmt,
+        /// Where iteration limit errors are reported
+        token: Option<Box<Token>>,
+        environment: Rc<RefCell<Environment>>,
+    },
+    ForIn {
+        name: Token,
+        iteration: Iteration,
+        body: Stmt,
+        environment: Rc<RefCell<Environment>>,
+    },
+}
+
+#[derive(Debug)]
+enum Iteration {
+    List {
+        values: Rc<RefCell<Vec<Value>>>,
+        index: usize,
+    },
+    Iterator(LoxInstance),
+}
+
+/// How a resumed coroutine stopped
+pub enum Resumed {
+    Yielded(Value),
+    Returned(Value),
+}
+
+/// What running a statement led to
+enum Step {
+    Continue,
+    Push(Box<Frame>),
+    Yield(Value, Target),
+    Return(Value),
+}
+
+impl Coroutine {
+    /// A coroutine running `function`, which takes the value of the first
+    /// `resume` if it has a parameter
+    pub fn new(function: Value) -> Result<Self> {
+        match &function {
+            Value::Function { parameters, .. } if parameters.len() <= 1 => Ok(Self {
+                function,
+                state: State::Created,
+            }),
+            Value::Function { .. } => Err(LoxError::NativeError(
+                "A coroutine's function can take at most one parameter.".into(),
+            )),
+            _ => Err(LoxError::NativeError(
+                "Expected a Lox function to run as a coroutine.".into(),
+            )),
+        }
+    }
+
+    /// A coroutine running a call of the generator `function` with
+    /// `arguments`, which starts on the first resume
+    pub fn call(function: Value, arguments: &[Value]) -> Result<Self> {
+        let frames = Self::frames(&function, arguments)?;
+
+        Ok(Self {
+            function,
+            state: State::Suspended {
+                frames,
+                target: Target::Discard,
+            },
+        })
+    }
+
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, State::Finished)
+    }
+
+    /// Runs the coroutine held by `instance` until it yields or returns,
+    /// giving back the value it yielded or returned. The first resume starts
+    /// it with `value` as its argument, and later ones make the `yield` it's
+    /// suspended at evaluate to `value`.
+    pub fn resume(
+        interpreter: &mut Interpreter,
+        instance: &LoxInstance,
+        value: Value,
+    ) -> Result<Resumed> {
+        let (function, state) = instance
+            .with_native_state(|coroutine: &mut Coroutine| {
+                let state = match coroutine.state {
+                    State::Finished => State::Finished,
+                    _ => mem::replace(&mut coroutine.state, State::Running),
+                };
+
+                (coroutine.function.clone(), state)
+            })
+            .ok_or_else(|| LoxError::NativeError("Coroutine was not initialized.".into()))?;
+
+        let (name, frames) = match (&function, state) {
+            (_, State::Finished) => {
+                return Err(LoxError::NativeError(
+                    "Can't resume a finished coroutine.".into(),
+                ))
+            }
+            (_, State::Running) => {
+                return Err(LoxError::NativeError(
+                    "Can't resume a coroutine that's running.".into(),
+                ))
+            }
+            (Value::Function { name, .. }, State::Created) => {
+                (name, Self::frames(&function, &[value]))
+            }
+            (Value::Function { name, .. }, State::Suspended { frames, target }) => {
+                (name, target.store(interpreter, value).map(|()| frames))
+            }
+            _ => return Err(LoxError::internal("Coroutine isn't running a function.")),
+        };
+
+        let result = frames.and_then(|mut frames| {
+            interpreter.enter_call(name)?;
+            let step = Self::run(interpreter, &mut frames);
+            interpreter.exit_call();
+
+            Ok((step?, frames))
+        });
+
+        let (state, result) = match result {
+            Ok((Step::Yield(value, target), frames)) => (
+                State::Suspended { frames, target },
+                Ok(Resumed::Yielded(value)),
+            ),
+            Ok((Step::Return(value), _)) => (State::Finished, Ok(Resumed::Returned(value))),
+            Ok((Step::Continue | Step::Push(_), _)) => {
+                (State::Finished, Ok(Resumed::Returned(Value::Nil)))
+            }
+            Err(error) => (State::Finished, Err(error)),
+        };
+
+        instance.with_native_state(|coroutine: &mut Coroutine| coroutine.state = state);
+
+        result
+    }
+
+    /// The frames of a call of `function`, whose parameters are bound to as
+    /// many `arguments` as there are
+    fn frames(function: &Value, arguments: &[Value]) -> Result<Vec<Frame>> {
+        let (parameters, body, closure) = match function {
+            Value::Function {
+                parameters,
+                body,
+                closure,
+                ..
+            } => (parameters, body, closure),
+            _ => return Err(LoxError::internal("Coroutine isn't running a function.")),
+        };
+
+        let scope = Environment::new_with_parent(closure.clone());
+        for (parameter, argument) in parameters.iter().zip(arguments) {
+            scope
+                .borrow_mut()
+                .define(&parameter.lexeme, argument.clone());
+        }
+
+        Ok(vec![Frame::Block {
+            statements: body.to_vec(),
+            index: 0,
+            environment: scope,
+        }])
+    }
+
+    /// Runs the frames until a `yield` or `return`, or until they're done
+    fn run(interpreter: &mut Interpreter, frames: &mut Vec<Frame>) -> Result<Step> {
+        while let Some(frame) = frames.last_mut() {
+            let step = match frame {
+                Frame::Block {
+                    statements,
+                    index,
+                    environment,
+                } => match statements.get(*index) {
+                    Some(statement) => {
+                        *index += 1;
+                        Some(Self::execute(interpreter, statement, environment.clone())?)
+                    }
+                    None => None,
+                },
+                Frame::While {
+                    condition,
+                    body,
+                    token,
+                    environment,
+                } => {
+                    let condition = interpreter
+                        .in_environment(environment.clone(), |interpreter| {
+                            interpreter.evaluate(condition)
+                        })?;
+
+                    match condition.is_truthy() {
+                        true => {
+                            interpreter.count_iteration(token.as_deref())?;
+                            Some(Self::execute(interpreter, body, environment.clone())?)
+                        }
+                        false => None,
+                    }
+                }
+                Frame::ForIn {
+                    name,
+                    iteration,
+                    body,
+                    environment,
+                } => match iteration.next(interpreter, name)? {
+                    Some(value) => {
+                        interpreter.count_iteration(Some(name))?;
+                        let scope = Environment::new_with_parent(environment.clone());
+                        scope.borrow_mut().define(&name.lexeme, value);
+
+                        Some(Step::Push(Box::new(Frame::Block {
+                            statements: vec![body.clone()],
+                            index: 0,
+                            environment: scope,
+                        })))
+                    }
+                    None => None,
+                },
+            };
+
+            match step {
+                None => {
+                    frames.pop();
+                }
+                Some(Step::Continue) => {}
+                Some(Step::Push(frame)) => frames.push(*frame),
+                Some(step) => return Ok(step),
+            }
+        }
+
+        Ok(Step::Return(Value::Nil))
+    }
+
+    /// Runs a statement in `environment`. Statements that can't yield run in
+    /// the interpreter, and the others start a frame or yield.
+    fn execute(
+        interpreter: &mut Interpreter,
+        statement: &Stmt,
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<Step> {
+        if !statement.yields() {
+            return match interpreter
+                .in_environment(environment, |interpreter| interpreter.execute(statement))
+            {
+                Ok(()) => Ok(Step::Continue),
+                Err(LoxError::ReturnJump(value)) => Ok(Step::Return(value)),
+                Err(error) => Err(error),
+            };
+        }
+
+        let evaluate = |interpreter: &mut Interpreter, expr: &Expr| {
+            interpreter.in_environment(environment.clone(), |interpreter| {
+                interpreter.evaluate(expr)
+            })
+        };
+
+        let yielded = |interpreter: &mut Interpreter, value: &Option<Box<Expr>>| match value {
+            Some(value) => evaluate(interpreter, value),
+            None => Ok(Value::Nil),
+        };
+
+        match statement {
+            Stmt::Expression(Expr::Yield(_, value)) => {
+                Ok(Step::Yield(yielded(interpreter, value)?, Target::Discard))
+            }
+            Stmt::Var(name, Some(Expr::Yield(_, value)), _) => Ok(Step::Yield(
+                yielded(interpreter, value)?,
+                Target::Define(name.clone(), environment.clone()),
+            )),
+            Stmt::Expression(Expr::Assign(name, assigned)) => match assigned.as_ref() {
+                Expr::Yield(_, value) => Ok(Step::Yield(
+                    yielded(interpreter, value)?,
+                    Target::Assign(name.clone(), environment.clone()),
+                )),
+                _ => Err(LoxError::internal("Assignment doesn't yield.")),
+            },
+            Stmt::Block(statements) => {
+                let environment = match Stmt::declares_names(statements) {
+                    true => Environment::new_with_parent(environment.clone()),
+                    false => environment.clone(),
+                };
+
+                Ok(Step::Push(Box::new(Frame::Block {
+                    statements: statements.clone(),
+                    index: 0,
+                    environment,
+                })))
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                let branch = match evaluate(interpreter, condition)?.is_truthy() {
+                    true => Some(then_branch),
+                    false => else_branch.as_ref(),
+                };
+
+                match branch {
+                    Some(branch) => Self::execute(interpreter, branch, environment.clone()),
+                    None => Ok(Step::Continue),
+                }
+            }
+            Stmt::While(condition, body, origin) => Ok(Step::Push(Box::new(Frame::While {
+                condition: condition.clone(),
+                body: body.as_ref().clone(),
+                token: origin.as_ref().or(condition.token()).cloned().map(Box::new),
+                environment: environment.clone(),
+            }))),
+            Stmt::ForIn(name, iterable, body) => {
+                let iterable = evaluate(interpreter, iterable)?;
+
+                Ok(Step::Push(Box::new(Frame::ForIn {
+                    name: name.clone(),
+                    iteration: Iteration::new(interpreter, iterable, name)?,
+                    body: body.as_ref().clone(),
+                    environment: environment.clone(),
+                })))
+            }
+            _ => Err(LoxError::internal("Statement can't yield.")),
+        }
+    }
+}
+
+impl Target {
+    fn store(self, interpreter: &mut Interpreter, value: Value) -> Result<()> {
+        match self {
+            Target::Discard => Ok(()),
+            Target::Define(name, environment) => {
+                environment.borrow_mut().define(&name.lexeme, value);
+                Ok(())
+            }
+            Target::Assign(name, environment) => interpreter
+                .in_environment(environment, |interpreter| {
+                    interpreter.assign_variable(&name, &value)
+                }),
+        }
+    }
+}
+
+impl Iteration {
+    /// Starts iterating over `iterable` the way a for-in loop with the
+    /// variable `name` does
+    fn new(interpreter: &mut Interpreter, iterable: Value, name: &Token) -> Result<Self> {
+        match iterable {
+            Value::List(values) => Ok(Iteration::List { values, index: 0 }),
+            Value::Instance(instance) => interpreter
+                .iterator(&instance, name)
+                .map(Iteration::Iterator),
+            _ => Err(LoxError::RuntimeError {
+                message: "Can only iterate over lists and instances.".into(),
+                token: Box::new(name.clone()),
+            }),
+        }
+    }
+
+    fn next(&mut self, interpreter: &mut Interpreter, name: &Token) -> Result<Option<Value>> {
+        match self {
+            Iteration::List { values, index } => {
+                // the list can be modified by the body, so it can't stay borrowed
+                let value = values.borrow().get(*index).cloned();
+                *index += 1;
+
+                Ok(value)
+            }
+            Iteration::Iterator(iterator) => {
+                match interpreter.call_method(iterator, "done", name)?.is_truthy() {
+                    true => Ok(None),
+                    false => interpreter.call_method(iterator, "next", name).map(Some),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+//! Crash reports for bugs in the interpreter itself.
+//!
+//! Once [`install`] is called, `Lox` notes what it's running, and a panic or
+//! an internal error can be written up with [`write_report`] as a directory
+//! of plain files in the temp directory:
+//!
+//! ```text
+//! report.txt    version, command line, phase, what went wrong and a backtrace
+//! source.lox    the source being run
+//! tokens.txt    its tokens, one per line
+//! ast.txt       its parsed statements
+//! ```
+//!
+//! Nothing is sent anywhere; it's up to the user to attach the directory to a
+//! bug report. Tokens and statements aren't kept while running, they're made
+//! again from the source when a report is written.
+
+use std::{
+    backtrace::Backtrace,
+    cell::RefCell,
+    env, fs,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::interpreter::{
+    error::LoxError, keywords::Keywords, observer::Phase, parser::Parser, parser::ParserConfig,
+    scanner::Scanner,
+};
+
+thread_local! {
+    static CONTEXT: RefCell<Option<Context>> = const { RefCell::new(None) };
+}
+
+/// What `Lox` was doing when the last run started, and the last panic
+#[derive(Debug, Default)]
+struct Context {
+    /// File the source came from, if any
+    file: Option<PathBuf>,
+    source: String,
+    keywords: Keywords,
+    parser_config: ParserConfig,
+    phase: Option<Phase>,
+    panic: Option<PanicDetails>,
+}
+
+#[derive(Debug)]
+struct PanicDetails {
+    message: String,
+    location: Option<String>,
+    backtrace: String,
+}
+
+/// Starts noting what `Lox` runs on this thread, and replaces the panic hook
+/// with one that keeps the panic for `write_report` instead of printing it
+pub fn install() {
+    CONTEXT.with(|context| *context.borrow_mut() = Some(Context::default()));
+
+    panic::set_hook(Box::new(|info| {
+        let message = match info.payload().downcast_ref::<&str>() {
+            Some(message) => message.to_string(),
+            None => match info.payload().downcast_ref::<String>() {
+                Some(message) => message.clone(),
+                None => "Box<dyn Any>".to_string(),
+            },
+        };
+
+        let details = PanicDetails {
+            message,
+            location: info.location().map(ToString::to_string),
+            backtrace: Backtrace::force_capture().to_string(),
+        };
+
+        let recorded = CONTEXT.with(|context| match &mut *context.borrow_mut() {
+            Some(context) => {
+                context.panic = Some(details);
+                true
+            }
+            None => false,
+        });
+
+        // Panics on threads without a report still need to be seen
+        if !recorded {
+            eprintln!("{}", info);
+        }
+    }));
+}
+
+/// Whether `install` was called on this thread
+pub fn is_installed() -> bool {
+    CONTEXT.with(|context| context.borrow().is_some())
+}
+
+/// Notes the source of a run that's about to start
+pub(crate) fn record_source(
+    file: Option<PathBuf>,
+    source: &str,
+    keywords: &Keywords,
+    parser_config: &ParserConfig,
+) {
+    CONTEXT.with(|context| {
+        if let Some(context) = &mut *context.borrow_mut() {
+            context.file = file;
+            context.source = source.to_string();
+            context.keywords = keywords.clone();
+            context.parser_config = parser_config.clone();
+            context.phase = None;
+        }
+    });
+}
+
+pub(crate) fn record_phase(phase: Phase) {
+    CONTEXT.with(|context| {
+        if let Some(context) = &mut *context.borrow_mut() {
+            context.phase = Some(phase);
+        }
+    });
+}
+
+/// The message of the panic caught last, if any
+pub fn panic_message() -> Option<String> {
+    CONTEXT.with(|context| {
+        context
+            .borrow()
+            .as_ref()
+            .and_then(|context| context.panic.as_ref())
+            .map(|panic| panic.message.clone())
+    })
+}
+
+/// Writes a report of the last panic, or of `error` when it's about an
+/// internal error instead, returning the directory it's in. Returns `None`
+/// if `install` wasn't called or the files couldn't be written.
+pub fn write_report(error: Option<&LoxError>) -> Option<PathBuf> {
+    let context = CONTEXT.with(|context| context.borrow_mut().take())?;
+    let directory = report_directory();
+    let written = write_files(&context, error, &directory);
+
+    // Keep noting runs, in case the program carries on after an internal error
+    let Context {
+        keywords,
+        parser_config,
+        ..
+    } = context;
+    CONTEXT.with(|slot| {
+        *slot.borrow_mut() = Some(Context {
+            keywords,
+            parser_config,
+            ..Context::default()
+        })
+    });
+
+    written.ok().map(|_| directory)
+}
+
+fn report_directory() -> PathBuf {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+
+    env::temp_dir().join(format!("ilox-crash-{}-{}", seconds, process::id()))
+}
+
+fn write_files(
+    context: &Context,
+    error: Option<&LoxError>,
+    directory: &Path,
+) -> std::io::Result<()> {
+    fs::create_dir_all(directory)?;
+    fs::write(directory.join("report.txt"), report(context, error))?;
+    fs::write(directory.join("source.lox"), &context.source)?;
+
+    // The scanner or parser may be what panicked, so their output is made
+    // again without letting a second panic escape
+    let tokens = panic::catch_unwind(AssertUnwindSafe(|| {
+        match Scanner::new(context.source.clone())
+            .with_keywords(context.keywords.clone())
+            .scan_tokens()
+        {
+            Ok(tokens) | Err(LoxError::ScanningError { tokens, .. }) => tokens,
+            Err(_) => vec![],
+        }
+    }));
+    let tokens = match tokens {
+        Ok(tokens) => tokens,
+        Err(_) => {
+            fs::write(directory.join("tokens.txt"), "Scanning panicked.\n")?;
+            return Ok(());
+        }
+    };
+
+    let listing: String = tokens
+        .iter()
+        .map(|token| format!("{:?}\n", token))
+        .collect();
+    fs::write(directory.join("tokens.txt"), listing)?;
+
+    let ast = panic::catch_unwind(AssertUnwindSafe(|| {
+        match Parser::new(tokens, context.parser_config.clone()).parse() {
+            Ok(statements) | Err(LoxError::ParseError { statements, .. }) => {
+                format!("{:#?}\n", statements)
+            }
+            Err(error) => format!("{}\n", error),
+        }
+    }))
+    .unwrap_or_else(|_| "Parsing panicked.\n".to_string());
+    fs::write(directory.join("ast.txt"), ast)
+}
+
+fn report(context: &Context, error: Option<&LoxError>) -> String {
+    let mut report = format!(
+        "ilox {}\ncommand: {}\nfile: {}\nphase: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        env::args().collect::<Vec<_>>().join(" "),
+        context
+            .file
+            .as_ref()
+            .map_or("-".to_string(), |file| file.display().to_string()),
+        context
+            .phase
+            .map_or("-".to_string(), |phase| phase.to_string()),
+    );
+
+    match (error, &context.panic) {
+        (Some(error), _) => {
+            report.push_str(&format!("\nError[{}]: {}\n", error.code(), error));
+            report.push_str(&format!("\n{}", Backtrace::force_capture()));
+        }
+        (None, Some(panic)) => {
+            report.push_str(&format!("\npanicked: {}\n", panic.message));
+            if let Some(location) = &panic.location {
+                report.push_str(&format!("at: {}\n", location));
+            }
+            report.push_str(&format!("\n{}", panic.backtrace));
+        }
+        (None, None) => report.push_str("\nNo panic was caught.\n"),
+    }
+
+    report
+}
+
+/// What to tell the user after writing a report to `directory`, or failing to
+pub fn notice(directory: Option<&Path>) -> String {
+    match directory {
+        Some(directory) => format!(
+            "This is a bug in the interpreter. A crash report with the program, its tokens and syntax tree, and a backtrace is in {}. Nothing was sent anywhere, please attach it to a bug report.",
+            directory.display()
+        ),
+        None => "This is a bug in the interpreter. Writing a crash report failed.".to_string(),
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    env,
+    fmt::{self, Display},
+    io::{self, IsTerminal},
+    str::FromStr,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl FromStr for Severity {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "error" => Ok(Severity::Error),
+            "warning" => Ok(Severity::Warning),
+            _ => Err(format!("Unknown severity '{}'.", name)),
+        }
+    }
+}
+
+impl Severity {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Severity::Error => "Error",
+            Severity::Warning => "Warning",
+        }
+    }
+
+    /// ANSI SGR code used to style the severity label
+    fn style(&self) -> &'static str {
+        match self {
+            Severity::Error => "1;31",
+            Severity::Warning => "1;33",
+        }
+    }
+}
+
+/// Kinds of warnings raised while a program runs, each with a code like
+/// errors have, so tools can tell them apart without matching messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeWarning {
+    /// `==` or `!=` between values of different types, with
+    /// `strict_equality` set to warn
+    MixedEquality,
+    /// An assignment to an undeclared variable defined a global, with
+    /// `implicit_globals` on
+    ImplicitGlobal,
+    /// A local variable wasn't in the scope it was resolved to, and was
+    /// looked up by name instead, see `ResolutionMismatch`
+    ResolutionFallback,
+}
+
+impl RuntimeWarning {
+    pub fn code(&self) -> &'static str {
+        match self {
+            RuntimeWarning::MixedEquality => "W4001",
+            RuntimeWarning::ImplicitGlobal => "W4002",
+            RuntimeWarning::ResolutionFallback => "W9001",
+        }
+    }
+}
+
+/// When diagnostics are rendered with ANSI colors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Colors when stderr is a terminal and `NO_COLOR` isn't set
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn enabled(&self) -> bool {
+        match self {
+            ColorChoice::Auto => io::stderr().is_terminal() && env::var_os("NO_COLOR").is_none(),
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            ColorChoice::Auto => "auto",
+            ColorChoice::Always => "always",
+            ColorChoice::Never => "never",
+        }
+    }
+}
+
+impl Display for ColorChoice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ColorChoice {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        [ColorChoice::Auto, ColorChoice::Always, ColorChoice::Never]
+            .into_iter()
+            .find(|choice| choice.name() == name)
+            .ok_or_else(|| format!("Unknown color choice '{}'.", name))
+    }
+}
+
+/// How diagnostics are written to stderr
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Human,
+    /// One JSON object per line, for editors and other tools
+    Json,
+}
+
+impl ErrorFormat {
+    fn name(&self) -> &'static str {
+        match self {
+            ErrorFormat::Human => "human",
+            ErrorFormat::Json => "json",
+        }
+    }
+}
+
+impl Display for ErrorFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for ErrorFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        [ErrorFormat::Human, ErrorFormat::Json]
+            .into_iter()
+            .find(|format| format.name() == name)
+            .ok_or_else(|| format!("Unknown error format '{}'.", name))
+    }
+}
+
+const BOLD: &str = "1";
+
+/// Formats diagnostics for stderr. Without colors the output is plain text,
+/// so it stays stable for scripts comparing error messages.
+#[derive(Debug, Clone)]
+pub struct DiagnosticRenderer {
+    color: bool,
+    format: ErrorFormat,
+    /// Name of the file being run, included in locations when set
+    source: Option<String>,
+}
+
+impl DiagnosticRenderer {
+    pub fn new(choice: ColorChoice, format: ErrorFormat) -> Self {
+        Self {
+            color: format == ErrorFormat::Human && choice.enabled(),
+            format,
+            source: None,
+        }
+    }
+
+    pub fn set_source(&mut self, source: Option<String>) {
+        self.source = source;
+    }
+
+    /// Renders a static diagnostic as `[line L:C] Severity[code] at 'x':
+    /// message`, leaving out the code when there's none
+    pub fn render(
+        &self,
+        severity: Severity,
+        code: Option<&str>,
+        (line, column): (usize, usize),
+        at: &str,
+        message: &str,
+    ) -> String {
+        if self.format == ErrorFormat::Json {
+            return self.json(severity, code, Some(line), Some(column), message);
+        }
+
+        format!(
+            "{} {}{}: {}",
+            self.paint(BOLD, &self.location((line, column))),
+            self.paint(severity.style(), &Self::label(severity, code)),
+            at,
+            message
+        )
+    }
+
+    /// Renders a runtime error as `Error[code]: message` followed by its
+    /// location
+    pub fn render_runtime(
+        &self,
+        code: &str,
+        message: &str,
+        (line, column): (usize, usize),
+    ) -> String {
+        if self.format == ErrorFormat::Json {
+            return self.json(
+                Severity::Error,
+                Some(code),
+                Some(line),
+                Some(column),
+                message,
+            );
+        }
+
+        format!(
+            "{}: {}\n{}",
+            self.paint(
+                Severity::Error.style(),
+                &Self::label(Severity::Error, Some(code))
+            ),
+            self.paint(Severity::Error.style(), message),
+            self.paint(BOLD, &self.location((line, column)))
+        )
+    }
+
+    /// Renders a diagnostic without a column or code, like those of `blox`,
+    /// as `file:line: message`
+    pub fn render_line(&self, severity: Severity, line: Option<usize>, message: &str) -> String {
+        if self.format == ErrorFormat::Json {
+            return self.json(severity, None, line, None, message);
+        }
+
+        let location = match (&self.source, line) {
+            (Some(source), Some(line)) => format!("{}:{}:", source, line),
+            (None, Some(line)) => format!("line {}:", line),
+            (Some(source), None) => format!("{}:", source),
+            (None, None) => return self.paint(severity.style(), message),
+        };
+
+        format!(
+            "{} {}",
+            self.paint(BOLD, &location),
+            self.paint(severity.style(), message)
+        )
+    }
+
+    fn label(severity: Severity, code: Option<&str>) -> String {
+        match code {
+            Some(code) => format!("{}[{}]", severity.label(), code),
+            None => severity.label().to_string(),
+        }
+    }
+
+    fn location(&self, (line, column): (usize, usize)) -> String {
+        match &self.source {
+            Some(source) => format!("[{} line {}:{}]", source, line, column),
+            None => format!("[line {}:{}]", line, column),
+        }
+    }
+
+    fn json(
+        &self,
+        severity: Severity,
+        code: Option<&str>,
+        line: Option<usize>,
+        column: Option<usize>,
+        message: &str,
+    ) -> String {
+        let code = match code {
+            Some(code) => format!("\"code\":{},", json_string(code)),
+            None => String::new(),
+        };
+        let file = match &self.source {
+            Some(source) => format!("\"file\":{},", json_string(source)),
+            None => String::new(),
+        };
+        let line = match line {
+            Some(line) => format!("\"line\":{},", line),
+            None => String::new(),
+        };
+        let column = match column {
+            Some(column) => format!("\"column\":{},", column),
+            None => String::new(),
+        };
+
+        format!(
+            "{{\"severity\":\"{}\",{}{}{}{}\"message\":{}}}",
+            severity.label().to_lowercase(),
+            code,
+            file,
+            line,
+            column,
+            json_string(message)
+        )
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        match self.color {
+            true => format!("\x1b[{}m{}\x1b[0m", style, text),
+            false => text.to_string(),
+        }
+    }
+}
+
+fn json_string(value: &str) -> String {
+    let mut output = String::from('"');
+    for c in value.chars() {
+        match c {
+            '"' => output.push_str("\\\""),
+            '\\' => output.push_str("\\\\"),
+            '\n' => output.push_str("\\n"),
+            c if c.is_control() => output.push_str(&format!("\\u{:04x}", c as u32)),
+            c => output.push(c),
+        }
+    }
+    output.push('"');
+    output
+}
@@ -0,0 +1,118 @@
+use crate::interpreter::Lox;
+
+const EXPECT: &str = "// expect: ";
+
+/// A ```` ```lox ```` code block from a markdown file.
+///
+/// Lines printed by the block are checked against its `// expect: ` comments,
+/// in order. Blocks without any are only checked to run without errors, and
+/// blocks tagged ```` ```lox ignore ```` are skipped.
+#[derive(Debug, Clone)]
+pub struct DocExample {
+    /// Line of the opening fence
+    pub line: usize,
+    /// The block's code, preceded by blank lines so its line numbers match
+    /// the markdown file
+    pub source: String,
+    /// Expected output lines with the line of their comment
+    pub expected: Vec<(usize, String)>,
+}
+
+impl DocExample {
+    /// Finds the Lox code blocks of `markdown`
+    pub fn extract(markdown: &str) -> Vec<DocExample> {
+        let mut examples = vec![];
+        // The fence of the open block, and the example when it's Lox
+        let mut open: Option<(&str, Option<DocExample>)> = None;
+
+        for (index, line) in markdown.lines().enumerate() {
+            let number = index + 1;
+            let trimmed = line.trim_start();
+
+            match &mut open {
+                Some((fence, example)) => {
+                    if trimmed.starts_with(*fence) && trimmed[fence.len()..].trim().is_empty() {
+                        examples.extend(example.take());
+                        open = None;
+                    } else if let Some(example) = example {
+                        example.source.push_str(line);
+                        example.source.push('\n');
+
+                        if let Some((_, expected)) = line.split_once(EXPECT) {
+                            example.expected.push((number, expected.trim_end().into()));
+                        }
+                    }
+                }
+                None => {
+                    let fence_length = match trimmed.chars().next() {
+                        Some(marker @ ('`' | '~')) => {
+                            trimmed.chars().take_while(|c| *c == marker).count()
+                        }
+                        _ => 0,
+                    };
+                    if fence_length < 3 {
+                        continue;
+                    }
+
+                    let (fence, info) = trimmed.split_at(fence_length);
+                    let mut tags = info.split(|c: char| c.is_whitespace() || c == ',');
+                    let example = match (tags.next(), tags.any(|tag| tag == "ignore")) {
+                        (Some("lox"), false) => Some(DocExample {
+                            line: number,
+                            source: "\n".repeat(number),
+                            expected: vec![],
+                        }),
+                        _ => None,
+                    };
+
+                    open = Some((fence, example));
+                }
+            }
+        }
+
+        examples
+    }
+
+    /// Runs the example in a fork of `lox`, printing any errors it reports
+    /// and returning a description of how it failed.
+    pub fn run(&self, lox: &Lox) -> Result<(), String> {
+        let mut lox = lox.fork();
+        let output = lox.capture_output(|lox| lox.run(self.source.clone()));
+
+        if lox.had_error {
+            return Err("doesn't compile".into());
+        }
+
+        if lox.had_runtime_error {
+            return Err("raised a runtime error".into());
+        }
+
+        if self.expected.is_empty() {
+            return Ok(());
+        }
+
+        let mut actual = output.lines();
+        for (line, expected) in &self.expected {
+            match actual.next() {
+                Some(actual) if actual == expected => {}
+                Some(actual) => {
+                    return Err(format!(
+                        "line {}: expected '{}', got '{}'",
+                        line, expected, actual
+                    ))
+                }
+                None => {
+                    return Err(format!(
+                        "line {}: expected '{}', got nothing",
+                        line, expected
+                    ))
+                }
+            }
+        }
+
+        match actual.next() {
+            Some(extra) => Err(format!("printed '{}' after the expected output", extra)),
+            None => Ok(()),
+        }
+    }
+}
@@ -1,15 +1,28 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, HashMap},
+    rc::Rc,
+};
 
-use crate::{
+use crate::interpreter::{
     error::{LoxError, Result},
     token::Token,
     value::Value,
 };
 
+/// Variables a function uses from its enclosing scopes, computed by the resolver
+#[derive(Debug, Clone, Default)]
+pub struct CaptureList {
+    /// Number of local scopes enclosing the function
+    pub depth: usize,
+    /// Captured names with their distance from the function's closure
+    pub names: BTreeSet<(usize, String)>,
+}
+
 #[derive(Debug)]
 pub struct Environment {
     pub enclosing: Option<Rc<RefCell<Environment>>>,
-    values: HashMap<String, Value>,
+    values: HashMap<String, Rc<RefCell<Value>>>,
 }
 
 impl Environment {
@@ -27,39 +40,152 @@ impl Environment {
         }))
     }
 
+    /// Builds a closure for a function declared in `environment` that only
+    /// holds the captured variables. The chain keeps the same depth so
+    /// resolved distances still line up, and the cells are shared so
+    /// assignments are seen on both sides.
+    pub fn capture(
+        environment: &Rc<RefCell<Environment>>,
+        captures: &CaptureList,
+    ) -> Rc<RefCell<Environment>> {
+        let mut levels = vec![];
+        let mut root = environment.clone();
+        for _ in 0..captures.depth {
+            let enclosing = match &root.borrow().enclosing {
+                Some(enclosing) => enclosing.clone(),
+                None => break,
+            };
+
+            levels.push(root);
+            root = enclosing;
+        }
+
+        levels
+            .iter()
+            .enumerate()
+            .rev()
+            .fold(root, |enclosing, (distance, level)| {
+                let pruned = Environment::new_with_parent(enclosing);
+                let level = level.borrow();
+
+                for (_, name) in captures.names.iter().filter(|(d, _)| *d == distance) {
+                    if let Some(cell) = level.cell(name) {
+                        pruned.borrow_mut().values.insert(name.clone(), cell);
+                    }
+                }
+
+                pruned
+            })
+    }
+
+    /// Clears the environment so it can be reused under a new parent
+    pub fn reset(&mut self, enclosing: Option<Rc<RefCell<Environment>>>) {
+        self.enclosing = enclosing;
+        self.values.clear();
+    }
+
+    /// Variables defined in this environment, sorted by name
+    pub fn bindings(&self) -> Vec<(String, Value)> {
+        let mut bindings: Vec<_> = self
+            .values
+            .iter()
+            .map(|(name, cell)| (name.clone(), cell.borrow().clone()))
+            .collect();
+        bindings.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        bindings
+    }
+
+    /// Names defined in this environment and every enclosing one
+    pub fn visible_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.values.keys().cloned().collect();
+        if let Some(enclosing) = &self.enclosing {
+            names.extend(enclosing.borrow().visible_names());
+        }
+
+        names
+    }
+
+    /// Defines `name`, reusing its cell when it's already defined so handles
+    /// returned by `cell` stay valid when a global is redefined
     pub fn define(&mut self, name: &str, value: Value) {
-        self.values.insert(name.into(), value);
+        match self.values.get(name) {
+            Some(cell) => *cell.borrow_mut() = value,
+            None => {
+                self.values
+                    .insert(name.into(), Rc::new(RefCell::new(value)));
+            }
+        }
+    }
+
+    /// Handles to the storage of every variable defined in this environment
+    pub fn cells(&self) -> Vec<(String, Rc<RefCell<Value>>)> {
+        self.values
+            .iter()
+            .map(|(name, cell)| (name.clone(), cell.clone()))
+            .collect()
+    }
+
+    /// Defines `name` with an existing cell, sharing it with wherever else
+    /// it's defined
+    pub fn insert_cell(&mut self, name: String, cell: Rc<RefCell<Value>>) {
+        self.values.insert(name, cell);
+    }
+
+    /// Removes a variable defined in this environment, returning its value
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        self.values.remove(name).map(|cell| cell.borrow().clone())
+    }
+
+    /// Handle to the storage of a variable defined in this environment
+    pub fn cell(&self, name: &str) -> Option<Rc<RefCell<Value>>> {
+        self.values.get(name).cloned()
     }
 
     pub fn get(&self, name: &Token) -> Result<Value> {
         match self.values.get(&name.lexeme) {
-            Some(value) => Ok(value.clone()),
+            Some(value) => Ok(value.borrow().clone()),
             None => match &self.enclosing {
                 Some(enclosing) => enclosing.borrow().get(name),
                 None => Err(LoxError::RuntimeError {
-                    token: name.clone(),
+                    token: Box::new(name.clone()),
                     message: format!("Undefined variable '{}'.", name.lexeme),
                 }),
             },
         }
     }
 
+    /// Reads `name` from the environment `distance` levels up, as resolved.
+    /// Fails with an internal error if it isn't there.
     pub fn get_at(&self, distance: usize, name: &Token) -> Result<Value> {
         match distance {
-            0 => Ok(self.values.get(&name.lexeme).unwrap().clone()),
+            0 => match self.values.get(&name.lexeme) {
+                Some(value) => Ok(value.borrow().clone()),
+                None => Err(Self::not_in_scope(name)),
+            },
             _ => match &self.enclosing {
                 Some(enclosing) => enclosing.borrow().get_at(distance - 1, name),
-                None => Err(LoxError::RuntimeError {
-                    token: name.clone(),
-                    message: format!("Undefined variable '{}'.", name.lexeme),
-                }),
+                None => Err(Self::not_in_scope(name)),
             },
         }
     }
 
+    fn not_in_scope(name: &Token) -> LoxError {
+        LoxError::internal(format!(
+            "'{}' isn't in the scope it was resolved to.",
+            name.lexeme
+        ))
+    }
+
     pub fn get_keyword_at(&self, distance: usize, name: &str) -> Result<Value> {
         match distance {
-            0 => Ok(self.values.get(name).unwrap().clone()),
+            0 => match self.values.get(name) {
+                Some(value) => Ok(value.borrow().clone()),
+                None => Err(LoxError::internal(format!(
+                    "'{}' isn't in the scope it was resolved to.",
+                    name
+                ))),
+            },
             _ => match &self.enclosing {
                 Some(enclosing) => enclosing.borrow().get_keyword_at(distance - 1, name),
                 None => Err(LoxError::UnresolvedKeywordError {
@@ -70,8 +196,8 @@ impl Environment {
     }
 
     pub fn assign(&mut self, name: &Token, value: &Value) -> Result<()> {
-        if self.values.contains_key(&name.lexeme) {
-            self.values.insert(name.lexeme.to_string(), value.clone());
+        if let Some(cell) = self.values.get(&name.lexeme) {
+            *cell.borrow_mut() = value.clone();
 
             return Ok(());
         }
@@ -79,25 +205,44 @@ impl Environment {
         match &self.enclosing {
             Some(enclosing) => enclosing.borrow_mut().assign(name, value),
             None => Err(LoxError::RuntimeError {
-                token: name.clone(),
+                token: Box::new(name.clone()),
                 message: format!("variable '{}' not defined.", name.lexeme),
             }),
         }
     }
 
+    /// Assigns `name` in the environment `distance` levels up, as resolved.
+    /// Fails with an internal error if it isn't there.
     pub fn assign_at(&mut self, distance: usize, name: &Token, value: &Value) -> Result<()> {
         if distance == 0 {
-            self.values.insert(name.lexeme.to_string(), value.clone());
-
-            return Ok(());
+            return match self.values.get(&name.lexeme) {
+                Some(cell) => {
+                    *cell.borrow_mut() = value.clone();
+                    Ok(())
+                }
+                None => Err(Self::not_in_scope(name)),
+            };
         }
 
         match &self.enclosing {
             Some(enclosing) => enclosing.borrow_mut().assign_at(distance - 1, name, value),
-            None => Err(LoxError::RuntimeError {
-                token: name.clone(),
-                message: format!("variable '{}' not defined.", name.lexeme),
-            }),
+            None => Err(Self::not_in_scope(name)),
+        }
+    }
+}
+
+/// Walks up the chain of enclosing environments this one owns the last
+/// reference to, so a chain that's thousands of scopes long is freed in a
+/// loop rather than through nested drops.
+impl Drop for Environment {
+    fn drop(&mut self) {
+        let mut enclosing = self.enclosing.take();
+
+        while let Some(environment) = enclosing {
+            enclosing = match Rc::try_unwrap(environment) {
+                Ok(environment) => environment.borrow_mut().enclosing.take(),
+                Err(_) => None,
+            };
         }
     }
 }
@@ -1,5 +1,11 @@
-use crate::{stmt::Stmt, token::Token, value::Value};
-use std::io;
+use crate::interpreter::{
+    diagnostic::Severity,
+    span::{LineIndex, Span},
+    stmt::Stmt,
+    token::Token,
+    value::Value,
+};
+use std::{convert::Infallible, fmt, io};
 use thiserror::Error;
 
 pub type Result<T, E = LoxError> = std::result::Result<T, E>;
@@ -9,13 +15,23 @@ pub type Result<T, E = LoxError> = std::result::Result<T, E>;
 pub struct ScannerErrorDetails {
     pub message: String,
     pub line: usize,
+    pub span: Span,
+}
+
+impl ScannerErrorDetails {
+    pub const CODE: &'static str = "E1001";
 }
 
 #[derive(Error, Debug)]
 #[error("{message}")]
 pub struct ParserErrorDetails {
     pub message: String,
-    pub token: Token,
+    pub token: Box<Token>,
+    pub severity: Severity,
+}
+
+impl ParserErrorDetails {
+    pub const CODE: &'static str = "E2001";
 }
 
 #[derive(Error, Debug)]
@@ -25,47 +41,204 @@ pub struct ResolverErrorDetails {
     pub token: Token,
 }
 
+impl ResolverErrorDetails {
+    pub const CODE: &'static str = "E3001";
+}
+
 #[derive(Error, Debug)]
+#[error("{message}")]
+pub struct TypeErrorDetails {
+    pub message: String,
+    pub token: Token,
+}
+
+impl TypeErrorDetails {
+    pub const CODE: &'static str = "E3003";
+}
+
+/// Errors from every phase of running Lox.
+///
+/// Every variant has a stable code from `LoxError::code`, grouped by phase:
+/// `E1xxx` for scanning, `E2xxx` for parsing, `E3xxx` for resolution, `E4xxx`
+/// at runtime, `E5xxx` for reading input and `E9xxx` for internal errors. The
+/// errors reporting a list of details chain to the first one in `source`.
+#[derive(Debug)]
 pub enum LoxError {
-    #[error("Scanning Error: {details:?}")]
     ScanningError {
         tokens: Vec<Token>,
+        line_index: LineIndex,
         details: Vec<ScannerErrorDetails>,
     },
 
-    #[error("Failed to parse literal value.")]
     LiteralParseError,
 
-    #[error("Parse Error: {details:?}")]
     ParseError {
         statements: Vec<Stmt>,
         details: Vec<ParserErrorDetails>,
     },
 
-    #[error("Resolution Error: {0:?}")]
     ResolutionError(Vec<ResolverErrorDetails>),
 
-    #[error("Runtime Error: {message}")]
-    RuntimeError { message: String, token: Token },
+    RuntimeError {
+        message: String,
+        token: Box<Token>,
+    },
 
-    #[error("Couldn't resolve '{keyword}'.")]
-    UnresolvedKeywordError { keyword: String },
+    UnresolvedKeywordError {
+        keyword: String,
+    },
 
-    #[error("Can only call functions and classes.")]
     NotCallableError,
 
-    #[error("Can only bind functions.")]
     NotBindableError,
 
-    #[error("Arguments did not match parameters")]
     IncorrectArityError,
 
-    #[error("Return jump signal")]
+    /// Error raised by a native function, reported at the call site
+    NativeError(String),
+
+    /// A limit from `Limits` was exceeded. It can't be caught by the program.
+    LimitExceeded {
+        message: String,
+        token: Option<Box<Token>>,
+    },
+
+    /// An import led back to a module that is still loading. Like exceeded
+    /// limits, it can't be caught by the program.
+    ImportCycle {
+        message: String,
+        token: Box<Token>,
+    },
+
     ReturnJump(Value),
 
-    #[error(transparent)]
-    Io(#[from] io::Error),
+    Thrown {
+        value: Value,
+        token: Box<Token>,
+    },
+
+    InvalidUtf8 {
+        path: String,
+        offset: usize,
+        line: usize,
+    },
+
+    Io(io::Error),
+
+    Other(anyhow::Error),
+
+    /// A broken invariant, reported instead of panicking
+    Internal {
+        context: String,
+    },
+
+    /// A panic caught at the top level, see `crash`
+    Panic {
+        message: String,
+    },
+}
+
+impl LoxError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LoxError::ScanningError { .. } => ScannerErrorDetails::CODE,
+            LoxError::LiteralParseError => "E1002",
+            LoxError::ParseError { .. } => ParserErrorDetails::CODE,
+            LoxError::ResolutionError(_) => ResolverErrorDetails::CODE,
+            LoxError::UnresolvedKeywordError { .. } => "E3002",
+            LoxError::RuntimeError { .. } => "E4001",
+            LoxError::NotCallableError => "E4002",
+            LoxError::NotBindableError => "E4003",
+            LoxError::IncorrectArityError => "E4004",
+            LoxError::NativeError(_) => "E4005",
+            LoxError::Thrown { .. } => "E4006",
+            LoxError::LimitExceeded { .. } => "E4007",
+            LoxError::ImportCycle { .. } => "E4008",
+            LoxError::InvalidUtf8 { .. } => "E5001",
+            LoxError::Io(_) => "E5002",
+            LoxError::ReturnJump(_) => "E9001",
+            LoxError::Other(_) => "E9002",
+            LoxError::Internal { .. } => "E9003",
+            LoxError::Panic { .. } => "E9004",
+        }
+    }
+
+    pub fn internal(context: impl Into<String>) -> Self {
+        LoxError::Internal {
+            context: context.into(),
+        }
+    }
+
+    fn first<E: std::error::Error + 'static>(
+        details: &[E],
+    ) -> Option<&(dyn std::error::Error + 'static)> {
+        details
+            .first()
+            .map(|detail| detail as &(dyn std::error::Error + 'static))
+    }
+}
+
+impl fmt::Display for LoxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoxError::ScanningError { details, .. } => write!(f, "Scanning Error: {:?}", details),
+            LoxError::LiteralParseError => write!(f, "Failed to parse literal value."),
+            LoxError::ParseError { details, .. } => write!(f, "Parse Error: {:?}", details),
+            LoxError::ResolutionError(details) => write!(f, "Resolution Error: {:?}", details),
+            LoxError::RuntimeError { message, .. } => write!(f, "Runtime Error: {}", message),
+            LoxError::UnresolvedKeywordError { keyword } => {
+                write!(f, "Couldn't resolve '{}'.", keyword)
+            }
+            LoxError::NotCallableError => write!(f, "Can only call functions and classes."),
+            LoxError::NotBindableError => write!(f, "Can only bind functions."),
+            LoxError::IncorrectArityError => write!(f, "Arguments did not match parameters"),
+            LoxError::NativeError(message) => write!(f, "{}", message),
+            LoxError::LimitExceeded { message, .. } => write!(f, "{}", message),
+            LoxError::ImportCycle { message, .. } => write!(f, "{}", message),
+            LoxError::ReturnJump(_) => write!(f, "Return jump signal"),
+            LoxError::Thrown { value, .. } => write!(f, "Uncaught exception: {}", value),
+            LoxError::InvalidUtf8 { path, offset, line } => write!(
+                f,
+                "'{}' isn't valid UTF-8: invalid byte sequence at offset {} on line {}.",
+                path, offset, line
+            ),
+            LoxError::Io(error) => error.fmt(f),
+            LoxError::Other(error) => error.fmt(f),
+            LoxError::Internal { context } => write!(f, "Internal error: {}", context),
+            LoxError::Panic { message } => write!(f, "The interpreter crashed: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for LoxError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LoxError::ScanningError { details, .. } => Self::first(details),
+            LoxError::ParseError { details, .. } => Self::first(details),
+            LoxError::ResolutionError(details) => Self::first(details),
+            LoxError::Io(error) => error.source(),
+            LoxError::Other(error) => error.source(),
+            _ => None,
+        }
+    }
+}
+
+/// Lets conversions that can't fail, like `Value` to `Value`, be used where
+/// fallible ones are expected
+impl From<Infallible> for LoxError {
+    fn from(error: Infallible) -> Self {
+        match error {}
+    }
+}
+
+impl From<io::Error> for LoxError {
+    fn from(error: io::Error) -> Self {
+        LoxError::Io(error)
+    }
+}
 
-    #[error(transparent)]
-    Other(#[from] anyhow::Error),
+impl From<anyhow::Error> for LoxError {
+    fn from(error: anyhow::Error) -> Self {
+        LoxError::Other(error)
+    }
 }
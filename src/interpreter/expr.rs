@@ -1,4 +1,4 @@
-use crate::{token::Token, value::Value};
+use crate::interpreter::{token::Token, value::Value};
 
 #[derive(Debug, Clone)]
 pub enum Expr {
@@ -9,14 +9,172 @@ pub enum Expr {
     Variable(Token),
     Assign(Token, Box<Expr>),
     Logical(Box<Expr>, Token, Box<Expr>),
-    Call(Box<Expr>, Vec<Expr>, Token),
+    /// Callee, arguments, closing paren and the first token of each argument
+    Call(Box<Expr>, Vec<Expr>, Token, Vec<Token>),
     Get(Box<Expr>, Token),
     Set(Box<Expr>, Token, Box<Expr>),
     This(Token),
     Super(Token, Token),
+    Match(Token, Box<Expr>, Vec<MatchArm>),
+    List(Token, Vec<Expr>),
+    Destructure(Token, Vec<Token>, Box<Expr>),
+    /// Keyword and the value, if any, handed to the coroutine's resumer
+    Yield(Token, Option<Box<Expr>>),
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    Literal(Token, Value),
+    Binding(Token),
+    Wildcard(Token),
+}
+
+impl Pattern {
+    pub fn token(&self) -> &Token {
+        match self {
+            Pattern::Literal(token, _) | Pattern::Binding(token) | Pattern::Wildcard(token) => {
+                token
+            }
+        }
+    }
+
+    /// Whether the pattern matches every value
+    pub fn is_irrefutable(&self) -> bool {
+        !matches!(self, Pattern::Literal(..))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Expr,
 }
 
 impl Expr {
+    /// Moves the expression out, leaving a `nil` literal behind. `Expr`
+    /// implements `Drop`, so it can't be destructured by value.
+    pub fn take(&mut self) -> Expr {
+        std::mem::replace(self, Expr::Literal(Value::Nil))
+    }
+
+    fn take_children(&mut self, children: &mut Vec<Expr>) {
+        match self {
+            Expr::Literal(_)
+            | Expr::Variable(_)
+            | Expr::This(_)
+            | Expr::Super(..)
+            | Expr::Yield(_, None) => {}
+            Expr::Grouping(expr)
+            | Expr::Unary(_, expr)
+            | Expr::Assign(_, expr)
+            | Expr::Get(expr, _)
+            | Expr::Destructure(_, _, expr)
+            | Expr::Yield(_, Some(expr)) => children.push(expr.take()),
+            Expr::Binary(left, _, right)
+            | Expr::Logical(left, _, right)
+            | Expr::Set(left, _, right) => {
+                children.push(left.take());
+                children.push(right.take());
+            }
+            Expr::Call(callee, arguments, ..) => {
+                children.push(callee.take());
+                children.append(arguments);
+            }
+            Expr::Match(_, subject, arms) => {
+                children.push(subject.take());
+                for arm in arms.drain(..) {
+                    children.extend(arm.guard);
+                    children.push(arm.body);
+                }
+            }
+            Expr::List(_, elements) => children.append(elements),
+        }
+    }
+
+    /// The expressions directly inside this one
+    pub fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Literal(_)
+            | Expr::Variable(_)
+            | Expr::This(_)
+            | Expr::Super(..)
+            | Expr::Yield(_, None) => vec![],
+            Expr::Grouping(expr)
+            | Expr::Unary(_, expr)
+            | Expr::Assign(_, expr)
+            | Expr::Get(expr, _)
+            | Expr::Destructure(_, _, expr)
+            | Expr::Yield(_, Some(expr)) => vec![expr],
+            Expr::Binary(left, _, right)
+            | Expr::Logical(left, _, right)
+            | Expr::Set(left, _, right) => vec![left, right],
+            Expr::Call(callee, arguments, ..) => {
+                std::iter::once(&**callee).chain(arguments).collect()
+            }
+            Expr::Match(_, subject, arms) => std::iter::once(&**subject)
+                .chain(
+                    arms.iter()
+                        .flat_map(|arm| arm.guard.iter().chain([&arm.body])),
+                )
+                .collect(),
+            Expr::List(_, elements) => elements.iter().collect(),
+        }
+    }
+
+    /// Whether the expression only contains literals, so it always evaluates to
+    /// the same value
+    pub fn is_literal_only(&self) -> bool {
+        match self {
+            Expr::Literal(_) => true,
+            Expr::Grouping(expr) | Expr::Unary(_, expr) => expr.is_literal_only(),
+            Expr::Binary(left, _, right) => left.is_literal_only() && right.is_literal_only(),
+            _ => false,
+        }
+    }
+
+    /// The chain of variables, property accesses and calls the expression
+    /// is made of, like `a.b().c`, with call arguments left out, or `None`
+    /// when it has other parts
+    pub fn access_path(&self) -> Option<String> {
+        match self {
+            Expr::Variable(name) => Some(name.lexeme.clone()),
+            Expr::This(_) => Some("this".into()),
+            Expr::Get(object, name) => Some(format!("{}.{}", object.access_path()?, name.lexeme)),
+            Expr::Call(callee, ..) => Some(format!("{}()", callee.access_path()?)),
+            _ => None,
+        }
+    }
+
+    /// Line of one of the expression's tokens, or `None` for literals
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Expr::Grouping(expr) => expr.line(),
+            _ => self.token().map(|token| token.line),
+        }
+    }
+
+    /// One of the expression's own tokens, or `None` for literals and groups
+    pub fn token(&self) -> Option<&Token> {
+        match self {
+            Expr::Binary(_, token, _)
+            | Expr::Unary(token, _)
+            | Expr::Variable(token)
+            | Expr::Assign(token, _)
+            | Expr::Logical(_, token, _)
+            | Expr::Call(_, _, token, _)
+            | Expr::Get(_, token)
+            | Expr::Set(_, token, _)
+            | Expr::This(token)
+            | Expr::Super(token, _)
+            | Expr::Match(token, _, _)
+            | Expr::List(token, _)
+            | Expr::Destructure(token, _, _)
+            | Expr::Yield(token, _) => Some(token),
+            Expr::Grouping(_) | Expr::Literal(_) => None,
+        }
+    }
+
     pub fn accept<T>(&self, visitor: &mut dyn ExprVisitor<T>) -> T {
         match self {
             Expr::Binary(left, operator, right) => visitor.visit_binary_expr(left, operator, right),
@@ -28,13 +186,19 @@ impl Expr {
             Expr::Logical(left, operator, right) => {
                 visitor.visit_logicial_expr(left, operator, right)
             }
-            Expr::Call(callee, arguments, paren) => {
-                visitor.visit_call_expr(callee, arguments, paren)
+            Expr::Call(callee, arguments, paren, argument_starts) => {
+                visitor.visit_call_expr(callee, arguments, paren, argument_starts)
             }
             Expr::Get(object, name) => visitor.visit_get_expr(object, name),
             Expr::Set(object, name, value) => visitor.visit_set_expr(object, name, value),
             Expr::This(keyword) => visitor.visit_this_expr(keyword),
             Expr::Super(keyword, method) => visitor.visit_super_expr(keyword, method),
+            Expr::Match(keyword, subject, arms) => visitor.visit_match_expr(keyword, subject, arms),
+            Expr::List(bracket, elements) => visitor.visit_list_expr(bracket, elements),
+            Expr::Destructure(paren, names, value) => {
+                visitor.visit_destructure_expr(paren, names, value)
+            }
+            Expr::Yield(keyword, value) => visitor.visit_yield_expr(keyword, value.as_deref()),
         }
     }
 }
@@ -47,9 +211,34 @@ pub trait ExprVisitor<T> {
     fn visit_variable_expr(&mut self, name: &Token) -> T;
     fn visit_assign_expr(&mut self, name: &Token, value: &Expr) -> T;
     fn visit_logicial_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> T;
-    fn visit_call_expr(&mut self, callee: &Expr, arguments: &[Expr], paren: &Token) -> T;
+    fn visit_call_expr(
+        &mut self,
+        callee: &Expr,
+        arguments: &[Expr],
+        paren: &Token,
+        argument_starts: &[Token],
+    ) -> T;
     fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> T;
     fn visit_set_expr(&mut self, object: &Expr, name: &Token, value: &Expr) -> T;
     fn visit_this_expr(&mut self, keyword: &Token) -> T;
     fn visit_super_expr(&mut self, keyword: &Token, method: &Token) -> T;
+    fn visit_match_expr(&mut self, keyword: &Token, subject: &Expr, arms: &[MatchArm]) -> T;
+    fn visit_list_expr(&mut self, bracket: &Token, elements: &[Expr]) -> T;
+    fn visit_destructure_expr(&mut self, paren: &Token, names: &[Token], value: &Expr) -> T;
+    fn visit_yield_expr(&mut self, keyword: &Token, value: Option<&Expr>) -> T;
+}
+
+/// Drops subexpressions from a work list instead of recursing, so a generated
+/// chain like `1 + 1 + ... + 1` that's thousands of nodes deep can't overflow
+/// the stack when it's freed. Each node's children are moved out before the
+/// node itself is dropped, leaving it with nothing left to recurse into.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut pending = vec![];
+        self.take_children(&mut pending);
+
+        while let Some(mut expr) = pending.pop() {
+            expr.take_children(&mut pending);
+        }
+    }
 }
@@ -0,0 +1,148 @@
+use std::collections::HashMap;
+
+use crate::interpreter::value::Value;
+
+/// Most fields kept in a vector and found by comparing names one by one.
+/// Instances rarely have more, and for so few short names a linear scan beats
+/// hashing, besides sparing every instance the allocation of a hash table.
+const MAX_SMALL_FIELDS: usize = 8;
+
+/// The fields of an instance, by name. Small instances keep them in the order
+/// they were first set, and switch to a hash table once they grow past
+/// `MAX_SMALL_FIELDS`.
+#[derive(Debug, Clone)]
+pub enum Fields {
+    Small(Vec<(String, Value)>),
+    Large(HashMap<String, Value>),
+}
+
+impl Default for Fields {
+    fn default() -> Self {
+        Fields::Small(vec![])
+    }
+}
+
+impl Fields {
+    /// Room for `capacity` fields without growing
+    pub fn with_capacity(capacity: usize) -> Self {
+        match capacity > MAX_SMALL_FIELDS {
+            true => Fields::Large(HashMap::with_capacity(capacity)),
+            false => Fields::Small(Vec::with_capacity(capacity)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        match self {
+            Fields::Small(fields) => fields.len(),
+            Fields::Large(fields) => fields.len(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        match self {
+            Fields::Small(fields) => fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value),
+            Fields::Large(fields) => fields.get(name),
+        }
+    }
+
+    /// Index of the field `name`, while the fields are kept in order
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        match self {
+            Fields::Small(fields) => fields.iter().position(|(field, _)| field == name),
+            Fields::Large(_) => None,
+        }
+    }
+
+    /// The field at index `slot`, if it's the one named `name`
+    pub fn get_at(&self, slot: usize, name: &str) -> Option<&Value> {
+        match self {
+            Fields::Small(fields) => fields
+                .get(slot)
+                .filter(|(field, _)| field == name)
+                .map(|(_, value)| value),
+            Fields::Large(_) => None,
+        }
+    }
+
+    /// Sets the field at index `slot` if it's the one named `name`, or adds
+    /// it there if it's new and `slot` is where it would go, like when an
+    /// initializer sets the fields of every instance in the same order.
+    /// Returns whether it did either.
+    pub fn set_at(&mut self, slot: usize, name: &str, value: Value) -> bool {
+        let fields = match self {
+            Fields::Small(fields) => fields,
+            Fields::Large(_) => return false,
+        };
+
+        if let Some((field, current)) = fields.get_mut(slot) {
+            if field != name {
+                return false;
+            }
+
+            *current = value;
+            return true;
+        }
+
+        if slot != fields.len()
+            || slot >= MAX_SMALL_FIELDS
+            || fields.iter().any(|(field, _)| field == name)
+        {
+            return false;
+        }
+
+        fields.push((name.to_string(), value));
+        true
+    }
+
+    pub fn contains_key(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Sets the field `name`, only allocating the name when it's new
+    pub fn insert(&mut self, name: &str, value: Value) {
+        match self {
+            Fields::Small(fields) => {
+                if let Some((_, field)) = fields.iter_mut().find(|(field, _)| field == name) {
+                    *field = value;
+                } else if fields.len() < MAX_SMALL_FIELDS {
+                    fields.push((name.to_string(), value));
+                } else {
+                    let mut large: HashMap<_, _> = std::mem::take(fields).into_iter().collect();
+                    large.insert(name.to_string(), value);
+                    *self = Fields::Large(large);
+                }
+            }
+            Fields::Large(fields) => match fields.get_mut(name) {
+                Some(field) => *field = value,
+                None => {
+                    fields.insert(name.to_string(), value);
+                }
+            },
+        }
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<Value> {
+        match self {
+            Fields::Small(fields) => {
+                let index = fields.iter().position(|(field, _)| field == name)?;
+                Some(fields.remove(index).1)
+            }
+            Fields::Large(fields) => fields.remove(name),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        let (small, large) = match self {
+            Fields::Small(fields) => (Some(fields.iter().map(|(name, value)| (name, value))), None),
+            Fields::Large(fields) => (None, Some(fields.iter())),
+        };
+
+        small
+            .into_iter()
+            .flatten()
+            .chain(large.into_iter().flatten())
+    }
+}
@@ -0,0 +1,123 @@
+use std::{any::Any, collections::HashMap, fmt, rc::Rc};
+
+use crate::interpreter::{
+    callable::Callable,
+    error::{LoxError, Result},
+    interpreter::Interpreter,
+    value::{LoxInstance, Value},
+};
+
+pub type ForeignMethodFn = fn(&mut Interpreter, &Foreign, &[Value]) -> Result<Value>;
+
+/// A Rust object handed to scripts by natives, like a file handle. Scripts
+/// can't look inside it: they can only pass it around, compare it by
+/// identity and call the methods of its `ForeignClass`. Natives get it back
+/// with `Value::foreign`, which checks its type.
+///
+/// ```text
+/// fn open(_: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+///     let file = RefCell::new(File::open(arguments[0].to_string())?);
+///     Ok(Value::Foreign(Foreign::new(file).with_class(file_class())))
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Foreign {
+    object: Rc<dyn Any>,
+    class: Option<Rc<ForeignClass>>,
+}
+
+impl Foreign {
+    pub fn new<T: Any>(object: T) -> Self {
+        Self {
+            object: Rc::new(object),
+            class: None,
+        }
+    }
+
+    /// Gives the object the methods of `class`, which classes of several
+    /// objects can share
+    pub fn with_class(self, class: Rc<ForeignClass>) -> Self {
+        Self {
+            class: Some(class),
+            ..self
+        }
+    }
+
+    /// The object, if it's a `T`
+    pub fn downcast_ref<T: Any>(&self) -> Option<&T> {
+        self.object.downcast_ref()
+    }
+
+    /// Name of its class, used when it's printed
+    pub fn name(&self) -> &str {
+        match &self.class {
+            Some(class) => &class.name,
+            None => "foreign",
+        }
+    }
+
+    /// Whether both are the same object
+    pub fn ptr_eq(&self, other: &Foreign) -> bool {
+        Rc::ptr_eq(&self.object, &other.object)
+    }
+
+    /// The method `name` bound to this object, as a value scripts can call
+    pub fn get(&self, name: &str) -> Option<Value> {
+        let (arity, function) = *self.class.as_ref()?.methods.get(name)?;
+
+        Some(Value::NativeFunction(Box::new(ForeignMethod {
+            arity,
+            function,
+            receiver: self.clone(),
+        })))
+    }
+}
+
+impl fmt::Debug for Foreign {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}>", self.name())
+    }
+}
+
+/// The methods scripts can call on foreign objects, supplied by the host
+#[derive(Debug, Clone)]
+pub struct ForeignClass {
+    name: String,
+    methods: HashMap<String, (usize, ForeignMethodFn)>,
+}
+
+impl ForeignClass {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            methods: HashMap::new(),
+        }
+    }
+
+    pub fn with_method(mut self, name: &str, arity: usize, function: ForeignMethodFn) -> Self {
+        self.methods.insert(name.to_string(), (arity, function));
+        self
+    }
+}
+
+/// A method of a `ForeignClass` bound to its object
+#[derive(Debug, Clone)]
+struct ForeignMethod {
+    arity: usize,
+    function: ForeignMethodFn,
+    receiver: Foreign,
+}
+
+impl Callable for ForeignMethod {
+    fn invoke(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+        (self.function)(interpreter, &self.receiver, arguments)
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn bind(&self, _instance: &LoxInstance) -> Result<Value> {
+        Err(LoxError::NotBindableError)
+    }
+}
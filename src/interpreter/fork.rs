@@ -0,0 +1,139 @@
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
+
+use crate::interpreter::{
+    environment::Environment,
+    value::{LoxClass, LoxInstance, LoxInstanceData, Value},
+};
+
+type EnvironmentRef = Rc<RefCell<Environment>>;
+
+/// Deep copies environments and the values reachable from them, so a forked
+/// interpreter can't change its parent's state. Environments, variable cells,
+/// lists and instances are copied once each, keeping values that were shared
+/// shared in the copy.
+///
+/// Native state of instances isn't copied, and bound native methods keep
+/// their original receiver.
+#[derive(Default)]
+pub struct DeepCopy {
+    environments: HashMap<*const RefCell<Environment>, EnvironmentRef>,
+    cells: HashMap<*const RefCell<Value>, Rc<RefCell<Value>>>,
+    lists: HashMap<*const RefCell<Vec<Value>>, Rc<RefCell<Vec<Value>>>>,
+    instances: HashMap<*const RefCell<LoxInstanceData>, LoxInstance>,
+}
+
+impl DeepCopy {
+    pub fn environment(&mut self, environment: &EnvironmentRef) -> EnvironmentRef {
+        if let Some(copy) = self.environments.get(&Rc::as_ptr(environment)) {
+            return copy.clone();
+        }
+
+        let enclosing = environment.borrow().enclosing.clone();
+        let enclosing = enclosing.map(|enclosing| self.environment(&enclosing));
+
+        // Copying the enclosing environment can reach this one through a
+        // closure, in which case it's already been copied
+        if let Some(copy) = self.environments.get(&Rc::as_ptr(environment)) {
+            return copy.clone();
+        }
+
+        let copy = match enclosing {
+            Some(enclosing) => Environment::new_with_parent(enclosing),
+            None => Environment::new(),
+        };
+        self.environments
+            .insert(Rc::as_ptr(environment), copy.clone());
+
+        let cells = environment.borrow().cells();
+        for (name, cell) in cells {
+            let cell = self.cell(&cell);
+            copy.borrow_mut().insert_cell(name, cell);
+        }
+
+        copy
+    }
+
+    fn cell(&mut self, cell: &Rc<RefCell<Value>>) -> Rc<RefCell<Value>> {
+        if let Some(copy) = self.cells.get(&Rc::as_ptr(cell)) {
+            return copy.clone();
+        }
+
+        let copy = Rc::new(RefCell::new(Value::Nil));
+        self.cells.insert(Rc::as_ptr(cell), copy.clone());
+
+        let value = self.value(&cell.borrow().clone());
+        *copy.borrow_mut() = value;
+
+        copy
+    }
+
+    pub fn value(&mut self, value: &Value) -> Value {
+        match value {
+            Value::Function {
+                name,
+                parameters,
+                body,
+                closure,
+                is_initializer,
+                is_generator,
+            } => Value::Function {
+                name: name.clone(),
+                parameters: parameters.clone(),
+                body: body.clone(),
+                closure: self.environment(closure),
+                is_initializer: *is_initializer,
+                is_generator: *is_generator,
+            },
+            Value::Class(class) => Value::Class(self.class(class)),
+            Value::Instance(instance) => Value::Instance(self.instance(instance)),
+            Value::List(list) => Value::List(self.list(list)),
+            value => value.clone(),
+        }
+    }
+
+    pub fn class(&mut self, class: &LoxClass) -> LoxClass {
+        let methods = class
+            .own_methods()
+            .iter()
+            .map(|(name, method)| (name.clone(), self.value(method)))
+            .collect();
+        let superclass = class.superclass().map(|superclass| self.class(superclass));
+
+        LoxClass::new(class.name.clone(), methods, superclass)
+    }
+
+    fn instance(&mut self, instance: &LoxInstance) -> LoxInstance {
+        if let Some(copy) = self.instances.get(&Rc::as_ptr(instance)) {
+            return copy.clone();
+        }
+
+        let class = instance.borrow().class().clone();
+        let mut copy = LoxInstance::new(self.class(&class));
+        self.instances.insert(Rc::as_ptr(instance), copy.clone());
+
+        for (name, value) in instance.fields() {
+            let value = self.value(&value);
+            copy.set_field(&name, value);
+        }
+
+        copy
+    }
+
+    fn list(&mut self, list: &Rc<RefCell<Vec<Value>>>) -> Rc<RefCell<Vec<Value>>> {
+        if let Some(copy) = self.lists.get(&Rc::as_ptr(list)) {
+            return copy.clone();
+        }
+
+        let copy = Rc::new(RefCell::new(vec![]));
+        self.lists.insert(Rc::as_ptr(list), copy.clone());
+
+        let elements: Vec<_> = list
+            .borrow()
+            .iter()
+            .map(|value| self.value(value))
+            .collect();
+        *copy.borrow_mut() = elements;
+
+        copy
+    }
+}
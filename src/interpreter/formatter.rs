@@ -0,0 +1,97 @@
+use crate::interpreter::{error::Result, scanner::Scanner, token::Token, token_kind::TokenKind};
+
+const INDENT: &str = "  ";
+
+/// Reindents Lox source by bracket depth.
+///
+/// Only whitespace is changed: every line is indented two spaces per open
+/// `(`, `[` or `{`, trailing whitespace is removed and runs of blank lines are
+/// collapsed into one. Comments are kept where they are and lines inside a
+/// multi-line string are left untouched.
+pub struct Formatter<'a> {
+    source: &'a str,
+}
+
+impl<'a> Formatter<'a> {
+    pub fn new(source: &'a str) -> Self {
+        Self { source }
+    }
+
+    pub fn format(&self) -> Result<String> {
+        let tokens = Scanner::new(self.source.to_string()).scan_tokens()?;
+
+        let mut output = String::new();
+        let mut depth = 0usize;
+        let mut next = 0;
+        let mut start = 0;
+        let mut pending_blank = false;
+
+        for line in self.source.split_inclusive('\n') {
+            let end = start + line.trim_end_matches(['\n', '\r']).len();
+
+            let in_string = next > 0 && tokens[next - 1].span.end > start;
+            let first = next;
+            while next < tokens.len() && tokens[next].span.start < end {
+                next += 1;
+            }
+
+            // A string opened on this line that continues on the next one
+            let open_string = next > first && tokens[next - 1].span.end > end;
+            let text = &self.source[start..end];
+            start += line.len();
+
+            let line_tokens = &tokens[first..next];
+            let indent = depth.saturating_sub(
+                line_tokens
+                    .iter()
+                    .take_while(|token| Self::is_closing(token))
+                    .count(),
+            );
+            depth = line_tokens.iter().fold(depth, Self::nest);
+
+            if in_string {
+                output.push_str(text);
+                output.push('\n');
+                continue;
+            }
+
+            let text = match open_string {
+                true => text.trim_start(),
+                false => text.trim(),
+            };
+
+            if text.is_empty() {
+                pending_blank = !output.is_empty();
+                continue;
+            }
+
+            if pending_blank {
+                output.push('\n');
+                pending_blank = false;
+            }
+
+            for _ in 0..indent {
+                output.push_str(INDENT);
+            }
+            output.push_str(text);
+            output.push('\n');
+        }
+
+        Ok(output)
+    }
+
+    fn nest(depth: usize, token: &Token) -> usize {
+        match token.kind {
+            TokenKind::LeftParen | TokenKind::LeftBrace | TokenKind::LeftBracket => depth + 1,
+            _ if Self::is_closing(token) => depth.saturating_sub(1),
+            _ => depth,
+        }
+    }
+
+    fn is_closing(token: &Token) -> bool {
+        matches!(
+            token.kind,
+            TokenKind::RightParen | TokenKind::RightBrace | TokenKind::RightBracket
+        )
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Write,
+    rc::Rc,
+};
+
+use crate::interpreter::{environment::Environment, value::Value};
+
+type EnvironmentRef = Rc<RefCell<Environment>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HeapFormat {
+    Text,
+    Dot,
+}
+
+struct EnvironmentNode {
+    label: Option<&'static str>,
+    /// Strong count of the environment, not counting the dump's own handle
+    strong_count: usize,
+    parent: Option<usize>,
+    bindings: Vec<(String, String)>,
+    /// Environments kept alive by a binding, labeled with the path to them
+    references: Vec<(String, usize)>,
+}
+
+/// A snapshot of every environment reachable from a set of roots, following
+/// parent links, function closures, class methods, instance fields and list
+/// elements.
+pub struct HeapDump {
+    nodes: Vec<EnvironmentNode>,
+}
+
+impl HeapDump {
+    pub fn collect(roots: &[(&'static str, EnvironmentRef)]) -> Self {
+        let mut ids: HashMap<*const RefCell<Environment>, usize> = HashMap::new();
+        let mut pending: VecDeque<EnvironmentRef> = VecDeque::new();
+        let mut labels: HashMap<usize, &'static str> = HashMap::new();
+
+        let mut discover =
+            |environment: &EnvironmentRef, pending: &mut VecDeque<EnvironmentRef>| -> usize {
+                let next_id = ids.len();
+                *ids.entry(Rc::as_ptr(environment)).or_insert_with(|| {
+                    pending.push_back(environment.clone());
+                    next_id
+                })
+            };
+
+        for (label, root) in roots {
+            let id = discover(root, &mut pending);
+            labels.entry(id).or_insert(label);
+        }
+
+        let mut nodes = vec![];
+        while let Some(environment) = pending.pop_front() {
+            let strong_count = Rc::strong_count(&environment) - 1;
+            let environment = environment.borrow();
+
+            let parent = environment
+                .enclosing
+                .as_ref()
+                .map(|parent| discover(parent, &mut pending));
+
+            let mut bindings = vec![];
+            let mut references = vec![];
+            for (name, value) in environment.bindings() {
+                let mut reachable = vec![];
+                Self::reachable_environments(
+                    name.clone(),
+                    &value,
+                    &mut reachable,
+                    &mut HashSet::new(),
+                );
+
+                for (path, environment) in reachable {
+                    references.push((path, discover(&environment, &mut pending)));
+                }
+
+                bindings.push((name, Self::describe(&value)));
+            }
+
+            nodes.push(EnvironmentNode {
+                label: labels.get(&nodes.len()).copied(),
+                strong_count,
+                parent,
+                bindings,
+                references,
+            });
+        }
+
+        Self { nodes }
+    }
+
+    fn reachable_environments(
+        path: String,
+        value: &Value,
+        reachable: &mut Vec<(String, EnvironmentRef)>,
+        seen: &mut HashSet<usize>,
+    ) {
+        match value {
+            Value::Function { closure, .. } => reachable.push((path, closure.clone())),
+            Value::Class(class) => {
+                for (name, method) in class.methods() {
+                    let path = format!("{}.{}", path, name);
+                    Self::reachable_environments(path, method, reachable, seen);
+                }
+            }
+            Value::Instance(instance) => {
+                if !seen.insert(instance.as_ptr() as usize) {
+                    return;
+                }
+
+                for (name, field) in instance.fields() {
+                    let path = format!("{}.{}", path, name);
+                    Self::reachable_environments(path, &field, reachable, seen);
+                }
+            }
+            Value::List(values) => {
+                if !seen.insert(values.as_ptr() as usize) {
+                    return;
+                }
+
+                for (i, element) in values.borrow().iter().enumerate() {
+                    let path = format!("{}[{}]", path, i);
+                    Self::reachable_environments(path, element, reachable, seen);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Short description of a value that doesn't recurse into its contents
+    fn describe(value: &Value) -> String {
+        match value {
+            Value::Instance(instance) => format!("<{} instance>", instance.borrow().class().name),
+            Value::List(values) => format!("<list of {}>", values.borrow().len()),
+            Value::String(value) => format!("{:?}", value),
+            value => value.to_string(),
+        }
+    }
+
+    pub fn render(&self, format: HeapFormat) -> String {
+        match format {
+            HeapFormat::Text => self.render_text(),
+            HeapFormat::Dot => self.render_dot(),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        let mut output = String::new();
+
+        for (id, node) in self.nodes.iter().enumerate() {
+            write!(output, "env{}", id).unwrap();
+            if let Some(label) = node.label {
+                write!(output, " ({})", label).unwrap();
+            }
+            write!(output, " strong={}", node.strong_count).unwrap();
+            if let Some(parent) = node.parent {
+                write!(output, " parent=env{}", parent).unwrap();
+            }
+            writeln!(output).unwrap();
+
+            for (name, value) in &node.bindings {
+                writeln!(output, "  {} = {}", name, value).unwrap();
+            }
+
+            for (path, target) in &node.references {
+                writeln!(output, "  {} -> env{}", path, target).unwrap();
+            }
+        }
+
+        output
+    }
+
+    fn render_dot(&self) -> String {
+        let mut output = String::from("digraph heap {\n  node [shape=box];\n");
+
+        for (id, node) in self.nodes.iter().enumerate() {
+            let mut label = format!("env{}", id);
+            if let Some(name) = node.label {
+                write!(label, " ({})", name).unwrap();
+            }
+            write!(label, "\\nstrong={}\\n", node.strong_count).unwrap();
+            for (name, value) in &node.bindings {
+                write!(label, "{} = {}\\l", name, value.replace('"', "\\\"")).unwrap();
+            }
+
+            writeln!(output, "  env{} [label=\"{}\"];", id, label).unwrap();
+
+            if let Some(parent) = node.parent {
+                writeln!(output, "  env{} -> env{} [label=\"parent\"];", id, parent).unwrap();
+            }
+
+            for (path, target) in &node.references {
+                writeln!(
+                    output,
+                    "  env{} -> env{} [label=\"{}\", style=dashed];",
+                    id, target, path
+                )
+                .unwrap();
+            }
+        }
+
+        output.push_str("}\n");
+        output
+    }
+}
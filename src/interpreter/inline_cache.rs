@@ -0,0 +1,133 @@
+use std::{collections::HashMap, rc::Rc};
+
+use crate::interpreter::{
+    callable::Callable,
+    error::Result,
+    profile::Profile,
+    token::Token,
+    value::{LoxInstance, Property, Shape, Value},
+};
+
+/// Remembers, for every property access in the program, what it found the
+/// last time: the slot of a field or the method of a class. An access on an
+/// instance of the same class tries that first, skipping the search through
+/// the fields and up the superclasses.
+///
+/// Sites are keyed by the id of the property's name token, like the
+/// resolver's locals. Instances of a class can still set their fields in
+/// different orders, so a cached slot is only used after checking its name.
+#[derive(Debug, Default)]
+pub(crate) struct InlineCaches {
+    sites: HashMap<usize, Entry>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    shape: Rc<Shape>,
+    lookup: Lookup,
+}
+
+#[derive(Debug)]
+enum Lookup {
+    Field(usize),
+    /// An unbound method, which is only found when the instance has no field
+    /// of the same name
+    Method(Value),
+}
+
+impl InlineCaches {
+    /// Reads the property `name` of `instance`, like `LoxInstance::get`
+    pub fn get(
+        &mut self,
+        instance: &LoxInstance,
+        name: &Token,
+        profile: Option<&mut Profile>,
+    ) -> Result<Value> {
+        match self.property(instance, name, profile)? {
+            Property::Field(value) => Ok(value),
+            Property::Method(method) => method.bind(instance),
+        }
+    }
+
+    /// Looks up the property `name` of `instance`, like
+    /// `LoxInstance::property`
+    pub fn property(
+        &mut self,
+        instance: &LoxInstance,
+        name: &Token,
+        profile: Option<&mut Profile>,
+    ) -> Result<Property> {
+        let cached = match self.sites.get(&name.id) {
+            Some(entry) if instance.has_shape(&entry.shape) => match &entry.lookup {
+                Lookup::Field(slot) => instance.field_at(*slot, &name.lexeme).map(Property::Field),
+                Lookup::Method(method) if !instance.has_field(&name.lexeme) => {
+                    Some(Property::Method(method.clone()))
+                }
+                Lookup::Method(_) => None,
+            },
+            _ => None,
+        };
+        Self::count(profile, cached.is_some());
+        if let Some(property) = cached {
+            return Ok(property);
+        }
+
+        let property = instance.property(name)?;
+
+        let lookup = match &property {
+            Property::Field(_) => instance.field_slot(&name.lexeme).map(Lookup::Field),
+            Property::Method(method) => Some(Lookup::Method(method.clone())),
+        };
+        self.remember(instance, name, lookup);
+
+        Ok(property)
+    }
+
+    /// Sets the field `name` of `instance`, like `LoxInstance::set`
+    pub fn set(
+        &mut self,
+        instance: &mut LoxInstance,
+        name: &Token,
+        value: &Value,
+        profile: Option<&mut Profile>,
+    ) {
+        let hit = match self.sites.get(&name.id) {
+            Some(Entry {
+                shape,
+                lookup: Lookup::Field(slot),
+            }) if instance.has_shape(shape) => {
+                instance.set_field_at(*slot, &name.lexeme, value.clone())
+            }
+            _ => false,
+        };
+        Self::count(profile, hit);
+        if hit {
+            return;
+        }
+
+        instance.set(name, value);
+        let lookup = instance.field_slot(&name.lexeme).map(Lookup::Field);
+        self.remember(instance, name, lookup);
+    }
+
+    fn remember(&mut self, instance: &LoxInstance, name: &Token, lookup: Option<Lookup>) {
+        match lookup {
+            Some(lookup) => {
+                let shape = instance.shape();
+                self.sites.insert(name.id, Entry { shape, lookup });
+            }
+            None => {
+                self.sites.remove(&name.id);
+            }
+        }
+    }
+
+    fn count(profile: Option<&mut Profile>, hit: bool) {
+        if let Some(profile) = profile {
+            match hit {
+                true => profile.property_hits += 1,
+                false => profile.property_misses += 1,
+            }
+        }
+    }
+}
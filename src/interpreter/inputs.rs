@@ -0,0 +1,149 @@
+//! Where natives whose results differ between runs, like `clock` and
+//! `readLine`, get them from, see
+//! [`Interpreter::set_inputs`](crate::interpreter::interpreter::Interpreter::set_inputs).
+//!
+//! A recording is a text file with one line per input, the name of the native
+//! followed by the value it returned:
+//!
+//! ```text
+//! clock 1697049600
+//! random 0.7380411429375227
+//! readLine "Ada
+//! readLine nil
+//! ```
+//!
+//! Strings start with a `"` and run to the end of the line, which is fine
+//! since lines read from stdin can't contain newlines.
+
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, Write},
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+
+use crate::interpreter::{
+    error::{LoxError, Result},
+    value::Value,
+};
+
+#[derive(Debug, Default)]
+pub enum Inputs {
+    /// Reads the clock, generator and stdin directly
+    #[default]
+    Live,
+    /// Reads them directly and appends every value to a log
+    Recording(File),
+    /// Returns the values of a log in order instead
+    Replaying {
+        entries: Vec<(String, Value)>,
+        next: usize,
+    },
+}
+
+impl Inputs {
+    /// Records the inputs of this run to the file at `path`, replacing it
+    pub fn record(path: &Path) -> Result<Self> {
+        Ok(Self::Recording(File::create(path)?))
+    }
+
+    /// Replays the inputs recorded to the file at `path`
+    pub fn replay(path: &Path) -> Result<Self> {
+        let entries = fs::read_to_string(path)?
+            .lines()
+            .enumerate()
+            .map(|(i, line)| {
+                parse_entry(line).ok_or_else(|| {
+                    LoxError::Other(anyhow!(
+                        "{}:{}: invalid input record '{}'.",
+                        path.display(),
+                        i + 1,
+                        line
+                    ))
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(Self::Replaying { entries, next: 0 })
+    }
+
+    /// Seconds since the Unix epoch, for `clock`
+    pub fn clock(&mut self) -> Result<Value> {
+        self.next("clock", || {
+            let elapsed = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_err(|error| LoxError::NativeError(error.to_string()))?;
+
+            Ok(Value::Number(elapsed.as_secs() as f64))
+        })
+    }
+
+    /// The result of `random`, taken from `live` unless replaying
+    pub fn random(&mut self, live: impl FnOnce() -> f64) -> Result<Value> {
+        self.next("random", || Ok(Value::Number(live())))
+    }
+
+    /// A line of stdin without its line ending, or `nil` at the end
+    pub fn read_line(&mut self) -> Result<Value> {
+        self.next("readLine", || {
+            let mut line = String::new();
+            if io::stdin().lock().read_line(&mut line)? == 0 {
+                return Ok(Value::Nil);
+            }
+
+            let trimmed = line.trim_end_matches(['\n', '\r']).len();
+            line.truncate(trimmed);
+            Ok(Value::String(line))
+        })
+    }
+
+    fn next(&mut self, native: &str, live: impl FnOnce() -> Result<Value>) -> Result<Value> {
+        match self {
+            Inputs::Live => live(),
+            Inputs::Recording(log) => {
+                let value = live()?;
+                writeln!(log, "{} {}", native, format_value(&value))?;
+                Ok(value)
+            }
+            Inputs::Replaying { entries, next } => match entries.get(*next) {
+                Some((recorded, value)) if recorded == native => {
+                    *next += 1;
+                    Ok(value.clone())
+                }
+                Some((recorded, _)) => Err(LoxError::NativeError(format!(
+                    "Replay diverged: input {} was recorded from {}() but the script called {}().",
+                    *next + 1,
+                    recorded,
+                    native
+                ))),
+                None => Err(LoxError::NativeError(format!(
+                    "Replay diverged: the recording has no more inputs for {}().",
+                    native
+                ))),
+            },
+        }
+    }
+}
+
+fn format_value(value: &Value) -> String {
+    match value {
+        Value::Number(number) => number.to_string(),
+        Value::String(string) => format!("\"{}", string),
+        _ => "nil".into(),
+    }
+}
+
+fn parse_entry(line: &str) -> Option<(String, Value)> {
+    let (native, value) = line.split_once(' ')?;
+    let value = match value {
+        "nil" => Value::Nil,
+        _ => match value.strip_prefix('"') {
+            Some(string) => Value::String(string.into()),
+            None => Value::Number(value.parse().ok()?),
+        },
+    };
+
+    Some((native.into(), value))
+}
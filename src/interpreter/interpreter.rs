@@ -1,144 +1,1491 @@
-use std::{cell::RefCell, collections::HashMap, rc::Rc};
+use std::{
+    any::{Any, TypeId},
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    io::{ErrorKind, Write},
+    path::Path,
+    rc::Rc,
+};
+
+use anyhow::anyhow;
 
+#[cfg(feature = "natives-random")]
+use crate::interpreter::native_functions::Random;
 use crate::{
-    callable::Callable,
-    environment::Environment,
-    error::{LoxError, Result},
-    expr::{Expr, ExprVisitor},
-    native_functions::ClockCallable,
-    stmt::{Stmt, StmtVisitor},
-    token::Token,
-    token_kind::TokenKind,
-    value::{LoxClass, Value},
+    interpreter::{
+        callable::Callable,
+        diagnostic::{RuntimeWarning, Severity},
+        environment::{CaptureList, Environment},
+        error::{LoxError, Result},
+        expr::{Expr, ExprVisitor, MatchArm, Pattern},
+        fork::DeepCopy,
+        inline_cache::InlineCaches,
+        inputs::Inputs,
+        limits::Limits,
+        module::{describe_error, import_path, Modules},
+        native_functions::{
+            error_class, module_class, stdlib_natives, string_builder_class, ClockCallable,
+            NativeCallable, NativeFn,
+        },
+        optimizer::Optimizer,
+        output::{Buffering, Capture},
+        parser::Parser,
+        profile::Profile,
+        resolver::{ResolutionMismatch, Resolver},
+        scanner::Scanner,
+        source::read_source,
+        stmt::{CatchClause, ImportTarget, Stmt, StmtVisitor},
+        strictness::Strictness,
+        suggestion::closest_match,
+        token::Token,
+        token_kind::TokenKind,
+        value::{LoxClass, LoxInstance, Property, Value},
+    },
+    semantics::{self, Comparison},
 };
 
+#[derive(Debug, Clone, Default)]
+pub struct InterpreterConfig {
+    /// Enables the optional optimizations applied during resolution
+    pub optimize: bool,
+    /// Only allows fields and methods starting with `_` to be accessed
+    /// through `this`
+    pub private_fields: bool,
+    /// Reports `==` and `!=` between values of different types, other than
+    /// `nil`, as a warning or a runtime error instead of quietly comparing
+    /// them as unequal
+    pub strict_equality: Option<Severity>,
+    /// Checks type annotations before the program runs, reporting mismatches
+    /// as warnings or errors
+    pub type_check: Option<Severity>,
+    /// Makes natives for debugging scripts, like `locals`, available
+    pub debug: bool,
+    /// Safety limits for untrusted code
+    pub limits: Limits,
+    /// How `print` output to stdout is buffered
+    pub buffering: Buffering,
+    /// Makes assigning to a variable that was never declared define it as a
+    /// global, with a warning, instead of failing. Meant for the REPL, where
+    /// typing `var` every time is a chore.
+    pub implicit_globals: bool,
+    /// Counts how often runtime shortcuts like the inline caches pay off,
+    /// see `Interpreter::profile`
+    pub profile: bool,
+    /// What to do when a local variable isn't where the resolver put it
+    pub resolution_mismatch: ResolutionMismatch,
+    /// Stricter rules, of which the resolver checks `use_before_assignment`
+    /// and the interpreter the rest, save `require_braces`
+    pub strictness: Strictness,
+}
+
 pub struct Interpreter {
     pub environment: Rc<RefCell<Environment>>,
     pub globals: Rc<RefCell<Environment>>,
+    pub config: InterpreterConfig,
     locals: HashMap<usize, usize>,
+    error_class: LoxClass,
+    /// Operator token ids of literal-only expressions found inside loops
+    constant_sites: HashSet<usize>,
+    constant_cache: HashMap<usize, Value>,
+    /// Variable token ids in loop conditions that refer to globals
+    global_sites: HashSet<usize>,
+    global_cells: HashMap<usize, Rc<RefCell<Value>>>,
+    /// Variable token ids in methods that name a method of the class
+    method_hints: HashSet<usize>,
+    /// What each property access found last time, by name token id
+    inline_caches: InlineCaches,
+    profile: Option<Profile>,
+    /// Capture lists of function declarations by name token id
+    captures: HashMap<usize, CaptureList>,
+    /// Block environments that weren't captured by a closure, reused to avoid
+    /// allocating a new one on every loop iteration
+    environment_pool: Vec<Rc<RefCell<Environment>>>,
+    /// Where `print` statements write to
+    output: Box<dyn Write>,
+    /// Runtime warnings not yet taken by `take_warnings`
+    warnings: Vec<(RuntimeWarning, String, Token)>,
+    /// Operator token ids that already produced a warning
+    warned_sites: HashSet<usize>,
+    /// Expressions re-evaluated after every statement
+    watches: Vec<Watch>,
+    /// Looks up variables by name from the current scope instead of by
+    /// resolved distance, for evaluating watches
+    dynamic_scope: bool,
+    /// Generator behind the `random` native
+    #[cfg(feature = "natives-random")]
+    random: Random,
+    /// Source of `clock`, `random` and `readLine` results, which can be
+    /// recorded and replayed
+    inputs: Inputs,
+    /// Lox function calls in progress
+    call_depth: usize,
+    /// Environments of the function calls and blocks being executed
+    environment_depth: usize,
+    /// Loop iterations in the current run
+    loop_iterations: u64,
+    /// Modules loaded by `import` statements
+    modules: Modules,
+    /// Calls queued by `schedule` for the next `run_pending`
+    pending: VecDeque<(Value, Vec<Value>)>,
+    /// Host state for natives, at most one value per type
+    userdata: HashMap<TypeId, Box<dyn Any>>,
+}
+
+/// An expression registered with `Interpreter::watch`, with the rendering of
+/// its last value, or `None` when it couldn't be evaluated
+struct Watch {
+    source: String,
+    expr: Expr,
+    value: Option<String>,
 }
 
-impl Interpreter {
-    pub fn new() -> Self {
-        let globals = Environment::new();
-        let environment = globals.clone();
+const MAX_POOLED_ENVIRONMENTS: usize = 64;
+
+impl Interpreter {
+    pub fn new() -> Self {
+        Self::with_config(InterpreterConfig::default())
+    }
+
+    pub fn with_config(config: InterpreterConfig) -> Self {
+        let globals = Environment::new();
+        let environment = globals.clone();
+
+        let error_class = error_class();
+        let output = config.buffering.stdout();
+        let profile = config.profile.then(Profile::new);
+
+        globals
+            .borrow_mut()
+            .define("clock", Value::NativeFunction(Box::new(ClockCallable)));
+        globals
+            .borrow_mut()
+            .define("Error", Value::Class(error_class.clone()));
+        globals
+            .borrow_mut()
+            .define("StringBuilder", Value::Class(string_builder_class()));
+
+        let mut interpreter = Self {
+            environment,
+            globals,
+            config,
+            locals: HashMap::new(),
+            error_class,
+            constant_sites: HashSet::new(),
+            constant_cache: HashMap::new(),
+            global_sites: HashSet::new(),
+            global_cells: HashMap::new(),
+            method_hints: HashSet::new(),
+            inline_caches: InlineCaches::default(),
+            profile,
+            captures: HashMap::new(),
+            environment_pool: vec![],
+            output,
+            warnings: vec![],
+            warned_sites: HashSet::new(),
+            watches: vec![],
+            dynamic_scope: false,
+            #[cfg(feature = "natives-random")]
+            random: Random::default(),
+            inputs: Inputs::default(),
+            call_depth: 0,
+            environment_depth: 0,
+            loop_iterations: 0,
+            modules: Modules::default(),
+            pending: VecDeque::new(),
+            userdata: HashMap::new(),
+        };
+
+        for (name, arity, function) in stdlib_natives() {
+            interpreter.define_native(name, arity, function);
+        }
+
+        interpreter
+    }
+
+    /// Creates an interpreter starting from a deep copy of this one's
+    /// globals, so scripts run from a common preloaded state, like the
+    /// prelude, without re-running it and without seeing each other's
+    /// changes. The fork prints to stdout, buffered as its config says, and
+    /// has no watches, scheduled calls or userdata, and its `random`
+    /// continues from the same state as this one's. It reads inputs live,
+    /// even if this one records or replays them.
+    pub fn fork(&self) -> Self {
+        let mut copy = DeepCopy::default();
+        let globals = copy.environment(&self.globals);
+        let environment = copy.environment(&self.environment);
+
+        Self {
+            environment,
+            globals,
+            config: self.config.clone(),
+            locals: self.locals.clone(),
+            error_class: copy.class(&self.error_class),
+            constant_sites: self.constant_sites.clone(),
+            constant_cache: HashMap::new(),
+            global_sites: self.global_sites.clone(),
+            global_cells: HashMap::new(),
+            method_hints: self.method_hints.clone(),
+            inline_caches: InlineCaches::default(),
+            profile: self.config.profile.then(Profile::new),
+            captures: self.captures.clone(),
+            environment_pool: vec![],
+            output: self.config.buffering.stdout(),
+            warnings: vec![],
+            warned_sites: HashSet::new(),
+            watches: vec![],
+            dynamic_scope: false,
+            #[cfg(feature = "natives-random")]
+            random: self.random.clone(),
+            inputs: Inputs::default(),
+            call_depth: 0,
+            environment_depth: 0,
+            loop_iterations: 0,
+            modules: self.modules.fork(),
+            pending: VecDeque::new(),
+            userdata: HashMap::new(),
+        }
+    }
+
+    /// Warnings raised while interpreting since the last call, each with the
+    /// token it's about
+    pub fn take_warnings(&mut self) -> Vec<(RuntimeWarning, String, Token)> {
+        std::mem::take(&mut self.warnings)
+    }
+
+    /// Applies `strict_equality` to an `==` or `!=` comparison
+    fn check_equality(&mut self, operator: &Token, left: &Value, right: &Value) -> Result<()> {
+        let strictness = self.config.strictness;
+        let severity = match self.config.strict_equality {
+            _ if strictness.strict_equality => Severity::Error,
+            Some(severity) => strictness.severity(severity),
+            None => return Ok(()),
+        };
+
+        if matches!(left, Value::Nil)
+            || matches!(right, Value::Nil)
+            || left.type_name() == right.type_name()
+        {
+            return Ok(());
+        }
+
+        let message = format!(
+            "Operands of '{}' are a {} and a {}, which are never equal.",
+            operator.lexeme,
+            left.type_name(),
+            right.type_name()
+        );
+
+        match severity {
+            Severity::Error => Err(LoxError::RuntimeError {
+                message,
+                token: Box::new(operator.clone()),
+            }),
+            Severity::Warning => {
+                if self.warned_sites.insert(operator.id) {
+                    self.warnings
+                        .push((RuntimeWarning::MixedEquality, message, operator.clone()));
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    /// What the runtime shortcuts did so far, when `InterpreterConfig::profile`
+    /// is set
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+
+    /// Number of local variable references the resolver has recorded
+    pub fn resolved_locals(&self) -> usize {
+        self.locals.len()
+    }
+
+    /// How many scopes out from the reference `name` the variable it refers
+    /// to is declared, or `None` for globals
+    pub fn resolved_depth(&self, name: &Token) -> Option<usize> {
+        self.locals.get(&name.id).copied()
+    }
+
+    /// Redirects the output of `print` statements, which goes to stdout by
+    /// default. Output still waiting in the current writer's buffer is
+    /// written out first.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        let _ = self.output.flush();
+        self.output = output;
+    }
+
+    /// Writes out the output of `print` statements waiting in a buffer, see
+    /// [`Buffering`]. Runs flush on their own once they end.
+    pub fn flush_output(&mut self) -> Result<()> {
+        Ok(self.output.flush()?)
+    }
+
+    /// Runs `f` with the output of `print` statements captured instead of
+    /// written out, returning what was printed
+    pub fn capture_output(&mut self, f: impl FnOnce(&mut Self)) -> String {
+        let capture = Capture::start(&mut self.output, false);
+        f(self);
+        capture.finish(&mut self.output)
+    }
+
+    /// Like `capture_output`, but the output still goes to the current
+    /// writer as well
+    pub fn tee_output(&mut self, f: impl FnOnce(&mut Self)) -> String {
+        let capture = Capture::start(&mut self.output, true);
+        f(self);
+        capture.finish(&mut self.output)
+    }
+
+    /// Records the results of `clock`, `random` and `readLine` to a log, or
+    /// replays a recorded one, so a run can be reproduced exactly
+    pub fn set_inputs(&mut self, inputs: Inputs) {
+        self.inputs = inputs;
+    }
+
+    #[cfg(feature = "natives-random")]
+    pub(crate) fn random_mut(&mut self) -> &mut Random {
+        &mut self.random
+    }
+
+    /// The next `random` result, from the generator unless replaying
+    #[cfg(feature = "natives-random")]
+    pub(crate) fn next_random(&mut self) -> Result<Value> {
+        let random = &mut self.random;
+        self.inputs.random(|| random.next_f64())
+    }
+
+    pub(crate) fn inputs_mut(&mut self) -> &mut Inputs {
+        &mut self.inputs
+    }
+
+    /// The writer `print` statements currently go to
+    pub(crate) fn output_mut(&mut self) -> &mut Box<dyn Write> {
+        &mut self.output
+    }
+
+    pub(crate) fn modules(&self) -> &Modules {
+        &self.modules
+    }
+
+    pub(crate) fn modules_mut(&mut self) -> &mut Modules {
+        &mut self.modules
+    }
+
+    /// Loads the module an import of `path` refers to, running it the first
+    /// time it's imported. Its top-level code runs in an environment of its
+    /// own, and the module's value is an instance with its exports as fields.
+    /// Importing a module that is still loading is an `ImportCycle` error.
+    fn import_module(&mut self, keyword: &Token, path: &Token) -> Result<LoxInstance> {
+        let name = import_path(path)?;
+        let file =
+            self.modules
+                .resolve(name)
+                .canonicalize()
+                .map_err(|error| LoxError::RuntimeError {
+                    message: match error.kind() {
+                        ErrorKind::NotFound => self.modules.not_found_message(name),
+                        _ => format!("Can't read module '{}': {}.", name, error),
+                    },
+                    token: Box::new(path.clone()),
+                })?;
+
+        if let Some(module) = self.modules.get(&file) {
+            return Ok(module);
+        }
+
+        self.modules
+            .start_loading(file.clone(), name, keyword.line)
+            .map_err(|message| LoxError::ImportCycle {
+                message,
+                token: Box::new(path.clone()),
+            })?;
+
+        let enclosing_file = self.modules.current_file.replace(file.clone());
+        let result = self.load_module(&file, keyword, path);
+        self.modules.current_file = enclosing_file;
+        self.modules.finish_loading(result.as_ref().ok().cloned());
+
+        result
+    }
+
+    fn load_module(&mut self, file: &Path, keyword: &Token, path: &Token) -> Result<LoxInstance> {
+        let name = import_path(path)?;
+        let import_error = |message: String| LoxError::RuntimeError {
+            message,
+            token: Box::new(path.clone()),
+        };
+
+        let source = read_source(file, false)
+            .map_err(|error| import_error(format!("Can't read module '{}': {}", name, error)))?;
+        let statements = self
+            .compile_module(source)
+            .map_err(|message| import_error(format!("'{}' has errors: {}", name, message)))?;
+
+        // Defined up front, like the resolver declares them, so closures
+        // capture variables declared after them
+        let environment = Environment::new_with_parent(self.globals.clone());
+        for name in statements.iter().flat_map(Stmt::declared_names) {
+            environment.borrow_mut().define(&name.lexeme, Value::Nil);
+        }
+
+        // Errors are reported at the import, since their own tokens are from
+        // the module's file
+        self.execute_block(&statements, environment.clone())
+            .map_err(|error| match error {
+                LoxError::RuntimeError { message, token } => LoxError::RuntimeError {
+                    message: format!("In '{}' line {}: {}", name, token.line, message),
+                    token: Box::new(keyword.clone()),
+                },
+                LoxError::ImportCycle { message, .. } => LoxError::ImportCycle {
+                    message,
+                    token: Box::new(keyword.clone()),
+                },
+                error => error,
+            })?;
+
+        let mut module = LoxInstance::new(module_class());
+        for name in Stmt::exported_names(&statements) {
+            module.set_field(&name.lexeme, environment.borrow().get(name)?);
+        }
+
+        Ok(module)
+    }
+
+    fn compile_module(&mut self, source: String) -> Result<Vec<Stmt>, String> {
+        let statements = self.modules.parse(source)?;
+        let statements = match self.config.optimize {
+            true => Optimizer::new().optimize(statements),
+            false => statements,
+        };
+
+        Resolver::new(self)
+            .resolve_module(&statements)
+            .map_err(|error| describe_error(&error))?;
+
+        Ok(statements)
+    }
+
+    /// Removes a native function from the globals, so scripts can't use it.
+    /// Fails if `name` isn't a native.
+    pub fn remove_native(&mut self, name: &str) -> Result<()> {
+        let is_native = matches!(
+            self.globals
+                .borrow()
+                .cell(name)
+                .map(|cell| cell.borrow().clone()),
+            Some(Value::NativeFunction(_))
+        );
+
+        match is_native {
+            true => {
+                self.globals.borrow_mut().remove(name);
+                Ok(())
+            }
+            false => Err(LoxError::NativeError(format!(
+                "'{}' isn't a native function.",
+                name
+            ))),
+        }
+    }
+
+    pub fn define_native(&mut self, name: &str, arity: usize, function: NativeFn) {
+        self.globals.borrow_mut().define(
+            name,
+            Value::NativeFunction(Box::new(NativeCallable::new(arity, function))),
+        );
+    }
+
+    /// The value of the global `name`, like a function defined by a script
+    /// the host ran earlier
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        self.globals
+            .borrow()
+            .cell(name)
+            .map(|cell| cell.borrow().clone())
+    }
+
+    /// Evaluates the expression in `source` against the globals, for hosts
+    /// reading expressions from config files or debugger input, and for
+    /// checking a script's state in tests:
+    ///
+    /// ```text
+    /// lox.run("var scores = [3, 4];".into());
+    /// let total = lox.interpreter.evaluate_str("sum(scores) * 2")?;
+    /// ```
+    ///
+    /// It's scanned and parsed with the same keywords and settings as the
+    /// last program `Lox` ran, and can have side effects, like assigning a
+    /// global or calling a function that prints.
+    pub fn evaluate_str(&mut self, source: &str) -> Result<Value> {
+        let tokens = Scanner::new(source.to_string())
+            .with_keywords(self.modules.keywords.clone())
+            .scan_tokens()?;
+        let expr = Parser::new(tokens, self.modules.parser_config.clone()).parse_expression()?;
+
+        Resolver::new(self).resolve(&[Stmt::Expression(expr.clone())])?;
+
+        self.loop_iterations = 0;
+        self.evaluate(&expr)
+    }
+
+    /// Calls a Lox function, class or native from the host, for example to
+    /// use a script's functions as callbacks. Errors the script doesn't catch,
+    /// including exceptions it throws, end the call.
+    pub fn call(&mut self, callee: &Value, arguments: &[Value]) -> Result<Value> {
+        self.loop_iterations = 0;
+        self.call_value(callee, arguments)
+    }
+
+    /// Calls the global function `name`, converting its result into `R`:
+    ///
+    /// ```text
+    /// lox.run("fun area(width, height) { return width * height; }".into());
+    /// let area: f64 = lox.interpreter.call_global("area", &[2.0.into(), 3.0.into()])?;
+    /// ```
+    pub fn call_global<R>(&mut self, name: &str, arguments: &[Value]) -> Result<R>
+    where
+        R: TryFrom<Value>,
+        LoxError: From<R::Error>,
+    {
+        let callee = self
+            .get_global(name)
+            .ok_or_else(|| anyhow!("There's no global named '{}'.", name))?;
+
+        Ok(R::try_from(self.call(&callee, arguments)?)?)
+    }
+
+    /// Stores host state for natives to use, like a database handle, without
+    /// globals. There's one slot per type, so this replaces any earlier value
+    /// of type `T`, which is returned.
+    ///
+    /// ```text
+    /// interpreter.set_userdata(World::new());
+    /// interpreter.define_native("spawn", 1, |interpreter, arguments| {
+    ///     let world = interpreter.get_userdata_mut::<World>().unwrap();
+    ///     ...
+    /// });
+    /// ```
+    pub fn set_userdata<T: Any>(&mut self, value: T) -> Option<T> {
+        self.userdata
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|previous| previous.downcast().ok())
+            .map(|previous| *previous)
+    }
+
+    pub fn get_userdata<T: Any>(&self) -> Option<&T> {
+        self.userdata
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    pub fn get_userdata_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.userdata
+            .get_mut(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_mut())
+    }
+
+    pub fn remove_userdata<T: Any>(&mut self) -> Option<T> {
+        self.userdata
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok())
+            .map(|value| *value)
+    }
+
+    /// Queues a call for the next `run_pending`, for hosts that drive event
+    /// handlers from their own loop, like once per frame
+    pub fn schedule(&mut self, callee: Value, arguments: Vec<Value>) {
+        self.pending.push_back((callee, arguments));
+    }
+
+    /// Whether calls are waiting for `run_pending`
+    pub fn has_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    /// Makes the scheduled calls in order. Calls scheduled while they run
+    /// wait for the next `run_pending`, so a handler that reschedules itself
+    /// runs once per call. A failing call doesn't stop the others, and every
+    /// error is returned.
+    pub fn run_pending(&mut self) -> Result<(), Vec<LoxError>> {
+        let mut errors = vec![];
+        for (callee, arguments) in std::mem::take(&mut self.pending) {
+            if let Err(error) = self.call(&callee, &arguments) {
+                errors.push(error);
+            }
+        }
+        if let Err(error) = self.flush_output() {
+            errors.push(error);
+        }
+
+        match errors.len() {
+            0 => Ok(()),
+            _ => Err(errors),
+        }
+    }
+
+    /// Calls a Lox callable from native code. Arity and callability problems
+    /// are reported as `LoxError::NativeError` so they surface at the call
+    /// site of the native.
+    pub fn call_value(&mut self, callee: &Value, arguments: &[Value]) -> Result<Value> {
+        callee.call(self, arguments).map_err(|error| match error {
+            LoxError::IncorrectArityError => LoxError::NativeError(format!(
+                "Expected callback to take {} arguments but it takes {}.",
+                arguments.len(),
+                callee.arity()
+            )),
+            LoxError::NotCallableError => {
+                LoxError::NativeError(format!("'{}' is not callable.", callee))
+            }
+            _ => error,
+        })
+    }
+
+    pub fn interpret(&mut self, stmts: Vec<Stmt>) -> Result<(), Vec<LoxError>> {
+        self.loop_iterations = 0;
+
+        let mut errors: Vec<LoxError> = vec![];
+        for stmt in stmts {
+            match self.execute(&stmt) {
+                Ok(()) => {}
+                // The rest of the program isn't run once it's over a limit, or
+                // once the interpreter can't be trusted after an internal error
+                Err(error @ (LoxError::LimitExceeded { .. } | LoxError::Internal { .. })) => {
+                    errors.push(error);
+                    break;
+                }
+                Err(error) => errors.push(error),
+            }
+        }
+        if let Err(error) = self.flush_output() {
+            errors.push(error);
+        }
+
+        match errors.len() {
+            0 => Ok(()),
+            _ => Err(errors),
+        }
+    }
+
+    /// Like `interpret`, but a trailing expression statement is evaluated
+    /// instead of executed and its value returned, unless it's an assignment
+    pub fn interpret_with_result(
+        &mut self,
+        mut stmts: Vec<Stmt>,
+    ) -> Result<Option<Value>, Vec<LoxError>> {
+        let expr = match stmts.last_mut() {
+            Some(Stmt::Expression(expr)) if !matches!(expr, Expr::Assign(..) | Expr::Set(..)) => {
+                let expr = expr.take();
+                stmts.pop();
+                Some(expr)
+            }
+            _ => None,
+        };
+
+        self.interpret(stmts)?;
+
+        let value = match expr {
+            Some(expr) => self.evaluate(&expr).map(Some).map_err(|error| vec![error]),
+            None => return Ok(None),
+        };
+        self.flush_output().map_err(|error| vec![error])?;
+
+        value
+    }
+
+    pub(crate) fn execute_block(
+        &mut self,
+        statements: &[Stmt],
+        environment: Rc<RefCell<Environment>>,
+    ) -> Result<()> {
+        if let Some(limit) = self.config.limits.max_environment_depth {
+            if self.environment_depth >= limit {
+                return Err(LoxError::LimitExceeded {
+                    message: format!("Scopes are nested more than {} deep.", limit),
+                    token: None,
+                });
+            }
+        }
+
+        let previous = self.environment.clone();
+        self.environment = environment;
+        self.environment_depth += 1;
+
+        let mut result = Ok(());
+        for statement in statements {
+            result = self.execute(statement);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        self.environment_depth -= 1;
+        self.environment = previous;
+
+        result
+    }
+
+    /// Runs `f` with `environment` as the current environment, without
+    /// counting it as a nested scope
+    pub(crate) fn in_environment<R>(
+        &mut self,
+        environment: Rc<RefCell<Environment>>,
+        f: impl FnOnce(&mut Self) -> R,
+    ) -> R {
+        let previous = std::mem::replace(&mut self.environment, environment);
+        let result = f(self);
+        self.environment = previous;
+
+        result
+    }
+
+    /// Counts a call to the Lox function `name`, failing when too many are
+    /// already in progress. Every successful call must be paired with
+    /// `exit_call`.
+    pub(crate) fn enter_call(&mut self, name: &Token) -> Result<()> {
+        let limit = self.config.limits.max_call_depth;
+        if self.call_depth >= limit {
+            return Err(LoxError::LimitExceeded {
+                message: format!(
+                    "Stack overflow: calling '{}' would nest more than {} calls.",
+                    name.lexeme, limit
+                ),
+                token: None,
+            });
+        }
+
+        self.call_depth += 1;
+        Ok(())
+    }
+
+    pub(crate) fn exit_call(&mut self) {
+        self.call_depth -= 1;
+    }
+
+    /// Counts an iteration of the loop at `token`, failing there when loops
+    /// have run for more than the limit
+    pub(crate) fn count_iteration(&mut self, token: Option<&Token>) -> Result<()> {
+        self.loop_iterations += 1;
+
+        match self.config.limits.max_loop_iterations {
+            Some(limit) if self.loop_iterations > limit => Err(LoxError::LimitExceeded {
+                message: format!("Loops ran for more than {} iterations.", limit),
+                token: token.cloned().map(Box::new),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails when a string of `length` characters would be longer than the
+    /// limit, reporting it at `token` when there is one
+    pub(crate) fn check_string_length(&self, length: usize, token: Option<&Token>) -> Result<()> {
+        match self.config.limits.max_string_length {
+            Some(limit) if length > limit => Err(LoxError::LimitExceeded {
+                message: format!(
+                    "String of {} characters is longer than the limit of {}.",
+                    length, limit
+                ),
+                token: token.cloned().map(Box::new),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails when a list of `length` elements would be longer than the limit
+    pub(crate) fn check_list_length(&self, length: usize, token: Option<&Token>) -> Result<()> {
+        match self.config.limits.max_list_length {
+            Some(limit) if length > limit => Err(LoxError::LimitExceeded {
+                message: format!(
+                    "List of {} elements is longer than the limit of {}.",
+                    length, limit
+                ),
+                token: token.cloned().map(Box::new),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn resolve(&mut self, name: &Token, depth: usize) {
+        self.locals.insert(name.id, depth);
+    }
+
+    /// Marks a variable in a method that has the name of a sibling method, so
+    /// if it turns out to be undefined the error can point to `this`
+    pub(crate) fn hint_method(&mut self, name: &Token) {
+        self.method_hints.insert(name.id);
+    }
+
+    pub(crate) fn capture(&mut self, name: &Token, captures: CaptureList) {
+        self.captures.insert(name.id, captures);
+    }
+
+    /// Marks the binary or unary expression with `operator` as only containing
+    /// literals, so its value is cached after the first evaluation. A
+    /// literal-only expression always produces the same value, so the cache
+    /// never needs to be invalidated.
+    pub(crate) fn cache_constant(&mut self, operator: &Token) {
+        self.constant_sites.insert(operator.id);
+    }
+
+    /// Marks a global read in a loop condition so the handle to its storage is
+    /// kept after the first lookup. Redefining a global reuses its cell, so the
+    /// handle never goes stale.
+    pub(crate) fn cache_global(&mut self, name: &Token) {
+        self.global_sites.insert(name.id);
+    }
+
+    fn lookup_global(&mut self, name: &Token) -> Result<Value> {
+        if let Some(cell) = self.global_cells.get(&name.id) {
+            return Ok(cell.borrow().clone());
+        }
+
+        let cell = match self.global_sites.contains(&name.id) {
+            true => self.globals.borrow().cell(&name.lexeme),
+            false => None,
+        };
+
+        match cell {
+            Some(cell) => {
+                let value = cell.borrow().clone();
+                self.global_cells.insert(name.id, cell);
+
+                Ok(value)
+            }
+            None => self
+                .globals
+                .borrow()
+                .get(name)
+                .map_err(|error| self.suggest_variable(error)),
+        }
+    }
+
+    fn cached_constant(&self, operator: &Token) -> Option<Value> {
+        self.constant_cache.get(&operator.id).cloned()
+    }
+
+    fn store_constant(&mut self, operator: &Token, value: &Value) {
+        if self.constant_sites.contains(&operator.id) {
+            self.constant_cache.insert(operator.id, value.clone());
+        }
+    }
+
+    pub(crate) fn evaluate(&mut self, expr: &Expr) -> Result<Value> {
+        expr.accept(self)
+    }
+
+    pub(crate) fn execute(&mut self, stmt: &Stmt) -> Result<()> {
+        let result = stmt.accept(self);
+        if !self.watches.is_empty() {
+            self.check_watches(stmt);
+        }
+
+        result
+    }
+
+    /// Re-evaluates `expr` after every statement from now on, reporting to
+    /// stderr whenever its value changes. The expression isn't resolved with
+    /// the program, so its variables are looked up by name from wherever the
+    /// program is at.
+    pub fn watch(&mut self, source: String, expr: Expr) {
+        let value = self.evaluate_watch(&expr);
+        self.watches.push(Watch {
+            source,
+            expr,
+            value,
+        });
+    }
+
+    fn evaluate_watch(&mut self, expr: &Expr) -> Option<String> {
+        let dynamic_scope = std::mem::replace(&mut self.dynamic_scope, true);
+        let value = self.evaluate(expr).ok().map(|value| value.to_string());
+        self.dynamic_scope = dynamic_scope;
+
+        value
+    }
+
+    fn check_watches(&mut self, stmt: &Stmt) {
+        // Taken while they're evaluated, so functions called by a watch don't
+        // check them again
+        let mut watches = std::mem::take(&mut self.watches);
+        for watch in &mut watches {
+            let value = self.evaluate_watch(&watch.expr);
+            if value == watch.value {
+                continue;
+            }
+
+            // So the report comes after what the statement printed
+            let _ = self.output.flush();
+
+            let location = match stmt.line() {
+                Some(line) => format!("[line {}] ", line),
+                None => String::new(),
+            };
+            let show = |value: &Option<String>| match value {
+                Some(value) => value.clone(),
+                None => "<undefined>".to_string(),
+            };
+
+            eprintln!(
+                "{}watch {}: {} -> {}",
+                location,
+                watch.source,
+                show(&watch.value),
+                show(&value)
+            );
+            watch.value = value;
+        }
+        self.watches = watches;
+    }
+
+    /// Reports a call with the wrong number of arguments at the first extra
+    /// argument, or at the closing paren with the names of the missing ones
+    fn arity_error(
+        callee: &Value,
+        count: usize,
+        paren: &Token,
+        argument_starts: &[Token],
+    ) -> LoxError {
+        let arity = callee.arity();
+        let mut message = format!("Expected {} arguments but got {}.", arity, count);
+
+        let token = match argument_starts.get(arity) {
+            Some(extra) if count > arity => extra,
+            _ => {
+                let missing = callee
+                    .parameter_names()
+                    .into_iter()
+                    .skip(count)
+                    .map(|name| format!("'{}'", name))
+                    .collect::<Vec<_>>();
+
+                if !missing.is_empty() {
+                    message.push_str(&format!(" Missing {}.", missing.join(", ")));
+                }
+
+                paren
+            }
+        };
+
+        LoxError::RuntimeError {
+            message,
+            token: Box::new(token.clone()),
+        }
+    }
+
+    /// Converts a runtime error into an instance of the built-in `Error` class
+    /// so it can be handled by a `catch` block
+    fn runtime_error_value(&self, message: &str, token: &Token) -> Value {
+        let mut instance = LoxInstance::new(self.error_class.clone());
+        instance.set_field("message", Value::String(message.into()));
+        instance.set_field("kind", Value::String("RuntimeError".into()));
+        instance.set_field("line", Value::Number(token.line as f64));
+
+        Value::Instance(instance)
+    }
+
+    /// Executes `statements` in a block environment taken from the pool
+    fn execute_scoped(
+        &mut self,
+        statements: &[Stmt],
+        bindings: impl FnOnce(&mut Environment),
+    ) -> Result<()> {
+        let environment = match self.environment_pool.pop() {
+            Some(environment) => {
+                environment
+                    .borrow_mut()
+                    .reset(Some(self.environment.clone()));
+                environment
+            }
+            None => Environment::new_with_parent(self.environment.clone()),
+        };
+        bindings(&mut environment.borrow_mut());
+
+        let result = self.execute_block(statements, environment.clone());
+
+        // Anything still holding the environment (like a closure) keeps it alive
+        if Rc::strong_count(&environment) == 1
+            && self.environment_pool.len() < MAX_POOLED_ENVIRONMENTS
+        {
+            environment.borrow_mut().reset(None);
+            self.environment_pool.push(environment);
+        }
+
+        result
+    }
+
+    fn execute_loop_body(&mut self, name: &Token, value: Value, body: &Stmt) -> Result<()> {
+        self.count_iteration(Some(name))?;
+        self.execute_scoped(std::slice::from_ref(body), |environment| {
+            environment.define(&name.lexeme, value)
+        })
+    }
+
+    /// The property `name` of `value`, the result of evaluating `object`
+    fn get_property(&mut self, value: Value, object: &Expr, name: &Token) -> Result<Value> {
+        let result = match value {
+            Value::Instance(instance) => {
+                self.inline_caches
+                    .get(&instance, name, self.profile.as_mut())
+            }
+            Value::Foreign(foreign) => {
+                foreign
+                    .get(&name.lexeme)
+                    .ok_or_else(|| LoxError::RuntimeError {
+                        message: format!(
+                            "Undefined property '{}' on <{}>.",
+                            name.lexeme,
+                            foreign.name()
+                        ),
+                        token: Box::new(name.clone()),
+                    })
+            }
+            _ => Err(LoxError::RuntimeError {
+                message: "Only instances have properties.".into(),
+                token: Box::new(name.clone()),
+            }),
+        };
+
+        result.map_err(|error| Self::property_error(error, object))
+    }
+
+    /// Adds the path of the object, like `a.b()` for `a.b().c`, to an error
+    /// getting one of its properties
+    fn property_error(error: LoxError, object: &Expr) -> LoxError {
+        Self::in_access_path(error, object.access_path())
+    }
+
+    /// Evaluates the callee of a call. When it's a method of an instance,
+    /// like `counter.increment`, the method is returned unbound along with
+    /// the instance, so the call can skip making a bound method.
+    fn callee(&mut self, callee: &Expr) -> Result<(Value, Option<LoxInstance>)> {
+        let (object, name) = match callee {
+            Expr::Get(object, name) => (object, name),
+            callee => return Ok((self.evaluate(callee)?, None)),
+        };
+
+        let instance = match self.evaluate(object)? {
+            Value::Instance(instance) => instance,
+            value => return Ok((self.get_property(value, object, name)?, None)),
+        };
+
+        let property = self
+            .inline_caches
+            .property(&instance, name, self.profile.as_mut())
+            .map_err(|error| Self::property_error(error, object))?;
+
+        Ok(match property {
+            Property::Field(value) => (value, None),
+            Property::Method(method) => {
+                if let Some(profile) = &mut self.profile {
+                    profile.method_calls += 1;
+                    if !method.calls_unbound() {
+                        profile.bound_method_calls += 1;
+                    }
+                }
+
+                (method, Some(instance))
+            }
+        })
+    }
+
+    /// Calls a zero argument method of the iterator protocol on `instance`,
+    /// reporting failures at `token`
+    pub(crate) fn call_method(
+        &mut self,
+        instance: &LoxInstance,
+        name: &str,
+        token: &Token,
+    ) -> Result<Value> {
+        let method_name = Token {
+            kind: TokenKind::Identifier,
+            lexeme: name.into(),
+            literal: None,
+            ..token.clone()
+        };
+
+        instance
+            .get(&method_name)?
+            .call(self, &[])
+            .map_err(|error| match error {
+                LoxError::IncorrectArityError => LoxError::RuntimeError {
+                    message: format!("Expected '{}' to take no arguments.", name),
+                    token: Box::new(token.clone()),
+                },
+                LoxError::NotCallableError => LoxError::RuntimeError {
+                    message: format!("Expected '{}' to be a method.", name),
+                    token: Box::new(token.clone()),
+                },
+                _ => error,
+            })
+    }
 
-        globals
-            .borrow_mut()
-            .define("clock", Value::NativeFunction(Box::new(ClockCallable)));
+    /// The iterator a for-in loop over `instance` gets from its `iterate`
+    /// method, reporting failures at the loop variable `name`
+    pub(crate) fn iterator(&mut self, instance: &LoxInstance, name: &Token) -> Result<LoxInstance> {
+        match self.call_method(instance, "iterate", name)? {
+            Value::Instance(iterator) => Ok(iterator),
+            _ => Err(LoxError::RuntimeError {
+                message: "'iterate' must return an instance.".into(),
+                token: Box::new(name.clone()),
+            }),
+        }
+    }
 
-        Self {
-            environment,
-            globals,
-            locals: HashMap::new(),
+    /// Adds the chain of accesses that succeeded before a failing property
+    /// access or call to its error, like "while accessing 'a.b().c'" when
+    /// calling `a.b().c()` fails. It's left out when the first link fails,
+    /// since that's obvious from the line alone.
+    fn in_access_path(error: LoxError, path: Option<String>) -> LoxError {
+        let links = |path: &String| path.matches(['.', '(']).count();
+        match (error, path) {
+            (LoxError::RuntimeError { message, token }, Some(path)) if links(&path) >= 1 => {
+                LoxError::RuntimeError {
+                    message: format!(
+                        "{} (while accessing '{}').",
+                        message.trim_end_matches('.'),
+                        path
+                    ),
+                    token,
+                }
+            }
+            (error, _) => error,
         }
     }
 
-    pub fn interpret(&mut self, stmts: Vec<Stmt>) -> Result<(), Vec<LoxError>> {
-        let mut errors: Vec<LoxError> = vec![];
-        for stmt in stmts {
-            if let Err(error) = self.execute(&stmt) {
-                errors.push(error);
+    fn evaluate_match_arm(&mut self, arm: &MatchArm) -> Result<Option<Value>> {
+        if let Some(guard) = &arm.guard {
+            if !self.evaluate(guard)?.is_truthy() {
+                return Ok(None);
             }
         }
 
-        match errors.len() {
-            0 => Ok(()),
-            _ => Err(errors),
+        self.evaluate(&arm.body).map(Some)
+    }
+
+    /// Whether `implicit_globals` applies, which the stricter rules rule out,
+    /// since its warning would be an error anyway with `deny_warnings`
+    fn allows_implicit_globals(&self) -> bool {
+        let strictness = self.config.strictness;
+        self.config.implicit_globals && !strictness.no_implicit_globals && !strictness.deny_warnings
+    }
+
+    pub(crate) fn assign_variable(&mut self, name: &Token, value: &Value) -> Result<()> {
+        match self.locals.get(&name.id) {
+            None if self.allows_implicit_globals()
+                && self.globals.borrow().cell(&name.lexeme).is_none() =>
+            {
+                self.warnings.push((
+                    RuntimeWarning::ImplicitGlobal,
+                    format!(
+                        "'{}' was never declared, so assigning it defined a global. Files need 'var {}' first.",
+                        name.lexeme, name.lexeme
+                    ),
+                    name.clone(),
+                ));
+                self.globals
+                    .borrow_mut()
+                    .define(&name.lexeme, value.clone());
+                Ok(())
+            }
+            None => self.globals.borrow_mut().assign(name, value),
+            Some(&distance) => {
+                let assigned = self
+                    .environment
+                    .borrow_mut()
+                    .assign_at(distance, name, value);
+                self.resolution_fallback(assigned, name, distance, |interpreter| {
+                    interpreter.environment.borrow_mut().assign(name, value)
+                })
+            }
         }
     }
 
-    pub(crate) fn execute_block(
+    /// Handles a local variable that wasn't in the scope it was resolved to,
+    /// `distance` levels up, as `resolution_mismatch` says. With a fallback,
+    /// it's looked up by name instead, warning once per site.
+    fn resolution_fallback<T>(
         &mut self,
-        statements: &[Stmt],
-        environment: Rc<RefCell<Environment>>,
-    ) -> Result<()> {
-        let previous = self.environment.clone();
-        self.environment = environment;
+        result: Result<T>,
+        name: &Token,
+        distance: usize,
+        fallback: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<T> {
+        if !matches!(result, Err(LoxError::Internal { .. })) {
+            return result;
+        }
 
-        for statement in statements {
-            if let error @ Err(_) = self.execute(statement) {
-                self.environment = previous;
-                return error;
+        let mismatch = format!(
+            "'{}' isn't in the scope it was resolved to, at distance {}",
+            name.lexeme, distance
+        );
+        match self.config.resolution_mismatch {
+            ResolutionMismatch::Fail => Err(LoxError::internal(format!(
+                "{}, on line {}.",
+                mismatch, name.line
+            ))),
+            ResolutionMismatch::Fallback => {
+                if self.warned_sites.insert(name.id) {
+                    self.warnings.push((
+                        RuntimeWarning::ResolutionFallback,
+                        format!(
+                            "Internal error: {}, so it was looked up by name instead. This is a bug in the interpreter.",
+                            mismatch
+                        ),
+                        name.clone(),
+                    ));
+                }
+
+                fallback(self)
             }
         }
+    }
 
-        self.environment = previous;
+    fn unpack(&self, paren: &Token, names: &[Token], value: &Value) -> Result<Vec<Value>> {
+        let values = match value {
+            Value::List(values) => values.borrow(),
+            _ => {
+                return Err(LoxError::RuntimeError {
+                    message: "Can only destructure lists.".into(),
+                    token: Box::new(paren.clone()),
+                })
+            }
+        };
 
-        Ok(())
+        if values.len() != names.len() {
+            return Err(LoxError::RuntimeError {
+                message: format!(
+                    "Expected {} values to unpack but got {}.",
+                    names.len(),
+                    values.len()
+                ),
+                token: Box::new(paren.clone()),
+            });
+        }
+
+        Ok(values.clone())
     }
 
-    pub(crate) fn resolve(&mut self, name: &Token, depth: usize) {
-        self.locals.insert(name.id, depth);
+    fn lookup_variable(&mut self, name: &Token) -> Result<Value> {
+        match self.locals.get(&name.id) {
+            Some(&distance) => {
+                let value = self.environment.borrow().get_at(distance, name);
+                self.resolution_fallback(value, name, distance, |interpreter| {
+                    interpreter.environment.borrow().get(name)
+                })
+            }
+            None if self.dynamic_scope => self.environment.borrow().get(name),
+            None => self.lookup_global(name),
+        }
     }
 
-    fn evaluate(&mut self, expr: &Expr) -> Result<Value> {
-        expr.accept(self)
+    /// Extends an undefined variable error with the closest visible name
+    fn suggest_variable(&self, error: LoxError) -> LoxError {
+        match error {
+            LoxError::RuntimeError { message, token } => {
+                let names = self.environment.borrow().visible_names();
+                let suggestion = match self.method_hints.contains(&token.id) {
+                    true => Some(format!("this.{}", token.lexeme)),
+                    false => closest_match(&token.lexeme, names.iter().map(String::as_str))
+                        .map(String::from),
+                };
+
+                let message = match suggestion {
+                    Some(suggestion) => format!("{} Did you mean '{}'?", message, suggestion),
+                    None => message,
+                };
+
+                LoxError::RuntimeError { message, token }
+            }
+            error => error,
+        }
+    }
+
+    fn number_operands(operator: &Token, left: &Value, right: &Value) -> Result<(f64, f64)> {
+        match (left, right) {
+            (Value::Number(left), Value::Number(right)) => Ok((*left, *right)),
+            _ => Err(Self::operand_error(
+                operator,
+                "Operands must be numbers",
+                left,
+                right,
+            )),
+        }
     }
 
-    fn execute(&mut self, stmt: &Stmt) -> Result<()> {
-        stmt.accept(self)
+    fn compare(
+        operator: &Token,
+        comparison: Comparison,
+        left: &Value,
+        right: &Value,
+    ) -> Result<Value> {
+        semantics::compare(comparison, left.primitive(), right.primitive())
+            .map(Value::Boolean)
+            .map_err(|message| LoxError::RuntimeError {
+                token: Box::new(operator.clone()),
+                message,
+            })
     }
 
-    fn lookup_variable(&mut self, name: &Token) -> Result<Value> {
-        match self.locals.get(&name.id) {
-            Some(distance) => self.environment.borrow().get_at(*distance, name),
-            None => self.globals.borrow().get(name),
+    /// A type error for a binary operator, naming the types it got
+    fn operand_error(operator: &Token, expected: &str, left: &Value, right: &Value) -> LoxError {
+        LoxError::RuntimeError {
+            token: Box::new(operator.clone()),
+            message: semantics::operand_error(expected, left.primitive(), right.primitive()),
+        }
+    }
+
+    /// Copies the methods of mixins into a class's method table. Methods
+    /// declared by the class win over mixin methods, and mixin methods win over
+    /// inherited ones. Two mixins providing the same method is an error unless
+    /// the class declares it too. Mixin initializers aren't copied.
+    fn mix_in(
+        name: &Token,
+        methods: &mut HashMap<String, Value>,
+        mixins: &[LoxClass],
+    ) -> Result<()> {
+        let mut providers: HashMap<&String, &LoxClass> = HashMap::new();
+
+        for mixin in mixins {
+            for method_name in mixin.own_methods().keys() {
+                if method_name == "init" || methods.contains_key(method_name) {
+                    continue;
+                }
+
+                if let Some(provider) = providers.insert(method_name, mixin) {
+                    return Err(LoxError::RuntimeError {
+                        message: format!(
+                            "Mixins '{}' and '{}' both define '{}'. Define it in '{}' to choose one.",
+                            provider.name, mixin.name, method_name, name.lexeme
+                        ),
+                        token: Box::new(name.clone()),
+                    });
+                }
+            }
+        }
+
+        for (method_name, mixin) in providers {
+            methods.insert(
+                method_name.clone(),
+                mixin.own_methods()[method_name].clone(),
+            );
         }
+
+        Ok(())
     }
 }
 
 impl ExprVisitor<Result<Value>> for Interpreter {
     fn visit_binary_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Result<Value> {
+        if let Some(value) = self.cached_constant(operator) {
+            return Ok(value);
+        }
+
         let left_value = self.evaluate(left)?;
         let right_value = self.evaluate(right)?;
 
-        Ok(match operator.kind {
+        let value = match operator.kind {
             TokenKind::Minus => {
-                Value::Number(left_value.to_number(operator)? - right_value.to_number(operator)?)
+                let (left, right) = Self::number_operands(operator, &left_value, &right_value)?;
+                Value::Number(left - right)
             }
             TokenKind::Slash => {
-                Value::Number(left_value.to_number(operator)? / right_value.to_number(operator)?)
+                let (left, right) = Self::number_operands(operator, &left_value, &right_value)?;
+                Value::Number(left / right)
             }
             TokenKind::Star => {
-                Value::Number(left_value.to_number(operator)? * right_value.to_number(operator)?)
+                let (left, right) = Self::number_operands(operator, &left_value, &right_value)?;
+                Value::Number(left * right)
             }
             TokenKind::Plus => match (left_value, right_value) {
                 (Value::Number(l), Value::Number(r)) => Value::Number(l + r),
-                (Value::String(l), Value::String(r)) => Value::String(format!("{}{}", l, r)),
-                _ => {
-                    return Err(LoxError::RuntimeError {
-                        token: operator.clone(),
-                        message: "Operands must be two numbers or two strings.".into(),
-                    });
+                (Value::String(l), Value::String(r)) => {
+                    if self.config.limits.max_string_length.is_some() {
+                        let length = l.chars().count() + r.chars().count();
+                        self.check_string_length(length, Some(operator))?;
+                    }
+
+                    Value::String(format!("{}{}", l, r))
+                }
+                (left, right) => {
+                    return Err(Self::operand_error(
+                        operator,
+                        "Operands must be two numbers or two strings",
+                        &left,
+                        &right,
+                    ));
                 }
             },
             TokenKind::Greater => {
-                Value::Boolean(left_value.to_number(operator)? > right_value.to_number(operator)?)
-            }
-            TokenKind::GreaterEqual => {
-                Value::Boolean(left_value.to_number(operator)? >= right_value.to_number(operator)?)
+                Self::compare(operator, Comparison::Greater, &left_value, &right_value)?
             }
+            TokenKind::GreaterEqual => Self::compare(
+                operator,
+                Comparison::GreaterEqual,
+                &left_value,
+                &right_value,
+            )?,
             TokenKind::Less => {
-                Value::Boolean(left_value.to_number(operator)? < right_value.to_number(operator)?)
+                Self::compare(operator, Comparison::Less, &left_value, &right_value)?
             }
             TokenKind::LessEqual => {
-                Value::Boolean(left_value.to_number(operator)? <= right_value.to_number(operator)?)
+                Self::compare(operator, Comparison::LessEqual, &left_value, &right_value)?
             }
-            TokenKind::BangEqual => Value::Boolean(!left_value.is_equal(&right_value)),
-            TokenKind::EqualEqual => Value::Boolean(left_value.is_equal(&right_value)),
-            _ => unreachable!(),
-        })
+            TokenKind::In => match (left_value, right_value) {
+                (Value::String(field), Value::Instance(instance)) => {
+                    Value::Boolean(instance.has_field(&field))
+                }
+                (left, right) => {
+                    return Err(Self::operand_error(
+                        operator,
+                        "Operands of 'in' must be a string and an instance",
+                        &left,
+                        &right,
+                    ));
+                }
+            },
+            TokenKind::BangEqual => {
+                self.check_equality(operator, &left_value, &right_value)?;
+                Value::Boolean(!left_value.is_equal(&right_value))
+            }
+            TokenKind::EqualEqual => {
+                self.check_equality(operator, &left_value, &right_value)?;
+                Value::Boolean(left_value.is_equal(&right_value))
+            }
+            _ => {
+                return Err(LoxError::internal(format!(
+                    "Unexpected binary operator '{}'.",
+                    operator.lexeme
+                )))
+            }
+        };
+
+        self.store_constant(operator, &value);
+
+        Ok(value)
     }
 
     fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> Result<Value> {
+        if let Some(value) = self.cached_constant(operator) {
+            return Ok(value);
+        }
+
         let right_value = self.evaluate(right)?;
 
-        Ok(match operator.kind {
+        let value = match operator.kind {
             TokenKind::Minus => Value::Number(-right_value.to_number(operator)?),
             TokenKind::Bang => Value::Boolean(!right_value.is_truthy()),
-            _ => unreachable!(),
-        })
+            _ => {
+                return Err(LoxError::internal(format!(
+                    "Unexpected unary operator '{}'.",
+                    operator.lexeme
+                )))
+            }
+        };
+
+        self.store_constant(operator, &value);
+
+        Ok(value)
     }
 
     fn visit_group_expr(&mut self, expr: &Expr) -> Result<Value> {
@@ -155,14 +1502,7 @@ impl ExprVisitor<Result<Value>> for Interpreter {
 
     fn visit_assign_expr(&mut self, name: &Token, expr: &Expr) -> Result<Value> {
         let value = self.evaluate(expr)?;
-
-        match self.locals.get(&name.id) {
-            None => self.globals.borrow_mut().assign(name, &value)?,
-            Some(distance) => self
-                .environment
-                .borrow_mut()
-                .assign_at(*distance, name, &value)?,
-        };
+        self.assign_variable(name, &value)?;
 
         Ok(value)
     }
@@ -185,7 +1525,12 @@ impl ExprVisitor<Result<Value>> for Interpreter {
                     return Ok(left_value);
                 }
             }
-            _ => unreachable!(),
+            _ => {
+                return Err(LoxError::internal(format!(
+                    "Unexpected logical operator '{}'.",
+                    operator.lexeme
+                )))
+            }
         }
 
         self.evaluate(right)
@@ -196,51 +1541,58 @@ impl ExprVisitor<Result<Value>> for Interpreter {
         callee: &Expr,
         arguments: &[Expr],
         paren: &Token,
+        argument_starts: &[Token],
     ) -> Result<Value> {
-        let callee = self.evaluate(callee)?;
+        let callee_expr = callee;
+        let (callee, this) = self.callee(callee)?;
 
         let arguments = arguments
             .iter()
             .map(|arg| self.evaluate(arg))
             .collect::<Result<Vec<_>>>()?;
 
-        callee.call(self, &arguments).map_err(|error| match error {
-            LoxError::IncorrectArityError => LoxError::RuntimeError {
-                message: format!(
-                    "Expected {} arguments but got {}.",
-                    callee.arity(),
-                    arguments.len()
-                ),
-                token: paren.clone(),
-            },
-            LoxError::NotCallableError => LoxError::RuntimeError {
-                message: "Can only call functions and classes.".into(),
-                token: paren.clone(),
-            },
-            _ => error,
+        let result = match &this {
+            Some(instance) => callee.call_bound(self, instance, &arguments),
+            None => callee.call(self, &arguments),
+        };
+
+        result.map_err(|error| {
+            let error = match error {
+                LoxError::IncorrectArityError => {
+                    Self::arity_error(&callee, arguments.len(), paren, argument_starts)
+                }
+                LoxError::NotCallableError => LoxError::RuntimeError {
+                    message: "Can only call functions and classes.".into(),
+                    token: Box::new(paren.clone()),
+                },
+                LoxError::NativeError(message) => LoxError::RuntimeError {
+                    message,
+                    token: Box::new(paren.clone()),
+                },
+                // Errors from inside the function happened elsewhere
+                error => return error,
+            };
+
+            Self::in_access_path(error, callee_expr.access_path())
         })
     }
 
     fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> Result<Value> {
-        match self.evaluate(object)? {
-            Value::Instance(instance) => instance.get(name),
-            _ => Err(LoxError::RuntimeError {
-                message: "Only instances have properties.".into(),
-                token: name.clone(),
-            }),
-        }
+        let value = self.evaluate(object)?;
+        self.get_property(value, object, name)
     }
 
     fn visit_set_expr(&mut self, object: &Expr, name: &Token, value: &Expr) -> Result<Value> {
         match self.evaluate(object)? {
             Value::Instance(mut instance) => {
                 let value = self.evaluate(value)?;
-                instance.set(name, &value);
+                self.inline_caches
+                    .set(&mut instance, name, &value, self.profile.as_mut());
                 Ok(value)
             }
             _ => Err(LoxError::RuntimeError {
                 message: "Only instances have fields.".into(),
-                token: name.clone(),
+                token: Box::new(name.clone()),
             }),
         }
     }
@@ -250,31 +1602,103 @@ impl ExprVisitor<Result<Value>> for Interpreter {
     }
 
     fn visit_super_expr(&mut self, keyword: &Token, method: &Token) -> Result<Value> {
-        let distance = self
+        let distance = *self
             .locals
             .get(&keyword.id)
-            .expect("Could not find scope for 'super'.");
+            .ok_or_else(|| LoxError::internal("'super' wasn't resolved."))?;
 
         let environment = self.environment.borrow();
 
-        let superclass = match environment.get_keyword_at(*distance, "super")? {
+        let superclass = match environment.get_keyword_at(distance, "super")? {
             Value::Class(class) => class,
-            _ => unreachable!(),
+            _ => return Err(LoxError::internal("'super' isn't a class.")),
         };
 
-        let object = match environment.get_keyword_at(distance - 1, "this")? {
+        let this_distance = distance
+            .checked_sub(1)
+            .ok_or_else(|| LoxError::internal("'this' isn't inside the 'super' scope."))?;
+        let object = match environment.get_keyword_at(this_distance, "this")? {
             Value::Instance(instance) => instance,
-            _ => unreachable!(),
+            _ => return Err(LoxError::internal("'this' isn't an instance.")),
         };
 
         superclass
             .find_method(&method.lexeme)
             .ok_or_else(|| LoxError::RuntimeError {
                 message: format!("Undefined property '{}'.", method.lexeme),
-                token: method.clone(),
+                token: Box::new(method.clone()),
             })?
             .bind(&object)
     }
+
+    fn visit_match_expr(
+        &mut self,
+        keyword: &Token,
+        subject: &Expr,
+        arms: &[MatchArm],
+    ) -> Result<Value> {
+        let value = self.evaluate(subject)?;
+
+        for arm in arms {
+            let result = match &arm.pattern {
+                Pattern::Literal(_, literal) if !literal.is_equal(&value) => continue,
+                Pattern::Literal(..) | Pattern::Wildcard(_) => self.evaluate_match_arm(arm)?,
+                Pattern::Binding(name) => {
+                    let environment = Environment::new_with_parent(self.environment.clone());
+                    environment.borrow_mut().define(&name.lexeme, value.clone());
+
+                    let previous = std::mem::replace(&mut self.environment, environment);
+                    let result = self.evaluate_match_arm(arm);
+                    self.environment = previous;
+
+                    result?
+                }
+            };
+
+            if let Some(result) = result {
+                return Ok(result);
+            }
+        }
+
+        Err(LoxError::RuntimeError {
+            message: format!("No match arm matched value '{}'.", value),
+            token: Box::new(keyword.clone()),
+        })
+    }
+
+    fn visit_list_expr(&mut self, bracket: &Token, elements: &[Expr]) -> Result<Value> {
+        self.check_list_length(elements.len(), Some(bracket))?;
+
+        Ok(elements
+            .iter()
+            .map(|element| self.evaluate(element))
+            .collect::<Result<Vec<_>>>()?
+            .into())
+    }
+
+    fn visit_destructure_expr(
+        &mut self,
+        paren: &Token,
+        names: &[Token],
+        value: &Expr,
+    ) -> Result<Value> {
+        let value = self.evaluate(value)?;
+
+        for (name, element) in names.iter().zip(self.unpack(paren, names, &value)?) {
+            self.assign_variable(name, &element)?;
+        }
+
+        Ok(value)
+    }
+
+    /// Coroutines run their yields themselves, so reaching one here means
+    /// the function was called normally
+    fn visit_yield_expr(&mut self, keyword: &Token, _value: Option<&Expr>) -> Result<Value> {
+        Err(LoxError::RuntimeError {
+            message: "Can only yield inside a coroutine.".into(),
+            token: Box::new(keyword.clone()),
+        })
+    }
 }
 
 impl StmtVisitor<Result<()>> for Interpreter {
@@ -285,7 +1709,7 @@ impl StmtVisitor<Result<()>> for Interpreter {
 
     fn visit_print_stmt(&mut self, expr: &Expr) -> Result<()> {
         let value = self.evaluate(expr)?;
-        println!("{}", value);
+        writeln!(self.output, "{}", value)?;
         Ok(())
     }
 
@@ -300,11 +1724,31 @@ impl StmtVisitor<Result<()>> for Interpreter {
         Ok(())
     }
 
+    fn visit_var_destructure_stmt(
+        &mut self,
+        paren: &Token,
+        names: &[Token],
+        initializer: &Expr,
+    ) -> Result<()> {
+        let value = self.evaluate(initializer)?;
+
+        for (name, element) in names.iter().zip(self.unpack(paren, names, &value)?) {
+            self.environment.borrow_mut().define(&name.lexeme, element);
+        }
+
+        Ok(())
+    }
+
     fn visit_block_stmt(&mut self, statements: &[Stmt]) -> Result<()> {
-        self.execute_block(
-            statements,
-            Environment::new_with_parent(self.environment.clone()),
-        )
+        if !Stmt::declares_names(statements) {
+            for statement in statements {
+                self.execute(statement)?;
+            }
+
+            return Ok(());
+        }
+
+        self.execute_scoped(statements, |_| {})
     }
 
     fn visit_if_stmt(
@@ -322,26 +1766,80 @@ impl StmtVisitor<Result<()>> for Interpreter {
         Ok(())
     }
 
-    fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt) -> Result<()> {
+    fn visit_while_stmt(
+        &mut self,
+        condition: &Expr,
+        body: &Stmt,
+        origin: Option<&Token>,
+    ) -> Result<()> {
+        // A `for` loop's condition may be left out, or be an arbitrary part of
+        // it, so its errors point at the `for` clause
+        let token = origin.or(condition.token());
         while self.evaluate(condition)?.is_truthy() {
+            self.count_iteration(token)?;
             self.execute(body)?;
         }
 
         Ok(())
     }
 
+    fn visit_for_in_stmt(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> Result<()> {
+        match self.evaluate(iterable)? {
+            Value::List(values) => {
+                let mut index = 0;
+                loop {
+                    // the list can be modified by the body, so it can't stay borrowed
+                    let value = values.borrow().get(index).cloned();
+                    match value {
+                        Some(value) => self.execute_loop_body(name, value, body)?,
+                        None => break,
+                    }
+
+                    index += 1;
+                }
+            }
+            Value::Instance(instance) => {
+                let iterator = self.iterator(&instance, name)?;
+                while !self.call_method(&iterator, "done", name)?.is_truthy() {
+                    let value = self.call_method(&iterator, "next", name)?;
+                    self.execute_loop_body(name, value, body)?;
+                }
+            }
+            _ => {
+                return Err(LoxError::RuntimeError {
+                    message: "Can only iterate over lists and instances.".into(),
+                    token: Box::new(name.clone()),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
     fn visit_function_stmt(
         &mut self,
         name: &Token,
         parameters: &[Token],
         body: &[Stmt],
+        is_generator: bool,
     ) -> Result<()> {
+        // Defined first so a recursive function can capture its own name
+        self.environment
+            .borrow_mut()
+            .define(&name.lexeme, Value::Nil);
+
+        let closure = match self.captures.get(&name.id) {
+            Some(captures) => Environment::capture(&self.environment, captures),
+            None => self.environment.clone(),
+        };
+
         let function = Value::Function {
             name: name.clone().into(),
             parameters: parameters.to_vec(),
-            body: body.to_vec(),
-            closure: self.environment.clone(),
+            body: Rc::new(body.to_vec()),
+            closure,
             is_initializer: false,
+            is_generator,
         };
 
         self.environment.borrow_mut().define(&name.lexeme, function);
@@ -360,15 +1858,30 @@ impl StmtVisitor<Result<()>> for Interpreter {
         &mut self,
         name: &Token,
         superclass: Option<&Expr>,
+        mixins: &[Expr],
         methods: &[Stmt],
     ) -> Result<()> {
+        let mixins = mixins
+            .iter()
+            .map(|mixin| match self.evaluate(mixin)? {
+                Value::Class(class) => Ok(class),
+                _ => Err(LoxError::RuntimeError {
+                    message: "Mixins must be classes.".into(),
+                    token: Box::new(match mixin {
+                        Expr::Variable(token) => token.clone(),
+                        _ => name.clone(),
+                    }),
+                }),
+            })
+            .collect::<Result<Vec<_>>>()?;
+
         let superclass = superclass
             .map(|value| {
                 self.evaluate(value).and_then(|v| match v {
                     Value::Class(class) => Ok(class),
                     _ => Err(LoxError::RuntimeError {
                         message: "Superclass must be a class.".into(),
-                        token: name.clone(),
+                        token: Box::new(name.clone()),
                     }),
                 })
             })
@@ -391,27 +1904,33 @@ impl StmtVisitor<Result<()>> for Interpreter {
             None => None,
         };
 
-        let methods = methods
-            .iter()
-            .fold(HashMap::new(), |mut acc, method| match method {
-                Stmt::Function(name, parameters, body) => {
-                    acc.insert(
+        let mut class_methods = HashMap::new();
+        for method in methods {
+            match method {
+                Stmt::Function(name, parameters, body, is_generator, _) => {
+                    class_methods.insert(
                         name.lexeme.to_string(),
                         Value::Function {
                             name: name.clone().into(),
                             parameters: parameters.clone(),
-                            body: body.clone(),
+                            body: Rc::new(body.clone()),
                             closure: self.environment.clone(),
                             is_initializer: name.lexeme == "init",
+                            is_generator: *is_generator,
                         },
                     );
-
-                    acc
                 }
-                _ => unreachable!(),
-            });
+                _ => return Err(LoxError::internal("Class body contains a non-method.")),
+            }
+        }
 
-        let class = Value::Class(LoxClass::new(name.lexeme.clone(), methods, superclass));
+        Self::mix_in(name, &mut class_methods, &mixins)?;
+
+        let class = Value::Class(LoxClass::new(
+            name.lexeme.clone(),
+            class_methods,
+            superclass,
+        ));
 
         if let Some(environment) = enclosing_environment {
             self.environment = environment;
@@ -421,6 +1940,101 @@ impl StmtVisitor<Result<()>> for Interpreter {
 
         Ok(())
     }
+
+    fn visit_try_stmt(
+        &mut self,
+        body: &[Stmt],
+        catch: Option<&CatchClause>,
+        finally: Option<&[Stmt]>,
+    ) -> Result<()> {
+        let mut result = self.visit_block_stmt(body);
+
+        if let Some(catch) = catch {
+            let caught = match result {
+                Err(LoxError::RuntimeError { message, token }) => {
+                    Ok(self.runtime_error_value(&message, &token))
+                }
+                Err(LoxError::Thrown { value, .. }) => Ok(value),
+                uncaught => Err(uncaught),
+            };
+
+            result = match caught {
+                Ok(error) => {
+                    let environment = Environment::new_with_parent(self.environment.clone());
+                    environment.borrow_mut().define(&catch.name.lexeme, error);
+
+                    self.execute_block(&catch.body, environment)
+                }
+                Err(uncaught) => uncaught,
+            };
+        }
+
+        // The finally block runs however the try exited, including `return`
+        // unwinding through it. Its own errors and returns take precedence.
+        if let Some(finally) = finally {
+            self.visit_block_stmt(finally)?;
+        }
+
+        result
+    }
+
+    fn visit_throw_stmt(&mut self, keyword: &Token, value: &Expr) -> Result<()> {
+        Err(LoxError::Thrown {
+            value: self.evaluate(value)?,
+            token: Box::new(keyword.clone()),
+        })
+    }
+
+    /// Deleting a field the instance doesn't have is a no-op, so records can
+    /// be cleaned up without checking first
+    fn visit_delete_stmt(&mut self, object: &Expr, name: &Token) -> Result<()> {
+        match self.evaluate(object)? {
+            Value::Instance(mut instance) => {
+                instance.remove_field(&name.lexeme);
+                Ok(())
+            }
+            _ => Err(LoxError::RuntimeError {
+                token: Box::new(name.clone()),
+                message: "Only instances have fields.".into(),
+            }),
+        }
+    }
+
+    fn visit_import_stmt(
+        &mut self,
+        keyword: &Token,
+        path: &Token,
+        target: &ImportTarget,
+    ) -> Result<()> {
+        let module = self.import_module(keyword, path)?;
+        let path = import_path(path)?;
+
+        match target {
+            ImportTarget::Module(alias) => self
+                .environment
+                .borrow_mut()
+                .define(&alias.lexeme, Value::Instance(module)),
+            ImportTarget::Names(names) => {
+                for name in names {
+                    let value =
+                        module
+                            .get_field(&name.lexeme)
+                            .ok_or_else(|| LoxError::RuntimeError {
+                                message: format!("'{}' isn't exported by '{}'.", name.lexeme, path),
+                                token: Box::new(name.clone()),
+                            })?;
+
+                    self.environment.borrow_mut().define(&name.lexeme, value);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn visit_export_stmt(&mut self, _keyword: &Token, declaration: &Stmt) -> Result<()> {
+        self.execute(declaration)
+    }
 }
 
 impl Default for Interpreter {
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+
+use anyhow::anyhow;
+
+use crate::interpreter::{error::Result, token_kind::TokenKind};
+
+const KEYWORDS: &[(&str, TokenKind)] = &[
+    ("and", TokenKind::And),
+    ("catch", TokenKind::Catch),
+    ("class", TokenKind::Class),
+    ("delete", TokenKind::Delete),
+    ("else", TokenKind::Else),
+    ("export", TokenKind::Export),
+    ("false", TokenKind::False),
+    ("finally", TokenKind::Finally),
+    ("for", TokenKind::For),
+    ("fun", TokenKind::Fun),
+    ("if", TokenKind::If),
+    ("import", TokenKind::Import),
+    ("in", TokenKind::In),
+    ("match", TokenKind::Match),
+    ("nil", TokenKind::Nil),
+    ("or", TokenKind::Or),
+    ("print", TokenKind::Print),
+    ("return", TokenKind::Return),
+    ("super", TokenKind::Super),
+    ("this", TokenKind::This),
+    ("throw", TokenKind::Throw),
+    ("true", TokenKind::True),
+    ("try", TokenKind::Try),
+    ("using", TokenKind::Using),
+    ("var", TokenKind::Var),
+    ("while", TokenKind::While),
+    ("with", TokenKind::With),
+    ("yield", TokenKind::Yield),
+];
+
+/// The words the scanner treats as keywords.
+///
+/// The standard keywords can be given aliases, for teaching Lox in another
+/// language or embedding it as a DSL, and removed so they can be used as
+/// identifiers:
+///
+/// ```text
+/// let keywords = Keywords::default()
+///     .with_alias("función", "fun")?
+///     .with_alias("imprimir", "print")?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct Keywords {
+    words: HashMap<String, TokenKind>,
+}
+
+impl Keywords {
+    /// Makes `alias` mean the same as the standard keyword `keyword`
+    pub fn with_alias(mut self, alias: &str, keyword: &str) -> Result<Self> {
+        let kind = Self::standard(keyword)?;
+        if !Self::is_identifier(alias) {
+            return Err(anyhow!("'{}' can't be used as a keyword.", alias).into());
+        }
+
+        self.words.insert(alias.to_string(), kind);
+        Ok(self)
+    }
+
+    /// Stops treating `word` as a keyword
+    pub fn without(mut self, word: &str) -> Self {
+        self.words.remove(word);
+        self
+    }
+
+    /// Reads aliases from a file with one `alias = keyword` per line. Blank
+    /// lines and lines starting with `#` are ignored.
+    pub fn parse(self, source: &str) -> Result<Self> {
+        source
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .try_fold(self, |keywords, (number, line)| {
+                match line.split_once('=') {
+                    Some((alias, keyword)) => keywords.with_alias(alias.trim(), keyword.trim()),
+                    None => Err(anyhow!("Expected 'alias = keyword'.").into()),
+                }
+                .map_err(|error| {
+                    anyhow!("Invalid keyword alias on line {}: {}", number, error).into()
+                })
+            })
+    }
+
+    pub fn get(&self, word: &str) -> Option<TokenKind> {
+        self.words.get(word).copied()
+    }
+
+    /// Spelling of a keyword in standard Lox
+    pub fn standard_spelling(kind: TokenKind) -> Option<&'static str> {
+        KEYWORDS
+            .iter()
+            .find(|(_, keyword)| *keyword == kind)
+            .map(|(word, _)| *word)
+    }
+
+    fn standard(keyword: &str) -> Result<TokenKind> {
+        KEYWORDS
+            .iter()
+            .find(|(word, _)| *word == keyword)
+            .map(|(_, kind)| *kind)
+            .ok_or_else(|| anyhow!("'{}' isn't a keyword.", keyword).into())
+    }
+
+    fn is_identifier(word: &str) -> bool {
+        let mut chars = word.chars();
+        matches!(chars.next(), Some(c) if c.is_alphabetic() || c == '_')
+            && chars.all(|c| c.is_alphabetic() || c.is_ascii_digit() || c == '_')
+    }
+}
+
+impl Default for Keywords {
+    fn default() -> Self {
+        Self {
+            words: KEYWORDS
+                .iter()
+                .map(|(word, kind)| (word.to_string(), *kind))
+                .collect(),
+        }
+    }
+}
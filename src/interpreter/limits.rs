@@ -0,0 +1,45 @@
+/// Calls that can be nested unless `Limits` sets another limit. Each Lox call
+/// takes several native stack frames, so this stays well below where the
+/// native stack would overflow, even in debug builds.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 256;
+
+/// Statements and expressions that can be nested unless `Limits` sets another
+/// limit. Every phase walks the syntax tree recursively, so this bounds how
+/// much native stack they can use.
+pub const DEFAULT_MAX_NESTING_DEPTH: usize = 256;
+
+/// Safety limits for running untrusted code. Exceeding one stops the program
+/// with `LoxError::LimitExceeded`, which `catch` can't intercept, except for
+/// the nesting depth, which the parser reports as a parse error. Only the call
+/// and nesting depths are limited by default.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Most Lox function calls in progress at once
+    pub max_call_depth: usize,
+    /// Most loop iterations in a single run, counted across all loops
+    pub max_loop_iterations: Option<u64>,
+    /// Longest string, in characters, that concatenation and `StringBuilder`
+    /// can produce
+    pub max_string_length: Option<usize>,
+    /// Most elements a list literal or `push` can produce
+    pub max_list_length: Option<usize>,
+    /// Most environments in use at once, one per function call and per
+    /// running block that declares variables
+    pub max_environment_depth: Option<usize>,
+    /// Deepest blocks, bodies and subexpressions can be nested, checked by
+    /// the parser
+    pub max_nesting_depth: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_loop_iterations: None,
+            max_string_length: None,
+            max_list_length: None,
+            max_environment_depth: None,
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+        }
+    }
+}
@@ -0,0 +1,541 @@
+use std::path::{Path, PathBuf};
+
+use crate::interpreter::{
+    crash,
+    diagnostic::{DiagnosticRenderer, Severity},
+    error::{
+        LoxError, ParserErrorDetails, ResolverErrorDetails, Result, ScannerErrorDetails,
+        TypeErrorDetails,
+    },
+    interpreter::{Interpreter, InterpreterConfig},
+    keywords::Keywords,
+    observer::{Phase, PhaseObserver},
+    optimizer::Optimizer,
+    output::Capture,
+    parser::{Parser, ParserConfig},
+    resolver::Resolver,
+    scanner::Scanner,
+    source::read_source,
+    span::LineIndex,
+    stmt::Stmt,
+    strictness::Strictness,
+    token_kind::TokenKind,
+    typechecker::TypeChecker,
+    value::Value,
+};
+
+const PRELUDE: &str = include_str!("prelude.lox");
+
+/// Runs Lox source through the whole pipeline: scanning, parsing, resolving
+/// and interpreting, reporting errors to stderr along the way.
+pub struct Lox {
+    pub had_error: bool,
+    pub had_runtime_error: bool,
+    pub interpreter: Interpreter,
+    renderer: DiagnosticRenderer,
+    pub parser_config: ParserConfig,
+    /// Words scanned as keywords, the standard ones unless aliases are added
+    pub keywords: Keywords,
+    /// Prints the parsed statements before they're resolved
+    pub debug: bool,
+    /// Replaces invalid UTF-8 in scripts instead of refusing to run them
+    pub lossy: bool,
+    observer: Option<Box<dyn PhaseObserver>>,
+}
+
+impl Lox {
+    pub fn new(config: InterpreterConfig, renderer: DiagnosticRenderer) -> Self {
+        Self {
+            had_error: false,
+            had_runtime_error: false,
+            interpreter: Interpreter::with_config(config),
+            renderer,
+            parser_config: ParserConfig::default(),
+            keywords: Keywords::default(),
+            debug: false,
+            lossy: false,
+            observer: None,
+        }
+    }
+
+    /// Creates a driver with the same settings whose interpreter is a fork
+    /// of this one's, see [`Interpreter::fork`]. Errors from earlier runs
+    /// and the phase observer aren't carried over.
+    pub fn fork(&self) -> Self {
+        Self {
+            had_error: false,
+            had_runtime_error: false,
+            interpreter: self.interpreter.fork(),
+            renderer: self.renderer.clone(),
+            parser_config: self.parser_config.clone(),
+            keywords: self.keywords.clone(),
+            debug: self.debug,
+            lossy: self.lossy,
+            observer: None,
+        }
+    }
+
+    /// Runs `f` with the output of `print` statements captured, returning
+    /// what was printed. Diagnostics still go to stderr.
+    pub fn capture_output(&mut self, f: impl FnOnce(&mut Self)) -> String {
+        let capture = Capture::start(self.interpreter.output_mut(), false);
+        f(self);
+        capture.finish(self.interpreter.output_mut())
+    }
+
+    /// Like `capture_output`, but the output is also written where it
+    /// normally goes
+    pub fn tee_output(&mut self, f: impl FnOnce(&mut Self)) -> String {
+        let capture = Capture::start(self.interpreter.output_mut(), true);
+        f(self);
+        capture.finish(self.interpreter.output_mut())
+    }
+
+    /// Names the file later runs come from in diagnostics, to tell them apart
+    /// from errors in the main program
+    pub fn set_source_name(&mut self, name: Option<String>) {
+        self.renderer.set_source(name);
+    }
+
+    /// Applies `strictness` to later runs, in every phase that checks one of
+    /// its rules
+    pub fn set_strictness(&mut self, strictness: Strictness) {
+        self.parser_config.strictness = strictness;
+        self.interpreter.config.strictness = strictness;
+    }
+
+    /// Notifies `observer` at the start and end of every phase of later runs
+    pub fn set_observer(&mut self, observer: Box<dyn PhaseObserver>) {
+        self.observer = Some(observer);
+    }
+
+    fn start_phase(&mut self, phase: Phase) {
+        crash::record_phase(phase);
+        if let Some(observer) = &mut self.observer {
+            observer.phase_started(phase);
+        }
+    }
+
+    fn finish_phase(&mut self, phase: Phase, count: usize) {
+        if let Some(observer) = &mut self.observer {
+            observer.phase_finished(phase, count);
+        }
+    }
+
+    /// Runs the file at `path`, or stdin when it's `-`
+    pub fn run_file<T: AsRef<Path>>(&mut self, path: T) -> Result<()> {
+        let source = read_source(path.as_ref(), self.lossy)?;
+        self.set_current_file(path.as_ref());
+        self.run(source);
+
+        Ok(())
+    }
+
+    /// Reports static errors in the file at `path` without running it
+    pub fn check_file<T: AsRef<Path>>(&mut self, path: T) -> Result<()> {
+        let source = read_source(path.as_ref(), self.lossy)?;
+        self.set_current_file(path.as_ref());
+        self.compile(source);
+
+        Ok(())
+    }
+
+    /// Adds a directory to search for imported modules that aren't next to
+    /// the importing file. Directories are searched in the order they're
+    /// added.
+    pub fn add_search_path(&mut self, directory: PathBuf) {
+        self.interpreter.modules_mut().search_paths.push(directory);
+    }
+
+    /// The dependency graph of the script at `path` and the modules it
+    /// imports, in the DOT language
+    pub fn import_graph<T: AsRef<Path>>(&mut self, path: T) -> String {
+        self.sync_module_syntax();
+        self.interpreter.modules().import_graph(path.as_ref())
+    }
+
+    /// The script at `path` and the files of the modules it imports, directly
+    /// or not, including imported files that don't exist yet
+    pub fn module_files<T: AsRef<Path>>(&mut self, path: T) -> Vec<PathBuf> {
+        self.sync_module_syntax();
+        self.interpreter.modules().module_files(path.as_ref())
+    }
+
+    /// Makes modules use the same keywords and parser settings as the
+    /// program importing them
+    fn sync_module_syntax(&mut self) {
+        let parser_config = self.parser_config();
+        let modules = self.interpreter.modules_mut();
+        modules.keywords = self.keywords.clone();
+        modules.parser_config = parser_config;
+    }
+
+    /// The parser settings, with the nesting limit taken from the
+    /// interpreter's `Limits` so there's one place to change it
+    fn parser_config(&self) -> ParserConfig {
+        ParserConfig {
+            max_nesting_depth: self.interpreter.config.limits.max_nesting_depth,
+            ..self.parser_config.clone()
+        }
+    }
+
+    /// Makes imports in later runs relative to the directory of `path`
+    fn set_current_file(&mut self, path: &Path) {
+        self.interpreter.modules_mut().current_file =
+            (path != Path::new("-")).then(|| path.to_path_buf());
+    }
+
+    /// Reports the value of the expression in `source` whenever it changes
+    /// while later runs execute, see `Interpreter::watch`
+    pub fn watch(&mut self, source: &str) {
+        let (statements, line_index) = match self.compile(format!("{};", source)) {
+            Some(compiled) => compiled,
+            None => return,
+        };
+
+        match statements.as_slice() {
+            [Stmt::Expression(expr)] => self
+                .interpreter
+                .watch(source.trim().to_string(), expr.clone()),
+            _ => self.report_error(
+                &line_index,
+                Severity::Error,
+                Some(ParserErrorDetails::CODE),
+                0,
+                "",
+                "Expected an expression to watch.",
+            ),
+        }
+    }
+
+    /// Defines the prelude's functions in the globals
+    pub fn load_prelude(&mut self) {
+        self.run(PRELUDE.to_string());
+    }
+
+    pub fn run(&mut self, source: String) {
+        self.execute(source, false);
+    }
+
+    /// Runs a line of REPL input, returning the value of its last statement
+    /// when that's an expression
+    pub fn run_line(&mut self, source: String) -> Option<Value> {
+        self.execute(source, true)
+    }
+
+    fn execute(&mut self, source: String, with_result: bool) -> Option<Value> {
+        let (statements, line_index) = self.compile(source)?;
+
+        let count = statements.len();
+        self.start_phase(Phase::Interpreting);
+        let result = match with_result {
+            true => self.interpreter.interpret_with_result(statements),
+            false => self.interpreter.interpret(statements).map(|_| None),
+        };
+        self.finish_phase(Phase::Interpreting, count);
+
+        for (warning, message, token) in self.interpreter.take_warnings() {
+            let location = line_index.line_column(token.span.start);
+            let at = format!(" at '{}'", token.lexeme);
+            eprintln!(
+                "{}",
+                self.renderer.render(
+                    Severity::Warning,
+                    Some(warning.code()),
+                    location,
+                    &at,
+                    &message
+                )
+            );
+        }
+
+        match result {
+            Ok(value) => value,
+            Err(errors) => {
+                for error in errors {
+                    self.runtime_error(&line_index, &error);
+                }
+
+                None
+            }
+        }
+    }
+
+    /// Scans, parses and resolves `source`, returning the statements ready to
+    /// be interpreted, with the line index of the source to report later
+    /// errors with, or `None` after reporting any errors
+    fn compile(&mut self, source: String) -> Option<(Vec<Stmt>, LineIndex)> {
+        self.sync_module_syntax();
+        crash::record_source(
+            self.interpreter.modules().current_file.clone(),
+            &source,
+            &self.keywords,
+            &self.parser_config(),
+        );
+
+        self.start_phase(Phase::Scanning);
+        let (tokens, line_index) = match Scanner::new(source)
+            .with_keywords(self.keywords.clone())
+            .scan()
+        {
+            Ok(scan) => (scan.tokens, scan.line_index),
+            Err(LoxError::ScanningError {
+                tokens,
+                line_index,
+                details,
+            }) => {
+                self.report_scanning_error(&line_index, &details);
+                (tokens, line_index)
+            }
+            Err(error) => {
+                self.report_internal_error(&error);
+                return None;
+            }
+        };
+        self.finish_phase(Phase::Scanning, tokens.len());
+
+        self.start_phase(Phase::Parsing);
+        let statements = match Parser::new(tokens, self.parser_config()).parse() {
+            Ok(statements) => statements,
+            Err(LoxError::ParseError {
+                statements,
+                details,
+            }) => {
+                self.report_parse_error(&line_index, &details);
+                statements
+            }
+            Err(error) => {
+                self.report_internal_error(&error);
+                return None;
+            }
+        };
+        self.finish_phase(Phase::Parsing, statements.len());
+
+        if self.had_error {
+            return None;
+        }
+
+        if let Some(severity) = self.interpreter.config.type_check {
+            self.start_phase(Phase::TypeChecking);
+            let errors = TypeChecker::new().check(&statements);
+            self.finish_phase(Phase::TypeChecking, statements.len());
+            let severity = self.interpreter.config.strictness.severity(severity);
+            self.report_type_errors(&line_index, severity, &errors);
+        }
+
+        let statements = match self.interpreter.config.optimize {
+            true => {
+                self.start_phase(Phase::Optimizing);
+                let statements = Optimizer::new().optimize(statements);
+                self.finish_phase(Phase::Optimizing, statements.len());
+                statements
+            }
+            false => statements,
+        };
+
+        if self.debug {
+            eprintln!("{:#?}", statements);
+        }
+
+        self.start_phase(Phase::Resolving);
+        let resolved = self.interpreter.resolved_locals();
+        match Resolver::new(&mut self.interpreter).resolve(&statements) {
+            Err(LoxError::ResolutionError(details)) => {
+                self.report_resolution_error(&line_index, &details)
+            }
+            Err(error) => self.report_internal_error(&error),
+            _ => (),
+        };
+        let resolved = self.interpreter.resolved_locals() - resolved;
+        self.finish_phase(Phase::Resolving, resolved);
+
+        match self.had_error {
+            true => None,
+            false => Some((statements, line_index)),
+        }
+    }
+
+    /// Whether `source` could be run as is, or is missing the end of a
+    /// statement, block or string that later input could provide
+    pub fn is_complete(&self, source: &str) -> bool {
+        let tokens = match Scanner::new(source.to_string())
+            .with_keywords(self.keywords.clone())
+            .scan_tokens()
+        {
+            Ok(tokens) => tokens,
+            Err(LoxError::ScanningError {
+                tokens, details, ..
+            }) => {
+                let end = source.trim_end().len();
+                if details.iter().any(|detail| detail.span.end >= end) {
+                    return false;
+                }
+
+                tokens
+            }
+            Err(_) => return true,
+        };
+
+        match Parser::new(tokens, self.parser_config()).parse() {
+            Err(LoxError::ParseError { details, .. }) => details
+                .iter()
+                .all(|detail| detail.token.kind != TokenKind::Eof),
+            _ => true,
+        }
+    }
+
+    fn report_scanning_error(&mut self, line_index: &LineIndex, details: &[ScannerErrorDetails]) {
+        for detail in details {
+            self.report_error(
+                line_index,
+                Severity::Error,
+                Some(ScannerErrorDetails::CODE),
+                detail.span.start,
+                "",
+                &detail.message,
+            )
+        }
+    }
+
+    fn report_parse_error(&mut self, line_index: &LineIndex, details: &[ParserErrorDetails]) {
+        for detail in details {
+            let at = match detail.token.kind {
+                TokenKind::Eof => " at end".to_string(),
+                _ => format!(" at '{}'", detail.token.lexeme),
+            };
+
+            let severity = self.parser_config.strictness.severity(detail.severity);
+            let code = match severity {
+                Severity::Error => Some(ParserErrorDetails::CODE),
+                Severity::Warning => None,
+            };
+
+            self.report_error(
+                line_index,
+                severity,
+                code,
+                detail.token.span.start,
+                &at,
+                &detail.message,
+            )
+        }
+    }
+
+    fn report_resolution_error(
+        &mut self,
+        line_index: &LineIndex,
+        details: &[ResolverErrorDetails],
+    ) {
+        for detail in details {
+            let at = match detail.token.kind {
+                TokenKind::Eof => " at end".to_string(),
+                _ => format!(" at '{}'", detail.token.lexeme),
+            };
+
+            self.report_error(
+                line_index,
+                Severity::Error,
+                Some(ResolverErrorDetails::CODE),
+                detail.token.span.start,
+                &at,
+                &detail.message,
+            )
+        }
+    }
+
+    /// Reports type mismatches with `severity`. Only errors stop the program
+    /// from running.
+    fn report_type_errors(
+        &mut self,
+        line_index: &LineIndex,
+        severity: Severity,
+        details: &[TypeErrorDetails],
+    ) {
+        for detail in details {
+            let at = format!(" at '{}'", detail.token.lexeme);
+            match severity {
+                Severity::Error => self.report_error(
+                    line_index,
+                    severity,
+                    Some(TypeErrorDetails::CODE),
+                    detail.token.span.start,
+                    &at,
+                    &detail.message,
+                ),
+                Severity::Warning => {
+                    let location = line_index.line_column(detail.token.span.start);
+                    eprintln!(
+                        "{}",
+                        self.renderer
+                            .render(severity, None, location, &at, &detail.message)
+                    );
+                }
+            }
+        }
+    }
+
+    fn runtime_error(&mut self, line_index: &LineIndex, error: &LoxError) {
+        let message = match error {
+            LoxError::RuntimeError { message, token }
+            | LoxError::ImportCycle { message, token } => self.renderer.render_runtime(
+                error.code(),
+                message,
+                line_index.line_column(token.span.start),
+            ),
+            LoxError::Thrown { value, token } => {
+                let description = match value {
+                    Value::Instance(instance) => instance
+                        .get_field("message")
+                        .unwrap_or_else(|| value.clone()),
+                    value => value.clone(),
+                };
+
+                self.renderer.render_runtime(
+                    error.code(),
+                    &format!("Uncaught exception: {}", description),
+                    line_index.line_column(token.span.start),
+                )
+            }
+            LoxError::LimitExceeded {
+                message,
+                token: Some(token),
+            } => self.renderer.render_runtime(
+                error.code(),
+                message,
+                line_index.line_column(token.span.start),
+            ),
+            error => format!("Error[{}]: {}", error.code(), error),
+        };
+
+        eprintln!("{}", message);
+        self.had_runtime_error = true;
+    }
+
+    /// Reports an error that doesn't come with a location, like a broken
+    /// invariant, without stopping the process
+    fn report_internal_error(&mut self, error: &LoxError) {
+        eprintln!("Error[{}]: {}", error.code(), error);
+        if crash::is_installed() {
+            let directory = crash::write_report(Some(error));
+            eprintln!("{}", crash::notice(directory.as_deref()));
+        }
+        self.had_error = true;
+    }
+
+    fn report_error(
+        &mut self,
+        line_index: &LineIndex,
+        severity: Severity,
+        code: Option<&str>,
+        offset: usize,
+        at: &str,
+        message: &str,
+    ) {
+        let location = line_index.line_column(offset);
+        eprintln!(
+            "{}",
+            self.renderer.render(severity, code, location, at, message)
+        );
+        self.had_error = true;
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+use anyhow::anyhow;
+
+use crate::interpreter::{
+    diagnostic::Severity, error::Result, interpreter::InterpreterConfig, source::read_source,
+};
+
+/// Name of the manifest file looked for by `Manifest::find`
+pub const MANIFEST_NAME: &str = "lox.toml";
+
+/// A project manifest, `lox.toml`, written in a small subset of TOML:
+/// `[table]` headers, and `key = value` lines where the value is a string,
+/// an integer, `true`, `false` or a one-line array of strings.
+///
+/// ```toml
+/// [project]
+/// entry = "src/main.lox"
+/// include = ["lib"]
+///
+/// [lint]
+/// strict-equality = "warning"
+/// type-check = "error"
+/// private-fields = true
+///
+/// [limits]
+/// max-call-depth = 512
+///
+/// [natives]
+/// clock = false
+/// ```
+///
+/// Paths are relative to the directory holding the manifest. Unknown tables
+/// and keys are errors, so typos don't go unnoticed.
+#[derive(Debug, Clone, Default)]
+pub struct Manifest {
+    /// Directory holding the manifest
+    pub root: PathBuf,
+    /// Script run by `ilox run` without arguments
+    pub entry: Option<PathBuf>,
+    /// Directories searched for modules
+    pub include: Vec<PathBuf>,
+    pub private_fields: Option<bool>,
+    pub strict_equality: Option<Severity>,
+    pub type_check: Option<Severity>,
+    pub max_arguments: Option<usize>,
+    pub max_call_depth: Option<usize>,
+    pub max_loop_iterations: Option<u64>,
+    pub max_string_length: Option<usize>,
+    pub max_list_length: Option<usize>,
+    pub max_environment_depth: Option<usize>,
+    pub max_nesting_depth: Option<usize>,
+    /// Natives switched off with `name = false` in `[natives]`
+    pub disabled_natives: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+enum TomlValue {
+    String(String),
+    Integer(u64),
+    Boolean(bool),
+    Array(Vec<String>),
+}
+
+impl TomlValue {
+    fn type_name(&self) -> &'static str {
+        match self {
+            TomlValue::String(_) => "a string",
+            TomlValue::Integer(_) => "an integer",
+            TomlValue::Boolean(_) => "a boolean",
+            TomlValue::Array(_) => "an array",
+        }
+    }
+}
+
+impl Manifest {
+    /// Looks for a manifest in `directory` and then its ancestors
+    pub fn find(directory: &Path) -> Result<Option<Manifest>> {
+        for directory in directory.ancestors() {
+            let path = directory.join(MANIFEST_NAME);
+            if path.is_file() {
+                return Manifest::load(&path).map(Some);
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Looks for a manifest from the current directory up
+    pub fn find_from_current_dir() -> Result<Option<Manifest>> {
+        Manifest::find(&env::current_dir()?)
+    }
+
+    pub fn load(path: &Path) -> Result<Manifest> {
+        let source = read_source(path, false)?;
+        let root = path.parent().unwrap_or_else(|| Path::new("")).to_path_buf();
+
+        Manifest::parse(&source, root)
+            .map_err(|error| anyhow!("{}:{}", path.display(), error).into())
+    }
+
+    /// Parses the text of a manifest kept in `root`
+    pub fn parse(source: &str, root: PathBuf) -> Result<Manifest> {
+        let mut manifest = Manifest {
+            root,
+            ..Manifest::default()
+        };
+
+        let mut table = String::new();
+        for (index, line) in source.lines().enumerate() {
+            let number = index + 1;
+            let line = Self::strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(name) = line
+                .strip_prefix('[')
+                .and_then(|line| line.strip_suffix(']'))
+            {
+                table = name.trim().to_string();
+                if !matches!(table.as_str(), "project" | "lint" | "limits" | "natives") {
+                    return Err(anyhow!("{}: Unknown table '[{}]'.", number, table).into());
+                }
+
+                continue;
+            }
+
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| anyhow!("{}: Expected 'key = value'.", number))?;
+            let key = key.trim();
+            let value = Self::parse_value(value.trim())
+                .map_err(|message| anyhow!("{}: {}", number, message))?;
+
+            manifest
+                .set(&table, key, value)
+                .map_err(|message| anyhow!("{}: {}", number, message))?;
+        }
+
+        Ok(manifest)
+    }
+
+    /// The entry script, resolved against the manifest's directory
+    pub fn entry_path(&self) -> Option<PathBuf> {
+        self.entry.as_ref().map(|entry| self.root.join(entry))
+    }
+
+    /// The module search directories, resolved against the manifest's
+    /// directory
+    pub fn include_paths(&self) -> Vec<PathBuf> {
+        self.include
+            .iter()
+            .map(|path| self.root.join(path))
+            .collect()
+    }
+
+    /// Applies the lint settings and limits the manifest sets
+    pub fn configure(&self, config: &mut InterpreterConfig) {
+        if let Some(private_fields) = self.private_fields {
+            config.private_fields = private_fields;
+        }
+
+        if self.strict_equality.is_some() {
+            config.strict_equality = self.strict_equality;
+        }
+
+        if self.type_check.is_some() {
+            config.type_check = self.type_check;
+        }
+
+        let limits = &mut config.limits;
+        if let Some(max_call_depth) = self.max_call_depth {
+            limits.max_call_depth = max_call_depth;
+        }
+
+        if let Some(max_nesting_depth) = self.max_nesting_depth {
+            limits.max_nesting_depth = max_nesting_depth;
+        }
+
+        limits.max_loop_iterations = self.max_loop_iterations.or(limits.max_loop_iterations);
+        limits.max_string_length = self.max_string_length.or(limits.max_string_length);
+        limits.max_list_length = self.max_list_length.or(limits.max_list_length);
+        limits.max_environment_depth = self.max_environment_depth.or(limits.max_environment_depth);
+    }
+
+    fn set(&mut self, table: &str, key: &str, value: TomlValue) -> Result<(), String> {
+        match (table, key, value) {
+            ("project", "entry", TomlValue::String(entry)) => self.entry = Some(entry.into()),
+            ("project", "include", TomlValue::Array(paths)) => {
+                self.include = paths.into_iter().map(PathBuf::from).collect()
+            }
+            ("lint", "private-fields", TomlValue::Boolean(value)) => {
+                self.private_fields = Some(value)
+            }
+            ("lint", "strict-equality", TomlValue::String(severity)) => {
+                self.strict_equality = Some(severity.parse().map_err(|_| {
+                    format!(
+                        "Expected \"warning\" or \"error\" for 'strict-equality', got \"{}\".",
+                        severity
+                    )
+                })?)
+            }
+            ("lint", "type-check", TomlValue::String(severity)) => {
+                self.type_check = Some(severity.parse().map_err(|_| {
+                    format!(
+                        "Expected \"warning\" or \"error\" for 'type-check', got \"{}\".",
+                        severity
+                    )
+                })?)
+            }
+            ("lint", "max-arguments", TomlValue::Integer(value)) => {
+                self.max_arguments = Some(value as usize)
+            }
+            ("limits", "max-call-depth", TomlValue::Integer(value)) => {
+                self.max_call_depth = Some(value as usize)
+            }
+            ("limits", "max-loop-iterations", TomlValue::Integer(value)) => {
+                self.max_loop_iterations = Some(value)
+            }
+            ("limits", "max-string-length", TomlValue::Integer(value)) => {
+                self.max_string_length = Some(value as usize)
+            }
+            ("limits", "max-list-length", TomlValue::Integer(value)) => {
+                self.max_list_length = Some(value as usize)
+            }
+            ("limits", "max-environment-depth", TomlValue::Integer(value)) => {
+                self.max_environment_depth = Some(value as usize)
+            }
+            ("limits", "max-nesting-depth", TomlValue::Integer(value)) => {
+                self.max_nesting_depth = Some(value as usize)
+            }
+            ("natives", name, TomlValue::Boolean(enabled)) => {
+                self.disabled_natives.retain(|native| native != name);
+                if !enabled {
+                    self.disabled_natives.push(name.to_string());
+                }
+            }
+            ("", key, _) => return Err(format!("'{}' must be inside a table.", key)),
+            (table, key, value) => {
+                return Err(match Self::expected_type(table, key) {
+                    Some(expected) => format!(
+                        "Expected {} for '{}', got {}.",
+                        expected,
+                        key,
+                        value.type_name()
+                    ),
+                    None => format!("Unknown key '{}' in '[{}]'.", key, table),
+                })
+            }
+        }
+
+        Ok(())
+    }
+
+    fn expected_type(table: &str, key: &str) -> Option<&'static str> {
+        match (table, key) {
+            ("project", "entry") | ("lint", "strict-equality") => Some("a string"),
+            ("project", "include") => Some("an array of strings"),
+            ("lint", "private-fields") => Some("a boolean"),
+            ("lint", "max-arguments") => Some("an integer"),
+            ("limits", "max-call-depth" | "max-loop-iterations" | "max-string-length")
+            | ("limits", "max-list-length" | "max-environment-depth" | "max-nesting-depth") => {
+                Some("an integer")
+            }
+            ("natives", _) => Some("a boolean"),
+            _ => None,
+        }
+    }
+
+    fn parse_value(value: &str) -> Result<TomlValue, String> {
+        match value {
+            "true" => return Ok(TomlValue::Boolean(true)),
+            "false" => return Ok(TomlValue::Boolean(false)),
+            _ => {}
+        }
+
+        if value.starts_with('"') {
+            return Self::parse_string(value).map(TomlValue::String);
+        }
+
+        if let Some(elements) = value
+            .strip_prefix('[')
+            .and_then(|value| value.strip_suffix(']'))
+        {
+            return elements
+                .split(',')
+                .map(str::trim)
+                .filter(|element| !element.is_empty())
+                .map(Self::parse_string)
+                .collect::<Result<_, _>>()
+                .map(TomlValue::Array);
+        }
+
+        value
+            .replace('_', "")
+            .parse()
+            .map(TomlValue::Integer)
+            .map_err(|_| format!("Unsupported value '{}'.", value))
+    }
+
+    fn parse_string(value: &str) -> Result<String, String> {
+        value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .filter(|contents| !contents.contains('"'))
+            .map(|contents| contents.replace("\\\\", "\\"))
+            .ok_or_else(|| format!("Expected a string, got '{}'.", value))
+    }
+
+    /// Cuts a `#` comment off a line, unless the `#` is inside a string
+    fn strip_comment(line: &str) -> &str {
+        let mut in_string = false;
+        for (index, c) in line.char_indices() {
+            match c {
+                '"' => in_string = !in_string,
+                '#' if !in_string => return &line[..index],
+                _ => {}
+            }
+        }
+
+        line
+    }
+}
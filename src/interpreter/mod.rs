@@ -0,0 +1,51 @@
+//! Tree-walk interpreter, following Part 2 of Crafting Interpreters.
+//!
+//! [`Lox`] runs source through every phase and reports errors;
+//! the phases are also usable on their own.
+
+pub mod annotate;
+mod callable;
+mod coroutine;
+pub mod crash;
+pub mod diagnostic;
+pub mod doc_examples;
+mod environment;
+pub mod error;
+pub mod expr;
+mod fields;
+pub mod foreign;
+mod fork;
+pub mod formatter;
+pub mod heap;
+mod inline_cache;
+pub mod inputs;
+#[allow(clippy::module_inception)]
+pub mod interpreter;
+pub mod keywords;
+pub mod limits;
+mod lox;
+pub mod manifest;
+mod module;
+mod native_functions;
+pub mod observer;
+pub mod optimizer;
+pub mod output;
+pub mod parser;
+pub mod profile;
+pub mod rename;
+#[cfg(feature = "repl")]
+pub mod repl;
+pub mod resolver;
+pub mod rewriter;
+pub mod scanner;
+pub mod source;
+pub mod span;
+pub mod stmt;
+pub mod strictness;
+mod suggestion;
+pub mod token;
+pub mod token_kind;
+pub mod typechecker;
+pub mod value;
+
+pub use lox::Lox;
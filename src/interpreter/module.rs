@@ -0,0 +1,337 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    path::{Path, PathBuf},
+};
+
+use crate::interpreter::{
+    error::{LoxError, Result},
+    keywords::Keywords,
+    parser::{Parser, ParserConfig},
+    scanner::Scanner,
+    source::read_source,
+    stmt::Stmt,
+    token::Token,
+    value::{LoxInstance, Value},
+};
+
+/// The modules loaded by `import` statements, and what's needed to load more
+#[derive(Debug, Clone, Default)]
+pub struct Modules {
+    /// Syntax modules are scanned and parsed with, kept in step with the
+    /// `Lox` driver's settings
+    pub keywords: Keywords,
+    pub parser_config: ParserConfig,
+    /// File whose code is running, which relative imports start from
+    pub current_file: Option<PathBuf>,
+    /// Directories searched, in order, for imports that aren't found next to
+    /// the importing file
+    pub search_paths: Vec<PathBuf>,
+    /// Modules by canonical path
+    loaded: HashMap<PathBuf, LoxInstance>,
+    /// Modules whose top-level code is running, outermost first
+    loading: Vec<Loading>,
+}
+
+/// A module being loaded
+#[derive(Debug, Clone)]
+struct Loading {
+    file: PathBuf,
+    /// The path it was imported as
+    name: String,
+    /// Line of the import that loaded it
+    line: usize,
+}
+
+impl Modules {
+    /// A copy with the same settings that hasn't loaded any modules, for a
+    /// forked interpreter
+    pub fn fork(&self) -> Self {
+        Self {
+            loaded: HashMap::new(),
+            loading: vec![],
+            ..self.clone()
+        }
+    }
+
+    /// The file an import of `path` refers to from the current file, or from
+    /// the working directory outside of one
+    pub fn resolve(&self, path: &str) -> PathBuf {
+        self.resolve_from(self.current_file.as_deref(), path)
+    }
+
+    /// The file an import of `path` in `importer` refers to: the one next to
+    /// the importer if it exists, or else the first one found in the search
+    /// paths. When there's none, that's the one next to the importer.
+    fn resolve_from(&self, importer: Option<&Path>, path: &str) -> PathBuf {
+        let relative = match importer.and_then(Path::parent) {
+            Some(directory) => directory.join(path),
+            None => PathBuf::from(path),
+        };
+
+        if relative.is_file() || Path::new(path).is_absolute() {
+            return relative;
+        }
+
+        self.search_paths
+            .iter()
+            .map(|directory| directory.join(path))
+            .find(|candidate| candidate.is_file())
+            .unwrap_or(relative)
+    }
+
+    /// Explains that an import of `path` wasn't found
+    pub fn not_found_message(&self, path: &str) -> String {
+        match self.search_paths.len() {
+            0 => format!("Can't find module '{}'.", path),
+            _ => format!(
+                "Can't find module '{}', also searched in {}.",
+                path,
+                self.search_paths
+                    .iter()
+                    .map(|directory| format!("'{}'", directory.display()))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    /// The imports of the program in `entry` and, transitively, of the
+    /// modules it imports, as a graph in the DOT language. Imports of files
+    /// that can't be found are dashed, and files that can't be parsed are
+    /// shown without their imports.
+    pub fn import_graph(&self, entry: &Path) -> String {
+        let current_dir = env::current_dir().unwrap_or_default();
+        let label = |file: &Path| {
+            let file = canonical(file);
+            match file.strip_prefix(&current_dir) {
+                Ok(relative) => relative.display().to_string(),
+                Err(_) => file.display().to_string(),
+            }
+        };
+
+        let mut lines = vec!["digraph imports {".to_string()];
+        for (importer, imported) in self.imports(entry) {
+            let style = match imported.is_file() {
+                true => "",
+                false => " [style=dashed]",
+            };
+
+            lines.push(format!(
+                "    {:?} -> {:?}{};",
+                label(&importer),
+                label(&imported),
+                style
+            ));
+        }
+
+        lines.push("}".to_string());
+        lines.join("\n")
+    }
+
+    /// The files of the program in `entry` and of every module it imports,
+    /// directly or not, including imported files that don't exist yet
+    pub fn module_files(&self, entry: &Path) -> Vec<PathBuf> {
+        let mut files = vec![canonical(entry)];
+        for (_, imported) in self.imports(entry) {
+            let imported = canonical(&imported);
+            if !files.contains(&imported) {
+                files.push(imported);
+            }
+        }
+
+        files
+    }
+
+    /// Every import of the program in `entry` and, transitively, of the
+    /// modules it imports, as the importing and the imported file. Files that
+    /// can't be parsed are left without imports.
+    fn imports(&self, entry: &Path) -> Vec<(PathBuf, PathBuf)> {
+        let mut imports = vec![];
+        let mut visited = HashSet::new();
+        let mut pending = vec![entry.to_path_buf()];
+        while let Some(file) = pending.pop() {
+            if !visited.insert(canonical(&file)) {
+                continue;
+            }
+
+            let statements = match read_source(&file, false).map(|source| self.parse(source)) {
+                Ok(Ok(statements)) => statements,
+                _ => continue,
+            };
+
+            for path in Stmt::import_paths(&statements)
+                .into_iter()
+                .filter_map(|path| import_path(path).ok())
+            {
+                let imported = self.resolve_from(Some(&file), path);
+                if imported.is_file() {
+                    pending.push(imported.clone());
+                }
+
+                imports.push((file.clone(), imported));
+            }
+        }
+
+        imports
+    }
+
+    pub fn get(&self, path: &Path) -> Option<LoxInstance> {
+        self.loaded.get(path).cloned()
+    }
+
+    /// Marks the module at `file`, imported as `name` on `line`, as loading.
+    /// Fails with the chain of imports when it's already loading, since an
+    /// import cycle led back to it. Running it again would either recurse
+    /// forever or give its importers a half-initialized module.
+    pub fn start_loading(&mut self, file: PathBuf, name: &str, line: usize) -> Result<(), String> {
+        if let Some(start) = self.loading.iter().position(|loading| loading.file == file) {
+            let cycle = &self.loading[start..];
+            let steps: Vec<String> = cycle
+                .iter()
+                .enumerate()
+                .map(|(index, loading)| {
+                    let (imported, line) = match cycle.get(index + 1) {
+                        Some(next) => (next.name.as_str(), next.line),
+                        None => (name, line),
+                    };
+
+                    format!("'{}' line {} imports '{}'", loading.name, line, imported)
+                })
+                .collect();
+
+            return Err(format!("Circular import: {}.", steps.join(", ")));
+        }
+
+        self.loading.push(Loading {
+            file,
+            name: name.to_string(),
+            line,
+        });
+
+        Ok(())
+    }
+
+    /// Marks the innermost loading module as done, caching it unless it
+    /// failed
+    pub fn finish_loading(&mut self, module: Option<LoxInstance>) {
+        if let (Some(loading), Some(module)) = (self.loading.pop(), module) {
+            self.loaded.insert(loading.file, module);
+        }
+    }
+
+    /// Scans and parses a module's source, describing the first error when
+    /// it has any
+    pub fn parse(&self, source: String) -> Result<Vec<Stmt>, String> {
+        let tokens = Scanner::new(source)
+            .with_keywords(self.keywords.clone())
+            .scan_tokens()
+            .map_err(|error| describe_error(&error))?;
+
+        Parser::new(tokens, self.parser_config.clone())
+            .parse()
+            .map_err(|error| describe_error(&error))
+    }
+
+    /// Names exported by the module an import of `path` refers to, or `None`
+    /// when it can't be read or parsed, leaving that to be reported when the
+    /// import runs
+    pub fn exports(&self, path: &str) -> Option<Vec<String>> {
+        let source = read_source(&self.resolve(path), false).ok()?;
+        let statements = self.parse(source).ok()?;
+
+        Some(
+            Stmt::exported_names(&statements)
+                .into_iter()
+                .map(|name| name.lexeme.clone())
+                .collect(),
+        )
+    }
+}
+
+/// The canonical form of `file`, or `file` itself when it doesn't exist
+fn canonical(file: &Path) -> PathBuf {
+    file.canonicalize().unwrap_or_else(|_| file.to_path_buf())
+}
+
+/// The path written in an import statement
+pub fn import_path(path: &Token) -> Result<&str> {
+    match &path.literal {
+        Some(Value::String(path)) => Ok(path),
+        _ => Err(LoxError::internal("Import path isn't a string.")),
+    }
+}
+
+/// The first error found compiling a module, with the line it's on
+pub fn describe_error(error: &LoxError) -> String {
+    let first = match error {
+        LoxError::ScanningError { details, .. } => {
+            details.first().map(|detail| (detail.line, &detail.message))
+        }
+        LoxError::ParseError { details, .. } => details
+            .first()
+            .map(|detail| (detail.token.line, &detail.message)),
+        LoxError::ResolutionError(details) => details
+            .first()
+            .map(|detail| (detail.token.line, &detail.message)),
+        _ => None,
+    };
+
+    match first {
+        Some((line, message)) => format!("line {}: {}", line, message),
+        None => error.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn start(modules: &mut Modules, name: &str, line: usize) -> Result<(), String> {
+        modules.start_loading(PathBuf::from("/project").join(name), name, line)
+    }
+
+    #[test]
+    fn cycle_names_every_import_in_it() {
+        let mut modules = Modules::default();
+        start(&mut modules, "main.lox", 0).unwrap();
+        start(&mut modules, "a.lox", 2).unwrap();
+        start(&mut modules, "b.lox", 5).unwrap();
+        start(&mut modules, "c.lox", 1).unwrap();
+
+        // main.lox isn't part of the cycle, so it's left out
+        assert_eq!(
+            start(&mut modules, "a.lox", 7),
+            Err(
+                "Circular import: 'a.lox' line 5 imports 'b.lox', 'b.lox' line 1 imports 'c.lox', \
+                 'c.lox' line 7 imports 'a.lox'."
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn module_importing_itself_is_a_cycle() {
+        let mut modules = Modules::default();
+        start(&mut modules, "a.lox", 1).unwrap();
+
+        assert_eq!(
+            start(&mut modules, "a.lox", 3),
+            Err("Circular import: 'a.lox' line 3 imports 'a.lox'.".into())
+        );
+    }
+
+    #[test]
+    fn module_imported_twice_without_a_cycle_is_not_one() {
+        let mut modules = Modules::default();
+        start(&mut modules, "main.lox", 0).unwrap();
+        start(&mut modules, "a.lox", 1).unwrap();
+        start(&mut modules, "shared.lox", 1).unwrap();
+        modules.finish_loading(None);
+        modules.finish_loading(None);
+
+        // main.lox -> b.lox -> shared.lox is a diamond, not a cycle
+        start(&mut modules, "b.lox", 2).unwrap();
+        assert_eq!(start(&mut modules, "shared.lox", 1), Ok(()));
+    }
+}
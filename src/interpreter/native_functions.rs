@@ -1,23 +1,116 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "natives-introspection")]
+use std::collections::BTreeSet;
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, rc::Rc};
 
-use crate::{
+use crate::interpreter::{
     callable::Callable,
+    coroutine::{Coroutine, Resumed},
     error::{LoxError, Result},
     interpreter::Interpreter,
-    value::{LoxInstance, Value},
+    value::{LoxClass, LoxInstance, Value},
 };
 
+pub type NativeFn = fn(&mut Interpreter, &[Value]) -> Result<Value>;
+
+/// Native function backed by a plain Rust function
+#[derive(Debug, Clone)]
+pub struct NativeCallable {
+    arity: usize,
+    function: NativeFn,
+}
+
+impl NativeCallable {
+    pub fn new(arity: usize, function: NativeFn) -> Self {
+        Self { arity, function }
+    }
+}
+
+impl Callable for NativeCallable {
+    fn invoke(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+        (self.function)(interpreter, arguments)
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn bind(&self, _instance: &LoxInstance) -> Result<Value> {
+        Err(LoxError::NotBindableError)
+    }
+}
+
+pub type NativeMethodFn = fn(&mut Interpreter, &LoxInstance, &[Value]) -> Result<Value>;
+
+/// Method implemented in Rust that is bound to an instance like a Lox method,
+/// used to build native classes
+#[derive(Debug, Clone)]
+pub struct NativeMethod {
+    arity: usize,
+    function: NativeMethodFn,
+    receiver: Option<LoxInstance>,
+}
+
+impl NativeMethod {
+    pub fn new(arity: usize, function: NativeMethodFn) -> Self {
+        Self {
+            arity,
+            function,
+            receiver: None,
+        }
+    }
+}
+
+impl Callable for NativeMethod {
+    fn invoke(&self, interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+        match &self.receiver {
+            Some(receiver) => (self.function)(interpreter, receiver, arguments),
+            None => Err(LoxError::NotCallableError),
+        }
+    }
+
+    fn arity(&self) -> usize {
+        self.arity
+    }
+
+    fn bind(&self, instance: &LoxInstance) -> Result<Value> {
+        Ok(Value::NativeFunction(Box::new(Self {
+            receiver: Some(instance.clone()),
+            ..self.clone()
+        })))
+    }
+}
+
+/// The built-in `Error` class. Runtime errors are converted into instances of
+/// it when caught, and scripts can throw or subclass it.
+pub fn error_class() -> LoxClass {
+    let mut methods = HashMap::new();
+    methods.insert(
+        "init".to_string(),
+        Value::NativeFunction(Box::new(NativeMethod::new(1, error_init))),
+    );
+
+    LoxClass::new("Error".into(), methods, None)
+}
+
+fn error_init(
+    _interpreter: &mut Interpreter,
+    instance: &LoxInstance,
+    arguments: &[Value],
+) -> Result<Value> {
+    let mut instance = instance.clone();
+    instance.set_field("message", arguments[0].clone());
+    instance.set_field("kind", Value::String("Error".into()));
+    instance.set_field("line", Value::Nil);
+
+    Ok(Value::Nil)
+}
+
 #[derive(Debug, Clone)]
 pub struct ClockCallable;
 
 impl Callable for ClockCallable {
-    fn invoke(&self, _interpreter: &mut Interpreter, _arguments: &[Value]) -> Result<Value> {
-        let elapsed = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-
-        Ok(Value::Number(elapsed as f64))
+    fn invoke(&self, interpreter: &mut Interpreter, _arguments: &[Value]) -> Result<Value> {
+        interpreter.inputs_mut().clock()
     }
 
     fn arity(&self) -> usize {
@@ -28,3 +121,581 @@ impl Callable for ClockCallable {
         Err(LoxError::NotBindableError)
     }
 }
+
+/// The built-in `StringBuilder` class, which builds up strings in linear time
+/// rather than allocating a new string for every `+`:
+///
+/// ```text
+/// var builder = StringBuilder();
+/// builder.append("a").append(1);
+/// print builder.toString(); // a1
+/// ```
+pub fn string_builder_class() -> LoxClass {
+    let methods = [
+        ("init", NativeMethod::new(0, string_builder_init)),
+        ("append", NativeMethod::new(1, string_builder_append)),
+        ("length", NativeMethod::new(0, string_builder_length)),
+        ("toString", NativeMethod::new(0, string_builder_to_string)),
+    ]
+    .into_iter()
+    .map(|(name, method)| (name.to_string(), Value::NativeFunction(Box::new(method))))
+    .collect();
+
+    LoxClass::new("StringBuilder".into(), methods, None)
+}
+
+/// Class of the values `import "path" as name;` binds. A module's exports
+/// are its fields, and `members` lists their names:
+///
+/// ```text
+/// import "shapes.lox" as shapes;
+/// print shapes.members(); // [Circle, area]
+/// ```
+pub fn module_class() -> LoxClass {
+    let methods = [("members", NativeMethod::new(0, module_members))]
+        .into_iter()
+        .map(|(name, method)| (name.to_string(), Value::NativeFunction(Box::new(method))))
+        .collect();
+
+    LoxClass::new("Module".into(), methods, None)
+}
+
+fn module_members(
+    _interpreter: &mut Interpreter,
+    instance: &LoxInstance,
+    _arguments: &[Value],
+) -> Result<Value> {
+    let mut names: Vec<String> = instance
+        .fields()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+    names.sort();
+
+    Ok(names_list(names))
+}
+
+fn with_buffer<R>(instance: &LoxInstance, f: impl FnOnce(&mut String) -> R) -> Result<R> {
+    instance
+        .with_native_state(f)
+        .ok_or_else(|| LoxError::NativeError("StringBuilder was not initialized.".into()))
+}
+
+fn string_builder_init(
+    _interpreter: &mut Interpreter,
+    instance: &LoxInstance,
+    _arguments: &[Value],
+) -> Result<Value> {
+    instance.set_native_state(String::new());
+    Ok(Value::Nil)
+}
+
+fn string_builder_append(
+    interpreter: &mut Interpreter,
+    instance: &LoxInstance,
+    arguments: &[Value],
+) -> Result<Value> {
+    let value = match &arguments[0] {
+        Value::String(value) => value.clone(),
+        value => value.to_string(),
+    };
+
+    if interpreter.config.limits.max_string_length.is_some() {
+        let length = with_buffer(instance, |buffer| buffer.chars().count())?;
+        interpreter.check_string_length(length + value.chars().count(), None)?;
+    }
+
+    with_buffer(instance, |buffer| buffer.push_str(&value))?;
+
+    Ok(Value::Instance(instance.clone()))
+}
+
+fn string_builder_length(
+    _interpreter: &mut Interpreter,
+    instance: &LoxInstance,
+    _arguments: &[Value],
+) -> Result<Value> {
+    with_buffer(instance, |buffer| {
+        Value::Number(buffer.chars().count() as f64)
+    })
+}
+
+fn string_builder_to_string(
+    _interpreter: &mut Interpreter,
+    instance: &LoxInstance,
+    _arguments: &[Value],
+) -> Result<Value> {
+    with_buffer(instance, |buffer| Value::String(buffer.clone()))
+}
+
+/// `coroutine(fn)`, which wraps a function in a `Coroutine` without running
+/// it, and `resume(co, value)`, which runs it until it yields or returns:
+///
+/// ```text
+/// fun* counter(step) {
+///   var total = 0;
+///   while (true) step = yield total = total + step;
+/// }
+/// ```
+pub fn coroutine_natives() -> Vec<(&'static str, usize, NativeFn)> {
+    vec![("coroutine", 1, coroutine), ("resume", 2, resume)]
+}
+
+fn coroutine(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    let instance = LoxInstance::new(coroutine_class());
+    instance.set_native_state(Coroutine::new(arguments[0].clone())?);
+
+    Ok(Value::Instance(instance))
+}
+
+fn resume(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    match &arguments[0] {
+        Value::Instance(instance) if instance.borrow().class().name == "Coroutine" => {
+            match Coroutine::resume(interpreter, instance, arguments[1].clone())? {
+                Resumed::Yielded(value) | Resumed::Returned(value) => Ok(value),
+            }
+        }
+        _ => Err(LoxError::NativeError("Can only resume coroutines.".into())),
+    }
+}
+
+/// Class of the values `coroutine` returns, whose `done` method tells if the
+/// function has returned
+fn coroutine_class() -> LoxClass {
+    let methods = [("done", NativeMethod::new(0, coroutine_done))]
+        .into_iter()
+        .map(|(name, method)| (name.to_string(), Value::NativeFunction(Box::new(method))))
+        .collect();
+
+    LoxClass::new("Coroutine".into(), methods, None)
+}
+
+fn coroutine_done(
+    _interpreter: &mut Interpreter,
+    instance: &LoxInstance,
+    _arguments: &[Value],
+) -> Result<Value> {
+    instance
+        .with_native_state(|coroutine: &mut Coroutine| Value::Boolean(coroutine.is_finished()))
+        .ok_or_else(|| LoxError::NativeError("Coroutine was not initialized.".into()))
+}
+
+/// State of a `Generator`: the coroutine running the generator function's
+/// call, and the value it yielded that `next` hasn't returned yet
+#[derive(Debug)]
+struct Generator {
+    coroutine: LoxInstance,
+    next: Option<Value>,
+}
+
+/// What a call of the generator `function` returns: a `Generator` that runs
+/// the function's body a step at a time, as its values are asked for. It
+/// follows the iterator protocol, so for-in loops can consume it:
+///
+/// ```text
+/// fun* naturals() {
+///   var n = 0;
+///   while (true) { yield n; n = n + 1; }
+/// }
+/// ```
+pub fn new_generator(function: Value, arguments: &[Value]) -> Result<Value> {
+    let coroutine = LoxInstance::new(coroutine_class());
+    coroutine.set_native_state(Coroutine::call(function, arguments)?);
+
+    let methods = [
+        ("done", NativeMethod::new(0, generator_done)),
+        ("next", NativeMethod::new(0, generator_next)),
+        ("iterate", NativeMethod::new(0, generator_iterate)),
+    ]
+    .into_iter()
+    .map(|(name, method)| (name.to_string(), Value::NativeFunction(Box::new(method))))
+    .collect();
+
+    let generator = LoxInstance::new(LoxClass::new("Generator".into(), methods, None));
+    generator.set_native_state(Generator {
+        coroutine,
+        next: None,
+    });
+
+    Ok(Value::Instance(generator))
+}
+
+fn with_generator<R>(instance: &LoxInstance, f: impl FnOnce(&mut Generator) -> R) -> Result<R> {
+    instance
+        .with_native_state(f)
+        .ok_or_else(|| LoxError::NativeError("Generator was not initialized.".into()))
+}
+
+/// Runs the generator to its next `yield`, unless it's already stopped at
+/// one whose value hasn't been taken, returning whether there's a value
+fn generator_advance(interpreter: &mut Interpreter, instance: &LoxInstance) -> Result<bool> {
+    let (coroutine, has_next) = with_generator(instance, |generator| {
+        (generator.coroutine.clone(), generator.next.is_some())
+    })?;
+    let finished = coroutine
+        .with_native_state(|coroutine: &mut Coroutine| coroutine.is_finished())
+        .unwrap_or(true);
+
+    if has_next || finished {
+        return Ok(has_next);
+    }
+
+    match Coroutine::resume(interpreter, &coroutine, Value::Nil)? {
+        Resumed::Yielded(value) => {
+            with_generator(instance, |generator| generator.next = Some(value))?;
+            Ok(true)
+        }
+        Resumed::Returned(_) => Ok(false),
+    }
+}
+
+fn generator_done(
+    interpreter: &mut Interpreter,
+    instance: &LoxInstance,
+    _arguments: &[Value],
+) -> Result<Value> {
+    generator_advance(interpreter, instance).map(|has_next| Value::Boolean(!has_next))
+}
+
+fn generator_next(
+    interpreter: &mut Interpreter,
+    instance: &LoxInstance,
+    _arguments: &[Value],
+) -> Result<Value> {
+    generator_advance(interpreter, instance)?;
+    with_generator(instance, |generator| generator.next.take())?
+        .ok_or_else(|| LoxError::NativeError("Generator has no more values.".into()))
+}
+
+fn generator_iterate(
+    _interpreter: &mut Interpreter,
+    instance: &LoxInstance,
+    _arguments: &[Value],
+) -> Result<Value> {
+    Ok(Value::Instance(instance.clone()))
+}
+
+/// `globals()` and `locals()`, listing the names of the variables defined in
+/// the global scope or the local scopes enclosing the call
+#[cfg(feature = "natives-introspection")]
+pub fn introspection_natives() -> Vec<(&'static str, usize, NativeFn)> {
+    vec![("globals", 0, globals), ("locals", 0, locals)]
+}
+
+fn names_list(names: impl IntoIterator<Item = String>) -> Value {
+    new_list(names.into_iter().map(Value::String).collect())
+}
+
+#[cfg(feature = "natives-introspection")]
+fn globals(interpreter: &mut Interpreter, _arguments: &[Value]) -> Result<Value> {
+    let bindings = interpreter.globals.borrow().bindings();
+    Ok(names_list(bindings.into_iter().map(|(name, _)| name)))
+}
+
+/// Functions only keep the variables they capture from enclosing scopes, so
+/// inside one this lists its own locals and the captured ones
+#[cfg(feature = "natives-introspection")]
+fn locals(interpreter: &mut Interpreter, _arguments: &[Value]) -> Result<Value> {
+    if !interpreter.config.debug {
+        return Err(LoxError::NativeError(
+            "locals() is only available in debug mode.".into(),
+        ));
+    }
+
+    let mut names = BTreeSet::new();
+    let mut environment = interpreter.environment.clone();
+    while !Rc::ptr_eq(&environment, &interpreter.globals) {
+        names.extend(
+            environment
+                .borrow()
+                .bindings()
+                .into_iter()
+                .map(|(name, _)| name),
+        );
+
+        let enclosing = environment.borrow().enclosing.clone();
+        match enclosing {
+            Some(enclosing) => environment = enclosing,
+            None => break,
+        }
+    }
+
+    Ok(names_list(names))
+}
+
+/// Seed used until a script calls `seedRandom`, so runs are reproducible by
+/// default
+#[cfg(feature = "natives-random")]
+const DEFAULT_SEED: u64 = 0x5eed;
+
+/// SplitMix64 generator behind `random`. It's implemented here rather than
+/// taken from the OS so a seed gives the same numbers on every platform.
+#[cfg(feature = "natives-random")]
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: u64,
+}
+
+#[cfg(feature = "natives-random")]
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn seed(&mut self, seed: u64) {
+        self.state = seed;
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `[0, 1)`
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(feature = "natives-random")]
+impl Default for Random {
+    fn default() -> Self {
+        Self::new(DEFAULT_SEED)
+    }
+}
+
+/// `random()`, returning a number in `[0, 1)`, and `seedRandom(n)`, which
+/// restarts the sequence `random` follows
+#[cfg(feature = "natives-random")]
+pub fn random_natives() -> Vec<(&'static str, usize, NativeFn)> {
+    vec![("random", 0, random), ("seedRandom", 1, seed_random)]
+}
+
+#[cfg(feature = "natives-random")]
+fn random(interpreter: &mut Interpreter, _arguments: &[Value]) -> Result<Value> {
+    interpreter.next_random()
+}
+
+#[cfg(feature = "natives-random")]
+fn seed_random(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    match &arguments[0] {
+        Value::Number(seed) => {
+            interpreter.random_mut().seed(seed.to_bits());
+            Ok(Value::Nil)
+        }
+        _ => Err(LoxError::NativeError(
+            "seedRandom() expects a number.".into(),
+        )),
+    }
+}
+
+/// `isNan(n)` and `isFinite(n)`, which tell the results of dividing by zero
+/// apart from other numbers
+pub fn number_natives() -> Vec<(&'static str, usize, NativeFn)> {
+    vec![("isNan", 1, is_nan), ("isFinite", 1, is_finite)]
+}
+
+fn number_argument(native: &str, value: &Value) -> Result<f64> {
+    match value {
+        Value::Number(number) => Ok(*number),
+        _ => Err(LoxError::NativeError(format!(
+            "{}() expects a number.",
+            native
+        ))),
+    }
+}
+
+fn is_nan(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    Ok(Value::Boolean(
+        number_argument("isNan", &arguments[0])?.is_nan(),
+    ))
+}
+
+fn is_finite(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    Ok(Value::Boolean(
+        number_argument("isFinite", &arguments[0])?.is_finite(),
+    ))
+}
+
+/// `readLine()`, returning the next line of stdin without its line ending, or
+/// `nil` once it's exhausted
+#[cfg(feature = "natives-io")]
+pub fn input_natives() -> Vec<(&'static str, usize, NativeFn)> {
+    vec![("readLine", 0, read_line)]
+}
+
+#[cfg(feature = "natives-io")]
+fn read_line(interpreter: &mut Interpreter, _arguments: &[Value]) -> Result<Value> {
+    // So a prompt printed before shows up while waiting
+    interpreter.flush_output()?;
+    interpreter.inputs_mut().read_line()
+}
+
+/// `flush()`, writing out `print` output waiting in a buffer
+#[cfg(feature = "natives-io")]
+pub fn output_natives() -> Vec<(&'static str, usize, NativeFn)> {
+    vec![("flush", 0, flush)]
+}
+
+#[cfg(feature = "natives-io")]
+fn flush(interpreter: &mut Interpreter, _arguments: &[Value]) -> Result<Value> {
+    interpreter.flush_output()?;
+    Ok(Value::Nil)
+}
+
+/// Natives of every group, leaving out those whose feature is off
+pub fn stdlib_natives() -> Vec<(&'static str, usize, NativeFn)> {
+    let mut natives = list_natives();
+    #[cfg(feature = "natives-random")]
+    natives.extend(random_natives());
+    natives.extend(number_natives());
+    #[cfg(feature = "natives-io")]
+    {
+        natives.extend(input_natives());
+        natives.extend(output_natives());
+    }
+    #[cfg(feature = "natives-introspection")]
+    natives.extend(introspection_natives());
+    natives.extend(coroutine_natives());
+
+    natives
+}
+
+/// List natives: `len` and `push`, and the higher-order `map`, `filter`,
+/// `reduce` and `sort`
+pub fn list_natives() -> Vec<(&'static str, usize, NativeFn)> {
+    vec![
+        ("len", 1, len),
+        ("push", 2, push),
+        ("map", 2, map),
+        ("filter", 2, filter),
+        ("reduce", 3, reduce),
+        ("sort", 2, sort),
+    ]
+}
+
+/// Copies the elements out of a list argument so callbacks are free to modify
+/// the original list
+fn list_argument(native: &str, value: &Value) -> Result<Vec<Value>> {
+    match value {
+        Value::List(values) => Ok(values.borrow().clone()),
+        _ => Err(LoxError::NativeError(format!(
+            "{}() expects a list as its first argument.",
+            native
+        ))),
+    }
+}
+
+fn new_list(values: Vec<Value>) -> Value {
+    Value::List(Rc::new(RefCell::new(values)))
+}
+
+fn len(_interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    match &arguments[0] {
+        Value::List(values) => Ok(Value::Number(values.borrow().len() as f64)),
+        Value::String(value) => Ok(Value::Number(value.chars().count() as f64)),
+        _ => Err(LoxError::NativeError(
+            "len() expects a list or a string.".into(),
+        )),
+    }
+}
+
+/// Appends to the list in place
+fn push(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    match &arguments[0] {
+        Value::List(values) => {
+            interpreter.check_list_length(values.borrow().len() + 1, None)?;
+            values.borrow_mut().push(arguments[1].clone());
+            Ok(Value::Nil)
+        }
+        _ => Err(LoxError::NativeError(
+            "push() expects a list as its first argument.".into(),
+        )),
+    }
+}
+
+fn map(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    let values = list_argument("map", &arguments[0])?
+        .into_iter()
+        .map(|value| interpreter.call_value(&arguments[1], &[value]))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(new_list(values))
+}
+
+fn filter(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    let mut values = vec![];
+    for value in list_argument("filter", &arguments[0])? {
+        if interpreter
+            .call_value(&arguments[1], std::slice::from_ref(&value))?
+            .is_truthy()
+        {
+            values.push(value);
+        }
+    }
+
+    Ok(new_list(values))
+}
+
+fn reduce(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    list_argument("reduce", &arguments[0])?
+        .into_iter()
+        .try_fold(arguments[2].clone(), |accumulator, value| {
+            interpreter.call_value(&arguments[1], &[accumulator, value])
+        })
+}
+
+/// Sorts a copy of the list. The comparator returns a negative number, zero or
+/// a positive number when its first argument sorts before, equal to or after
+/// the second.
+///
+/// `slice::sort_by` panics when its comparator isn't a total order, which a
+/// Lox comparator doesn't have to be, so this is a merge sort of its own. An
+/// inconsistent comparator gives some order of the elements, and an error from
+/// it stops the sort.
+fn sort(interpreter: &mut Interpreter, arguments: &[Value]) -> Result<Value> {
+    let values = list_argument("sort", &arguments[0])?;
+    let mut compare = |a: &Value, b: &Value| -> Result<Ordering> {
+        match interpreter.call_value(&arguments[1], &[a.clone(), b.clone()])? {
+            Value::Number(order) => Ok(order.partial_cmp(&0.0).unwrap_or(Ordering::Equal)),
+            _ => Err(LoxError::NativeError(
+                "sort() comparator must return a number.".into(),
+            )),
+        }
+    };
+
+    Ok(new_list(merge_sort(values, &mut compare)?))
+}
+
+/// Stable merge sort, calling `compare` once per comparison
+fn merge_sort(
+    mut values: Vec<Value>,
+    compare: &mut impl FnMut(&Value, &Value) -> Result<Ordering>,
+) -> Result<Vec<Value>> {
+    if values.len() <= 1 {
+        return Ok(values);
+    }
+
+    let right = values.split_off(values.len() / 2);
+    let left = merge_sort(values, compare)?;
+    let right = merge_sort(right, compare)?;
+
+    let mut merged = Vec::with_capacity(left.len() + right.len());
+    let mut left = left.into_iter().peekable();
+    let mut right = right.into_iter().peekable();
+    while let (Some(a), Some(b)) = (left.peek(), right.peek()) {
+        // Taking from the left on ties keeps equal elements in order
+        let next = match compare(a, b)? {
+            Ordering::Greater => right.next(),
+            _ => left.next(),
+        };
+        merged.extend(next);
+    }
+    merged.extend(left);
+    merged.extend(right);
+
+    Ok(merged)
+}
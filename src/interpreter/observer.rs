@@ -0,0 +1,86 @@
+use std::{
+    fmt::{self, Display},
+    time::{Duration, Instant},
+};
+
+/// A step of the pipeline `Lox` runs source through
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Scanning,
+    Parsing,
+    TypeChecking,
+    Optimizing,
+    Resolving,
+    Interpreting,
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Phase::Scanning => "scanning",
+            Phase::Parsing => "parsing",
+            Phase::TypeChecking => "typechecking",
+            Phase::Optimizing => "optimizing",
+            Phase::Resolving => "resolving",
+            Phase::Interpreting => "interpreting",
+        };
+
+        write!(f, "{}", name)
+    }
+}
+
+impl Phase {
+    /// What the count passed to `PhaseObserver::phase_finished` measures
+    pub fn count_label(&self) -> &'static str {
+        match self {
+            Phase::Scanning => "tokens",
+            Phase::Parsing | Phase::TypeChecking | Phase::Optimizing | Phase::Interpreting => {
+                "statements"
+            }
+            Phase::Resolving => "resolved locals",
+        }
+    }
+}
+
+/// Instrumentation hooks called by `Lox` around every phase of a run.
+///
+/// The count is the number of tokens scanned, top-level statements parsed,
+/// type checked, optimized or interpreted, or local variable references resolved.
+pub trait PhaseObserver {
+    fn phase_started(&mut self, _phase: Phase) {}
+
+    fn phase_finished(&mut self, _phase: Phase, _count: usize) {}
+}
+
+/// Prints how long each phase took to stderr as it finishes
+#[derive(Debug, Default)]
+pub struct PhaseTimings {
+    started: Option<Instant>,
+}
+
+impl PhaseTimings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl PhaseObserver for PhaseTimings {
+    fn phase_started(&mut self, _phase: Phase) {
+        self.started = Some(Instant::now());
+    }
+
+    fn phase_finished(&mut self, phase: Phase, count: usize) {
+        let elapsed = self
+            .started
+            .take()
+            .map_or(Duration::ZERO, |started| started.elapsed());
+
+        eprintln!(
+            "{:<12} {:>10.2?}  {} {}",
+            phase.to_string(),
+            elapsed,
+            count,
+            phase.count_label()
+        );
+    }
+}
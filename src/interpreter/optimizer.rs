@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+
+use crate::interpreter::{
+    expr::{Expr, Pattern},
+    stmt::Stmt,
+    token::Token,
+};
+
+/// Largest number of expression nodes a function body can have to be inlined
+const MAX_INLINE_SIZE: usize = 12;
+
+/// A top-level function whose body is a single `return` of `body`
+struct InlineFunction {
+    parameters: Vec<Token>,
+    body: Expr,
+}
+
+/// Rewrites the AST before resolution when optimizations are enabled.
+///
+/// Calls to tiny top-level functions like `fun square(x) { return x * x; }`
+/// are replaced with the returned expression. A function is only inlined when
+/// its name is declared once and never assigned, the body only uses its
+/// parameters, literals and operators, and every argument at the call site is
+/// a literal or variable, so evaluating the inlined expression can't differ
+/// from calling the function.
+pub struct Optimizer {
+    /// How many times each name is declared or assigned in the program
+    bindings: HashMap<String, usize>,
+    inlinable: HashMap<String, InlineFunction>,
+}
+
+impl Optimizer {
+    pub fn new() -> Self {
+        Self {
+            bindings: HashMap::new(),
+            inlinable: HashMap::new(),
+        }
+    }
+
+    pub fn optimize(mut self, statements: Vec<Stmt>) -> Vec<Stmt> {
+        for statement in &statements {
+            self.count_statement(statement);
+        }
+
+        // Calls are only inlined after the declaration, so calling a function
+        // before it's defined still fails at runtime
+        statements
+            .into_iter()
+            .map(|mut statement| {
+                self.statement(&mut statement);
+                self.register_inlinable(&statement);
+                statement
+            })
+            .collect()
+    }
+
+    fn register_inlinable(&mut self, statement: &Stmt) {
+        let (name, parameters, body) = match statement {
+            Stmt::Function(name, parameters, body, false, _) => (name, parameters, body),
+            _ => return,
+        };
+
+        let value = match body.as_slice() {
+            [Stmt::Return(_, Some(value))] => value,
+            _ => return,
+        };
+
+        let is_inlinable = self.bindings.get(&name.lexeme) == Some(&1)
+            && Self::size(value, parameters) <= MAX_INLINE_SIZE
+            && parameters
+                .iter()
+                .all(|parameter| Self::uses(value, &parameter.lexeme));
+
+        if is_inlinable {
+            self.inlinable.insert(
+                name.lexeme.clone(),
+                InlineFunction {
+                    parameters: parameters.clone(),
+                    body: value.clone(),
+                },
+            );
+        }
+    }
+
+    /// Number of nodes in an inlinable expression, or `usize::MAX` when it
+    /// contains anything other than operators, literals and `parameters`
+    fn size(expr: &Expr, parameters: &[Token]) -> usize {
+        match expr {
+            Expr::Literal(_) => 1,
+            Expr::Variable(name) => match parameters.iter().any(|p| p.lexeme == name.lexeme) {
+                true => 1,
+                false => usize::MAX,
+            },
+            Expr::Grouping(expr) | Expr::Unary(_, expr) => {
+                Self::size(expr, parameters).saturating_add(1)
+            }
+            Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+                Self::size(left, parameters)
+                    .saturating_add(Self::size(right, parameters))
+                    .saturating_add(1)
+            }
+            _ => usize::MAX,
+        }
+    }
+
+    fn uses(expr: &Expr, name: &str) -> bool {
+        match expr {
+            Expr::Variable(variable) => variable.lexeme == name,
+            Expr::Grouping(expr) | Expr::Unary(_, expr) => Self::uses(expr, name),
+            Expr::Binary(left, _, right) | Expr::Logical(left, _, right) => {
+                Self::uses(left, name) || Self::uses(right, name)
+            }
+            _ => false,
+        }
+    }
+
+    /// Copies the function body with its parameters replaced by `arguments`.
+    /// Every token gets a fresh id so each inlined copy is resolved and cached
+    /// on its own. `None` when the body has anything but operators, literals
+    /// and parameters, which `size` already rules out.
+    fn substitute(expr: &Expr, parameters: &[Token], arguments: &[Expr]) -> Option<Expr> {
+        let substitute =
+            |expr: &Expr| Some(Box::new(Self::substitute(expr, parameters, arguments)?));
+
+        Some(match expr {
+            Expr::Variable(name) => {
+                let index = parameters
+                    .iter()
+                    .position(|parameter| parameter.lexeme == name.lexeme)?;
+
+                Self::renumber(arguments.get(index)?)
+            }
+            Expr::Literal(value) => Expr::Literal(value.clone()),
+            Expr::Grouping(expr) => Expr::Grouping(substitute(expr)?),
+            Expr::Unary(operator, expr) => Expr::Unary(operator.renumbered(), substitute(expr)?),
+            Expr::Binary(left, operator, right) => {
+                Expr::Binary(substitute(left)?, operator.renumbered(), substitute(right)?)
+            }
+            Expr::Logical(left, operator, right) => {
+                Expr::Logical(substitute(left)?, operator.renumbered(), substitute(right)?)
+            }
+            _ => return None,
+        })
+    }
+
+    fn renumber(argument: &Expr) -> Expr {
+        match argument {
+            Expr::Variable(name) => Expr::Variable(name.renumbered()),
+            argument => argument.clone(),
+        }
+    }
+
+    fn inline_call(&self, callee: &Expr, arguments: &[Expr]) -> Option<Expr> {
+        let function = match callee {
+            Expr::Variable(name) => self.inlinable.get(&name.lexeme)?,
+            _ => return None,
+        };
+
+        let is_simple = arguments
+            .iter()
+            .all(|argument| matches!(argument, Expr::Literal(_) | Expr::Variable(_)));
+
+        match is_simple && arguments.len() == function.parameters.len() {
+            true => Some(Expr::Grouping(Box::new(Self::substitute(
+                &function.body,
+                &function.parameters,
+                arguments,
+            )?))),
+            false => None,
+        }
+    }
+
+    fn bind(&mut self, name: &Token) {
+        *self.bindings.entry(name.lexeme.clone()).or_default() += 1;
+    }
+
+    fn count_statements(&mut self, statements: &[Stmt]) {
+        for statement in statements {
+            self.count_statement(statement);
+        }
+    }
+
+    fn count_statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Expression(expr)
+            | Stmt::Print(_, expr)
+            | Stmt::Throw(_, expr)
+            | Stmt::Delete(expr, _) => self.count_expression(expr),
+            Stmt::Var(name, initializer, _) => {
+                self.bind(name);
+                if let Some(initializer) = initializer {
+                    self.count_expression(initializer);
+                }
+            }
+            Stmt::VarDestructure(_, names, initializer) => {
+                names.iter().for_each(|name| self.bind(name));
+                self.count_expression(initializer);
+            }
+            Stmt::Block(statements) => self.count_statements(statements),
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.count_expression(condition);
+                self.count_statement(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.count_statement(else_branch);
+                }
+            }
+            Stmt::While(condition, body, _) => {
+                self.count_expression(condition);
+                self.count_statement(body);
+            }
+            Stmt::ForIn(name, iterable, body) => {
+                self.bind(name);
+                self.count_expression(iterable);
+                self.count_statement(body);
+            }
+            Stmt::Function(name, parameters, body, ..) => {
+                self.bind(name);
+                parameters.iter().for_each(|parameter| self.bind(parameter));
+                self.count_statements(body);
+            }
+            Stmt::Return(_, value) => {
+                if let Some(value) = value {
+                    self.count_expression(value);
+                }
+            }
+            Stmt::Class(name, superclass, mixins, methods) => {
+                self.bind(name);
+                if let Some(superclass) = superclass {
+                    self.count_expression(superclass);
+                }
+                for mixin in mixins {
+                    self.count_expression(mixin);
+                }
+                self.count_statements(methods);
+            }
+            Stmt::Try(body, catch, finally) => {
+                self.count_statements(body);
+                if let Some(catch) = catch {
+                    self.bind(&catch.name);
+                    self.count_statements(&catch.body);
+                }
+                if let Some(finally) = finally {
+                    self.count_statements(finally);
+                }
+            }
+            Stmt::Import(..) => statement
+                .declared_names()
+                .into_iter()
+                .for_each(|name| self.bind(name)),
+            Stmt::Export(_, declaration) => self.count_statement(declaration),
+        }
+    }
+
+    fn count_expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Literal(_) | Expr::Variable(_) | Expr::This(_) | Expr::Super(..) => {}
+            Expr::Grouping(expr) | Expr::Unary(_, expr) | Expr::Get(expr, _) => {
+                self.count_expression(expr)
+            }
+            Expr::Binary(left, _, right)
+            | Expr::Logical(left, _, right)
+            | Expr::Set(left, _, right) => {
+                self.count_expression(left);
+                self.count_expression(right);
+            }
+            Expr::Assign(name, value) => {
+                self.bind(name);
+                self.count_expression(value);
+            }
+            Expr::Call(callee, arguments, ..) => {
+                self.count_expression(callee);
+                arguments
+                    .iter()
+                    .for_each(|argument| self.count_expression(argument));
+            }
+            Expr::Match(_, subject, arms) => {
+                self.count_expression(subject);
+                for arm in arms {
+                    if let Pattern::Binding(name) = &arm.pattern {
+                        self.bind(name);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.count_expression(guard);
+                    }
+                    self.count_expression(&arm.body);
+                }
+            }
+            Expr::List(_, elements) => elements
+                .iter()
+                .for_each(|element| self.count_expression(element)),
+            Expr::Destructure(_, names, value) => {
+                names.iter().for_each(|name| self.bind(name));
+                self.count_expression(value);
+            }
+            Expr::Yield(_, value) => {
+                if let Some(value) = value {
+                    self.count_expression(value);
+                }
+            }
+        }
+    }
+
+    fn statements(&mut self, statements: &mut [Stmt]) {
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn statement(&mut self, statement: &mut Stmt) {
+        match statement {
+            Stmt::Expression(expr)
+            | Stmt::Print(_, expr)
+            | Stmt::VarDestructure(_, _, expr)
+            | Stmt::Throw(_, expr)
+            | Stmt::Delete(expr, _) => self.expression(expr),
+            Stmt::Var(_, initializer, _) | Stmt::Return(_, initializer) => {
+                if let Some(expr) = initializer {
+                    self.expression(expr);
+                }
+            }
+            Stmt::Block(statements) | Stmt::Function(_, _, statements, ..) => {
+                self.statements(statements)
+            }
+            Stmt::If(condition, then_branch, else_branch) => {
+                self.expression(condition);
+                self.statement(then_branch);
+                if let Some(branch) = else_branch {
+                    self.statement(branch);
+                }
+            }
+            Stmt::While(condition, body, _) | Stmt::ForIn(_, condition, body) => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            Stmt::Class(_, superclass, mixins, methods) => {
+                if let Some(expr) = superclass {
+                    self.expression(expr);
+                }
+                for mixin in mixins {
+                    self.expression(mixin);
+                }
+                self.statements(methods);
+            }
+            Stmt::Try(body, catch, finally) => {
+                self.statements(body);
+                if let Some(catch) = catch {
+                    self.statements(&mut catch.body);
+                }
+                if let Some(finally) = finally {
+                    self.statements(finally);
+                }
+            }
+            Stmt::Import(..) => {}
+            Stmt::Export(_, declaration) => self.statement(declaration),
+        }
+    }
+
+    fn expression(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Literal(_) | Expr::Variable(_) | Expr::This(_) | Expr::Super(..) => {}
+            Expr::Grouping(expr)
+            | Expr::Unary(_, expr)
+            | Expr::Assign(_, expr)
+            | Expr::Get(expr, _)
+            | Expr::Destructure(_, _, expr) => self.expression(expr),
+            Expr::Binary(left, _, right)
+            | Expr::Logical(left, _, right)
+            | Expr::Set(left, _, right) => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Call(callee, arguments, ..) => {
+                for argument in arguments.iter_mut() {
+                    self.expression(argument);
+                }
+
+                match self.inline_call(callee, arguments) {
+                    Some(inlined) => *expr = inlined,
+                    None => self.expression(callee),
+                }
+            }
+            Expr::Match(_, subject, arms) => {
+                self.expression(subject);
+                for arm in arms {
+                    if let Some(guard) = &mut arm.guard {
+                        self.expression(guard);
+                    }
+                    self.expression(&mut arm.body);
+                }
+            }
+            Expr::List(_, elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+            }
+            Expr::Yield(_, value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,132 @@
+//! Writers for the output of `print` statements, see
+//! [`Interpreter::set_output`](crate::interpreter::interpreter::Interpreter::set_output).
+
+use std::{
+    cell::RefCell,
+    io::{self, BufWriter, Write},
+    rc::Rc,
+};
+
+/// How the output of `print` statements is buffered on its way to stdout.
+///
+/// Buffered output is written out when the buffer fills up, when a script
+/// calls `flush()`, before `readLine()` waits for input and at the end of
+/// every run, so each line of the REPL shows its output straight away.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Buffering {
+    /// Writes every line out as it's printed
+    Line,
+    /// Collects up to this many bytes before writing them out
+    Block(usize),
+}
+
+impl Default for Buffering {
+    fn default() -> Self {
+        Buffering::Block(8 * 1024)
+    }
+}
+
+impl Buffering {
+    /// A writer to stdout buffered this way
+    pub fn stdout(&self) -> Box<dyn Write> {
+        match self {
+            Buffering::Line => Box::new(io::stdout()),
+            Buffering::Block(capacity) => {
+                Box::new(BufWriter::with_capacity(*capacity, io::stdout()))
+            }
+        }
+    }
+}
+
+/// An in-memory writer whose clones share the same buffer, so one clone can
+/// be handed to the interpreter and another used to read what it printed
+#[derive(Debug, Clone, Default)]
+pub struct CaptureBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl CaptureBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Everything written so far, with invalid UTF-8 replaced
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for CaptureBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes everything to two writers
+pub struct Tee<A: Write, B: Write>(pub A, pub B);
+
+impl<A: Write, B: Write> Write for Tee<A, B> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write_all(buf)?;
+        self.1.write_all(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()?;
+        self.1.flush()
+    }
+}
+
+/// Shares the writer being replaced during a capture, so it can be both
+/// teed to and given back afterwards
+struct Shared(Rc<RefCell<Box<dyn Write>>>);
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// Redirects `output` into a buffer until `finish` puts the original writer
+/// back. With `tee` the original writer keeps receiving the output too.
+pub(crate) struct Capture {
+    buffer: CaptureBuffer,
+    original: Rc<RefCell<Box<dyn Write>>>,
+}
+
+impl Capture {
+    pub fn start(output: &mut Box<dyn Write>, tee: bool) -> Self {
+        let buffer = CaptureBuffer::new();
+        let original = std::mem::replace(output, Box::new(io::sink()));
+        let original = Rc::new(RefCell::new(original));
+
+        *output = match tee {
+            true => Box::new(Tee(Shared(original.clone()), buffer.clone())),
+            false => Box::new(buffer.clone()),
+        };
+
+        Self { buffer, original }
+    }
+
+    pub fn finish(self, output: &mut Box<dyn Write>) -> String {
+        // Drops the tee's handle to the original writer
+        *output = Box::new(io::sink());
+
+        let original = match Rc::try_unwrap(self.original) {
+            Ok(original) => original.into_inner(),
+            Err(_) => Box::new(io::stdout()),
+        };
+        *output = original;
+        let _ = output.flush();
+
+        self.buffer.contents()
+    }
+}
@@ -1,76 +1,145 @@
-use crate::{
+use crate::interpreter::{
+    diagnostic::Severity,
     error::{LoxError, ParserErrorDetails, Result},
-    expr::Expr,
-    stmt::Stmt,
+    expr::{Expr, MatchArm, Pattern},
+    limits::DEFAULT_MAX_NESTING_DEPTH,
+    span::Span,
+    stmt::{CatchClause, ImportTarget, Signature, Stmt, TypeAnnotation},
+    strictness::Strictness,
     token::Token,
     token_kind::TokenKind,
     value::Value,
 };
 
-const MAX_ARGUMENT_COUNT: usize = 255;
+/// Parameter and argument limit used unless `ParserConfig` sets another one
+pub const DEFAULT_MAX_ARGUMENTS: usize = 255;
 
 /// Result used internally to interupt parsing until synchronization can occur
 type ParserResult<T> = Result<T, ParserErrorDetails>;
 
 /// Grammar:
 ///
-/// program             -> declaration* EOF ;
+/// program             -> ( exportDeclaration | declaration )* EOF ;
 ///
+/// exportDeclaration   -> "export" ( classDeclaration | varDeclaration
+///                      | functionDeclaration ) ;
 /// declaration         -> classDeclaration | varDeclaration
-///                      | functionDeclaration | statement ;
+///                      | functionDeclaration | importDeclaration
+///                      | statement ;
+/// importDeclaration   -> "import" ( "{" IDENTIFIER ( "," IDENTIFIER )* "}"
+///                      "from" STRING | STRING "as" IDENTIFIER ) ";" ;
 /// classDeclaration    -> "class" IDENTIFIER ( "<" IDENTIFIER )?
+///                      ( "with" IDENTIFIER ( "," IDENTIFIER )* )?
 ///                      "{" function* "}" ;
-/// varDeclaration      -> "var" IDENTIFIER ( "=" expression )? ";" ;
+/// varDeclaration      -> "var" ( IDENTIFIER typeAnnotation? ( "=" expression )?
+///                      | destructure "=" expression ) ";" ;
+/// destructure         -> "(" IDENTIFIER ( "," IDENTIFIER )* ")" ;
 /// functionDeclaration -> "fun" function ;
-/// function            -> IDENTIFIER "(" parameters? ")" block ;
-/// parameters          -> IDENTIFIER ( "," IDENTIFIER )* ;
+/// function            -> "*"? IDENTIFIER "(" parameters? ")" typeAnnotation?
+///                      block ;
+/// parameters          -> parameter ( "," parameter )* ","? ;
+/// parameter           -> IDENTIFIER typeAnnotation? ;
+/// typeAnnotation      -> ":" IDENTIFIER "?"? ;
 ///
 /// statement           -> expressionStatement | printStatement | block
-///                      | ifStatement | whileStatement | returnStatment ;
+///                      | ifStatement | whileStatement | returnStatment
+///                      | tryStatement | throwStatement | deleteStatement
+///                      | usingStatement ;
 /// ifStatement         -> "if" "(" expression ")" statement
 ///                      ( "else" statement )? ;
 /// whileStatement      -> "while" "(" expression ")" statement ;
 /// forStatement        -> "for" "("
 ///                      ( varDeclaration | expressionStatement | ";" )
-///                      expression? ";" expression?  ")" statement ;
+///                      expression? ";" expression?  ")" statement
+///                      | "for" "(" "var" IDENTIFIER "in" expression ")"
+///                      statement ;
 /// expressionStatement -> expression ";" ;
 /// printStatement      -> "print" expression ";" ;
 /// block               -> "{" declaration* "}" ;
 /// returnStatment      -> "return" expression? ";" ;
+/// tryStatement        -> "try" block ( catchClause finallyClause?
+///                      | finallyClause ) ;
+/// catchClause         -> "catch" "(" IDENTIFIER ")" block ;
+/// finallyClause       -> "finally" block ;
+/// throwStatement      -> "throw" expression ";" ;
+/// deleteStatement     -> "delete" call "." IDENTIFIER ";" ;
+/// usingStatement      -> "using" "(" "var" IDENTIFIER "=" expression ")"
+///                      block ;
 ///
 /// expression          -> assignment ;
-/// assignment          -> ( call "." )? IDENTIFIER "=" assignment | logicOr ;
+/// assignment          -> ( call "." )? IDENTIFIER "=" assignment
+///                      | destructure "=" assignment
+///                      | "yield" assignment? | logicOr ;
 /// logicOr             -> logicAnd ( "or" logicAnd )* ;
 /// logicAnd            -> equality ( "and" equality )* ;
 /// equality            -> comparison ( ( "==" | "!=" ) comparison )* ;
-/// comparison          -> term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
+/// comparison          -> term ( ( ">" | ">=" | "<" | "<=" | "in" ) term )* ;
 /// term                -> factor ( ( "-" | "+" ) factor )* ;
 /// factor              -> unary ( ( "/" | "*" ) unary )* ;
 /// unary               -> ( "!" | "-" ) unary | call ;
 /// call                -> primary ( "(" arguments? ")" | "." IDENTIFIER )* ;
-/// arguments           -> expression ( "," expression )* ;
+/// arguments           -> expression ( "," expression )* ","? ;
 /// primary             -> NUMBER | STRING | "nil" | "true" | "false"
 ///                      | "(" expression ")" | IDENTIFIER
-///                      | "super" "." IDENTIFIER ;
+///                      | "super" "." IDENTIFIER | matchExpression
+///                      | "[" ( expression ( "," expression )* ","? )? "]" ;
+/// matchExpression     -> "match" "(" expression ")"
+///                      "{" ( matchArm ( "," matchArm )* ","? )? "}" ;
+/// matchArm            -> pattern ( "if" expression )? "->" expression ;
+/// pattern             -> NUMBER | "-" NUMBER | STRING | "nil" | "true"
+///                      | "false" | IDENTIFIER ;
+#[derive(Debug, Clone)]
+pub struct ParserConfig {
+    /// Inserts a missing `;` at the end of a line when the statement is
+    /// otherwise complete
+    pub lenient_semicolons: bool,
+    /// Most parameters a function can declare and arguments a call can pass
+    pub max_arguments: usize,
+    /// Stricter rules, of which the parser checks `require_braces`
+    pub strictness: Strictness,
+    /// Deepest statements and expressions can be nested, see
+    /// `Limits::max_nesting_depth`
+    pub max_nesting_depth: usize,
+}
+
+impl Default for ParserConfig {
+    fn default() -> Self {
+        Self {
+            lenient_semicolons: false,
+            max_arguments: DEFAULT_MAX_ARGUMENTS,
+            strictness: Strictness::default(),
+            max_nesting_depth: DEFAULT_MAX_NESTING_DEPTH,
+        }
+    }
+}
+
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
     parsing_errors: Vec<ParserErrorDetails>,
+    config: ParserConfig,
+    /// Statements and expressions being parsed that contain the current token
+    depth: usize,
+    /// Whether an expression is being parsed, so nested ones are part of it
+    in_expression: bool,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
+    pub fn new(tokens: Vec<Token>, config: ParserConfig) -> Self {
         Self {
             tokens,
             current: 0,
             parsing_errors: vec![],
+            config,
+            depth: 0,
+            in_expression: false,
         }
     }
 
     pub fn parse(mut self) -> Result<Vec<Stmt>> {
         let mut statements: Vec<Stmt> = vec![];
         while !self.is_at_end() {
-            match self.declaration() {
+            match self.top_level_declaration() {
                 Ok(statement) => statements.push(statement),
                 Err(error) => self.parsing_errors.push(error),
             }
@@ -85,13 +154,51 @@ impl Parser {
         }
     }
 
-    fn declaration(&mut self) -> ParserResult<Stmt> {
-        self.try_declaration().map_err(|error| {
-            self.synchronize();
-            error
+    /// Parses the tokens as a single expression with nothing after it
+    pub fn parse_expression(mut self) -> Result<Expr> {
+        let expr = self.expression().and_then(|expr| match self.is_at_end() {
+            true => Ok(expr),
+            false => Err(self.parser_error(self.peek().clone(), "Expected end of expression.")),
+        });
+
+        expr.map_err(|error| LoxError::ParseError {
+            statements: vec![],
+            details: vec![error],
         })
     }
 
+    /// A declaration that can be marked with `export`, which is only
+    /// allowed at the top level of a file
+    fn top_level_declaration(&mut self) -> ParserResult<Stmt> {
+        if !self.matches(&[TokenKind::Export]) {
+            return self.declaration();
+        }
+
+        self.export_declaration()
+            .inspect_err(|_| self.synchronize())
+    }
+
+    fn export_declaration(&mut self) -> ParserResult<Stmt> {
+        let keyword = self.previous().clone();
+        if !matches!(
+            self.peek().kind,
+            TokenKind::Class | TokenKind::Fun | TokenKind::Var
+        ) {
+            return Err(self.parser_error(
+                self.peek().clone(),
+                "Expected a class, function or variable declaration after 'export'.",
+            ));
+        }
+
+        let declaration = self.try_declaration()?;
+
+        Ok(Stmt::Export(keyword, Box::new(declaration)))
+    }
+
+    fn declaration(&mut self) -> ParserResult<Stmt> {
+        self.try_declaration().inspect_err(|_| self.synchronize())
+    }
+
     fn try_declaration(&mut self) -> ParserResult<Stmt> {
         if self.matches(&[TokenKind::Class]) {
             return self.class_declaration();
@@ -105,25 +212,79 @@ impl Parser {
             return self.function("function");
         }
 
+        if self.matches(&[TokenKind::Import]) {
+            return self.import_declaration();
+        }
+
+        if self.check(TokenKind::Export) {
+            return Err(self.parser_error(
+                self.peek().clone(),
+                "Only top-level declarations can be exported.",
+            ));
+        }
+
         self.statement()
     }
 
+    fn import_declaration(&mut self) -> ParserResult<Stmt> {
+        let keyword = self.previous().clone();
+
+        if self.matches(&[TokenKind::LeftBrace]) {
+            let mut names = vec![];
+            loop {
+                names.push(self.consume_identifier("variable", "Expected imported name.")?);
+
+                if !self.matches(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+
+            self.try_consume(TokenKind::RightBrace, "Expected '}' after imported names.")?;
+            self.consume_word("from", "Expected 'from' after imported names.")?;
+            let path = self.module_path()?;
+            self.consume_semicolon("Expected ';' after import.")?;
+
+            return Ok(Stmt::Import(keyword, path, ImportTarget::Names(names)));
+        }
+
+        let path = self.module_path()?;
+        self.consume_word("as", "Expected 'as' after module path.")?;
+        let alias = self.consume_identifier("variable", "Expected module name after 'as'.")?;
+        self.consume_semicolon("Expected ';' after import.")?;
+
+        Ok(Stmt::Import(keyword, path, ImportTarget::Module(alias)))
+    }
+
+    fn module_path(&mut self) -> ParserResult<Token> {
+        Ok(self
+            .try_consume(TokenKind::String, "Expected module path string.")?
+            .clone())
+    }
+
     fn class_declaration(&mut self) -> ParserResult<Stmt> {
-        let name = self
-            .try_consume(TokenKind::Identifier, "Expected class name.")?
-            .clone();
+        let name = self.consume_identifier("class", "Expected class name.")?;
 
         let superclass = match self.matches(&[TokenKind::Less]) {
             false => None,
             true => {
-                let name = self
-                    .try_consume(TokenKind::Identifier, "Expected super class name.")?
-                    .clone();
+                let name = self.consume_identifier("class", "Expected super class name.")?;
 
                 Some(Expr::Variable(name))
             }
         };
 
+        let mut mixins = vec![];
+        if self.matches(&[TokenKind::With]) {
+            loop {
+                let name = self.consume_identifier("class", "Expected mixin class name.")?;
+                mixins.push(Expr::Variable(name));
+
+                if !self.matches(&[TokenKind::Comma]) {
+                    break;
+                }
+            }
+        }
+
         self.try_consume(TokenKind::LeftBrace, "Expected '{' before class body.")?;
 
         let mut methods = vec![];
@@ -133,77 +294,144 @@ impl Parser {
 
         self.try_consume(TokenKind::RightBrace, "Expected '}' after class body.")?;
 
-        Ok(Stmt::Class(name, superclass, methods))
+        Ok(Stmt::Class(name, superclass, mixins, methods))
     }
 
     fn var_declaration(&mut self) -> ParserResult<Stmt> {
-        let identifier = self
-            .try_consume(TokenKind::Identifier, "Expected variable name.")?
-            .clone();
+        if self.matches(&[TokenKind::LeftParen]) {
+            return self.var_destructure_declaration();
+        }
+
+        let identifier = self.consume_identifier("variable", "Expected variable name.")?;
+        let annotation = self.type_annotation()?;
 
         let initializer = match self.matches(&[TokenKind::Equal]) {
             true => Some(self.expression()?),
             false => None,
         };
 
+        self.consume_semicolon("Expected ';' after variable declaration.")?;
+
+        Ok(Stmt::Var(identifier, initializer, annotation))
+    }
+
+    fn var_destructure_declaration(&mut self) -> ParserResult<Stmt> {
+        let paren = self.previous().clone();
+        let names = self.destructure_targets()?;
+
+        self.try_consume(
+            TokenKind::Equal,
+            "Expected '=' after destructuring pattern.",
+        )?;
+        let initializer = self.expression()?;
+
+        self.consume_semicolon("Expected ';' after variable declaration.")?;
+
+        Ok(Stmt::VarDestructure(paren, names, initializer))
+    }
+
+    fn destructure_targets(&mut self) -> ParserResult<Vec<Token>> {
+        let mut names = vec![];
+
+        loop {
+            names.push(self.consume_identifier(
+                "variable",
+                "Expected variable name in destructuring pattern.",
+            )?);
+
+            if !self.matches(&[TokenKind::Comma]) {
+                break;
+            }
+        }
+
         self.try_consume(
-            TokenKind::Semicolon,
-            "Expected ';' after variable declaration.",
+            TokenKind::RightParen,
+            "Expected ')' after destructuring pattern.",
         )?;
 
-        Ok(Stmt::Var(identifier, initializer))
+        Ok(names)
     }
 
     fn function(&mut self, kind: &str) -> ParserResult<Stmt> {
-        let name = self
-            .try_consume(TokenKind::Identifier, &format!("Expected {} name.", kind))?
-            .clone();
+        let is_generator = self.matches(&[TokenKind::Star]);
+        let name = self.consume_identifier(kind, &format!("Expected {} name.", kind))?;
 
         self.try_consume(
             TokenKind::LeftParen,
             &format!("Expected '(' after {} name.", kind),
         )?;
 
-        let parameters = match self.check(TokenKind::RightParen) {
-            true => vec![],
+        let (parameters, parameter_types) = match self.check(TokenKind::RightParen) {
+            true => (vec![], vec![]),
             false => self.parameters()?,
         };
 
         self.try_consume(TokenKind::RightParen, "Expeced ')' after parameters.")?;
 
+        let signature = Signature {
+            parameters: parameter_types,
+            returns: self.type_annotation()?,
+        };
+
         self.try_consume(
             TokenKind::LeftBrace,
             &format!("Expected '{{' before {} body.", kind),
         )?;
 
-        let body = self.block_statements()?;
+        let body = self.nested(Self::block_statements)?;
 
-        Ok(Stmt::Function(name, parameters, body))
+        Ok(Stmt::Function(
+            name,
+            parameters,
+            body,
+            is_generator,
+            signature,
+        ))
     }
 
-    fn parameters(&mut self) -> ParserResult<Vec<Token>> {
+    /// Parameter names and their type annotations
+    fn parameters(&mut self) -> ParserResult<(Vec<Token>, Vec<Option<TypeAnnotation>>)> {
         let mut parameters = vec![];
+        let mut types = vec![];
 
         loop {
-            if parameters.len() >= MAX_ARGUMENT_COUNT {
-                self.report_warning(
+            if parameters.len() == self.config.max_arguments {
+                self.report_error(
                     self.peek().clone(),
-                    &format!("Can't have more than {} arguments.", MAX_ARGUMENT_COUNT),
+                    &format!(
+                        "Can't have more than {} parameters.",
+                        self.config.max_arguments
+                    ),
                 );
             }
 
-            parameters.push(
-                self.try_consume(TokenKind::Identifier, "Expeced parameter name")?
-                    .clone(),
-            );
+            parameters.push(self.consume_identifier("parameter", "Expeced parameter name")?);
+            types.push(self.type_annotation()?);
 
-            if !self.matches(&[TokenKind::Comma]) {
-                return Ok(parameters);
+            if !self.matches(&[TokenKind::Comma]) || self.check(TokenKind::RightParen) {
+                return Ok((parameters, types));
             }
         }
     }
 
+    /// An optional `: Type` after a name or parameter list
+    fn type_annotation(&mut self) -> ParserResult<Option<TypeAnnotation>> {
+        if !self.matches(&[TokenKind::Colon]) {
+            return Ok(None);
+        }
+
+        let name = self.consume_identifier("type", "Expected type name after ':'.")?;
+        let nullable = self.matches(&[TokenKind::Question]);
+
+        Ok(Some(TypeAnnotation { name, nullable }))
+    }
+
     fn statement(&mut self) -> ParserResult<Stmt> {
+        self.nested(Self::unguarded_statement)
+    }
+
+    /// `statement` without the nesting check
+    fn unguarded_statement(&mut self) -> ParserResult<Stmt> {
         if self.matches(&[TokenKind::Print]) {
             return self.print_statement();
         }
@@ -228,14 +456,31 @@ impl Parser {
             return self.return_statement();
         }
 
+        if self.matches(&[TokenKind::Try]) {
+            return self.try_statement();
+        }
+
+        if self.matches(&[TokenKind::Throw]) {
+            return self.throw_statement();
+        }
+
+        if self.matches(&[TokenKind::Delete]) {
+            return self.delete_statement();
+        }
+
+        if self.matches(&[TokenKind::Using]) {
+            return self.using_statement();
+        }
+
         self.expression_statement()
     }
 
     fn print_statement(&mut self) -> ParserResult<Stmt> {
+        let keyword = self.previous().clone();
         let value = self.expression()?;
-        self.try_consume(TokenKind::Semicolon, "Expected ';' after value.")?;
+        self.consume_semicolon("Expected ';' after value.")?;
 
-        Ok(Stmt::Print(value))
+        Ok(Stmt::Print(keyword, value))
     }
 
     fn if_statement(&mut self) -> ParserResult<Stmt> {
@@ -243,10 +488,11 @@ impl Parser {
         let condition = self.expression()?;
         self.try_consume(TokenKind::RightParen, "Expected ')' after condition.")?;
 
-        let then_branch = self.statement()?;
+        let then_branch = self.body("if")?;
 
         let else_branch = match self.matches(&[TokenKind::Else]) {
-            true => Some(Box::new(self.statement()?)),
+            true if self.check(TokenKind::If) => Some(Box::new(self.statement()?)),
+            true => Some(Box::new(self.body("else")?)),
             _ => None,
         };
 
@@ -258,13 +504,22 @@ impl Parser {
         let condition = self.expression()?;
         self.try_consume(TokenKind::RightParen, "Expected ')' after condition.")?;
 
-        let body = self.statement()?;
+        let body = self.body("while")?;
 
-        Ok(Stmt::While(condition, body.into()))
+        Ok(Stmt::While(condition, body.into(), None))
     }
 
     fn for_statement(&mut self) -> ParserResult<Stmt> {
+        let keyword = self.previous().clone();
         self.try_consume(TokenKind::LeftParen, "Expected '(' after for.")?;
+
+        if self.check(TokenKind::Var)
+            && self.peek_kind_at(1) == Some(TokenKind::Identifier)
+            && self.peek_kind_at(2) == Some(TokenKind::In)
+        {
+            return self.for_in_statement();
+        }
+
         let initializer = if self.matches(&[TokenKind::Var]) {
             Some(self.var_declaration()?)
         } else if self.matches(&[TokenKind::Semicolon]) {
@@ -286,13 +541,17 @@ impl Parser {
         };
 
         self.try_consume(TokenKind::RightParen, "Expected ')' after for clauses.")?;
+        let origin = Token {
+            span: Span::new(keyword.span.start, self.previous().span.end),
+            ..keyword
+        };
 
-        let body = match (increment, self.statement()?) {
+        let body = match (increment, self.body("for")?) {
             (Some(inc), body) => Stmt::Block(vec![body, Stmt::Expression(inc)]),
             (_, body) => body,
         };
 
-        let while_statement = Stmt::While(condition, body.into());
+        let while_statement = Stmt::While(condition, body.into(), Some(origin));
 
         Ok(match initializer {
             Some(init) => Stmt::Block(vec![init, while_statement]),
@@ -300,21 +559,131 @@ impl Parser {
         })
     }
 
+    fn for_in_statement(&mut self) -> ParserResult<Stmt> {
+        self.advance();
+        let name = self.advance().clone();
+        self.advance();
+
+        let iterable = self.expression()?;
+        self.try_consume(TokenKind::RightParen, "Expected ')' after for-in clause.")?;
+
+        let body = self.body("for")?;
+
+        Ok(Stmt::ForIn(name, iterable, body.into()))
+    }
+
     fn return_statement(&mut self) -> ParserResult<Stmt> {
         let keyword = self.previous().clone();
-        let value = match self.check(TokenKind::Semicolon) {
+        let value = match self.check(TokenKind::Semicolon) || self.semicolon_insertable() {
             false => Some(self.expression()?),
             true => None,
         };
 
-        self.try_consume(TokenKind::Semicolon, "Expected ';' after return.")?;
+        self.consume_semicolon("Expected ';' after return.")?;
 
         Ok(Stmt::Return(keyword, value))
     }
 
+    fn try_statement(&mut self) -> ParserResult<Stmt> {
+        self.try_consume(TokenKind::LeftBrace, "Expected '{' after try.")?;
+        let body = self.block_statements()?;
+
+        let catch = match self.matches(&[TokenKind::Catch]) {
+            true => Some(self.catch_clause()?),
+            false => None,
+        };
+
+        let finally = match self.matches(&[TokenKind::Finally]) {
+            true => {
+                self.try_consume(TokenKind::LeftBrace, "Expected '{' after finally.")?;
+                Some(self.block_statements()?)
+            }
+            false => None,
+        };
+
+        if catch.is_none() && finally.is_none() {
+            return Err(self.parser_error(
+                self.peek().clone(),
+                "Expected 'catch' or 'finally' after try block.",
+            ));
+        }
+
+        Ok(Stmt::Try(body, catch, finally))
+    }
+
+    /// Desugars `using (var name = value) { body }` into a block declaring
+    /// `name` and running the body in a try whose finally calls
+    /// `name.close()`, so the resource is closed however the body exits
+    fn using_statement(&mut self) -> ParserResult<Stmt> {
+        let keyword = self.previous().clone();
+        self.try_consume(TokenKind::LeftParen, "Expected '(' after using.")?;
+        self.try_consume(TokenKind::Var, "Expected 'var' to declare the resource.")?;
+        let name = self.consume_identifier("variable", "Expected resource variable name.")?;
+        self.try_consume(
+            TokenKind::Equal,
+            "Expected '=' after resource variable name.",
+        )?;
+        let value = self.expression()?;
+        self.try_consume(TokenKind::RightParen, "Expected ')' after using clause.")?;
+
+        self.try_consume(TokenKind::LeftBrace, "Expected '{' before using body.")?;
+        let body = self.block_statements()?;
+
+        let close_name = Token {
+            kind: TokenKind::Identifier,
+            lexeme: "close".into(),
+            literal: None,
+            ..keyword.renumbered()
+        };
+        let close = Expr::Call(
+            Expr::Get(Expr::Variable(name.renumbered()).into(), close_name).into(),
+            vec![],
+            keyword.renumbered(),
+            vec![],
+        );
+
+        Ok(Stmt::Block(vec![
+            Stmt::Var(name, Some(value), None),
+            Stmt::Try(body, None, Some(vec![Stmt::Expression(close)])),
+        ]))
+    }
+
+    fn catch_clause(&mut self) -> ParserResult<CatchClause> {
+        self.try_consume(TokenKind::LeftParen, "Expected '(' after catch.")?;
+        let name = self.consume_identifier("variable", "Expected error variable name.")?;
+        self.try_consume(
+            TokenKind::RightParen,
+            "Expected ')' after error variable name.",
+        )?;
+
+        self.try_consume(TokenKind::LeftBrace, "Expected '{' before catch body.")?;
+        let body = self.block_statements()?;
+
+        Ok(CatchClause { name, body })
+    }
+
+    fn throw_statement(&mut self) -> ParserResult<Stmt> {
+        let keyword = self.previous().clone();
+        let value = self.expression()?;
+        self.consume_semicolon("Expected ';' after thrown value.")?;
+
+        Ok(Stmt::Throw(keyword, value))
+    }
+
+    fn delete_statement(&mut self) -> ParserResult<Stmt> {
+        let keyword = self.previous().clone();
+        let mut target = self.call()?;
+        self.consume_semicolon("Expected ';' after deleted field.")?;
+
+        match &mut target {
+            Expr::Get(object, name) => Ok(Stmt::Delete(object.take(), name.clone())),
+            _ => Err(self.parser_error(keyword, "Can only delete instance fields.")),
+        }
+    }
+
     fn expression_statement(&mut self) -> ParserResult<Stmt> {
         let value = self.expression()?;
-        self.try_consume(TokenKind::Semicolon, "Expected ';' after expression.")?;
+        self.consume_semicolon("Expected ';' after expression.")?;
 
         Ok(Stmt::Expression(value))
     }
@@ -335,22 +704,58 @@ impl Parser {
     }
 
     fn expression(&mut self) -> ParserResult<Expr> {
-        self.assignment()
+        if self.in_expression {
+            return self.nested(Self::assignment);
+        }
+
+        self.in_expression = true;
+        let expr = self.nested(Self::assignment);
+        self.in_expression = false;
+
+        // Chains like `1 + 1 + ... + 1` are parsed in a loop rather than by
+        // recursing, so only the finished expression shows how deep they go
+        let expr = expr?;
+        self.check_nesting(&expr)?;
+
+        Ok(expr)
     }
 
     fn assignment(&mut self) -> ParserResult<Expr> {
-        let expr = self.or()?;
+        if self.is_destructure_target() {
+            let paren = self.advance().clone();
+            let names = self.destructure_targets()?;
+
+            self.try_consume(
+                TokenKind::Equal,
+                "Expected '=' after destructuring pattern.",
+            )?;
+            let value = self.nested(Self::assignment)?;
+
+            return Ok(Expr::Destructure(paren, names, value.into()));
+        }
+
+        if self.matches(&[TokenKind::Yield]) {
+            let keyword = self.previous().clone();
+            let value = match self.check(TokenKind::Semicolon) || self.semicolon_insertable() {
+                true => None,
+                false => Some(self.nested(Self::assignment)?.into()),
+            };
+
+            return Ok(Expr::Yield(keyword, value));
+        }
+
+        let mut expr = self.or()?;
 
         if self.matches(&[TokenKind::Equal]) {
             let equal = self.previous().clone();
-            let value = self.assignment()?;
+            let value = self.nested(Self::assignment)?;
 
-            if let Expr::Variable(name) = expr {
-                return Ok(Expr::Assign(name, value.into()));
+            if let Expr::Variable(name) = &expr {
+                return Ok(Expr::Assign(name.clone(), value.into()));
             }
 
-            if let Expr::Get(object, name) = expr {
-                return Ok(Expr::Set(object, name, value.into()));
+            if let Expr::Get(object, name) = &mut expr {
+                return Ok(Expr::Set(object.take().into(), name.clone(), value.into()));
             }
 
             self.parser_error(equal, "Invalid assignment target.");
@@ -405,6 +810,7 @@ impl Parser {
             TokenKind::GreaterEqual,
             TokenKind::Less,
             TokenKind::LessEqual,
+            TokenKind::In,
         ]) {
             let operator = self.previous().clone();
             let right = self.term()?;
@@ -441,7 +847,7 @@ impl Parser {
     fn unary(&mut self) -> ParserResult<Expr> {
         if self.matches(&[TokenKind::Bang, TokenKind::Minus]) {
             let operator = self.previous().clone();
-            let right = self.unary()?;
+            let right = self.nested(Self::unary)?;
             return Ok(Expr::Unary(operator, right.into()));
         }
 
@@ -458,9 +864,8 @@ impl Parser {
             }
 
             if self.matches(&[TokenKind::Dot]) {
-                let name = self
-                    .try_consume(TokenKind::Identifier, "Expected property name after '.'.")?
-                    .clone();
+                let name =
+                    self.consume_identifier("property", "Expected property name after '.'.")?;
 
                 expr = Expr::Get(expr.into(), name);
 
@@ -472,32 +877,46 @@ impl Parser {
     }
 
     fn finish_call(&mut self, callee: Expr) -> ParserResult<Expr> {
-        let arguments = match self.check(TokenKind::RightParen) {
-            true => vec![],
+        let (arguments, starts) = match self.check(TokenKind::RightParen) {
+            true => (vec![], vec![]),
             false => self.arguments()?,
         };
 
         let right_paren =
             self.try_consume(TokenKind::RightParen, "Expected ')' after arguments.")?;
 
-        Ok(Expr::Call(callee.into(), arguments, right_paren.clone()))
+        Ok(Expr::Call(
+            callee.into(),
+            arguments,
+            right_paren.clone(),
+            starts,
+        ))
     }
 
-    fn arguments(&mut self) -> ParserResult<Vec<Expr>> {
-        let mut args = vec![self.expression()?];
+    /// Parses the arguments of a call, along with the first token of each so
+    /// errors about a specific argument can point at it
+    fn arguments(&mut self) -> ParserResult<(Vec<Expr>, Vec<Token>)> {
+        let mut args = vec![];
+        let mut starts = vec![];
 
-        while self.matches(&[TokenKind::Comma]) {
-            if args.len() >= MAX_ARGUMENT_COUNT {
-                self.report_warning(
+        loop {
+            if args.len() == self.config.max_arguments {
+                self.report_error(
                     self.peek().clone(),
-                    &format!("Can't have more than {} arguments.", MAX_ARGUMENT_COUNT),
+                    &format!(
+                        "Can't have more than {} arguments.",
+                        self.config.max_arguments
+                    ),
                 );
             }
 
+            starts.push(self.peek().clone());
             args.push(self.expression()?);
-        }
 
-        Ok(args)
+            if !self.matches(&[TokenKind::Comma]) || self.check(TokenKind::RightParen) {
+                return Ok((args, starts));
+            }
+        }
     }
 
     fn primary(&mut self) -> ParserResult<Expr> {
@@ -514,13 +933,8 @@ impl Parser {
         }
 
         if self.matches(&[TokenKind::String, TokenKind::Number]) {
-            let literal = self
-                .previous()
-                .clone()
-                .literal
-                .expect("Expected literal value on token");
-
-            return Ok(Expr::Literal(literal));
+            let token = self.previous().clone();
+            return Ok(Expr::Literal(self.literal(token)?));
         }
 
         if self.matches(&[TokenKind::LeftParen]) {
@@ -537,13 +951,19 @@ impl Parser {
         if self.matches(&[TokenKind::Super]) {
             let keyword = self.previous().clone();
             self.try_consume(TokenKind::Dot, "Expected '.' after 'super'")?;
-            let method = self
-                .try_consume(TokenKind::Identifier, "Expected superclass method name")?
-                .clone();
+            let method = self.consume_identifier("method", "Expected superclass method name")?;
 
             return Ok(Expr::Super(keyword, method));
         }
 
+        if self.matches(&[TokenKind::Match]) {
+            return self.match_expression();
+        }
+
+        if self.matches(&[TokenKind::LeftBracket]) {
+            return self.list_expression();
+        }
+
         if self.matches(&[TokenKind::Identifier]) {
             return Ok(Expr::Variable(self.previous().clone()));
         }
@@ -551,6 +971,114 @@ impl Parser {
         Err(self.parser_error(self.peek().clone(), "Expected expression."))
     }
 
+    fn list_expression(&mut self) -> ParserResult<Expr> {
+        let bracket = self.previous().clone();
+        let mut elements = vec![];
+
+        if !self.check(TokenKind::RightBracket) {
+            loop {
+                elements.push(self.expression()?);
+
+                if !self.matches(&[TokenKind::Comma]) || self.check(TokenKind::RightBracket) {
+                    break;
+                }
+            }
+        }
+
+        self.try_consume(TokenKind::RightBracket, "Expected ']' after list elements.")?;
+
+        Ok(Expr::List(bracket, elements))
+    }
+
+    fn match_expression(&mut self) -> ParserResult<Expr> {
+        let keyword = self.previous().clone();
+        self.try_consume(TokenKind::LeftParen, "Expected '(' after match.")?;
+        let subject = self.expression()?;
+        self.try_consume(TokenKind::RightParen, "Expected ')' after match subject.")?;
+        self.try_consume(TokenKind::LeftBrace, "Expected '{' before match arms.")?;
+
+        let mut arms = vec![];
+        while !self.check(TokenKind::RightBrace) && !self.is_at_end() {
+            arms.push(self.match_arm()?);
+
+            if !self.matches(&[TokenKind::Comma]) {
+                break;
+            }
+        }
+
+        self.try_consume(TokenKind::RightBrace, "Expected '}' after match arms.")?;
+
+        Ok(Expr::Match(keyword, subject.into(), arms))
+    }
+
+    fn match_arm(&mut self) -> ParserResult<MatchArm> {
+        let pattern = self.pattern()?;
+
+        let guard = match self.matches(&[TokenKind::If]) {
+            true => Some(self.expression()?),
+            false => None,
+        };
+
+        self.try_consume(TokenKind::Arrow, "Expected '->' after match pattern.")?;
+        let body = self.expression()?;
+
+        Ok(MatchArm {
+            pattern,
+            guard,
+            body,
+        })
+    }
+
+    fn pattern(&mut self) -> ParserResult<Pattern> {
+        if self.matches(&[TokenKind::True]) {
+            return Ok(Pattern::Literal(
+                self.previous().clone(),
+                Value::Boolean(true),
+            ));
+        }
+
+        if self.matches(&[TokenKind::False]) {
+            return Ok(Pattern::Literal(
+                self.previous().clone(),
+                Value::Boolean(false),
+            ));
+        }
+
+        if self.matches(&[TokenKind::Nil]) {
+            return Ok(Pattern::Literal(self.previous().clone(), Value::Nil));
+        }
+
+        if self.matches(&[TokenKind::String, TokenKind::Number]) {
+            let token = self.previous().clone();
+            let literal = self.literal(token.clone())?;
+
+            return Ok(Pattern::Literal(token, literal));
+        }
+
+        if self.matches(&[TokenKind::Minus]) {
+            let minus = self.previous().clone();
+            let token = self
+                .try_consume(TokenKind::Number, "Expected number after '-' in pattern.")?
+                .clone();
+
+            return match self.literal(token.clone())? {
+                Value::Number(value) => Ok(Pattern::Literal(minus, Value::Number(-value))),
+                _ => Err(self.parser_error(token, "Internal error: number token isn't a number.")),
+            };
+        }
+
+        if self.matches(&[TokenKind::Identifier]) {
+            let name = self.previous().clone();
+
+            return Ok(match name.lexeme.as_str() {
+                "_" => Pattern::Wildcard(name),
+                _ => Pattern::Binding(name),
+            });
+        }
+
+        Err(self.parser_error(self.peek().clone(), "Expected pattern."))
+    }
+
     fn synchronize(&mut self) {
         self.advance();
 
@@ -561,10 +1089,16 @@ impl Parser {
 
             match self.peek().kind {
                 TokenKind::Class
+                | TokenKind::Delete
+                | TokenKind::Export
                 | TokenKind::Fun
                 | TokenKind::If
+                | TokenKind::Import
                 | TokenKind::Print
                 | TokenKind::Return
+                | TokenKind::Throw
+                | TokenKind::Try
+                | TokenKind::Using
                 | TokenKind::Var
                 | TokenKind::While => return,
                 _ => self.advance(),
@@ -590,18 +1124,133 @@ impl Parser {
         Err(self.parser_error(self.peek().clone(), message))
     }
 
+    /// Consumes the `;` ending a statement. In lenient mode it can be left out
+    /// at the end of a line.
+    fn consume_semicolon(&mut self, message: &str) -> ParserResult<()> {
+        if self.matches(&[TokenKind::Semicolon]) || self.semicolon_insertable() {
+            return Ok(());
+        }
+
+        Err(self.parser_error(self.peek().clone(), message))
+    }
+
+    fn semicolon_insertable(&self) -> bool {
+        self.config.lenient_semicolons
+            && (self.is_at_end() || self.peek().line > self.previous().line)
+    }
+
+    /// Consumes an identifier, reporting a targeted error when a reserved
+    /// keyword is used in its place
+    fn consume_identifier(&mut self, role: &str, message: &str) -> ParserResult<Token> {
+        if self.peek().kind.is_keyword() {
+            let keyword = self.peek().clone();
+            let message = format!(
+                "'{}' is a reserved keyword and cannot be used as a {} name.",
+                keyword.lexeme, role
+            );
+
+            return Err(self.parser_error(keyword, &message));
+        }
+
+        Ok(self.try_consume(TokenKind::Identifier, message)?.clone())
+    }
+
+    /// Consumes an identifier used as a contextual keyword, like the `from`
+    /// and `as` of an import, which stay usable as names elsewhere
+    fn consume_word(&mut self, word: &str, message: &str) -> ParserResult<()> {
+        if self.check(TokenKind::Identifier) && self.peek().lexeme == word {
+            self.advance();
+            return Ok(());
+        }
+
+        Err(self.parser_error(self.peek().clone(), message))
+    }
+
+    /// Runs `parse` one level deeper, failing at the current token instead if
+    /// that's deeper than `max_nesting_depth`. Recursive descent uses native
+    /// stack for every level, and so does every phase after parsing.
+    fn nested<T>(&mut self, parse: fn(&mut Self) -> ParserResult<T>) -> ParserResult<T> {
+        if self.depth >= self.config.max_nesting_depth {
+            return Err(self.nesting_error(self.peek().clone()));
+        }
+
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+
+        result
+    }
+
+    /// Fails at the first part of `expr`, or the closest part around it with a
+    /// token, that's nested deeper than `max_nesting_depth`
+    fn check_nesting(&mut self, expr: &Expr) -> ParserResult<()> {
+        let mut pending = vec![(expr, self.depth + 1, None)];
+        while let Some((expr, depth, outer_token)) = pending.pop() {
+            let token = expr.token().or(outer_token);
+            if depth > self.config.max_nesting_depth {
+                let token = token.unwrap_or_else(|| self.peek()).clone();
+                return Err(self.nesting_error(token));
+            }
+
+            pending.extend(
+                expr.children()
+                    .into_iter()
+                    .map(|child| (child, depth + 1, token)),
+            );
+        }
+
+        Ok(())
+    }
+
+    fn nesting_error(&mut self, token: Token) -> ParserErrorDetails {
+        let message = format!(
+            "Code is nested more than {} deep.",
+            self.config.max_nesting_depth
+        );
+        self.parser_error(token, &message)
+    }
+
     fn parser_error(&mut self, token: Token, message: &str) -> ParserErrorDetails {
         ParserErrorDetails {
             message: message.into(),
-            token,
+            token: Box::new(token),
+            severity: Severity::Error,
         }
     }
 
-    fn report_warning(&mut self, token: Token, message: &str) {
+    /// The value of a string or number token
+    fn literal(&mut self, token: Token) -> ParserResult<Value> {
+        match token.literal.clone() {
+            Some(literal) => Ok(literal),
+            None => Err(self.parser_error(token, "Internal error: token has no literal value.")),
+        }
+    }
+
+    /// Records an error without interrupting parsing, for problems that
+    /// don't leave the parser confused about where it is
+    fn report_error(&mut self, token: Token, message: &str) {
         let error = self.parser_error(token, message);
         self.parsing_errors.push(error);
     }
 
+    /// Parses the body of a `keyword` statement, which has to be a block with
+    /// `require_braces` on
+    fn body(&mut self, keyword: &str) -> ParserResult<Stmt> {
+        if self.config.strictness.require_braces && !self.check(TokenKind::LeftBrace) {
+            let message = format!("Expected '{{' before {} body.", keyword);
+            self.report_error(self.peek().clone(), &message);
+        }
+
+        self.statement()
+    }
+
+    /// Looks ahead for `( IDENTIFIER ,` which can only begin a destructuring target
+    fn is_destructure_target(&self) -> bool {
+        self.check(TokenKind::LeftParen)
+            && self.peek_kind_at(1) == Some(TokenKind::Identifier)
+            && self.peek_kind_at(2) == Some(TokenKind::Comma)
+    }
+
     fn check(&self, kind: TokenKind) -> bool {
         if self.is_at_end() {
             return false;
@@ -626,6 +1275,12 @@ impl Parser {
         &self.tokens[self.current]
     }
 
+    fn peek_kind_at(&self, offset: usize) -> Option<TokenKind> {
+        self.tokens
+            .get(self.current + offset)
+            .map(|token| token.kind)
+    }
+
     fn previous(&self) -> &Token {
         &self.tokens[self.current - 1]
     }
@@ -0,0 +1,71 @@
+use std::fmt::Display;
+
+/// Counts of how the interpreter's runtime shortcuts fared, collected when
+/// `InterpreterConfig::profile` is set
+#[derive(Debug, Default, Clone)]
+pub struct Profile {
+    /// Property reads and writes answered by the inline cache of their site
+    pub property_hits: usize,
+    /// Property reads and writes that had to look the property up, because
+    /// the site saw another class or field layout last time, or none yet
+    pub property_misses: usize,
+    /// Calls of a method looked up right where it's called, like
+    /// `counter.increment(1)`
+    pub method_calls: usize,
+    /// Method calls that had to make a bound method first, because the
+    /// method is a native or a generator
+    pub bound_method_calls: usize,
+}
+
+impl Profile {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Share of property accesses answered by an inline cache, from 0 to 1
+    pub fn property_hit_rate(&self) -> f64 {
+        match self.property_hits + self.property_misses {
+            0 => 0.0,
+            total => self.property_hits as f64 / total as f64,
+        }
+    }
+
+    /// Share of method calls made without a bound method, from 0 to 1
+    pub fn unbound_call_rate(&self) -> f64 {
+        match self.method_calls {
+            0 => 0.0,
+            total => (total - self.bound_method_calls) as f64 / total as f64,
+        }
+    }
+}
+
+impl Display for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<22} {:>10}",
+            "property accesses",
+            self.property_hits + self.property_misses
+        )?;
+        writeln!(
+            f,
+            "  {:<20} {:>10} ({:.1}%)",
+            "inline cache hits",
+            self.property_hits,
+            self.property_hit_rate() * 100.0
+        )?;
+        writeln!(
+            f,
+            "  {:<20} {:>10}",
+            "inline cache misses", self.property_misses
+        )?;
+        writeln!(f, "{:<22} {:>10}", "method calls", self.method_calls)?;
+        write!(
+            f,
+            "  {:<20} {:>10} ({:.1}%)",
+            "without binding",
+            self.method_calls - self.bound_method_calls,
+            self.unbound_call_rate() * 100.0
+        )
+    }
+}
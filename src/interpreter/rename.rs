@@ -0,0 +1,196 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::anyhow;
+
+use crate::interpreter::{
+    error::{LoxError, Result},
+    interpreter::Interpreter,
+    parser::{Parser, ParserConfig},
+    resolver::{Resolver, Symbol, SymbolTable},
+    rewriter::Rewriter,
+    scanner::{Scan, Scanner},
+    stmt::Stmt,
+    token::Token,
+    token_kind::TokenKind,
+};
+
+/// Renames the variable, function or class named `old` at the 1-based `line`
+/// and `column` of `source` to `new`, along with every reference to it, and
+/// returns the rewritten source.
+///
+/// The resolver decides which names are references, so other variables that
+/// happen to share the name, properties and comments are left alone. Renames
+/// that would change what any name refers to, like one to the name of a
+/// variable in the same scope, are refused.
+///
+/// ```ignore
+/// let source = "var a = 1;\n{ var a = 2; print a; }\nprint a;\n";
+/// let renamed = rename(source, (1, 5), "a", "count").unwrap();
+/// assert_eq!(renamed, "var count = 1;\n{ var a = 2; print a; }\nprint count;\n");
+/// ```
+pub fn rename(
+    source: &str,
+    (line, column): (usize, usize),
+    old: &str,
+    new: &str,
+) -> Result<String> {
+    check_identifier(new)?;
+
+    let (scan, statements) = parse(source)?;
+    let offset = scan
+        .line_index
+        .offset(line, column)
+        .ok_or_else(|| other(format!("There's no line {}.", line)))?;
+    let tokens = scan.tokens;
+    let symbols = resolve(&statements)?;
+
+    let symbol = match symbols.at(offset) {
+        Some((token, symbol)) if is_name(source, token) && token.lexeme == old => symbol,
+        _ if tokens
+            .iter()
+            .any(|token| token.lexeme == old && contains(token, offset)) =>
+        {
+            return Err(other(format!(
+                "Can't rename '{}': only variables, functions and classes can be renamed.",
+                old
+            )))
+        }
+        _ => return Err(other(format!("There's no '{}' to rename there.", old))),
+    };
+
+    if !symbols.is_declared(symbol) {
+        return Err(other(format!(
+            "Can't rename '{}' because it's a native.",
+            old
+        )));
+    }
+    if let Some(path) = symbols.import_path(symbol) {
+        return Err(other(format!(
+            "Can't rename '{}' because it's imported from {}.",
+            old, path
+        )));
+    }
+    if old == new {
+        return Ok(source.to_string());
+    }
+
+    // Desugaring can copy a name, like the resource of a `using` block, so
+    // several occurrences can share a span
+    let mut renamed = HashSet::new();
+    let mut rewriter = Rewriter::new(source, tokens);
+    for (token, _) in symbols.occurrences().filter(|(_, other)| *other == symbol) {
+        if renamed.insert(token.span.start) {
+            rewriter.replace_token(token, new);
+        }
+    }
+    let output = rewriter.finish()?;
+
+    check_meaning_kept(source, &output, old, new)?;
+
+    Ok(output)
+}
+
+fn parse(source: &str) -> Result<(Scan, Vec<Stmt>)> {
+    let scan = Scanner::new(source.to_string()).scan()?;
+    let statements = Parser::new(scan.tokens.clone(), ParserConfig::default()).parse()?;
+
+    Ok((scan, statements))
+}
+
+fn resolve(statements: &[Stmt]) -> Result<SymbolTable> {
+    Resolver::new(&mut Interpreter::new()).resolve_symbols(statements)
+}
+
+/// Fails unless `name` scans as a single identifier, so not a keyword either
+fn check_identifier(name: &str) -> Result<()> {
+    let tokens = Scanner::new(name.to_string()).scan_tokens().ok();
+    match tokens.as_deref() {
+        Some([token, _eof]) if token.kind == TokenKind::Identifier && token.lexeme == name => {
+            Ok(())
+        }
+        _ => Err(other(format!("'{}' isn't a valid name.", name))),
+    }
+}
+
+/// Checks that every name in the renamed source refers to the same thing as
+/// before. Renaming only changes identifiers, so the tokens of both sources
+/// line up one to one.
+fn check_meaning_kept(source: &str, output: &str, old: &str, new: &str) -> Result<()> {
+    let clash = |line: usize| {
+        other(format!(
+            "Can't rename '{}' to '{}': the name on line {} would refer to something else.",
+            old, new, line
+        ))
+    };
+
+    let (before, _) = symbols_by_index(source)?;
+    let (after, tokens) = match symbols_by_index(output) {
+        Ok(symbols) => symbols,
+        // Like a variable declared twice in one scope
+        Err(LoxError::ResolutionError(details)) => {
+            return Err(clash(details.first().map_or(0, |detail| detail.token.line)))
+        }
+        Err(error) => return Err(error),
+    };
+
+    let mut forward = HashMap::new();
+    let mut backward = HashMap::new();
+    for (index, symbol) in &before {
+        let renamed = after.get(index).ok_or_else(|| clash(tokens[*index].line))?;
+        if *forward.entry(symbol).or_insert(renamed) != renamed
+            || *backward.entry(renamed).or_insert(symbol) != symbol
+        {
+            return Err(clash(tokens[*index].line));
+        }
+    }
+
+    Ok(())
+}
+
+/// The symbol of every name in `source` by the index of its token, with
+/// locals identified by the index of their declaration's token, so they can
+/// be compared between sources
+fn symbols_by_index(source: &str) -> Result<(HashMap<usize, Symbol>, Vec<Token>)> {
+    let (Scan { tokens, .. }, statements) = parse(source)?;
+    let symbols = resolve(&statements)?;
+
+    let index_of = |token: &Token| {
+        tokens
+            .binary_search_by_key(&token.span.start, |other| other.span.start)
+            .ok()
+            .filter(|index| is_name(source, token) && tokens[*index].lexeme == token.lexeme)
+    };
+    let declarations: HashMap<usize, &Token> = symbols
+        .occurrences()
+        .map(|(token, _)| (token.id, token))
+        .collect();
+
+    let by_index = symbols
+        .occurrences()
+        .filter_map(|(token, symbol)| {
+            let symbol = match symbol {
+                Symbol::Local(id) => Symbol::Local(index_of(declarations.get(id)?)?),
+                global => global.clone(),
+            };
+
+            Some((index_of(token)?, symbol))
+        })
+        .collect();
+
+    Ok((by_index, tokens))
+}
+
+/// Whether `token` is an identifier written in the source, not one made up
+/// while desugaring
+fn is_name(source: &str, token: &Token) -> bool {
+    token.kind == TokenKind::Identifier
+        && source.get(token.span.start..token.span.end) == Some(token.lexeme.as_str())
+}
+
+fn contains(token: &Token, offset: usize) -> bool {
+    token.span.start <= offset && offset < token.span.end
+}
+
+fn other(message: String) -> LoxError {
+    LoxError::Other(anyhow!(message))
+}
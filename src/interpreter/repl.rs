@@ -0,0 +1,166 @@
+use std::{
+    io::{self, BufRead, Write},
+    time::Instant,
+};
+
+use crate::interpreter::{
+    error::Result,
+    heap::{HeapDump, HeapFormat},
+    value::Value,
+    Lox,
+};
+
+const DEFAULT_PROMPT: &str = "> ";
+const CONTINUATION_PROMPT: &str = "... ";
+
+pub struct ReplConfig {
+    /// Suppresses the version banner printed on startup
+    pub quiet: bool,
+    /// Prints the banner and prompts. Without it, like when input is piped
+    /// in, only what the lines print comes out, and `had_error` is left set
+    /// once any line had an error so the exit status can report it.
+    pub interactive: bool,
+    pub prompt: Option<String>,
+    /// Prints how long each line took to evaluate
+    pub timing: bool,
+    /// Lets statements leave out the `;` at the end of a line
+    pub lenient_semicolons: bool,
+    /// Lets assigning to an undeclared variable define it as a global, with
+    /// a warning, see `InterpreterConfig::implicit_globals`
+    pub implicit_globals: bool,
+}
+
+pub struct Repl {
+    config: ReplConfig,
+}
+
+impl Repl {
+    pub fn new(config: ReplConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn run(&self, lox: &mut Lox) -> Result<()> {
+        if self.config.interactive && !self.config.quiet {
+            self.print_banner();
+        }
+
+        lox.parser_config.lenient_semicolons = self.config.lenient_semicolons;
+        lox.interpreter.config.implicit_globals = self.config.implicit_globals;
+
+        let stdin = io::stdin();
+        let mut buffer = String::new();
+        let mut results = 0;
+        let mut had_error = false;
+        self.print_prompt(&buffer)?;
+
+        for line in stdin.lock().lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            if buffer.is_empty() && line.trim_start().starts_with(':') {
+                self.run_command(lox, line.trim());
+                self.print_prompt(&buffer)?;
+                continue;
+            }
+
+            buffer.push_str(&line);
+            buffer.push('\n');
+
+            // Unfinished input keeps buffering until a blank line forces it to run
+            if line.trim().is_empty() || lox.is_complete(&buffer) {
+                let start = Instant::now();
+                if let Some(value) = Self::result(lox.run_line(std::mem::take(&mut buffer))) {
+                    results += 1;
+                    self.record_result(lox, results, value);
+                }
+                had_error |= lox.had_error;
+                lox.had_error = false;
+
+                if self.config.timing {
+                    eprintln!("({:.2?})", start.elapsed());
+                }
+            }
+
+            self.print_prompt(&buffer)?;
+        }
+
+        if !buffer.trim().is_empty() {
+            if let Some(value) = Self::result(lox.run_line(buffer)) {
+                self.record_result(lox, results + 1, value);
+            }
+        }
+
+        if !self.config.interactive {
+            lox.had_error |= had_error;
+        }
+
+        Ok(())
+    }
+
+    /// The value worth echoing from a line, leaving out `nil` so calls made
+    /// for their side effects stay quiet
+    fn result(value: Option<Value>) -> Option<Value> {
+        value.filter(|value| !matches!(value, Value::Nil))
+    }
+
+    /// Prints the value of an expression typed at the prompt and binds it to
+    /// `_` and `_<index>` so later lines can refer back to it
+    fn record_result(&self, lox: &mut Lox, index: usize, value: Value) {
+        println!("{}", value);
+
+        let mut globals = lox.interpreter.globals.borrow_mut();
+        globals.define(&format!("_{}", index), value.clone());
+        globals.define("_", value);
+    }
+
+    /// Runs a REPL command like `:heap`
+    fn run_command(&self, lox: &mut Lox, line: &str) {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(":watch"), Some(_)) => {
+                let expression = line.trim_start_matches(":watch");
+                lox.watch(expression);
+                lox.had_error = false;
+            }
+            (Some(":heap"), format) => {
+                let format = match format {
+                    Some("dot") => HeapFormat::Dot,
+                    _ => HeapFormat::Text,
+                };
+
+                let dump = HeapDump::collect(&[
+                    ("globals", lox.interpreter.globals.clone()),
+                    ("current", lox.interpreter.environment.clone()),
+                ]);
+                print!("{}", dump.render(format));
+            }
+            (Some(command), _) => eprintln!("Unknown command '{}'.", command),
+            (None, _) => {}
+        }
+    }
+
+    fn print_banner(&self) {
+        println!(
+            "lox-rs {} (tree-walk interpreter)",
+            env!("CARGO_PKG_VERSION")
+        );
+        println!("Press Ctrl-D to exit.");
+    }
+
+    fn print_prompt(&self, buffer: &str) -> Result<()> {
+        if !self.config.interactive {
+            return Ok(());
+        }
+
+        let prompt = match buffer.is_empty() {
+            true => self.config.prompt.as_deref().unwrap_or(DEFAULT_PROMPT),
+            false => CONTINUATION_PROMPT,
+        };
+        print!("{}", prompt);
+        io::stdout().flush()?;
+
+        Ok(())
+    }
+}
@@ -1,21 +1,74 @@
 use core::slice::Iter;
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
 
-use crate::{
+use crate::interpreter::{
+    environment::CaptureList,
     error::{LoxError, ResolverErrorDetails, Result},
-    expr::{Expr, ExprVisitor},
+    expr::{Expr, ExprVisitor, MatchArm, Pattern},
     interpreter::Interpreter,
-    stmt::{Stmt, StmtVisitor},
+    module::import_path,
+    stmt::{CatchClause, ImportTarget, Stmt, StmtVisitor},
     token::Token,
     value::Value,
 };
 
+/// What the interpreter does when a local variable isn't in the scope the
+/// resolver said it's in, which would be a bug in the resolver
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ResolutionMismatch {
+    /// Looks the variable up by name through the enclosing scopes instead,
+    /// with a warning giving its token and the distance it was resolved to
+    #[default]
+    Fallback,
+    /// Stops with an internal error straight away, for test suites and CI
+    /// that should catch resolver bugs rather than run past them
+    Fail,
+}
+
+impl FromStr for ResolutionMismatch {
+    type Err = String;
+
+    fn from_str(name: &str) -> std::result::Result<Self, Self::Err> {
+        match name {
+            "fallback" => Ok(ResolutionMismatch::Fallback),
+            "fail" => Ok(ResolutionMismatch::Fail),
+            _ => Err(format!("Unknown resolution mismatch handling '{}'.", name)),
+        }
+    }
+}
+
 pub struct Resolver<'a> {
     interpreter: &'a mut Interpreter,
     scopes: Stack<HashMap<String, bool>>,
     current_function_kind: Option<FunctionKind>,
     current_class_kind: Option<ClassKind>,
+    loop_depth: usize,
+    in_loop_condition: bool,
     errors: Vec<ResolverErrorDetails>,
+    /// Capture lists of the functions currently being resolved
+    captures: Vec<CaptureList>,
+    /// Method names of the classes currently being resolved
+    class_methods: Vec<HashSet<String>>,
+    /// Resolving a module, whose top-level names live in the outermost scope
+    /// instead of the globals
+    module: bool,
+    /// Id of the `yield` keyword that can suspend a coroutine here, set for
+    /// the expression of the statement being resolved
+    yield_site: Option<usize>,
+    /// Try statements around the code being resolved, in the current function
+    try_depth: usize,
+    /// Whether the current function is a generator, the only kind that can
+    /// yield
+    in_generator: bool,
+    /// What every name refers to, recorded when resolving for tools
+    symbols: Option<SymbolTable>,
+    /// Variables declared without an initializer and not assigned since, by
+    /// the index of the scope they're in, or `None` for globals. Only tracked
+    /// with `use_before_assignment` on.
+    unassigned: Vec<(Option<usize>, String)>,
 }
 
 impl<'a> Resolver<'a> {
@@ -25,13 +78,51 @@ impl<'a> Resolver<'a> {
             scopes: Stack::new(),
             current_function_kind: None,
             current_class_kind: None,
+            loop_depth: 0,
+            in_loop_condition: false,
             errors: vec![],
+            captures: vec![],
+            class_methods: vec![],
+            module: false,
+            yield_site: None,
+            try_depth: 0,
+            in_generator: false,
+            symbols: None,
+            unassigned: vec![],
         }
     }
 
     pub fn resolve(mut self, statements: &[Stmt]) -> Result<()> {
         self.resolve_statements(statements)?;
+        self.finish()
+    }
+
+    /// Resolves the top-level code of an imported module, which runs in a
+    /// scope of its own. All of its top-level names are declared up front, so
+    /// functions can use the ones declared after them, like globals.
+    pub fn resolve_module(mut self, statements: &[Stmt]) -> Result<()> {
+        self.module = true;
+        self.begin_scope();
+        for name in statements.iter().flat_map(Stmt::declared_names) {
+            self.define(name);
+        }
 
+        self.resolve_statements(statements)?;
+        self.end_scope();
+        self.finish()
+    }
+
+    /// Resolves `statements` and returns the declaration every variable,
+    /// function and class name in them refers to
+    pub fn resolve_symbols(mut self, statements: &[Stmt]) -> Result<SymbolTable> {
+        self.symbols = Some(SymbolTable::default());
+        self.resolve_statements(statements)?;
+
+        let symbols = self.symbols.take().unwrap_or_default();
+        self.finish().map(|_| symbols)
+    }
+
+    fn finish(self) -> Result<()> {
         match self.errors.len() {
             0 => Ok(()),
             _ => Err(LoxError::ResolutionError(self.errors)),
@@ -54,23 +145,107 @@ impl<'a> Resolver<'a> {
         expr.accept(self)
     }
 
+    /// Resolves the expression of an expression statement or a variable's
+    /// initializer. A `yield` can suspend a coroutine there if it's the whole
+    /// expression or the value assigned to a variable, since the coroutine
+    /// resumes at a statement boundary.
+    fn resolve_statement_expression(&mut self, expr: &Expr) -> Result<()> {
+        let value = match expr {
+            Expr::Assign(_, value) => value,
+            expr => expr,
+        };
+
+        self.yield_site = match value {
+            Expr::Yield(keyword, _) => Some(keyword.id),
+            _ => None,
+        };
+
+        self.resolve_expression(expr)
+    }
+
     fn resolve_local(&mut self, name: &Token) {
+        if let Some(symbols) = &mut self.symbols {
+            symbols.reference(name);
+        }
+
         for (i, scope) in self.scopes.iter().enumerate().rev() {
             if scope.contains_key(&name.lexeme) {
                 self.interpreter.resolve(name, self.scopes.len() - 1 - i);
+                self.capture(&name.lexeme, i);
                 return;
             }
         }
     }
 
+    /// Index of the innermost scope declaring `name`, or `None` for a global
+    fn declaring_scope(&self, name: &str) -> Option<usize> {
+        self.scopes
+            .iter()
+            .enumerate()
+            .rev()
+            .find(|(_, scope)| scope.contains_key(name))
+            .map(|(i, _)| i)
+    }
+
+    /// Reports reading `name` before any assignment to it, with
+    /// `use_before_assignment` on. Reads in functions declared after the
+    /// variable are left alone, since they may well run after it's assigned.
+    fn check_assigned(&mut self, name: &Token) {
+        let scope = self.declaring_scope(&name.lexeme);
+        let position = self
+            .unassigned
+            .iter()
+            .position(|(index, unassigned)| *index == scope && *unassigned == name.lexeme);
+        let function_scope = self.captures.last().map(|captures| captures.depth);
+        let same_function = match (scope, function_scope) {
+            (_, None) => true,
+            (Some(index), Some(depth)) => index >= depth,
+            (None, Some(_)) => false,
+        };
+
+        if let (Some(position), true) = (position, same_function) {
+            // Once is enough, later reads would only repeat the error
+            self.unassigned.remove(position);
+            self.errors.push(ResolverErrorDetails {
+                message: format!("Can't read '{}' before it's assigned.", name.lexeme),
+                token: name.clone(),
+            });
+        }
+    }
+
+    fn mark_assigned(&mut self, name: &Token) {
+        let scope = self.declaring_scope(&name.lexeme);
+        self.unassigned
+            .retain(|(index, unassigned)| *index != scope || *unassigned != name.lexeme);
+    }
+
+    /// Adds a variable declared in the scope at `index` to the capture list of
+    /// every function being resolved that it's declared outside of
+    fn capture(&mut self, name: &str, index: usize) {
+        for captures in self.captures.iter_mut().filter(|c| c.depth > index) {
+            captures
+                .names
+                .insert((captures.depth - 1 - index, name.to_string()));
+        }
+    }
+
     fn resolve_function(
         &mut self,
         kind: FunctionKind,
         parameters: &[Token],
         body: &[Stmt],
-    ) -> Result<()> {
+        is_generator: bool,
+    ) -> Result<CaptureList> {
         let enclosing_function_kind = self.current_function_kind;
+        let enclosing_try_depth = self.try_depth;
+        let enclosing_in_generator = self.in_generator;
         self.current_function_kind = Some(kind);
+        self.try_depth = 0;
+        self.in_generator = is_generator;
+        self.captures.push(CaptureList {
+            depth: self.scopes.len(),
+            ..CaptureList::default()
+        });
 
         self.begin_scope();
         for parameter in parameters {
@@ -81,16 +256,23 @@ impl<'a> Resolver<'a> {
         self.resolve_statements(body)?;
         self.end_scope();
         self.current_function_kind = enclosing_function_kind;
+        self.try_depth = enclosing_try_depth;
+        self.in_generator = enclosing_in_generator;
 
-        Ok(())
+        Ok(self.captures.pop().unwrap_or_default())
     }
 
     #[allow(clippy::needless_return)]
     fn declare(&mut self, name: &Token) {
+        let redeclarable = self.module && self.scopes.len() == 1;
+        if let Some(symbols) = &mut self.symbols {
+            symbols.declare(name);
+        }
+
         match self.scopes.peek_mut() {
             None => return,
             Some(scope) => {
-                if scope.contains_key(&name.lexeme) {
+                if scope.contains_key(&name.lexeme) && !redeclarable {
                     self.errors.push(ResolverErrorDetails {
                         message: "Already a variable with this name in this scope.".into(),
                         token: name.clone(),
@@ -110,23 +292,116 @@ impl<'a> Resolver<'a> {
         };
     }
 
+    /// Whether the value of a literal-only expression with `operator` should
+    /// be cached instead of being re-evaluated on every loop iteration
+    fn cache_constant(&mut self, operator: &Token, operands: &[&Expr]) -> bool {
+        let is_constant = self.interpreter.config.optimize
+            && self.loop_depth > 0
+            && operands.iter().all(|operand| operand.is_literal_only());
+
+        if is_constant {
+            self.interpreter.cache_constant(operator);
+        }
+
+        is_constant
+    }
+
+    /// With private fields enabled, members whose name starts with `_` can only
+    /// be used through `this`, which limits them to the class's own methods
+    fn check_private_access(&mut self, object: &Expr, name: &Token) {
+        if self.interpreter.config.private_fields
+            && name.lexeme.starts_with('_')
+            && !matches!(object, Expr::This(_))
+        {
+            self.errors.push(ResolverErrorDetails {
+                message: format!(
+                    "'{}' is private and can only be accessed through 'this'.",
+                    name.lexeme
+                ),
+                token: name.clone(),
+            });
+        }
+    }
+
+    fn resolve_loop_body(&mut self, body: &Stmt) -> Result<()> {
+        self.loop_depth += 1;
+        let result = self.resolve_statement(body);
+        self.loop_depth -= 1;
+
+        result
+    }
+
     fn begin_scope(&mut self) {
         self.scopes.push(HashMap::new());
+        if let Some(symbols) = &mut self.symbols {
+            symbols.scopes.push(HashMap::new());
+        }
     }
 
     fn end_scope(&mut self) {
+        let depth = self.scopes.len();
+        self.unassigned
+            .retain(|(index, _)| index.is_none_or(|index| index + 1 < depth));
         self.scopes.pop();
+        if let Some(symbols) = &mut self.symbols {
+            symbols.scopes.pop();
+        }
+    }
+
+    fn resolve_try(
+        &mut self,
+        body: &[Stmt],
+        catch: Option<&CatchClause>,
+        finally: Option<&[Stmt]>,
+    ) -> Result<()> {
+        self.visit_block_stmt(body)?;
+
+        if let Some(catch) = catch {
+            self.begin_scope();
+            self.declare(&catch.name);
+            self.define(&catch.name);
+            self.resolve_statements(&catch.body)?;
+            self.end_scope();
+        }
+
+        if let Some(finally) = finally {
+            self.visit_block_stmt(finally)?;
+        }
+
+        Ok(())
+    }
+
+    /// Defines `this` or `super` in the innermost scope
+    fn declare_keyword(&mut self, keyword: &str) -> Result<()> {
+        match self.scopes.peek_mut() {
+            Some(scope) => {
+                scope.insert(keyword.into(), true);
+                Ok(())
+            }
+            None => Err(LoxError::internal(format!(
+                "No scope to declare '{}' in.",
+                keyword
+            ))),
+        }
     }
 }
 
 impl<'a> ExprVisitor<Result<()>> for Resolver<'a> {
-    fn visit_binary_expr(&mut self, left: &Expr, _operator: &Token, right: &Expr) -> Result<()> {
+    fn visit_binary_expr(&mut self, left: &Expr, operator: &Token, right: &Expr) -> Result<()> {
+        if self.cache_constant(operator, &[left, right]) {
+            return Ok(());
+        }
+
         self.resolve_expression(left)?;
         self.resolve_expression(right)?;
         Ok(())
     }
 
-    fn visit_unary_expr(&mut self, _operator: &Token, right: &Expr) -> Result<()> {
+    fn visit_unary_expr(&mut self, operator: &Token, right: &Expr) -> Result<()> {
+        if self.cache_constant(operator, &[right]) {
+            return Ok(());
+        }
+
         self.resolve_expression(right)
     }
 
@@ -147,12 +422,31 @@ impl<'a> ExprVisitor<Result<()>> for Resolver<'a> {
         }
 
         self.resolve_local(name);
+        self.check_assigned(name);
+
+        let is_global = !self
+            .scopes
+            .iter()
+            .any(|scope| scope.contains_key(&name.lexeme));
+        if self.interpreter.config.optimize && self.in_loop_condition && is_global {
+            self.interpreter.cache_global(name);
+        }
+
+        let is_sibling_method = self
+            .class_methods
+            .last()
+            .is_some_and(|methods| methods.contains(&name.lexeme));
+        if is_global && is_sibling_method {
+            self.interpreter.hint_method(name);
+        }
+
         Ok(())
     }
 
     fn visit_assign_expr(&mut self, name: &Token, value: &Expr) -> Result<()> {
         self.resolve_expression(value)?;
         self.resolve_local(name);
+        self.mark_assigned(name);
         Ok(())
     }
 
@@ -162,7 +456,13 @@ impl<'a> ExprVisitor<Result<()>> for Resolver<'a> {
         Ok(())
     }
 
-    fn visit_call_expr(&mut self, callee: &Expr, arguments: &[Expr], _paren: &Token) -> Result<()> {
+    fn visit_call_expr(
+        &mut self,
+        callee: &Expr,
+        arguments: &[Expr],
+        _paren: &Token,
+        _argument_starts: &[Token],
+    ) -> Result<()> {
         self.resolve_expression(callee)?;
         for argument in arguments {
             self.resolve_expression(argument)?;
@@ -171,12 +471,14 @@ impl<'a> ExprVisitor<Result<()>> for Resolver<'a> {
         Ok(())
     }
 
-    fn visit_get_expr(&mut self, object: &Expr, _name: &Token) -> Result<()> {
+    fn visit_get_expr(&mut self, object: &Expr, name: &Token) -> Result<()> {
+        self.check_private_access(object, name);
         self.resolve_expression(object)?;
         Ok(())
     }
 
-    fn visit_set_expr(&mut self, object: &Expr, _name: &Token, value: &Expr) -> Result<()> {
+    fn visit_set_expr(&mut self, object: &Expr, name: &Token, value: &Expr) -> Result<()> {
+        self.check_private_access(object, name);
         self.resolve_expression(value)?;
         self.resolve_expression(object)?;
         Ok(())
@@ -208,13 +510,116 @@ impl<'a> ExprVisitor<Result<()>> for Resolver<'a> {
         };
 
         self.resolve_local(keyword);
+
+        // `super` also reads `this` from the scope right inside it
+        let super_scope = self
+            .scopes
+            .iter()
+            .rposition(|scope| scope.contains_key("super"));
+        if let Some(index) = super_scope {
+            self.capture("this", index + 1);
+        }
+
+        Ok(())
+    }
+
+    fn visit_match_expr(
+        &mut self,
+        _keyword: &Token,
+        subject: &Expr,
+        arms: &[MatchArm],
+    ) -> Result<()> {
+        self.resolve_expression(subject)?;
+
+        let mut has_catch_all = false;
+        for arm in arms {
+            if has_catch_all {
+                self.errors.push(ResolverErrorDetails {
+                    message: "Unreachable match arm after a catch-all pattern.".into(),
+                    token: arm.pattern.token().clone(),
+                });
+            }
+
+            let binding = match &arm.pattern {
+                Pattern::Binding(name) => Some(name),
+                _ => None,
+            };
+
+            if let Some(name) = binding {
+                self.begin_scope();
+                self.declare(name);
+                self.define(name);
+            }
+
+            if let Some(guard) = &arm.guard {
+                self.resolve_expression(guard)?;
+            }
+
+            self.resolve_expression(&arm.body)?;
+
+            if binding.is_some() {
+                self.end_scope();
+            }
+
+            has_catch_all |= arm.guard.is_none() && arm.pattern.is_irrefutable();
+        }
+
+        Ok(())
+    }
+
+    fn visit_list_expr(&mut self, _bracket: &Token, elements: &[Expr]) -> Result<()> {
+        for element in elements {
+            self.resolve_expression(element)?;
+        }
+
+        Ok(())
+    }
+
+    fn visit_destructure_expr(
+        &mut self,
+        _paren: &Token,
+        names: &[Token],
+        value: &Expr,
+    ) -> Result<()> {
+        self.resolve_expression(value)?;
+        for name in names {
+            self.resolve_local(name);
+            self.mark_assigned(name);
+        }
+
         Ok(())
     }
+
+    fn visit_yield_expr(&mut self, keyword: &Token, value: Option<&Expr>) -> Result<()> {
+        let message = match self.current_function_kind {
+            None => Some("Can't yield from top-level code."),
+            Some(_) if !self.in_generator => {
+                Some("Can only yield inside a generator, declared with 'fun*'.")
+            }
+            Some(_) if self.yield_site != Some(keyword.id) => Some(
+                "'yield' can only be a statement, a variable's initializer or the value assigned to a variable.",
+            ),
+            Some(_) if self.try_depth > 0 => Some("Can't yield inside a 'try' statement."),
+            Some(_) => None,
+        };
+
+        if let Some(message) = message {
+            self.errors.push(ResolverErrorDetails {
+                message: message.into(),
+                token: keyword.clone(),
+            });
+        }
+
+        match value {
+            Some(value) => self.resolve_expression(value),
+            None => Ok(()),
+        }
+    }
 }
 
 impl<'a> StmtVisitor<Result<()>> for Resolver<'a> {
     fn visit_expression_stmt(&mut self, expr: &Expr) -> Result<()> {
-        self.resolve_expression(expr)
+        self.resolve_statement_expression(expr)
     }
 
     fn visit_print_stmt(&mut self, expr: &Expr) -> Result<()> {
@@ -224,14 +629,43 @@ impl<'a> StmtVisitor<Result<()>> for Resolver<'a> {
     fn visit_var_stmt(&mut self, name: &Token, initializer: Option<&Expr>) -> Result<()> {
         self.declare(name);
         if let Some(init) = initializer {
-            self.resolve_expression(init)?;
+            self.resolve_statement_expression(init)?;
         }
         self.define(name);
 
+        self.mark_assigned(name);
+        if initializer.is_none() && self.interpreter.config.strictness.use_before_assignment {
+            self.unassigned
+                .push((self.scopes.len().checked_sub(1), name.lexeme.clone()));
+        }
+
+        Ok(())
+    }
+
+    fn visit_var_destructure_stmt(
+        &mut self,
+        _paren: &Token,
+        names: &[Token],
+        initializer: &Expr,
+    ) -> Result<()> {
+        for name in names {
+            self.declare(name);
+        }
+
+        self.resolve_expression(initializer)?;
+
+        for name in names {
+            self.define(name);
+        }
+
         Ok(())
     }
 
     fn visit_block_stmt(&mut self, statements: &[Stmt]) -> Result<()> {
+        if !Stmt::declares_names(statements) {
+            return self.resolve_statements(statements);
+        }
+
         self.begin_scope();
         self.resolve_statements(statements)?;
         self.end_scope();
@@ -254,9 +688,26 @@ impl<'a> StmtVisitor<Result<()>> for Resolver<'a> {
         Ok(())
     }
 
-    fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt) -> Result<()> {
-        self.resolve_expression(condition)?;
-        self.resolve_statement(body)?;
+    fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt, _: Option<&Token>) -> Result<()> {
+        self.loop_depth += 1;
+        self.in_loop_condition = true;
+        let result = self.resolve_expression(condition);
+        self.in_loop_condition = false;
+        self.loop_depth -= 1;
+
+        result?;
+        self.resolve_loop_body(body)
+    }
+
+    fn visit_for_in_stmt(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> Result<()> {
+        self.resolve_expression(iterable)?;
+
+        self.begin_scope();
+        self.declare(name);
+        self.define(name);
+        self.resolve_loop_body(body)?;
+        self.end_scope();
+
         Ok(())
     }
 
@@ -265,10 +716,13 @@ impl<'a> StmtVisitor<Result<()>> for Resolver<'a> {
         name: &Token,
         parameters: &[Token],
         body: &[Stmt],
+        is_generator: bool,
     ) -> Result<()> {
         self.declare(name);
         self.define(name);
-        self.resolve_function(FunctionKind::Function, parameters, body)?;
+        let captures =
+            self.resolve_function(FunctionKind::Function, parameters, body, is_generator)?;
+        self.interpreter.capture(name, captures);
 
         Ok(())
     }
@@ -299,42 +753,66 @@ impl<'a> StmtVisitor<Result<()>> for Resolver<'a> {
         &mut self,
         name: &Token,
         superclass: Option<&Expr>,
+        mixins: &[Expr],
         methods: &[Stmt],
     ) -> Result<()> {
         let enclosing_class_kind = self.current_class_kind;
         self.current_class_kind = Some(ClassKind::Class);
+        self.class_methods.push(
+            methods
+                .iter()
+                .filter_map(|method| match method {
+                    Stmt::Function(name, ..) => Some(name.lexeme.clone()),
+                    _ => None,
+                })
+                .collect(),
+        );
 
         self.declare(name);
         self.define(name);
 
+        for mixin in mixins {
+            if let Expr::Variable(mixin_name) = mixin {
+                if mixin_name.lexeme == name.lexeme {
+                    self.errors.push(ResolverErrorDetails {
+                        message: "A class can't mix in itself.".into(),
+                        token: mixin_name.clone(),
+                    });
+                }
+            }
+
+            self.resolve_expression(mixin)?;
+        }
+
         if let Some(superclass) = superclass {
             self.current_class_kind = Some(ClassKind::Subclass);
             // TODO staticly determine if a class tries to extend itself
             self.resolve_expression(superclass)?;
             self.begin_scope();
-            self.scopes
-                .peek_mut()
-                .expect("Unexpected global scope")
-                .insert("super".into(), true);
+            self.declare_keyword("super")?;
         }
 
         self.begin_scope();
-        self.scopes
-            .peek_mut()
-            .expect("Unexpected global scope")
-            .insert("this".into(), true);
+        self.declare_keyword("this")?;
 
         for method in methods {
             match method {
-                Stmt::Function(name, parameters, body) => {
+                Stmt::Function(name, parameters, body, is_generator, _) => {
                     let kind = match name.lexeme == "init" {
                         true => FunctionKind::Initializer,
                         false => FunctionKind::Method,
                     };
 
-                    self.resolve_function(kind, parameters, body)?;
+                    if *is_generator && matches!(kind, FunctionKind::Initializer) {
+                        self.errors.push(ResolverErrorDetails {
+                            message: "An initializer can't be a generator.".into(),
+                            token: name.clone(),
+                        });
+                    }
+
+                    self.resolve_function(kind, parameters, body, *is_generator)?;
                 }
-                _ => unreachable!(),
+                _ => return Err(LoxError::internal("Class body contains a non-method.")),
             };
         }
 
@@ -345,9 +823,159 @@ impl<'a> StmtVisitor<Result<()>> for Resolver<'a> {
         }
 
         self.current_class_kind = enclosing_class_kind;
+        self.class_methods.pop();
+
+        Ok(())
+    }
+
+    fn visit_try_stmt(
+        &mut self,
+        body: &[Stmt],
+        catch: Option<&CatchClause>,
+        finally: Option<&[Stmt]>,
+    ) -> Result<()> {
+        self.try_depth += 1;
+        let result = self.resolve_try(body, catch, finally);
+        self.try_depth -= 1;
+
+        result
+    }
+
+    fn visit_throw_stmt(&mut self, _keyword: &Token, value: &Expr) -> Result<()> {
+        self.resolve_expression(value)
+    }
+
+    fn visit_delete_stmt(&mut self, object: &Expr, name: &Token) -> Result<()> {
+        self.check_private_access(object, name);
+        self.resolve_expression(object)
+    }
+
+    fn visit_import_stmt(
+        &mut self,
+        _keyword: &Token,
+        path: &Token,
+        target: &ImportTarget,
+    ) -> Result<()> {
+        if let ImportTarget::Names(names) = target {
+            let path = import_path(path)?;
+            if let Some(exports) = self.interpreter.modules().exports(path) {
+                for name in names.iter().filter(|name| !exports.contains(&name.lexeme)) {
+                    self.errors.push(ResolverErrorDetails {
+                        message: format!("'{}' isn't exported by '{}'.", name.lexeme, path),
+                        token: name.clone(),
+                    });
+                }
+            }
+        }
+
+        for name in target.names() {
+            self.declare(name);
+            self.define(name);
+        }
+
+        if let (ImportTarget::Names(names), Some(symbols)) = (target, &mut self.symbols) {
+            for name in names {
+                symbols.import(name, &path.lexeme);
+            }
+        }
 
         Ok(())
     }
+
+    fn visit_export_stmt(&mut self, _keyword: &Token, declaration: &Stmt) -> Result<()> {
+        self.resolve_statement(declaration)
+    }
+}
+
+/// What a name refers to
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Symbol {
+    /// A variable in a local scope, identified by the id of the token
+    /// declaring it
+    Local(usize),
+    /// A global, or a native when the program doesn't declare it
+    Global(String),
+}
+
+/// The symbol every declaration of and reference to a name refers to, built by
+/// [`Resolver::resolve_symbols`]
+#[derive(Debug, Default)]
+pub struct SymbolTable {
+    occurrences: Vec<(Token, Symbol)>,
+    /// Globals the program declares, as opposed to natives
+    globals: HashSet<String>,
+    /// Path of the module each name imported with `import { .. }` comes from
+    imports: HashMap<Symbol, String>,
+    /// Ids of the declaring tokens of the names in each open scope
+    scopes: Vec<HashMap<String, usize>>,
+}
+
+impl SymbolTable {
+    /// Declarations and references, in the order they were resolved
+    pub fn occurrences(&self) -> impl Iterator<Item = (&Token, &Symbol)> {
+        self.occurrences
+            .iter()
+            .map(|(token, symbol)| (token, symbol))
+    }
+
+    /// The symbol of the name covering byte `offset` of the source
+    pub fn at(&self, offset: usize) -> Option<(&Token, &Symbol)> {
+        self.occurrences()
+            .find(|(token, _)| token.span.start <= offset && offset < token.span.end)
+    }
+
+    /// Whether the program declares `symbol`, which natives aren't
+    pub fn is_declared(&self, symbol: &Symbol) -> bool {
+        match symbol {
+            Symbol::Local(_) => true,
+            Symbol::Global(name) => self.globals.contains(name),
+        }
+    }
+
+    /// Path of the module `symbol` is imported from by name
+    pub fn import_path(&self, symbol: &Symbol) -> Option<&str> {
+        self.imports.get(symbol).map(String::as_str)
+    }
+
+    fn declare(&mut self, name: &Token) {
+        let symbol = match self.scopes.last_mut() {
+            Some(scope) => {
+                scope.insert(name.lexeme.clone(), name.id);
+                Symbol::Local(name.id)
+            }
+            None => {
+                self.globals.insert(name.lexeme.clone());
+                Symbol::Global(name.lexeme.clone())
+            }
+        };
+
+        self.occurrences.push((name.clone(), symbol));
+    }
+
+    /// Marks the name just declared by an import as coming from `path`
+    fn import(&mut self, name: &Token, path: &str) {
+        if let Some((_, symbol)) = self
+            .occurrences
+            .iter()
+            .rfind(|(token, _)| token.id == name.id)
+        {
+            self.imports.insert(symbol.clone(), path.to_string());
+        }
+    }
+
+    fn reference(&mut self, name: &Token) {
+        let symbol = self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&name.lexeme))
+            .map_or_else(
+                || Symbol::Global(name.lexeme.clone()),
+                |id| Symbol::Local(*id),
+            );
+
+        self.occurrences.push((name.clone(), symbol));
+    }
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -396,7 +1024,7 @@ impl<T> Stack<T> {
         self.0.len()
     }
 
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T> {
         self.0.iter()
     }
 }
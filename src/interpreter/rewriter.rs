@@ -0,0 +1,285 @@
+use anyhow::anyhow;
+
+use crate::interpreter::{
+    error::{LoxError, Result},
+    expr::Expr,
+    parser::{Parser, ParserConfig},
+    scanner::Scanner,
+    span::Span,
+    stmt::Stmt,
+    token::Token,
+    token_kind::TokenKind,
+};
+
+/// Replaces the source text in `span` with `text`. An empty span inserts it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Edit {
+    pub span: Span,
+    pub text: String,
+}
+
+/// A source-to-source transform, like a codemod.
+///
+/// `rewrite` calls the hooks for every statement and expression of the
+/// program, enclosing ones before the ones nested in them, and the transform
+/// records what to change with the `Rewriter`. The default hooks change
+/// nothing.
+///
+/// ```ignore
+/// struct Rename;
+///
+/// impl Transform for Rename {
+///     fn expression(&mut self, expr: &Expr, rewriter: &mut Rewriter) {
+///         if let Expr::Variable(name) = expr {
+///             if name.lexeme == "total" {
+///                 rewriter.replace_token(name, "sum");
+///             }
+///         }
+///     }
+/// }
+///
+/// let source = "var total = 1;\nprint total; // the total\n";
+/// let rewritten = rewrite(source, &mut Rename).unwrap();
+/// assert_eq!(rewritten, "var total = 1;\nprint sum; // the total\n");
+/// ```
+pub trait Transform {
+    fn statement(&mut self, _statement: &Stmt, _rewriter: &mut Rewriter) {}
+
+    fn expression(&mut self, _expr: &Expr, _rewriter: &mut Rewriter) {}
+}
+
+/// Parses `source`, runs `transform` over it and returns the rewritten source
+pub fn rewrite(source: &str, transform: &mut dyn Transform) -> Result<String> {
+    let tokens = Scanner::new(source.to_string()).scan_tokens()?;
+    let statements = Parser::new(tokens.clone(), ParserConfig::default()).parse()?;
+
+    rewrite_parsed(source, tokens, &statements, transform)
+}
+
+/// Like `rewrite`, for a program already parsed from `source` into `tokens`
+/// and `statements`, so a transform can use what other passes, like the
+/// resolver, recorded about them
+pub fn rewrite_parsed(
+    source: &str,
+    tokens: Vec<Token>,
+    statements: &[Stmt],
+    transform: &mut dyn Transform,
+) -> Result<String> {
+    let mut rewriter = Rewriter::new(source, tokens);
+    walk_statements(statements, transform, &mut rewriter);
+
+    rewriter.finish()
+}
+
+/// Collects edits to a source file and applies them all at once.
+///
+/// Edits are made to spans of the original source, so everything a transform
+/// doesn't touch, comments and formatting included, comes out unchanged.
+pub struct Rewriter<'a> {
+    source: &'a str,
+    /// Tokens of the source, in order
+    tokens: Vec<Token>,
+    edits: Vec<Edit>,
+}
+
+impl<'a> Rewriter<'a> {
+    /// A rewriter for `source`, which `tokens` were scanned from
+    pub fn new(source: &'a str, tokens: Vec<Token>) -> Self {
+        Self {
+            source,
+            tokens,
+            edits: vec![],
+        }
+    }
+
+    pub fn source(&self) -> &str {
+        self.source
+    }
+
+    /// Source text in `span`
+    pub fn text(&self, span: Span) -> &str {
+        &self.source[span.start..span.end]
+    }
+
+    pub fn replace(&mut self, span: Span, text: impl Into<String>) {
+        self.edits.push(Edit {
+            span,
+            text: text.into(),
+        });
+    }
+
+    /// Replaces the text of `token`, returning whether it did. Tokens made up
+    /// while desugaring, like the `close` call of a `using` block, don't
+    /// appear in the source and are left alone.
+    pub fn replace_token(&mut self, token: &Token, text: impl Into<String>) -> bool {
+        if self.source.get(token.span.start..token.span.end) != Some(token.lexeme.as_str()) {
+            return false;
+        }
+
+        self.replace(token.span, text);
+        true
+    }
+
+    pub fn insert_before(&mut self, token: &Token, text: impl Into<String>) {
+        self.replace(Span::new(token.span.start, token.span.start), text);
+    }
+
+    pub fn insert_after(&mut self, token: &Token, text: impl Into<String>) {
+        self.replace(Span::new(token.span.end, token.span.end), text);
+    }
+
+    /// The token following `token` in the source
+    pub fn next_token(&self, token: &Token) -> Option<&Token> {
+        self.tokens.get(self.index_after(token))
+    }
+
+    /// The first `kind` token after `token` that isn't nested in brackets
+    /// opened after it, like the `;` ending a statement. Stops at the end of
+    /// the brackets `token` is in.
+    pub fn find_after(&self, token: &Token, kind: TokenKind) -> Option<&Token> {
+        let mut depth = 0usize;
+        for next in &self.tokens[self.index_after(token)..] {
+            if depth == 0 && next.kind == kind {
+                return Some(next);
+            }
+
+            match next.kind {
+                TokenKind::LeftParen | TokenKind::LeftBrace | TokenKind::LeftBracket => depth += 1,
+                TokenKind::RightParen | TokenKind::RightBrace | TokenKind::RightBracket => {
+                    depth = depth.checked_sub(1)?
+                }
+                _ => (),
+            }
+        }
+
+        None
+    }
+
+    fn index_after(&self, token: &Token) -> usize {
+        self.tokens
+            .partition_point(|other| other.span.start <= token.span.start)
+    }
+
+    /// Applies the edits, failing if any two of them overlap. Insertions at
+    /// the same place are kept in the order they were made.
+    pub fn finish(mut self) -> Result<String> {
+        self.edits
+            .sort_by_key(|edit| (edit.span.start, edit.span.end));
+
+        let mut output = String::with_capacity(self.source.len());
+        let mut copied = 0;
+        for edit in &self.edits {
+            if edit.span.start < copied {
+                return Err(LoxError::Other(anyhow!(
+                    "Edits overlap at byte {}.",
+                    edit.span.start
+                )));
+            }
+
+            output.push_str(&self.source[copied..edit.span.start]);
+            output.push_str(&edit.text);
+            copied = edit.span.end;
+        }
+        output.push_str(&self.source[copied..]);
+
+        Ok(output)
+    }
+}
+
+fn walk_statements(statements: &[Stmt], transform: &mut dyn Transform, rewriter: &mut Rewriter) {
+    for statement in statements {
+        walk_statement(statement, transform, rewriter);
+    }
+}
+
+fn walk_statement(statement: &Stmt, transform: &mut dyn Transform, rewriter: &mut Rewriter) {
+    transform.statement(statement, rewriter);
+
+    match statement {
+        Stmt::Expression(expr)
+        | Stmt::Print(_, expr)
+        | Stmt::VarDestructure(_, _, expr)
+        | Stmt::Throw(_, expr)
+        | Stmt::Delete(expr, _) => walk_expression(expr, transform, rewriter),
+        Stmt::Var(_, initializer, _) | Stmt::Return(_, initializer) => {
+            if let Some(expr) = initializer {
+                walk_expression(expr, transform, rewriter);
+            }
+        }
+        Stmt::Block(statements) | Stmt::Function(_, _, statements, ..) => {
+            walk_statements(statements, transform, rewriter)
+        }
+        Stmt::If(condition, then_branch, else_branch) => {
+            walk_expression(condition, transform, rewriter);
+            walk_statement(then_branch, transform, rewriter);
+            if let Some(else_branch) = else_branch {
+                walk_statement(else_branch, transform, rewriter);
+            }
+        }
+        Stmt::While(condition, body, _) | Stmt::ForIn(_, condition, body) => {
+            walk_expression(condition, transform, rewriter);
+            walk_statement(body, transform, rewriter);
+        }
+        Stmt::Class(_, superclass, mixins, methods) => {
+            for expr in superclass.iter().chain(mixins) {
+                walk_expression(expr, transform, rewriter);
+            }
+            walk_statements(methods, transform, rewriter);
+        }
+        Stmt::Try(body, catch, finally) => {
+            walk_statements(body, transform, rewriter);
+            if let Some(catch) = catch {
+                walk_statements(&catch.body, transform, rewriter);
+            }
+            if let Some(finally) = finally {
+                walk_statements(finally, transform, rewriter);
+            }
+        }
+        Stmt::Import(..) => (),
+        Stmt::Export(_, declaration) => walk_statement(declaration, transform, rewriter),
+    }
+}
+
+fn walk_expression(expr: &Expr, transform: &mut dyn Transform, rewriter: &mut Rewriter) {
+    transform.expression(expr, rewriter);
+
+    match expr {
+        Expr::Literal(_)
+        | Expr::Variable(_)
+        | Expr::This(_)
+        | Expr::Super(..)
+        | Expr::Yield(_, None) => (),
+        Expr::Grouping(expr)
+        | Expr::Unary(_, expr)
+        | Expr::Assign(_, expr)
+        | Expr::Get(expr, _)
+        | Expr::Destructure(_, _, expr)
+        | Expr::Yield(_, Some(expr)) => walk_expression(expr, transform, rewriter),
+        Expr::Binary(left, _, right)
+        | Expr::Logical(left, _, right)
+        | Expr::Set(left, _, right) => {
+            walk_expression(left, transform, rewriter);
+            walk_expression(right, transform, rewriter);
+        }
+        Expr::Call(callee, arguments, ..) => {
+            walk_expression(callee, transform, rewriter);
+            for argument in arguments {
+                walk_expression(argument, transform, rewriter);
+            }
+        }
+        Expr::Match(_, subject, arms) => {
+            walk_expression(subject, transform, rewriter);
+            for arm in arms {
+                if let Some(guard) = &arm.guard {
+                    walk_expression(guard, transform, rewriter);
+                }
+                walk_expression(&arm.body, transform, rewriter);
+            }
+        }
+        Expr::List(_, elements) => {
+            for element in elements {
+                walk_expression(element, transform, rewriter);
+            }
+        }
+    }
+}
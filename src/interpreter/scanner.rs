@@ -1,10 +1,20 @@
-use crate::{
+use crate::interpreter::{
     error::{LoxError, Result, ScannerErrorDetails},
+    keywords::Keywords,
+    span::{LineIndex, Span},
     token::Token,
     token_kind::TokenKind,
     value::Value,
 };
 
+/// The tokens of a source, and the index to find the line and column of
+/// their spans with
+#[derive(Debug)]
+pub struct Scan {
+    pub tokens: Vec<Token>,
+    pub line_index: LineIndex,
+}
+
 pub struct Scanner {
     source: String,
     tokens: Vec<Token>,
@@ -12,6 +22,7 @@ pub struct Scanner {
     current: usize,
     line: usize,
     scanning_errors: Vec<ScannerErrorDetails>,
+    keywords: Keywords,
 }
 
 impl Scanner {
@@ -23,32 +34,68 @@ impl Scanner {
             current: 0,
             line: 1,
             scanning_errors: vec![],
+            keywords: Keywords::default(),
         }
     }
 
-    pub fn scan_tokens(mut self) -> Result<Vec<Token>> {
+    /// Scans with `keywords` instead of the standard ones
+    pub fn with_keywords(mut self, keywords: Keywords) -> Self {
+        self.keywords = keywords;
+        self
+    }
+
+    pub fn scan_tokens(self) -> Result<Vec<Token>> {
+        self.scan().map(|scan| scan.tokens)
+    }
+
+    /// Scans the tokens like `scan_tokens`, along with the source's line
+    /// index, which scanning errors carry too
+    pub fn scan(mut self) -> Result<Scan> {
+        self.skip_preamble();
+
         while !self.is_at_end() {
             self.start = self.current;
             self.scan_token();
         }
 
         self.tokens.push(Token {
-            id: self.current,
+            id: Token::next_id(),
             kind: TokenKind::Eof,
             lexeme: "".into(),
             literal: None,
             line: self.line,
+            span: Span::new(self.current, self.current),
         });
 
+        let line_index = LineIndex::new(&self.source);
         match self.scanning_errors.len() {
-            0 => Ok(self.tokens),
+            0 => Ok(Scan {
+                tokens: self.tokens,
+                line_index,
+            }),
             _ => Err(LoxError::ScanningError {
                 tokens: self.tokens,
+                line_index,
                 details: self.scanning_errors,
             }),
         }
     }
 
+    /// Skips a leading UTF-8 byte order mark and a `#!` line so scripts can be
+    /// executed directly. The newline ending the shebang is left to be scanned
+    /// so line numbers stay accurate.
+    fn skip_preamble(&mut self) {
+        if self.source.starts_with('\u{feff}') {
+            self.current += '\u{feff}'.len_utf8();
+        }
+
+        if self.source[self.current..].starts_with("#!") {
+            while self.peek() != '\n' && !self.is_at_end() {
+                self.advance();
+            }
+        }
+    }
+
     fn scan_token(&mut self) {
         match self.advance() {
             // Single-character tokens
@@ -56,11 +103,17 @@ impl Scanner {
             ')' => self.add_token(TokenKind::RightParen),
             '{' => self.add_token(TokenKind::LeftBrace),
             '}' => self.add_token(TokenKind::RightBrace),
+            '[' => self.add_token(TokenKind::LeftBracket),
+            ']' => self.add_token(TokenKind::RightBracket),
             ',' => self.add_token(TokenKind::Comma),
+            '.' if Scanner::is_digit(self.peek()) => self.parse_leading_dot_number(),
             '.' => self.add_token(TokenKind::Dot),
+            '-' if self.match_char('>') => self.add_token(TokenKind::Arrow),
             '-' => self.add_token(TokenKind::Minus),
             '+' => self.add_token(TokenKind::Plus),
             ';' => self.add_token(TokenKind::Semicolon),
+            ':' => self.add_token(TokenKind::Colon),
+            '?' => self.add_token(TokenKind::Question),
             '*' => self.add_token(TokenKind::Star),
 
             // One or two character tokens
@@ -87,23 +140,118 @@ impl Scanner {
             '\n' => self.line += 1,
 
             // Literals and keywords
-            '"' => self.parse_string(),
+            '"' => self.parse_string(false),
+            'r' if self.match_char('"') => self.parse_string(true),
 
+            '0' if matches!(self.peek(), 'x' | 'X') => self.parse_radix_number(16),
+            '0' if matches!(self.peek(), 'b' | 'B') => self.parse_radix_number(2),
             c if Scanner::is_digit(c) => self.parse_number(),
 
             c if Scanner::is_alpha(c) => self.parse_identifier(),
 
-            c => self.report_error(self.line, &format!("Unexpected character '{}'.", c)),
+            c if Scanner::closing_smart_quote(c).is_some() => self.parse_smart_quoted(c),
+
+            _ => self.skip_unexpected(),
+        }
+    }
+
+    /// Skips a run of characters that can't start a token, reporting them
+    /// all in one error, so pasted text doesn't bury the source in one error
+    /// per character
+    fn skip_unexpected(&mut self) {
+        while !self.is_at_end() && Scanner::is_unexpected(self.peek()) {
+            self.advance();
+        }
+
+        let snippet = self.str_at(self.start, self.current);
+        let message = match snippet.chars().count() {
+            1 => format!("Unexpected character '{}'.", snippet),
+            _ => format!("Unexpected characters '{}'.", snippet),
+        };
+        self.report_error(&message);
+    }
+
+    /// Whether `c` can't start a token, nor be skipped as whitespace
+    fn is_unexpected(c: char) -> bool {
+        !matches!(
+            c,
+            '(' | ')'
+                | '{'
+                | '}'
+                | '['
+                | ']'
+                | ','
+                | '.'
+                | '-'
+                | '+'
+                | ';'
+                | ':'
+                | '?'
+                | '*'
+                | '!'
+                | '='
+                | '<'
+                | '>'
+                | '/'
+                | '"'
+                | ' '
+                | '\r'
+                | '\t'
+                | '\n'
+        ) && !Scanner::is_alpha_numeric(c)
+            && Scanner::closing_smart_quote(c).is_none()
+    }
+
+    /// The quotes that can close a string opened with the typographic quote
+    /// `c`, which word processors and chat apps put in place of `"`
+    fn closing_smart_quote(c: char) -> Option<[char; 2]> {
+        match c {
+            '\u{201c}' | '\u{201d}' | '\u{201e}' => Some(['\u{201c}', '\u{201d}']),
+            '\u{2018}' | '\u{2019}' | '\u{201a}' => Some(['\u{2018}', '\u{2019}']),
+            _ => None,
         }
     }
 
+    /// Scans text between typographic quotes, like `“hello”`, as the string
+    /// it was meant to be, so parsing carries on, with a single error
+    /// suggesting straight quotes. An opening quote without a closing one on
+    /// the same line is only reported.
+    fn parse_smart_quoted(&mut self, opening: char) {
+        let closing = Scanner::closing_smart_quote(opening).unwrap_or_default();
+        let rest = &self.source[self.current..];
+        let end = rest
+            .find(|c| c == '\n' || closing.contains(&c))
+            .filter(|&end| rest[end..].starts_with(closing));
+
+        let end = match end {
+            Some(end) => end,
+            None => {
+                return self.report_error(&format!(
+                    "Unexpected character '{}'. Strings need straight quotes, like \"text\".",
+                    opening
+                ))
+            }
+        };
+
+        let contents = rest[..end].to_string();
+        self.current += end;
+        self.advance();
+
+        self.report_error(&format!(
+            "Strings need straight quotes: \"{}\" instead of {}.",
+            contents,
+            self.str_at(self.start, self.current)
+        ));
+        self.add_token_literal(TokenKind::String, contents);
+    }
+
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
 
     fn advance(&mut self) -> char {
         let c = self.char_at(self.current);
-        self.current += 1;
+        self.current += c.len_utf8();
         c
     }
 
@@ -116,11 +264,11 @@ impl Scanner {
     }
 
     fn peek_next(&self) -> char {
-        if self.current + 1 >= self.source.len() {
-            return '\0';
-        }
+        self.peek_nth(1)
+    }
 
-        self.char_at(self.current + 1)
+    fn peek_nth(&self, n: usize) -> char {
+        self.source[self.current..].chars().nth(n).unwrap_or('\0')
     }
 
     fn add_token(&mut self, kind: TokenKind) {
@@ -135,11 +283,12 @@ impl Scanner {
     fn create_token(&self, kind: TokenKind, literal: Option<Value>) -> Token {
         let lexeme = self.str_at(self.start, self.current).to_string();
         Token {
-            id: self.current,
+            id: Token::next_id(),
             kind,
             lexeme,
             literal,
             line: self.line,
+            span: Span::new(self.start, self.current),
         }
     }
 
@@ -152,11 +301,19 @@ impl Scanner {
             return false;
         }
 
-        self.current += 1;
+        self.current += expected.len_utf8();
         true
     }
 
-    fn parse_string(&mut self) {
+    /// Scans a string literal. In normal strings a `\` at the end of a line
+    /// joins it with the next one, leaving out the line break and the next
+    /// line's indentation. Raw strings, written `r"..."`, keep their contents
+    /// exactly as written, and so do strings opened with three or more quotes.
+    fn parse_string(&mut self, raw: bool) {
+        if self.peek() == '"' && self.peek_next() == '"' {
+            return self.parse_long_string();
+        }
+
         while self.peek() != '"' && !self.is_at_end() {
             if self.peek() == '\n' {
                 self.line += 1;
@@ -166,7 +323,7 @@ impl Scanner {
         }
 
         if self.is_at_end() {
-            self.report_error(self.line, "Unterminated string.");
+            self.report_error("Unterminated string.");
 
             return;
         }
@@ -174,11 +331,87 @@ impl Scanner {
         // the closing "
         self.advance();
 
-        // trim surrounding quotes
-        let value = self.str_at(self.start + 1, self.current - 1).to_string();
+        // trim surrounding quotes and the r of raw strings
+        let opening = if raw { 2 } else { 1 };
+        let contents = self.str_at(self.start + opening, self.current - 1);
+        let value = match raw {
+            true => contents.to_string(),
+            false => Scanner::join_continued_lines(contents),
+        };
         self.add_token_literal(TokenKind::String, value);
     }
 
+    /// Scans a string delimited by a run of three or more quotes, like
+    /// `"""..."""`, which ends at the next run at least as long. Quotes
+    /// inside need no escaping, and more of them can be used as the delimiter
+    /// when the contents have a run of three. A line break right after the
+    /// opening quotes isn't part of the string, so the contents can start on
+    /// their own line.
+    fn parse_long_string(&mut self) {
+        let mut quotes = 1;
+        while self.peek() == '"' {
+            self.advance();
+            quotes += 1;
+        }
+
+        let delimiter = "\"".repeat(quotes);
+        let contents_start = self.current;
+        while !self.source[self.current..].starts_with(&delimiter) {
+            if self.is_at_end() {
+                self.report_error("Unterminated string.");
+                return;
+            }
+
+            if self.peek() == '\n' {
+                self.line += 1;
+            }
+
+            self.advance();
+        }
+
+        // Quotes before the closing delimiter in a longer run are contents
+        while self.source[self.current + 1..].starts_with(&delimiter) {
+            self.advance();
+        }
+
+        let contents = self.str_at(contents_start, self.current);
+        let contents = contents
+            .strip_prefix("\r\n")
+            .or_else(|| contents.strip_prefix('\n'))
+            .unwrap_or(contents)
+            .to_string();
+
+        self.current += quotes;
+        self.add_token_literal(TokenKind::String, contents);
+    }
+
+    fn join_continued_lines(contents: &str) -> String {
+        let mut value = String::with_capacity(contents.len());
+        let mut lines = contents.split('\n').peekable();
+        let mut continued = false;
+
+        while let Some(line) = lines.next() {
+            let line = if continued { line.trim_start() } else { line };
+            let is_last = lines.peek().is_none();
+
+            match line.trim_end_matches('\r').strip_suffix('\\') {
+                Some(joined) if !is_last => {
+                    value.push_str(joined);
+                    continued = true;
+                }
+                _ => {
+                    value.push_str(line);
+                    if !is_last {
+                        value.push('\n');
+                    }
+                    continued = false;
+                }
+            }
+        }
+
+        value
+    }
+
     fn parse_number(&mut self) {
         while Scanner::is_digit(self.peek()) {
             self.advance();
@@ -193,56 +426,133 @@ impl Scanner {
             self.advance();
         }
 
+        if matches!(self.peek(), 'e' | 'E') {
+            self.parse_exponent();
+        }
+
         let value = self
             .str_at(self.start, self.current)
             .to_string()
             .parse::<f64>()
-            .unwrap();
+            .unwrap_or(0.0);
+
+        self.add_token_literal(TokenKind::Number, value);
+    }
+
+    /// Scans `e10`, `e+10` or `e-10` after the digits of a number. An `e` that
+    /// isn't followed by digits is an error unless it starts an identifier.
+    fn parse_exponent(&mut self) {
+        let digits_at = match self.peek_next() {
+            '+' | '-' => 2,
+            _ => 1,
+        };
+
+        if Scanner::is_digit(self.peek_nth(digits_at)) {
+            for _ in 0..digits_at {
+                self.advance();
+            }
+
+            while Scanner::is_digit(self.peek()) {
+                self.advance();
+            }
+        } else if !Scanner::is_alpha_numeric(self.peek_next()) {
+            self.advance();
+            if matches!(self.peek(), '+' | '-') {
+                self.advance();
+            }
+
+            self.report_error("Expected digits after exponent.");
+        }
+    }
+
+    /// Scans a `0x` hexadecimal or `0b` binary integer literal
+    fn parse_radix_number(&mut self, radix: u32) {
+        // consume x or b
+        self.advance();
+
+        while self.peek().is_digit(radix) {
+            self.advance();
+        }
+
+        let digits = self.str_at(self.start + 2, self.current);
+        let value = match u64::from_str_radix(digits, radix) {
+            Ok(value) => value as f64,
+            Err(_) if digits.is_empty() => {
+                let prefix = self.str_at(self.start, self.start + 2).to_string();
+                self.report_error(&format!("Expected digits after '{}'.", prefix));
+                0.0
+            }
+            Err(_) => {
+                self.report_error("Number literal is too large.");
+                0.0
+            }
+        };
+
+        if Scanner::is_alpha_numeric(self.peek()) {
+            while Scanner::is_alpha_numeric(self.peek()) {
+                self.advance();
+            }
+
+            let lexeme = self.str_at(self.start, self.current).to_string();
+            self.report_error(&format!("Invalid digit in number literal '{}'.", lexeme));
+        }
 
         self.add_token_literal(TokenKind::Number, value);
     }
 
+    /// Numbers need a digit before the decimal point. The number is still
+    /// scanned so parsing can continue after the error.
+    fn parse_leading_dot_number(&mut self) {
+        while Scanner::is_digit(self.peek()) {
+            self.advance();
+        }
+
+        let lexeme = self.str_at(self.start, self.current).to_string();
+        self.report_error(&format!(
+            "Numbers can't start with '.'. Write '0{}' instead.",
+            lexeme
+        ));
+
+        let value = format!("0{}", lexeme).parse::<f64>().unwrap_or(0.0);
+        self.add_token_literal(TokenKind::Number, value);
+    }
+
     fn parse_identifier(&mut self) {
         while Scanner::is_alpha_numeric(self.peek()) {
             self.advance();
         }
 
-        self.add_token(match self.str_at(self.start, self.current) {
-            "and" => TokenKind::And,
-            "class" => TokenKind::Class,
-            "else" => TokenKind::Else,
-            "false" => TokenKind::False,
-            "for" => TokenKind::For,
-            "fun" => TokenKind::Fun,
-            "if" => TokenKind::If,
-            "nil" => TokenKind::Nil,
-            "or" => TokenKind::Or,
-            "print" => TokenKind::Print,
-            "return" => TokenKind::Return,
-            "super" => TokenKind::Super,
-            "this" => TokenKind::This,
-            "true" => TokenKind::True,
-            "var" => TokenKind::Var,
-            "while" => TokenKind::While,
-            _ => TokenKind::Identifier,
-        });
+        let word = self.str_at(self.start, self.current);
+        let kind = self.keywords.get(word).unwrap_or(TokenKind::Identifier);
+
+        let mut token = self.create_token(kind, None);
+        // `this` and `super` are looked up like variables by their lexeme
+        if matches!(kind, TokenKind::This | TokenKind::Super) {
+            if let Some(spelling) = Keywords::standard_spelling(kind) {
+                token.lexeme = spelling.to_string();
+            }
+        }
+
+        self.tokens.push(token);
     }
 
+    /// The character at `index`, or `'\0'` past the end of the source
     fn char_at(&self, index: usize) -> char {
         self.source
-            .chars()
-            .nth(index)
-            .expect("Unexpected end of input")
+            .get(index..)
+            .and_then(|rest| rest.chars().next())
+            .unwrap_or('\0')
     }
 
     fn str_at(&self, start: usize, end: usize) -> &str {
         &self.source[start..end]
     }
 
-    fn report_error(&mut self, line: usize, message: &str) {
+    fn report_error(&mut self, message: &str) {
         self.scanning_errors.push(ScannerErrorDetails {
-            line,
+            line: self.line,
             message: message.into(),
+            span: Span::new(self.start, self.current),
         });
     }
 
@@ -251,10 +561,172 @@ impl Scanner {
     }
 
     fn is_alpha(c: char) -> bool {
-        c.is_ascii_alphabetic() || c == '_'
+        c.is_alphabetic() || c == '_'
     }
 
     fn is_alpha_numeric(c: char) -> bool {
         Scanner::is_alpha(c) || Scanner::is_digit(c)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Kinds and number values of the tokens of `source`, and the messages of
+    /// any errors, which don't stop the rest from being scanned
+    fn scan(source: &str) -> (Vec<(TokenKind, Option<f64>)>, Vec<String>) {
+        let (tokens, errors) = match Scanner::new(source.into()).scan_tokens() {
+            Ok(tokens) => (tokens, vec![]),
+            Err(LoxError::ScanningError {
+                tokens, details, ..
+            }) => (tokens, details),
+            Err(error) => panic!("unexpected error: {}", error),
+        };
+
+        let tokens = tokens
+            .into_iter()
+            .filter(|token| token.kind != TokenKind::Eof)
+            .map(|token| {
+                let number = match token.literal {
+                    Some(Value::Number(number)) => Some(number),
+                    _ => None,
+                };
+                (token.kind, number)
+            })
+            .collect();
+
+        (
+            tokens,
+            errors.into_iter().map(|error| error.message).collect(),
+        )
+    }
+
+    fn number(source: &str) -> f64 {
+        match scan(source) {
+            (tokens, errors) if errors.is_empty() => match tokens[..] {
+                [(TokenKind::Number, Some(number))] => number,
+                _ => panic!("expected a single number in {:?}, got {:?}", source, tokens),
+            },
+            (_, errors) => panic!("errors scanning {:?}: {:?}", source, errors),
+        }
+    }
+
+    #[test]
+    fn scientific_notation() {
+        assert_eq!(number("1e9"), 1e9);
+        assert_eq!(number("1E9"), 1e9);
+        assert_eq!(number("2.5e+3"), 2500.0);
+        assert_eq!(number("25e-1"), 2.5);
+        assert_eq!(number("1e400"), f64::INFINITY);
+    }
+
+    #[test]
+    fn exponent_without_digits() {
+        for source in ["1e", "1e;", "1e+", "1E-;", "2.5e"] {
+            let (tokens, errors) = scan(source);
+            assert_eq!(errors, ["Expected digits after exponent."], "{:?}", source);
+            assert_eq!(tokens[0].0, TokenKind::Number, "{:?}", source);
+        }
+    }
+
+    #[test]
+    fn e_starting_an_identifier_is_not_an_exponent() {
+        let (tokens, errors) = scan("1else");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            tokens,
+            [(TokenKind::Number, Some(1.0)), (TokenKind::Else, None)]
+        );
+
+        let (tokens, _) = scan("2ex");
+        assert_eq!(
+            tokens,
+            [
+                (TokenKind::Number, Some(2.0)),
+                (TokenKind::Identifier, None)
+            ]
+        );
+    }
+
+    #[test]
+    fn hex_and_binary() {
+        assert_eq!(number("0x1F"), 31.0);
+        assert_eq!(number("0xff"), 255.0);
+        assert_eq!(number("0b101"), 5.0);
+        assert_eq!(number("0x0"), 0.0);
+    }
+
+    #[test]
+    fn radix_prefix_without_digits() {
+        assert_eq!(scan("0x").1, ["Expected digits after '0x'."]);
+        assert_eq!(scan("0b;").1, ["Expected digits after '0b'."]);
+        assert_eq!(
+            scan("0x").0,
+            [(TokenKind::Number, Some(0.0))],
+            "a number is still scanned so parsing can go on"
+        );
+    }
+
+    #[test]
+    fn invalid_radix_digits() {
+        assert_eq!(
+            scan("0b102").1,
+            ["Invalid digit in number literal '0b102'."]
+        );
+        assert_eq!(scan("0x1G").1, ["Invalid digit in number literal '0x1G'."]);
+        assert_eq!(
+            scan("0xFFFFFFFFFFFFFFFFF").1,
+            ["Number literal is too large."]
+        );
+    }
+
+    #[test]
+    fn leading_dot() {
+        let (tokens, errors) = scan(".5");
+        assert_eq!(
+            errors,
+            ["Numbers can't start with '.'. Write '0.5' instead."]
+        );
+        assert_eq!(tokens, [(TokenKind::Number, Some(0.5))]);
+
+        // A property access isn't a number
+        let (tokens, errors) = scan("a.b");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(tokens[1], (TokenKind::Dot, None));
+    }
+
+    #[test]
+    fn trailing_dot_is_not_part_of_the_number() {
+        let (tokens, errors) = scan("1.");
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(
+            tokens,
+            [(TokenKind::Number, Some(1.0)), (TokenKind::Dot, None)]
+        );
+        assert_eq!(number("1.25"), 1.25);
+    }
+
+    #[test]
+    fn scan_locates_tokens_with_its_line_index() {
+        let scan = Scanner::new("var a;\n  print a;\n".into())
+            .scan()
+            .expect("source scans");
+
+        let print = &scan.tokens[3];
+        assert_eq!(print.kind, TokenKind::Print);
+        assert_eq!(scan.line_index.line_column(print.span.start), (2, 3));
+    }
+
+    #[test]
+    fn scanning_errors_carry_the_line_index() {
+        match Scanner::new("print 1;\n\"open".into()).scan() {
+            Err(LoxError::ScanningError {
+                line_index,
+                details,
+                ..
+            }) => assert_eq!(line_index.line_column(details[0].span.start), (2, 1)),
+            result => panic!("expected a scanning error, got {:?}", result),
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::Path,
+};
+
+use crate::interpreter::error::{LoxError, Result};
+
+/// Reads a script, or stdin when `path` is `-`.
+///
+/// Invalid UTF-8 is an error naming where the first invalid sequence starts,
+/// unless `lossy` is set, in which case invalid sequences are replaced with
+/// U+FFFD.
+pub fn read_source(path: &Path, lossy: bool) -> Result<String> {
+    let bytes = match path.to_str() {
+        Some("-") => {
+            let mut bytes = vec![];
+            io::stdin().lock().read_to_end(&mut bytes)?;
+            bytes
+        }
+        _ => fs::read(path)?,
+    };
+
+    if lossy {
+        return Ok(String::from_utf8_lossy(&bytes).into_owned());
+    }
+
+    String::from_utf8(bytes).map_err(|error| {
+        let offset = error.utf8_error().valid_up_to();
+        let line = error.as_bytes()[..offset]
+            .iter()
+            .filter(|&&byte| byte == b'\n')
+            .count()
+            + 1;
+
+        LoxError::InvalidUtf8 {
+            path: path.display().to_string(),
+            offset,
+            line,
+        }
+    })
+}
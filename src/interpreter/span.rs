@@ -0,0 +1,47 @@
+/// Half-open range of byte offsets into the source
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+}
+
+/// Byte offsets of the start of every line, used to convert offsets into
+/// line/column pairs
+#[derive(Debug, Clone)]
+pub struct LineIndex {
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(source: &str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(source.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+
+        Self { line_starts }
+    }
+
+    /// Converts a byte offset into a 1-based (line, column) pair, where the
+    /// column is measured in bytes from the start of the line
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+
+        (line + 1, offset - self.line_starts[line] + 1)
+    }
+
+    /// Converts a 1-based (line, column) pair back into a byte offset, if the
+    /// line exists
+    pub fn offset(&self, line: usize, column: usize) -> Option<usize> {
+        let start = *self.line_starts.get(line.checked_sub(1)?)?;
+        Some(start + column.checked_sub(1)?)
+    }
+}
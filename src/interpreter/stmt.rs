@@ -1,36 +1,270 @@
-use crate::{expr::Expr, token::Token};
+use crate::interpreter::{expr::Expr, token::Token};
 
 #[derive(Debug, Clone)]
 pub enum Stmt {
     Expression(Expr),
-    Print(Expr),
-    Var(Token, Option<Expr>),
+    /// The `print` keyword and the printed value
+    Print(Token, Expr),
+    /// Name, initializer and type annotation
+    Var(Token, Option<Expr>, Option<TypeAnnotation>),
+    VarDestructure(Token, Vec<Token>, Expr),
     Block(Vec<Stmt>),
     If(Expr, Box<Stmt>, Option<Box<Stmt>>),
-    While(Expr, Box<Stmt>),
-    Function(Token, Vec<Token>, Vec<Stmt>),
+    /// The last part is the `for` keyword of the loop it was desugared from,
+    /// if any, with a span covering the whole `for (...)` clause
+    While(Expr, Box<Stmt>, Option<Token>),
+    ForIn(Token, Expr, Box<Stmt>),
+    /// Name, parameters, body, whether it's a generator, declared with `*`,
+    /// and the types annotated on its parameters and return value
+    Function(Token, Vec<Token>, Vec<Stmt>, bool, Signature),
     Return(Token, Option<Expr>),
-    Class(Token, Option<Expr>, Vec<Stmt>),
+    Class(Token, Option<Expr>, Vec<Expr>, Vec<Stmt>),
+    Try(Vec<Stmt>, Option<CatchClause>, Option<Vec<Stmt>>),
+    Throw(Token, Expr),
+    Delete(Expr, Token),
+    /// The `import` keyword, the path string and what the import binds
+    Import(Token, Token, ImportTarget),
+    Export(Token, Box<Stmt>),
+}
+
+#[derive(Debug, Clone)]
+pub enum ImportTarget {
+    /// `import { a, b } from "path";` binds the listed exports
+    Names(Vec<Token>),
+    /// `import "path" as name;` binds the whole module
+    Module(Token),
+}
+
+impl ImportTarget {
+    /// Names the import binds
+    pub fn names(&self) -> Vec<&Token> {
+        match self {
+            ImportTarget::Names(names) => names.iter().collect(),
+            ImportTarget::Module(alias) => vec![alias],
+        }
+    }
+}
+
+/// A type written after a name or a function's parameter list, like the
+/// `Number` in `var n: Number = 1;`. Only the `typechecker` looks at them.
+#[derive(Debug, Clone)]
+pub struct TypeAnnotation {
+    pub name: Token,
+    /// Whether the type is followed by `?`, allowing `nil` as well
+    pub nullable: bool,
+}
+
+/// Type annotations of a function
+#[derive(Debug, Clone, Default)]
+pub struct Signature {
+    /// One entry per parameter, `None` for the ones without an annotation
+    pub parameters: Vec<Option<TypeAnnotation>>,
+    pub returns: Option<TypeAnnotation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CatchClause {
+    pub name: Token,
+    pub body: Vec<Stmt>,
 }
 
 impl Stmt {
+    /// Moves the statement out, leaving an empty block behind. `Stmt`
+    /// implements `Drop`, so it can't be destructured by value.
+    pub fn take(&mut self) -> Stmt {
+        std::mem::replace(self, Stmt::Block(vec![]))
+    }
+
+    /// Moves out the statements nested in this one. Expressions are left in
+    /// place since they drop their own children iteratively.
+    fn take_children(&mut self, children: &mut Vec<Stmt>) {
+        match self {
+            Stmt::Block(statements)
+            | Stmt::Function(_, _, statements, ..)
+            | Stmt::Class(_, _, _, statements) => children.append(statements),
+            Stmt::If(_, then_branch, else_branch) => {
+                children.push(then_branch.take());
+                if let Some(branch) = else_branch {
+                    children.push(branch.take());
+                }
+            }
+            Stmt::While(_, body, _) | Stmt::ForIn(_, _, body) | Stmt::Export(_, body) => {
+                children.push(body.take())
+            }
+            Stmt::Try(body, catch, finally) => {
+                children.append(body);
+                if let Some(catch) = catch {
+                    children.append(&mut catch.body);
+                }
+                if let Some(finally) = finally {
+                    children.append(finally);
+                }
+            }
+            Stmt::Expression(_)
+            | Stmt::Print(..)
+            | Stmt::Var(..)
+            | Stmt::VarDestructure(..)
+            | Stmt::Return(..)
+            | Stmt::Throw(..)
+            | Stmt::Delete(..)
+            | Stmt::Import(..) => {}
+        }
+    }
+
+    /// Whether any of the statements declares a name in the enclosing scope.
+    /// Blocks without declarations don't get a scope or environment of their
+    /// own, so the resolver and interpreter must agree on this.
+    pub fn declares_names(statements: &[Stmt]) -> bool {
+        statements.iter().any(|statement| {
+            matches!(
+                statement,
+                Stmt::Var(..)
+                    | Stmt::VarDestructure(..)
+                    | Stmt::Function(..)
+                    | Stmt::Class(..)
+                    | Stmt::Import(..)
+                    | Stmt::Export(..)
+            )
+        })
+    }
+
+    /// Whether running the statement can suspend a coroutine: it's a `yield`
+    /// or contains one outside of nested functions. The resolver only allows
+    /// `yield` as a statement's whole expression, a variable's initializer or
+    /// an assigned value, so nothing else needs to be looked at.
+    pub fn yields(&self) -> bool {
+        match self {
+            Stmt::Expression(Expr::Yield(..)) | Stmt::Var(_, Some(Expr::Yield(..)), _) => true,
+            Stmt::Expression(Expr::Assign(_, value)) => matches!(**value, Expr::Yield(..)),
+            Stmt::Block(statements) => statements.iter().any(Stmt::yields),
+            Stmt::If(_, then_branch, else_branch) => {
+                then_branch.yields() || else_branch.as_ref().is_some_and(|branch| branch.yields())
+            }
+            Stmt::While(_, body, _) | Stmt::ForIn(_, _, body) => body.yields(),
+            _ => false,
+        }
+    }
+
+    /// Names the statement declares in the enclosing scope
+    pub fn declared_names(&self) -> Vec<&Token> {
+        match self {
+            Stmt::Var(name, ..) | Stmt::Function(name, ..) | Stmt::Class(name, _, _, _) => {
+                vec![name]
+            }
+            Stmt::VarDestructure(_, names, _) => names.iter().collect(),
+            Stmt::Import(_, _, target) => target.names(),
+            Stmt::Export(_, declaration) => declaration.declared_names(),
+            _ => vec![],
+        }
+    }
+
+    /// Path tokens of the imports among the statements, including nested ones
+    pub fn import_paths(statements: &[Stmt]) -> Vec<&Token> {
+        let mut paths = vec![];
+        for statement in statements {
+            match statement {
+                Stmt::Import(_, path, _) => paths.push(path),
+                Stmt::Block(statements)
+                | Stmt::Function(_, _, statements, ..)
+                | Stmt::Class(_, _, _, statements) => paths.extend(Self::import_paths(statements)),
+                Stmt::If(_, then_branch, else_branch) => {
+                    paths.extend(Self::import_paths(std::slice::from_ref(then_branch)));
+                    if let Some(else_branch) = else_branch {
+                        paths.extend(Self::import_paths(std::slice::from_ref(else_branch)));
+                    }
+                }
+                Stmt::While(_, body, _) | Stmt::ForIn(_, _, body) | Stmt::Export(_, body) => {
+                    paths.extend(Self::import_paths(std::slice::from_ref(body)))
+                }
+                Stmt::Try(body, catch, finally) => {
+                    paths.extend(Self::import_paths(body));
+                    if let Some(catch) = catch {
+                        paths.extend(Self::import_paths(&catch.body));
+                    }
+                    if let Some(finally) = finally {
+                        paths.extend(Self::import_paths(finally));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        paths
+    }
+
+    /// Names a module's top-level statements export
+    pub fn exported_names(statements: &[Stmt]) -> Vec<&Token> {
+        statements
+            .iter()
+            .filter_map(|statement| match statement {
+                Stmt::Export(_, declaration) => Some(declaration.declared_names()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
+
+    /// The source a statement produced by desugaring was written as. Tools
+    /// should point at this span instead of the statement's own tokens.
+    pub fn origin(&self) -> Option<&Token> {
+        match self {
+            Stmt::While(_, _, origin) => origin.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Line of one of the statement's tokens, if it has any outside of nested
+    /// statements
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Stmt::Expression(expr) => expr.line(),
+            Stmt::Print(keyword, _) => Some(keyword.line),
+            Stmt::Var(token, ..)
+            | Stmt::VarDestructure(token, _, _)
+            | Stmt::ForIn(token, _, _)
+            | Stmt::Function(token, ..)
+            | Stmt::Return(token, _)
+            | Stmt::Class(token, _, _, _)
+            | Stmt::Throw(token, _)
+            | Stmt::Delete(_, token)
+            | Stmt::Import(token, _, _)
+            | Stmt::Export(token, _) => Some(token.line),
+            Stmt::While(condition, _, Some(origin)) => Some(origin.line).or(condition.line()),
+            Stmt::If(condition, _, _) | Stmt::While(condition, _, None) => condition.line(),
+            Stmt::Block(_) | Stmt::Try(..) => None,
+        }
+    }
+
     pub fn accept<T>(&self, visitor: &mut dyn StmtVisitor<T>) -> T {
         match self {
             Stmt::Expression(expr) => visitor.visit_expression_stmt(expr),
-            Stmt::Print(expr) => visitor.visit_print_stmt(expr),
-            Stmt::Var(name, initializer) => visitor.visit_var_stmt(name, initializer.as_ref()),
+            Stmt::Print(_, expr) => visitor.visit_print_stmt(expr),
+            Stmt::Var(name, initializer, _) => visitor.visit_var_stmt(name, initializer.as_ref()),
+            Stmt::VarDestructure(paren, names, initializer) => {
+                visitor.visit_var_destructure_stmt(paren, names, initializer)
+            }
             Stmt::Block(statements) => visitor.visit_block_stmt(statements),
             Stmt::If(condition, then_branch, else_branch) => {
                 visitor.visit_if_stmt(condition, then_branch, else_branch.as_deref())
             }
-            Stmt::While(condition, body) => visitor.visit_while_stmt(condition, body),
-            Stmt::Function(name, parameters, body) => {
-                visitor.visit_function_stmt(name, parameters, body)
+            Stmt::While(condition, body, origin) => {
+                visitor.visit_while_stmt(condition, body, origin.as_ref())
+            }
+            Stmt::ForIn(name, iterable, body) => visitor.visit_for_in_stmt(name, iterable, body),
+            Stmt::Function(name, parameters, body, is_generator, _) => {
+                visitor.visit_function_stmt(name, parameters, body, *is_generator)
             }
             Stmt::Return(keyword, value) => visitor.visit_return_stmt(keyword, value.as_ref()),
-            Stmt::Class(name, superclass, methods) => {
-                visitor.visit_class_stmt(name, superclass.as_ref(), methods)
+            Stmt::Class(name, superclass, mixins, methods) => {
+                visitor.visit_class_stmt(name, superclass.as_ref(), mixins, methods)
             }
+            Stmt::Try(body, catch, finally) => {
+                visitor.visit_try_stmt(body, catch.as_ref(), finally.as_deref())
+            }
+            Stmt::Throw(keyword, value) => visitor.visit_throw_stmt(keyword, value),
+            Stmt::Delete(object, name) => visitor.visit_delete_stmt(object, name),
+            Stmt::Import(keyword, path, target) => visitor.visit_import_stmt(keyword, path, target),
+            Stmt::Export(keyword, declaration) => visitor.visit_export_stmt(keyword, declaration),
         }
     }
 }
@@ -39,6 +273,12 @@ pub trait StmtVisitor<T> {
     fn visit_expression_stmt(&mut self, expr: &Expr) -> T;
     fn visit_print_stmt(&mut self, expr: &Expr) -> T;
     fn visit_var_stmt(&mut self, name: &Token, initializer: Option<&Expr>) -> T;
+    fn visit_var_destructure_stmt(
+        &mut self,
+        paren: &Token,
+        names: &[Token],
+        initializer: &Expr,
+    ) -> T;
     fn visit_block_stmt(&mut self, statements: &[Stmt]) -> T;
     fn visit_if_stmt(
         &mut self,
@@ -46,8 +286,45 @@ pub trait StmtVisitor<T> {
         then_branch: &Stmt,
         else_branch: Option<&Stmt>,
     ) -> T;
-    fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt) -> T;
-    fn visit_function_stmt(&mut self, name: &Token, parameters: &[Token], body: &[Stmt]) -> T;
+    fn visit_while_stmt(&mut self, condition: &Expr, body: &Stmt, origin: Option<&Token>) -> T;
+    fn visit_for_in_stmt(&mut self, name: &Token, iterable: &Expr, body: &Stmt) -> T;
+    fn visit_function_stmt(
+        &mut self,
+        name: &Token,
+        parameters: &[Token],
+        body: &[Stmt],
+        is_generator: bool,
+    ) -> T;
     fn visit_return_stmt(&mut self, keyword: &Token, value: Option<&Expr>) -> T;
-    fn visit_class_stmt(&mut self, name: &Token, superclass: Option<&Expr>, methods: &[Stmt]) -> T;
+    fn visit_class_stmt(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        mixins: &[Expr],
+        methods: &[Stmt],
+    ) -> T;
+    fn visit_try_stmt(
+        &mut self,
+        body: &[Stmt],
+        catch: Option<&CatchClause>,
+        finally: Option<&[Stmt]>,
+    ) -> T;
+    fn visit_throw_stmt(&mut self, keyword: &Token, value: &Expr) -> T;
+    fn visit_delete_stmt(&mut self, object: &Expr, name: &Token) -> T;
+    fn visit_import_stmt(&mut self, keyword: &Token, path: &Token, target: &ImportTarget) -> T;
+    fn visit_export_stmt(&mut self, keyword: &Token, declaration: &Stmt) -> T;
+}
+
+/// Drops nested statements from a work list instead of recursing, the same
+/// way `Expr` does, so deeply nested blocks and `else if` chains can't
+/// overflow the stack when a program is freed.
+impl Drop for Stmt {
+    fn drop(&mut self) {
+        let mut pending = vec![];
+        self.take_children(&mut pending);
+
+        while let Some(mut statement) = pending.pop() {
+            statement.take_children(&mut pending);
+        }
+    }
 }
@@ -0,0 +1,46 @@
+use crate::interpreter::diagnostic::Severity;
+
+/// Stricter rules a program can opt into, each off by default. They're
+/// checked by different phases, so the same settings are given to the parser,
+/// through `ParserConfig`, and to the resolver and interpreter, through
+/// `InterpreterConfig`. `Lox::set_strictness` sets both.
+///
+/// `ilox --strict` turns them all on with `Strictness::all`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Strictness {
+    /// Reports warnings as errors, so they stop the program like errors do
+    pub deny_warnings: bool,
+    /// Makes `==` and `!=` between values of different types, other than
+    /// `nil`, a runtime error, like `strict_equality` set to error
+    pub strict_equality: bool,
+    /// Makes assigning to an undeclared variable an error even with
+    /// `implicit_globals` on, as in the REPL
+    pub no_implicit_globals: bool,
+    /// Makes reading a variable declared without an initializer, before any
+    /// assignment to it, a resolution error instead of reading `nil`
+    pub use_before_assignment: bool,
+    /// Requires the bodies of `if`, `else`, `while` and `for` to be blocks,
+    /// except for the `if` of an `else if`
+    pub require_braces: bool,
+}
+
+impl Strictness {
+    /// Every stricter rule
+    pub fn all() -> Self {
+        Self {
+            deny_warnings: true,
+            strict_equality: true,
+            no_implicit_globals: true,
+            use_before_assignment: true,
+            require_braces: true,
+        }
+    }
+
+    /// The severity a diagnostic of `severity` is reported with
+    pub fn severity(&self, severity: Severity) -> Severity {
+        match self.deny_warnings {
+            true => Severity::Error,
+            false => severity,
+        }
+    }
+}
@@ -1,6 +1,9 @@
-use std::fmt::{Debug, Display};
+use std::{
+    fmt::{Debug, Display},
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
-use crate::{token_kind::TokenKind, value::Value};
+use crate::interpreter::{span::Span, token_kind::TokenKind, value::Value};
 
 #[derive(Debug, Clone)]
 pub struct Token {
@@ -9,6 +12,26 @@ pub struct Token {
     pub lexeme: String,
     pub literal: Option<Value>,
     pub line: usize,
+    pub span: Span,
+}
+
+static NEXT_TOKEN_ID: AtomicUsize = AtomicUsize::new(0);
+
+impl Token {
+    /// Allocates an id that is unique for the whole process, so tokens from
+    /// different REPL lines, or copied by the optimizer, never share
+    /// resolution data
+    pub fn next_id() -> usize {
+        NEXT_TOKEN_ID.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// A copy of the token with a new id
+    pub fn renumbered(&self) -> Self {
+        Self {
+            id: Token::next_id(),
+            ..self.clone()
+        }
+    }
 }
 
 impl Display for Token {
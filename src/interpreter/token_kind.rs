@@ -5,15 +5,20 @@ pub enum TokenKind {
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
     Plus,
     Semicolon,
+    Colon,
+    Question,
     Slash,
     Star,
 
     // One or two character tokens
+    Arrow,
     Bang,
     BangEqual,
     Equal,
@@ -30,21 +35,69 @@ pub enum TokenKind {
 
     // Keywords
     And,
+    Catch,
     Class,
+    Delete,
     Else,
+    Export,
     False,
+    Finally,
     Fun,
     For,
     If,
+    Import,
+    In,
+    Match,
     Nil,
     Or,
     Print,
     Return,
     Super,
     This,
+    Throw,
     True,
+    Try,
+    Using,
     Var,
     While,
+    With,
+    Yield,
 
     Eof,
 }
+
+impl TokenKind {
+    pub fn is_keyword(&self) -> bool {
+        matches!(
+            self,
+            TokenKind::And
+                | TokenKind::Catch
+                | TokenKind::Class
+                | TokenKind::Delete
+                | TokenKind::Else
+                | TokenKind::Export
+                | TokenKind::False
+                | TokenKind::Finally
+                | TokenKind::Fun
+                | TokenKind::For
+                | TokenKind::If
+                | TokenKind::Import
+                | TokenKind::In
+                | TokenKind::Match
+                | TokenKind::Nil
+                | TokenKind::Or
+                | TokenKind::Print
+                | TokenKind::Return
+                | TokenKind::Super
+                | TokenKind::This
+                | TokenKind::Throw
+                | TokenKind::True
+                | TokenKind::Try
+                | TokenKind::Using
+                | TokenKind::Var
+                | TokenKind::While
+                | TokenKind::With
+                | TokenKind::Yield
+        )
+    }
+}
@@ -0,0 +1,805 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+};
+
+use crate::interpreter::{
+    error::TypeErrorDetails,
+    expr::Expr,
+    stmt::{Signature, Stmt, TypeAnnotation},
+    token::Token,
+    token_kind::TokenKind,
+    value::Value,
+};
+
+/// Native classes that can be named in annotations
+const NATIVE_CLASSES: [&str; 2] = ["Error", "StringBuilder"];
+
+/// What the checker knows about a value. `Any` stands for everything it
+/// can't tell and never causes a mismatch.
+#[derive(Debug, Clone, PartialEq)]
+enum Type {
+    Any,
+    Nil,
+    Bool,
+    Number,
+    String,
+    List,
+    Function,
+    /// An instance of the named class or one of its subclasses
+    Instance(String),
+    /// The type or `nil`, written `Type?`
+    Nullable(Box<Type>),
+}
+
+impl Type {
+    /// Whether values of the type are never `nil`
+    fn is_non_nil(&self) -> bool {
+        !matches!(self, Type::Any | Type::Nil | Type::Nullable(_))
+    }
+
+    /// The type without `nil`
+    fn non_nil(&self) -> Type {
+        match self {
+            Type::Nullable(kind) => *kind.clone(),
+            kind => kind.clone(),
+        }
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Any => write!(f, "Any"),
+            Type::Nil => write!(f, "Nil"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Number => write!(f, "Number"),
+            Type::String => write!(f, "String"),
+            Type::List => write!(f, "List"),
+            Type::Function => write!(f, "Function"),
+            Type::Instance(class) => write!(f, "{}", class),
+            Type::Nullable(kind) => write!(f, "{}?", kind),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct FunctionType {
+    parameters: Vec<Type>,
+    returns: Type,
+}
+
+#[derive(Debug, Clone)]
+enum Binding {
+    /// The annotated type, and what the variable is known to hold at this
+    /// point of the program, which is narrower after `nil` checks
+    Variable {
+        declared: Type,
+        known: Type,
+    },
+    Function(FunctionType),
+    Class(String),
+}
+
+#[derive(Debug, Default)]
+struct ClassType {
+    superclass: Option<String>,
+    methods: HashMap<String, FunctionType>,
+}
+
+/// Checks a program's type annotations before it runs.
+///
+/// The check is best-effort: types are only known for literals, operators,
+/// annotated variables and calls to annotated functions and methods, and
+/// everything else is `Any`, which matches any annotation. Mismatches are
+/// reported for initializers, assignments, arguments and returned values.
+/// Unannotated programs never have errors.
+///
+/// Only variables annotated with a nullable type like `String?` can be `nil`.
+/// Using one for a property access or call is reported unless a condition
+/// like `x != nil` or `x` rules it out first: in the branch it guards, on the
+/// right of an `and`, in a `while` body, or after an `if` whose body always
+/// returns or throws. Assigning a value that may be `nil` undoes that.
+pub struct TypeChecker {
+    scopes: Vec<HashMap<String, Binding>>,
+    /// Every class declared in the program, by name
+    classes: HashMap<String, ClassType>,
+    /// Names annotations can refer to a class by
+    class_names: HashSet<String>,
+    /// Return types of the functions being checked, innermost last
+    returns: Vec<Type>,
+    /// Class whose methods are being checked, the type of `this`
+    class: Option<String>,
+    errors: Vec<TypeErrorDetails>,
+    /// Annotations already reported as naming an unknown type
+    reported: HashSet<usize>,
+}
+
+impl TypeChecker {
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+            classes: HashMap::new(),
+            class_names: NATIVE_CLASSES.iter().map(|name| name.to_string()).collect(),
+            returns: vec![],
+            class: None,
+            errors: vec![],
+            reported: HashSet::new(),
+        }
+    }
+
+    /// Mismatches found in `statements`, in the order they appear
+    pub fn check(mut self, statements: &[Stmt]) -> Vec<TypeErrorDetails> {
+        collect_class_names(statements, &mut self.class_names);
+        self.statements(statements);
+        self.errors
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) {
+        // Functions and classes can be used by the functions declared before
+        // them, so their types are known up front
+        for statement in statements {
+            self.declare(statement);
+        }
+
+        for statement in statements {
+            self.statement(statement);
+        }
+    }
+
+    fn scoped_statements(&mut self, statements: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        self.statements(statements);
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Function(name, parameters, _, is_generator, signature) => {
+                let function = self.function_type(parameters, *is_generator, signature);
+                self.define(name, Binding::Function(function));
+            }
+            Stmt::Class(name, superclass, _, methods) => {
+                let superclass = match superclass {
+                    Some(Expr::Variable(superclass)) => Some(superclass.lexeme.clone()),
+                    _ => None,
+                };
+
+                let mut class = ClassType {
+                    superclass,
+                    methods: HashMap::new(),
+                };
+                for method in methods {
+                    if let Stmt::Function(method, parameters, _, is_generator, signature) = method {
+                        let mut function = self.function_type(parameters, *is_generator, signature);
+                        if method.lexeme == "init" {
+                            function.returns = Type::Instance(name.lexeme.clone());
+                        }
+
+                        class.methods.insert(method.lexeme.clone(), function);
+                    }
+                }
+
+                self.classes.insert(name.lexeme.clone(), class);
+                self.define(name, Binding::Class(name.lexeme.clone()));
+            }
+            Stmt::Export(_, declaration) => self.declare(declaration),
+            _ => (),
+        }
+    }
+
+    fn statement(&mut self, statement: &Stmt) {
+        match statement {
+            Stmt::Expression(expr) | Stmt::Print(_, expr) | Stmt::Throw(_, expr) => {
+                self.expression(expr);
+            }
+            Stmt::Delete(object, _) => {
+                self.expression(object);
+            }
+            Stmt::Var(name, initializer, annotation) => {
+                let declared = self.annotation(annotation.as_ref());
+                let known = match initializer {
+                    Some(initializer) => {
+                        let found = self.expression(initializer);
+                        self.expect(&declared, &found, name, format!("'{}'", name.lexeme));
+                        Self::after_assignment(&declared, &found)
+                    }
+                    None => declared.clone(),
+                };
+
+                self.define(name, Binding::Variable { declared, known });
+            }
+            Stmt::VarDestructure(_, names, initializer) => {
+                self.expression(initializer);
+                for name in names {
+                    self.define(name, Binding::any());
+                }
+            }
+            Stmt::Block(statements) => self.scoped_statements(statements),
+            Stmt::If(condition, then_branch, else_branch) => {
+                let (when_true, when_false) = non_nil_names(condition);
+                self.expression(condition);
+                self.narrowed(&when_true, |checker| checker.statement(then_branch));
+
+                match else_branch {
+                    Some(else_branch) => {
+                        self.narrowed(&when_false, |checker| checker.statement(else_branch))
+                    }
+                    // Only the program where the condition is false gets past
+                    // an `if` that always exits
+                    None if always_exits(then_branch) => self.narrow(&when_false),
+                    None => (),
+                }
+            }
+            Stmt::While(condition, body, _) => {
+                let (when_true, _) = non_nil_names(condition);
+                self.expression(condition);
+                self.narrowed(&when_true, |checker| checker.statement(body));
+            }
+            Stmt::ForIn(name, iterable, body) => {
+                self.expression(iterable);
+                self.scopes.push(HashMap::new());
+                self.define(name, Binding::any());
+                self.statement(body);
+                self.scopes.pop();
+            }
+            Stmt::Function(name, parameters, body, is_generator, signature) => {
+                let function = match self.lookup(&name.lexeme) {
+                    Some(Binding::Function(function)) => function.clone(),
+                    _ => self.function_type(parameters, *is_generator, signature),
+                };
+
+                self.function(parameters, body, &function);
+            }
+            Stmt::Return(keyword, value) => {
+                let found = match value {
+                    Some(value) => self.expression(value),
+                    None => Type::Nil,
+                };
+
+                if let Some(expected) = self.returns.last().cloned() {
+                    self.expect(&expected, &found, keyword, "the returned value");
+                }
+            }
+            Stmt::Class(name, superclass, mixins, methods) => {
+                for expr in superclass.iter().chain(mixins) {
+                    self.expression(expr);
+                }
+
+                let enclosing = self.class.replace(name.lexeme.clone());
+                for method in methods {
+                    if let Stmt::Function(method, parameters, body, ..) = method {
+                        let function = self.method(&name.lexeme, &method.lexeme);
+                        if let Some(function) = function {
+                            self.function(parameters, body, &function);
+                        }
+                    }
+                }
+                self.class = enclosing;
+            }
+            Stmt::Try(body, catch, finally) => {
+                self.scoped_statements(body);
+                if let Some(catch) = catch {
+                    self.scopes.push(HashMap::new());
+                    self.define(&catch.name, Binding::any());
+                    self.scoped_statements(&catch.body);
+                    self.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.scoped_statements(finally);
+                }
+            }
+            Stmt::Import(_, _, target) => {
+                for name in target.names() {
+                    self.define(name, Binding::any());
+                }
+            }
+            Stmt::Export(_, declaration) => self.statement(declaration),
+        }
+    }
+
+    fn function(&mut self, parameters: &[Token], body: &[Stmt], function: &FunctionType) {
+        self.scopes.push(HashMap::new());
+        for (parameter, kind) in parameters.iter().zip(&function.parameters) {
+            let binding = Binding::Variable {
+                declared: kind.clone(),
+                known: kind.clone(),
+            };
+            self.define(parameter, binding);
+        }
+
+        self.returns.push(function.returns.clone());
+        self.statements(body);
+        self.returns.pop();
+        self.scopes.pop();
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Literal(value) => match value {
+                Value::Nil => Type::Nil,
+                Value::Boolean(_) => Type::Bool,
+                Value::Number(_) => Type::Number,
+                Value::String(_) => Type::String,
+                Value::List(_) => Type::List,
+                _ => Type::Any,
+            },
+            Expr::Grouping(expr) => self.expression(expr),
+            Expr::Unary(operator, right) => {
+                self.expression(right);
+                match operator.kind {
+                    TokenKind::Minus => Type::Number,
+                    TokenKind::Bang => Type::Bool,
+                    _ => Type::Any,
+                }
+            }
+            Expr::Binary(left, operator, right) => {
+                let left = self.expression(left);
+                let right = self.expression(right);
+                match operator.kind {
+                    TokenKind::Minus | TokenKind::Star | TokenKind::Slash => Type::Number,
+                    TokenKind::Plus => {
+                        match (left, right) {
+                            (Type::Number, Type::Number | Type::Any)
+                            | (Type::Any, Type::Number) => Type::Number,
+                            (Type::String, Type::String | Type::Any)
+                            | (Type::Any, Type::String) => Type::String,
+                            _ => Type::Any,
+                        }
+                    }
+                    TokenKind::Greater
+                    | TokenKind::GreaterEqual
+                    | TokenKind::Less
+                    | TokenKind::LessEqual
+                    | TokenKind::EqualEqual
+                    | TokenKind::BangEqual
+                    | TokenKind::In => Type::Bool,
+                    _ => Type::Any,
+                }
+            }
+            Expr::Logical(left, operator, right) => {
+                let (when_true, when_false) = non_nil_names(left);
+                let left = self.expression(left);
+
+                // The right operand only runs when the left one is truthy for
+                // `and` and falsey for `or`
+                let guard = match operator.kind {
+                    TokenKind::And => when_true,
+                    _ => when_false,
+                };
+                let mut right_type = Type::Any;
+                self.narrowed(&guard, |checker| right_type = checker.expression(right));
+
+                match (left, right_type) {
+                    (left, right) if left == right => left,
+                    (Type::Nullable(left), right) if operator.kind == TokenKind::Or => {
+                        match *left == right {
+                            true => right,
+                            false => Type::Any,
+                        }
+                    }
+                    _ => Type::Any,
+                }
+            }
+            Expr::Variable(name) => match self.lookup(&name.lexeme) {
+                Some(Binding::Variable { known, .. }) => known.clone(),
+                Some(Binding::Function(_) | Binding::Class(_)) => Type::Function,
+                None => Type::Any,
+            },
+            Expr::Assign(name, value) => {
+                let found = self.expression(value);
+                if let Some(Binding::Variable { declared, .. }) = self.lookup(&name.lexeme).cloned()
+                {
+                    self.expect(&declared, &found, name, format!("'{}'", name.lexeme));
+
+                    let known = Self::after_assignment(&declared, &found);
+                    if let Some(Binding::Variable { known: current, .. }) =
+                        self.lookup_mut(&name.lexeme)
+                    {
+                        *current = known;
+                    }
+                }
+
+                found
+            }
+            Expr::Call(callee, arguments, paren, argument_starts) => {
+                let function = match callee.as_ref() {
+                    Expr::Variable(name) => match self.lookup(&name.lexeme).cloned() {
+                        Some(Binding::Function(function)) => Some((name.lexeme.clone(), function)),
+                        Some(Binding::Class(class)) => Some((
+                            class.clone(),
+                            self.method(&class, "init").unwrap_or(FunctionType {
+                                parameters: vec![],
+                                returns: Type::Instance(class),
+                            }),
+                        )),
+                        Some(Binding::Variable { known, .. }) => {
+                            self.check_not_nil(&known, callee, paren, None);
+                            None
+                        }
+                        None => None,
+                    },
+                    Expr::Get(object, method) => match self.dereference(object, method) {
+                        Type::Instance(class) => self
+                            .method(&class, &method.lexeme)
+                            .map(|function| (method.lexeme.clone(), function)),
+                        _ => None,
+                    },
+                    callee => {
+                        let kind = self.expression(callee);
+                        self.check_not_nil(&kind, callee, paren, None);
+                        None
+                    }
+                };
+
+                let found: Vec<Type> = arguments
+                    .iter()
+                    .map(|argument| self.expression(argument))
+                    .collect();
+
+                match function {
+                    Some((name, function)) => {
+                        // Wrong argument counts are reported when the call runs
+                        if function.parameters.len() == found.len() {
+                            for (index, (expected, found)) in
+                                function.parameters.iter().zip(&found).enumerate()
+                            {
+                                self.expect(
+                                    expected,
+                                    found,
+                                    &argument_starts[index],
+                                    format!("argument {} of '{}'", index + 1, name),
+                                );
+                            }
+                        }
+
+                        function.returns
+                    }
+                    None => Type::Any,
+                }
+            }
+            Expr::Get(object, name) => match self.dereference(object, name) {
+                Type::Instance(class) if self.method(&class, &name.lexeme).is_some() => {
+                    Type::Function
+                }
+                _ => Type::Any,
+            },
+            Expr::Set(object, name, value) => {
+                self.dereference(object, name);
+                self.expression(value)
+            }
+            Expr::This(_) => self.class.clone().map_or(Type::Any, Type::Instance),
+            Expr::Super(..) => Type::Any,
+            Expr::Match(_, subject, arms) => {
+                self.expression(subject);
+
+                let mut kinds = vec![];
+                for arm in arms {
+                    if let Some(guard) = &arm.guard {
+                        self.expression(guard);
+                    }
+                    kinds.push(self.expression(&arm.body));
+                }
+
+                match kinds.split_first() {
+                    Some((first, rest)) if rest.iter().all(|kind| kind == first) => first.clone(),
+                    _ => Type::Any,
+                }
+            }
+            Expr::List(_, elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+
+                Type::List
+            }
+            Expr::Destructure(_, _, value) => {
+                self.expression(value);
+                Type::Any
+            }
+            Expr::Yield(_, value) => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+
+                Type::Any
+            }
+        }
+    }
+
+    fn function_type(
+        &mut self,
+        parameters: &[Token],
+        is_generator: bool,
+        signature: &Signature,
+    ) -> FunctionType {
+        let parameters = (0..parameters.len())
+            .map(|index| self.annotation(signature.parameters.get(index).and_then(Option::as_ref)))
+            .collect();
+        let returns = self.annotation(signature.returns.as_ref());
+
+        FunctionType {
+            parameters,
+            // Calling a generator returns the generator, whatever its
+            // `return` statements give back
+            returns: match is_generator {
+                true => Type::Any,
+                false => returns,
+            },
+        }
+    }
+
+    fn annotation(&mut self, annotation: Option<&TypeAnnotation>) -> Type {
+        let (name, nullable) = match annotation {
+            Some(annotation) => (&annotation.name, annotation.nullable),
+            None => return Type::Any,
+        };
+
+        match self.named_type(name) {
+            kind if nullable && kind.is_non_nil() => Type::Nullable(Box::new(kind)),
+            kind => kind,
+        }
+    }
+
+    fn named_type(&mut self, name: &Token) -> Type {
+        match name.lexeme.as_str() {
+            "Any" => Type::Any,
+            "Nil" => Type::Nil,
+            "Bool" => Type::Bool,
+            "Number" => Type::Number,
+            "String" => Type::String,
+            "List" => Type::List,
+            "Function" => Type::Function,
+            class if self.class_names.contains(class) => Type::Instance(class.to_string()),
+            unknown => {
+                if self.reported.insert(name.id) {
+                    self.errors.push(TypeErrorDetails {
+                        message: format!("Unknown type '{}'.", unknown),
+                        token: name.clone(),
+                    });
+                }
+
+                Type::Any
+            }
+        }
+    }
+
+    /// Reports `found` where a value of type `expected` is needed, describing
+    /// the place as `what`
+    fn expect(&mut self, expected: &Type, found: &Type, token: &Token, what: impl Display) {
+        if !self.is_compatible(expected, found) {
+            self.errors.push(TypeErrorDetails {
+                message: format!("Expected {} for {} but got {}.", expected, what, found),
+                token: token.clone(),
+            });
+        }
+    }
+
+    fn is_compatible(&self, expected: &Type, found: &Type) -> bool {
+        match (expected, found) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Nullable(_), Type::Nil) => true,
+            (Type::Nullable(expected), found) => self.is_compatible(expected, &found.non_nil()),
+            (_, Type::Nullable(_)) => false,
+            (Type::Instance(expected), Type::Instance(found)) => self.is_subclass(found, expected),
+            (expected, found) => expected == found,
+        }
+    }
+
+    /// What a variable declared as `declared` is known to hold after a value
+    /// of type `found` is assigned to it
+    fn after_assignment(declared: &Type, found: &Type) -> Type {
+        match found.is_non_nil() {
+            true => declared.non_nil(),
+            false => declared.clone(),
+        }
+    }
+
+    /// Type of `object` when accessing its property `name`, reporting it if
+    /// it may be `nil`
+    fn dereference(&mut self, object: &Expr, name: &Token) -> Type {
+        let kind = self.expression(object);
+        self.check_not_nil(&kind, object, name, Some(name));
+
+        kind.non_nil()
+    }
+
+    /// Reports using a value of type `kind`, computed by `expr`, that may be
+    /// `nil` for accessing `property` or calling it when that's `None`
+    fn check_not_nil(&mut self, kind: &Type, expr: &Expr, token: &Token, property: Option<&Token>) {
+        let state = match kind {
+            Type::Nil => "is nil",
+            Type::Nullable(_) => "may be nil",
+            _ => return,
+        };
+
+        let subject = match expr.access_path() {
+            Some(path) => format!("'{}'", path),
+            None => "the value".to_string(),
+        };
+
+        let message = match property {
+            Some(property) => format!(
+                "Can't access '{}' because {} {}.",
+                property.lexeme, subject, state
+            ),
+            None => format!("Can't call {} because it {}.", subject, state),
+        };
+
+        self.errors.push(TypeErrorDetails {
+            message,
+            token: token.clone(),
+        });
+    }
+
+    /// Marks the nullable variables among `names` as not `nil` in the current
+    /// scope
+    fn narrow(&mut self, names: &[&Token]) {
+        for name in names {
+            if let Some(Binding::Variable { declared, known }) = self.lookup(&name.lexeme).cloned()
+            {
+                let binding = Binding::Variable {
+                    declared,
+                    known: known.non_nil(),
+                };
+                self.define(name, binding);
+            }
+        }
+    }
+
+    /// Runs `f` in a scope where `names` aren't `nil`
+    fn narrowed(&mut self, names: &[&Token], f: impl FnOnce(&mut Self)) {
+        self.scopes.push(HashMap::new());
+        self.narrow(names);
+        f(self);
+        self.scopes.pop();
+    }
+
+    /// Superclasses of `class`, starting with itself. Stops at a class seen
+    /// before, since a name can be declared more than once.
+    fn ancestors<'a>(&'a self, class: &'a str) -> Vec<&'a str> {
+        let mut ancestors = vec![class];
+        let mut current = self.classes.get(class);
+        while let Some(superclass) = current.and_then(|class| class.superclass.as_deref()) {
+            if ancestors.contains(&superclass) {
+                break;
+            }
+
+            ancestors.push(superclass);
+            current = self.classes.get(superclass);
+        }
+
+        ancestors
+    }
+
+    fn is_subclass(&self, class: &str, ancestor: &str) -> bool {
+        self.ancestors(class).contains(&ancestor)
+    }
+
+    fn method(&self, class: &str, name: &str) -> Option<FunctionType> {
+        self.ancestors(class).into_iter().find_map(|class| {
+            self.classes
+                .get(class)
+                .and_then(|class| class.methods.get(name))
+                .cloned()
+        })
+    }
+
+    fn define(&mut self, name: &Token, binding: Binding) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(name.lexeme.clone(), binding);
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Option<&Binding> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name))
+    }
+
+    fn lookup_mut(&mut self, name: &str) -> Option<&mut Binding> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+}
+
+impl Binding {
+    fn any() -> Self {
+        Binding::Variable {
+            declared: Type::Any,
+            known: Type::Any,
+        }
+    }
+}
+
+impl Default for TypeChecker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Variables `condition` proves aren't `nil` when it's true, and when it's
+/// false
+fn non_nil_names(condition: &Expr) -> (Vec<&Token>, Vec<&Token>) {
+    let is_nil = |expr: &Expr| matches!(expr, Expr::Literal(Value::Nil));
+
+    match condition {
+        Expr::Variable(name) => (vec![name], vec![]),
+        Expr::Grouping(expr) => non_nil_names(expr),
+        Expr::Unary(operator, expr) if operator.kind == TokenKind::Bang => {
+            let (when_true, when_false) = non_nil_names(expr);
+            (when_false, when_true)
+        }
+        Expr::Binary(left, operator, right)
+            if matches!(operator.kind, TokenKind::BangEqual | TokenKind::EqualEqual) =>
+        {
+            let name = match (left.as_ref(), right.as_ref()) {
+                (Expr::Variable(name), other) | (other, Expr::Variable(name)) if is_nil(other) => {
+                    name
+                }
+                _ => return (vec![], vec![]),
+            };
+
+            match operator.kind {
+                TokenKind::BangEqual => (vec![name], vec![]),
+                _ => (vec![], vec![name]),
+            }
+        }
+        Expr::Logical(left, operator, right) => {
+            let (left_true, left_false) = non_nil_names(left);
+            let (right_true, right_false) = non_nil_names(right);
+
+            match operator.kind {
+                TokenKind::And => ([left_true, right_true].concat(), vec![]),
+                _ => (vec![], [left_false, right_false].concat()),
+            }
+        }
+        _ => (vec![], vec![]),
+    }
+}
+
+/// Whether running `statement` always ends with a `return` or `throw`
+fn always_exits(statement: &Stmt) -> bool {
+    match statement {
+        Stmt::Return(..) | Stmt::Throw(..) => true,
+        Stmt::Block(statements) => statements.last().is_some_and(always_exits),
+        Stmt::If(_, then_branch, Some(else_branch)) => {
+            always_exits(then_branch) && always_exits(else_branch)
+        }
+        _ => false,
+    }
+}
+
+/// Adds the names of the classes declared anywhere in `statements`
+fn collect_class_names(statements: &[Stmt], names: &mut HashSet<String>) {
+    for statement in statements {
+        match statement {
+            Stmt::Class(name, _, _, methods) => {
+                names.insert(name.lexeme.clone());
+                collect_class_names(methods, names);
+            }
+            Stmt::Block(statements) | Stmt::Function(_, _, statements, ..) => {
+                collect_class_names(statements, names)
+            }
+            Stmt::If(_, then_branch, else_branch) => {
+                collect_class_names(std::slice::from_ref(then_branch), names);
+                if let Some(else_branch) = else_branch {
+                    collect_class_names(std::slice::from_ref(else_branch), names);
+                }
+            }
+            Stmt::While(_, body, _) | Stmt::ForIn(_, _, body) | Stmt::Export(_, body) => {
+                collect_class_names(std::slice::from_ref(body), names)
+            }
+            Stmt::Try(body, catch, finally) => {
+                collect_class_names(body, names);
+                if let Some(catch) = catch {
+                    collect_class_names(&catch.body, names);
+                }
+                if let Some(finally) = finally {
+                    collect_class_names(finally, names);
+                }
+            }
+            _ => (),
+        }
+    }
+}
@@ -1,18 +1,25 @@
 use std::{
-    cell::RefCell,
-    collections::HashMap,
+    any::Any,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap, HashSet},
     fmt::{Debug, Display},
     ops::Deref,
     rc::Rc,
 };
 
 use crate::{
-    callable::Callable,
-    environment::Environment,
-    error::{LoxError, Result},
-    interpreter::Interpreter,
-    stmt::Stmt,
-    token::Token,
+    interpreter::{
+        callable::Callable,
+        environment::Environment,
+        error::{LoxError, Result},
+        fields::Fields,
+        foreign::Foreign,
+        interpreter::Interpreter,
+        native_functions::new_generator,
+        stmt::Stmt,
+        token::Token,
+    },
+    semantics::{self, Primitive},
 };
 
 #[derive(Debug, Clone)]
@@ -20,6 +27,16 @@ pub struct LoxClass {
     pub name: String,
     methods: HashMap<String, Value>,
     superclass: Option<Box<LoxClass>>,
+    shape: Rc<Shape>,
+}
+
+/// What all copies of a class share. Classes are copied freely, so this is
+/// also what identifies a class, for the inline caches.
+#[derive(Debug, Default)]
+pub(crate) struct Shape {
+    /// Most fields an instance of the class has had, so new instances start
+    /// with room for them
+    field_hint: Cell<usize>,
 }
 
 // TODO wrap LoxClass in Rc
@@ -33,9 +50,29 @@ impl LoxClass {
             name,
             methods,
             superclass: superclass.map(Box::new),
+            shape: Rc::default(),
         }
     }
 
+    /// Methods declared by this class and its superclasses
+    pub fn methods(&self) -> Vec<(&String, &Value)> {
+        let mut methods: Vec<_> = self.methods.iter().collect();
+        if let Some(superclass) = &self.superclass {
+            methods.extend(superclass.methods());
+        }
+
+        methods
+    }
+
+    /// Methods declared in the class body itself, without inherited ones
+    pub fn own_methods(&self) -> &HashMap<String, Value> {
+        &self.methods
+    }
+
+    pub fn superclass(&self) -> Option<&LoxClass> {
+        self.superclass.as_deref()
+    }
+
     pub fn find_method(&self, name: &str) -> Option<&Value> {
         if self.methods.contains_key(name) {
             return self.methods.get(name);
@@ -72,25 +109,55 @@ impl Callable for LoxClass {
     }
 }
 
+/// Shows the class with its superclass and the arity of each of its
+/// methods, inherited ones included, sorted by name:
+/// `class Square < Shape { area/0, init/1 }`
 impl Display for LoxClass {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.name)
+        write!(f, "class {}", self.name)?;
+        if let Some(superclass) = &self.superclass {
+            write!(f, " < {}", superclass.name)?;
+        }
+
+        // Overriding methods come first, so they win over inherited ones
+        let mut methods = BTreeMap::new();
+        for (name, method) in self.methods() {
+            methods.entry(name).or_insert_with(|| method.arity());
+        }
+
+        if methods.is_empty() {
+            return write!(f, " {{}}");
+        }
+
+        let methods: Vec<String> = methods
+            .into_iter()
+            .map(|(name, arity)| format!("{}/{}", name, arity))
+            .collect();
+
+        write!(f, " {{ {} }}", methods.join(", "))
     }
 }
 
 #[derive(Debug)]
 pub struct LoxInstanceData {
     class: LoxClass,
-    fields: HashMap<String, Value>,
+    fields: Fields,
+    /// Rust state owned by instances of native classes, invisible to scripts
+    native_state: Option<Box<dyn Any>>,
 }
 
 impl LoxInstanceData {
     pub fn new(class: LoxClass) -> Self {
         Self {
+            fields: Fields::with_capacity(class.shape.field_hint.get()),
             class,
-            fields: HashMap::new(),
+            native_state: None,
         }
     }
+
+    pub fn class(&self) -> &LoxClass {
+        &self.class
+    }
 }
 
 impl Display for LoxInstanceData {
@@ -108,6 +175,14 @@ impl Display for LoxInstanceData {
     }
 }
 
+/// What a property of an instance refers to
+#[derive(Debug)]
+pub(crate) enum Property {
+    Field(Value),
+    /// A method of its class, not yet bound to the instance
+    Method(Value),
+}
+
 #[derive(Debug)]
 pub struct LoxInstance(Rc<RefCell<LoxInstanceData>>);
 
@@ -117,27 +192,111 @@ impl LoxInstance {
     }
 
     pub fn get(&self, name: &Token) -> Result<Value> {
+        match self.property(name)? {
+            Property::Field(value) => Ok(value),
+            Property::Method(method) => method.bind(self),
+        }
+    }
+
+    /// Like `get`, but leaves a method unbound
+    pub(crate) fn property(&self, name: &Token) -> Result<Property> {
         let data = self.0.borrow();
 
         if let Some(value) = data.fields.get(&name.lexeme) {
-            return Ok(value.clone());
+            return Ok(Property::Field(value.clone()));
         }
 
-        if let Some(value) = data.class.find_method(&name.lexeme) {
-            return value.bind(self);
+        if let Some(method) = data.class.find_method(&name.lexeme) {
+            return Ok(Property::Method(method.clone()));
         }
 
         Err(LoxError::RuntimeError {
-            token: name.clone(),
+            token: Box::new(name.clone()),
             message: format!("Undefined property '{}'.", name.lexeme),
         })
     }
 
     pub fn set(&mut self, name: &Token, value: &Value) {
+        self.set_field(&name.lexeme, value.clone());
+    }
+
+    pub fn fields(&self) -> Vec<(String, Value)> {
         self.0
-            .borrow_mut()
+            .borrow()
             .fields
-            .insert(name.lexeme.to_string(), value.clone());
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect()
+    }
+
+    pub fn get_field(&self, name: &str) -> Option<Value> {
+        self.0.borrow().fields.get(name).cloned()
+    }
+
+    pub fn set_field(&mut self, name: &str, value: Value) {
+        let mut data = self.0.borrow_mut();
+        data.fields.insert(name, value);
+
+        let hint = &data.class.shape.field_hint;
+        hint.set(hint.get().max(data.fields.len()));
+    }
+
+    /// Where the field `name` is stored, for the inline caches to try first
+    /// next time. `None` when there's no such field or the instance has too
+    /// many fields to keep them in order.
+    pub(crate) fn field_slot(&self, name: &str) -> Option<usize> {
+        self.0.borrow().fields.slot(name)
+    }
+
+    /// The field in `slot`, if it's the one named `name`
+    pub(crate) fn field_at(&self, slot: usize, name: &str) -> Option<Value> {
+        self.0.borrow().fields.get_at(slot, name).cloned()
+    }
+
+    /// Sets the field named `name` in `slot`, see `Fields::set_at`
+    pub(crate) fn set_field_at(&mut self, slot: usize, name: &str, value: Value) -> bool {
+        let mut data = self.0.borrow_mut();
+        if !data.fields.set_at(slot, name, value) {
+            return false;
+        }
+
+        let hint = &data.class.shape.field_hint;
+        hint.set(hint.get().max(data.fields.len()));
+        true
+    }
+
+    /// Whether the instance is of the class with `shape`, not counting
+    /// subclasses
+    pub(crate) fn has_shape(&self, shape: &Rc<Shape>) -> bool {
+        Rc::ptr_eq(&self.0.borrow().class.shape, shape)
+    }
+
+    pub(crate) fn shape(&self) -> Rc<Shape> {
+        self.0.borrow().class.shape.clone()
+    }
+
+    /// Removes a field, returning whether it existed
+    pub fn remove_field(&mut self, name: &str) -> bool {
+        self.0.borrow_mut().fields.remove(name).is_some()
+    }
+
+    pub fn has_field(&self, name: &str) -> bool {
+        self.0.borrow().fields.contains_key(name)
+    }
+
+    pub fn set_native_state<T: Any>(&self, state: T) {
+        self.0.borrow_mut().native_state = Some(Box::new(state));
+    }
+
+    /// Runs `f` with the instance's native state, returning `None` when the
+    /// instance has no state of type `T`
+    pub fn with_native_state<T: Any, R>(&self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.0
+            .borrow_mut()
+            .native_state
+            .as_mut()
+            .and_then(|state| state.downcast_mut::<T>())
+            .map(f)
     }
 }
 
@@ -170,56 +329,213 @@ pub enum Value {
     Function {
         name: Box<Token>,
         parameters: Vec<Token>,
-        body: Vec<Stmt>,
+        /// Shared by the copies of a function, like the bound methods made
+        /// from it
+        body: Rc<Vec<Stmt>>,
         closure: Rc<RefCell<Environment>>,
         is_initializer: bool,
+        /// Calls return a `Generator` running the body instead of running it
+        is_generator: bool,
     },
     NativeFunction(Box<dyn Callable>),
     Class(LoxClass),
     Instance(LoxInstance),
+    List(Rc<RefCell<Vec<Value>>>),
+    /// An object of the host's, see `Foreign`
+    Foreign(Foreign),
     Nil,
 }
 
 impl Value {
+    /// The value as the rules of `semantics` see it
+    pub fn primitive(&self) -> Primitive<'_> {
+        match self {
+            Value::Nil => Primitive::Nil,
+            Value::Boolean(boolean) => Primitive::Boolean(*boolean),
+            Value::Number(number) => Primitive::Number(*number),
+            Value::String(string) => Primitive::String(string),
+            Value::Function { .. } | Value::NativeFunction(_) => Primitive::Object("function"),
+            Value::Class(_) => Primitive::Object("class"),
+            Value::Instance(_) => Primitive::Object("instance"),
+            Value::List(_) => Primitive::Object("list"),
+            Value::Foreign(_) => Primitive::Object("foreign"),
+        }
+    }
+
     pub fn is_equal(&self, other: &Value) -> bool {
-        match (self, other) {
-            (Value::Nil, Value::Nil) => true,
-            (Value::Nil, _) => false,
-            (Value::Boolean(v1), Value::Boolean(v2)) => v1 == v2,
-            #[allow(clippy::float_cmp)]
-            (Value::Number(v1), Value::Number(v2)) => v1 == v2,
-            (Value::String(v1), Value::String(v2)) => v1 == v2,
-            (_, _) => false,
+        semantics::is_equal(self.primitive(), other.primitive(), || {
+            match (self, other) {
+                (Value::List(v1), Value::List(v2)) => Rc::ptr_eq(v1, v2),
+                (Value::Instance(v1), Value::Instance(v2)) => Rc::ptr_eq(v1, v2),
+                (Value::Foreign(v1), Value::Foreign(v2)) => v1.ptr_eq(v2),
+                _ => false,
+            }
+        })
+    }
+
+    /// Names of the parameters of a Lox function, or of a class's `init`.
+    /// Natives don't name their parameters.
+    pub fn parameter_names(&self) -> Vec<String> {
+        match self {
+            Value::Function { parameters, .. } => parameters
+                .iter()
+                .map(|parameter| parameter.lexeme.clone())
+                .collect(),
+            Value::Class(class) => class
+                .find_method("init")
+                .map(Value::parameter_names)
+                .unwrap_or_default(),
+            _ => vec![],
+        }
+    }
+
+    /// Name of the value's type, as used in error messages
+    pub fn type_name(&self) -> &'static str {
+        self.primitive().type_name()
+    }
+
+    /// The host object, if this is a foreign value holding a `T`
+    pub fn foreign<T: Any>(&self) -> Option<&T> {
+        match self {
+            Value::Foreign(foreign) => foreign.downcast_ref(),
+            _ => None,
         }
     }
 
     pub fn is_truthy(&self) -> bool {
-        match *self {
-            Value::Nil => false,
-            Value::Boolean(value) => value,
-            _ => true,
+        semantics::is_truthy(self.primitive())
+    }
+
+    /// Whether `call_bound` can call this method without binding it first
+    pub(crate) fn calls_unbound(&self) -> bool {
+        matches!(
+            self,
+            Value::Function {
+                is_generator: false,
+                ..
+            }
+        )
+    }
+
+    /// Calls this method as if it were bound to `instance`, like
+    /// `self.bind(instance)?.call(..)`. For Lox methods it skips making the
+    /// bound method, and only adds the scope defining `this`.
+    pub(crate) fn call_bound(
+        &self,
+        interpreter: &mut Interpreter,
+        instance: &LoxInstance,
+        arguments: &[Value],
+    ) -> Result<Value> {
+        let closure = match self {
+            Value::Function { closure, .. } if self.calls_unbound() => closure,
+            _ => return self.bind(instance)?.call(interpreter, arguments),
+        };
+
+        self.validate(arguments)?;
+
+        let environment = Environment::new_with_parent(closure.clone());
+        environment
+            .borrow_mut()
+            .define("this", Value::Instance(instance.clone()));
+
+        self.run_function(interpreter, &environment, arguments)
+    }
+
+    /// Runs the body of this Lox function in a new scope of `closure`, with
+    /// its parameters bound to `arguments`
+    fn run_function(
+        &self,
+        interpreter: &mut Interpreter,
+        closure: &Rc<RefCell<Environment>>,
+        arguments: &[Value],
+    ) -> Result<Value> {
+        let (name, parameters, body, is_initializer) = match self {
+            Value::Function {
+                name,
+                parameters,
+                body,
+                is_initializer,
+                ..
+            } => (name, parameters, body, *is_initializer),
+            _ => return Err(LoxError::NotCallableError),
+        };
+
+        interpreter.enter_call(name)?;
+
+        let new_scope = Environment::new_with_parent(closure.clone());
+
+        for (i, parameter) in parameters.iter().enumerate() {
+            new_scope
+                .borrow_mut()
+                .define(&parameter.lexeme, arguments[i].clone())
+        }
+
+        let result = interpreter.execute_block(body, new_scope);
+        interpreter.exit_call();
+
+        match result {
+            Ok(()) => Ok(match is_initializer {
+                false => Value::Nil,
+                true => closure.borrow().get_keyword_at(0, "this")?,
+            }),
+            Err(LoxError::ReturnJump(value)) => Ok(match is_initializer {
+                false => value,
+                true => closure.borrow().get_keyword_at(0, "this")?,
+            }),
+            Err(error) => Err(error),
         }
     }
 
     pub fn to_number(&self, token: &Token) -> Result<f64> {
         self.try_into().map_err(|_| LoxError::RuntimeError {
-            token: token.clone(),
-            message: "Operand must be a number.".into(),
+            token: Box::new(token.clone()),
+            message: semantics::number_operand_error(self.primitive()),
         })
     }
 }
 
+thread_local! {
+    /// Lists being formatted on this thread, so a list that contains itself
+    /// prints as `[...]` the second time instead of recursing forever
+    static FORMATTING_LISTS: RefCell<HashSet<*const RefCell<Vec<Value>>>> =
+        RefCell::new(HashSet::new());
+}
+
 impl Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Self::String(value) => Display::fmt(value, f),
-            Self::Number(value) => Display::fmt(value, f),
-            Self::Boolean(value) => Display::fmt(value, f),
+            Self::Nil | Self::Boolean(_) | Self::Number(_) | Self::String(_) => {
+                Display::fmt(&self.primitive(), f)
+            }
             Self::NativeFunction(_) => Display::fmt("<native fn>", f),
             Self::Function { name, .. } => write!(f, "<fn {}>", name.lexeme),
             Self::Class(class) => Display::fmt(class, f),
             Self::Instance(instance) => Display::fmt(instance, f),
-            Self::Nil => Display::fmt("nil", f),
+            Self::List(values) => {
+                let list = Rc::as_ptr(values);
+                let is_new = FORMATTING_LISTS.with(|lists| lists.borrow_mut().insert(list));
+                if !is_new {
+                    // The list contains itself, somewhere down
+                    return write!(f, "[...]");
+                }
+
+                let result = (|| {
+                    write!(f, "[")?;
+                    for (i, value) in values.borrow().iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+
+                        write!(f, "{}", value)?;
+                    }
+
+                    write!(f, "]")
+                })();
+
+                FORMATTING_LISTS.with(|lists| lists.borrow_mut().remove(&list));
+                result
+            }
+            Self::Foreign(foreign) => write!(f, "<{}>", foreign.name()),
         }
     }
 }
@@ -230,32 +546,9 @@ impl Callable for Value {
             Value::NativeFunction(callee) => callee.call(interpreter, arguments),
             Value::Class(callee) => callee.call(interpreter, arguments),
             Value::Function {
-                parameters,
-                body,
-                closure,
-                is_initializer,
-                ..
-            } => {
-                let new_scope = Environment::new_with_parent(closure.clone());
-
-                for (i, parameter) in parameters.iter().enumerate() {
-                    new_scope
-                        .borrow_mut()
-                        .define(&parameter.lexeme, arguments[i].clone())
-                }
-
-                match interpreter.execute_block(body, new_scope) {
-                    Ok(()) => Ok(match is_initializer {
-                        false => Value::Nil,
-                        true => closure.borrow().get_keyword_at(0, "this")?,
-                    }),
-                    Err(LoxError::ReturnJump(value)) => Ok(match is_initializer {
-                        false => value,
-                        true => closure.borrow().get_keyword_at(0, "this")?,
-                    }),
-                    Err(error) => Err(error),
-                }
-            }
+                is_generator: true, ..
+            } => new_generator(self.clone(), arguments),
+            Value::Function { closure, .. } => self.run_function(interpreter, closure, arguments),
             _ => Err(LoxError::NotCallableError),
         }
     }
@@ -269,6 +562,19 @@ impl Callable for Value {
         }
     }
 
+    /// Values that can't be called fail as such, whatever the arguments
+    fn validate(&self, arguments: &[Value]) -> Result<()> {
+        match self {
+            Value::Function { .. } | Value::NativeFunction(_) | Value::Class(_)
+                if arguments.len() != self.arity() =>
+            {
+                Err(LoxError::IncorrectArityError)
+            }
+            Value::Function { .. } | Value::NativeFunction(_) | Value::Class(_) => Ok(()),
+            _ => Err(LoxError::NotCallableError),
+        }
+    }
+
     fn bind(&self, instance: &LoxInstance) -> Result<Value> {
         match self {
             Value::Function {
@@ -277,6 +583,7 @@ impl Callable for Value {
                 body,
                 closure,
                 is_initializer,
+                is_generator,
             } => {
                 let environment = Environment::new_with_parent(closure.clone());
                 environment
@@ -289,8 +596,10 @@ impl Callable for Value {
                     body: body.clone(),
                     closure: environment,
                     is_initializer: *is_initializer,
+                    is_generator: *is_generator,
                 })
             }
+            Value::NativeFunction(callable) => callable.bind(instance),
             _ => Err(LoxError::NotBindableError),
         }
     }
@@ -302,12 +611,89 @@ impl From<String> for Value {
     }
 }
 
+impl From<Vec<Value>> for Value {
+    fn from(values: Vec<Value>) -> Self {
+        Self::List(Rc::new(RefCell::new(values)))
+    }
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Self {
+        Self::String(value.to_string())
+    }
+}
+
 impl From<f64> for Value {
     fn from(value: f64) -> Self {
         Self::Number(value)
     }
 }
 
+impl From<bool> for Value {
+    fn from(value: bool) -> Self {
+        Self::Boolean(value)
+    }
+}
+
+impl From<()> for Value {
+    fn from(_: ()) -> Self {
+        Self::Nil
+    }
+}
+
+/// Error for a value of the wrong type returned to the host
+fn expected_type(expected: &str, value: &Value) -> LoxError {
+    LoxError::NativeError(format!(
+        "Expected {} but got a {}.",
+        expected,
+        value.type_name()
+    ))
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = LoxError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(number) => Ok(number),
+            value => Err(expected_type("a number", &value)),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = LoxError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Boolean(boolean) => Ok(boolean),
+            value => Err(expected_type("a boolean", &value)),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = LoxError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(string) => Ok(string),
+            value => Err(expected_type("a string", &value)),
+        }
+    }
+}
+
+impl TryFrom<Value> for () {
+    type Error = LoxError;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Nil => Ok(()),
+            value => Err(expected_type("nil", &value)),
+        }
+    }
+}
+
 impl TryFrom<&Value> for f64 {
     type Error = LoxError;
 
@@ -318,3 +704,28 @@ impl TryFrom<&Value> for f64 {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_containing_itself_prints_once() {
+        let list = Value::from(vec![Value::from(1.0)]);
+        if let Value::List(values) = &list {
+            values.borrow_mut().push(list.clone());
+        }
+
+        assert_eq!(list.to_string(), "[1, [...]]");
+        // Prints the same again, so nothing was left marked as being formatted
+        assert_eq!(list.to_string(), "[1, [...]]");
+    }
+
+    #[test]
+    fn same_list_twice_isnt_a_cycle() {
+        let inner = Value::from(vec![Value::from(1.0)]);
+        let outer = Value::from(vec![inner.clone(), inner]);
+
+        assert_eq!(outer.to_string(), "[[1], [1]]");
+    }
+}
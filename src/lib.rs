@@ -0,0 +1,27 @@
+//! A tree-walk interpreter and a bytecode VM for the Lox programming language.
+//!
+//! The `ilox` and `blox` binaries are thin command line wrappers around the
+//! [`interpreter`] and [`bytecode`] modules, which share the rules in
+//! [`semantics`].
+//!
+//! Everything but the tree-walk interpreter can be left out with cargo
+//! features, for embedding it in other programs:
+//!
+//! ```text
+//! cli                     the ilox and blox binaries, and their shared options
+//! repl                    interpreter::repl
+//! bytecode                the bytecode module
+//! natives-io              readLine and flush
+//! natives-random          random and seedRandom
+//! natives-introspection   globals and locals
+//! ```
+//!
+//! They're all on by default. `default-features = false` leaves only the
+//! interpreter, without the dependencies of the binaries.
+
+#[cfg(feature = "bytecode")]
+pub mod bytecode;
+#[cfg(feature = "cli")]
+pub mod cli;
+pub mod interpreter;
+pub mod semantics;
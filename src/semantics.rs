@@ -0,0 +1,256 @@
+//! Rules of the language that don't depend on how it's run: truthiness,
+//! equality, comparison and how values print. The tree-walk interpreter and
+//! the bytecode VM both defer to these, through [`Primitive`], so they can't
+//! disagree.
+//!
+//! ```text
+//! nil, false                 falsey; every other value, 0 and "" included, is truthy
+//! nil == nil                 true; nil equals nothing else
+//! 1 == "1"                   false; values of different types are never equal
+//! 0 / 0 == 0 / 0             true; NaN equals itself, otherwise numbers follow IEEE 754
+//! [] == []                   false; objects are only equal to themselves
+//! 1 < 2                      true; only numbers can be ordered
+//! true < false               error: Operands must be numbers: left operand is boolean, right is boolean.
+//! ```
+
+use std::fmt::{self, Display};
+
+/// What the rules need to know about a value of either backend
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Primitive<'a> {
+    Nil,
+    Boolean(bool),
+    Number(f64),
+    String(&'a str),
+    /// Anything compared by identity, like lists, instances and functions,
+    /// with the name of its type
+    Object(&'static str),
+}
+
+impl<'a> Primitive<'a> {
+    /// Name of the type, as used in error messages
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Primitive::Nil => "nil",
+            Primitive::Boolean(_) => "boolean",
+            Primitive::Number(_) => "number",
+            Primitive::String(_) => "string",
+            Primitive::Object(name) => name,
+        }
+    }
+}
+
+/// Writes numbers, strings, booleans and `nil` the way `print` shows them.
+/// Objects have no text of their own here, each backend shows them its way.
+impl<'a> Display for Primitive<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Primitive::Nil => write!(f, "nil"),
+            Primitive::Boolean(boolean) => write!(f, "{}", boolean),
+            Primitive::Number(number) => write!(f, "{}", format_number(*number)),
+            Primitive::String(string) => write!(f, "{}", string),
+            Primitive::Object(name) => write!(f, "<{}>", name),
+        }
+    }
+}
+
+/// Only `nil` and `false` are falsey
+pub fn is_truthy(value: Primitive) -> bool {
+    !matches!(value, Primitive::Nil | Primitive::Boolean(false))
+}
+
+/// Whether `==` holds. Values of different types are never equal, and two
+/// objects of the same type are equal if `same_object` says they're the same
+/// one.
+pub fn is_equal(left: Primitive, right: Primitive, same_object: impl FnOnce() -> bool) -> bool {
+    match (left, right) {
+        (Primitive::Nil, Primitive::Nil) => true,
+        (Primitive::Boolean(a), Primitive::Boolean(b)) => a == b,
+        (Primitive::Number(a), Primitive::Number(b)) => numbers_equal(a, b),
+        (Primitive::String(a), Primitive::String(b)) => a == b,
+        (Primitive::Object(a), Primitive::Object(b)) => a == b && same_object(),
+        _ => false,
+    }
+}
+
+/// Equality of numbers. It follows IEEE 754, so `-0 == 0`, except that NaN
+/// equals itself: `==` stays reflexive, and NaN can be found in a list or
+/// matched like any other value. Ordering comparisons with NaN are all false.
+pub fn numbers_equal(a: f64, b: f64) -> bool {
+    #[allow(clippy::float_cmp)]
+    let equal = a == b;
+    equal || (a.is_nan() && b.is_nan())
+}
+
+/// An ordering operator
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+/// Applies `comparison` to two values. Only numbers can be ordered, so for
+/// anything else, booleans and `nil` included, this returns the error message
+/// naming the types it got.
+pub fn compare(comparison: Comparison, left: Primitive, right: Primitive) -> Result<bool, String> {
+    let (a, b) = match (left, right) {
+        (Primitive::Number(a), Primitive::Number(b)) => (a, b),
+        _ => return Err(operand_error("Operands must be numbers", left, right)),
+    };
+
+    Ok(match comparison {
+        Comparison::Less => a < b,
+        Comparison::LessEqual => a <= b,
+        Comparison::Greater => a > b,
+        Comparison::GreaterEqual => a >= b,
+    })
+}
+
+/// Error message for a binary operator given operands it can't use, like
+/// `Operands must be numbers: left operand is string, right is nil.`
+pub fn operand_error(expected: &str, left: Primitive, right: Primitive) -> String {
+    format!(
+        "{}: left operand is {}, right is {}.",
+        expected,
+        left.type_name(),
+        right.type_name()
+    )
+}
+
+/// Error message for a unary operator given a value that isn't a number,
+/// like `Operand must be a number, got nil.`
+pub fn number_operand_error(operand: Primitive) -> String {
+    format!("Operand must be a number, got {}.", operand.type_name())
+}
+
+/// How numbers print: integers without a fractional part, and `inf`, `-inf`
+/// and `NaN` for the results of dividing by zero
+pub fn format_number(number: f64) -> String {
+    number.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIST: Primitive = Primitive::Object("list");
+
+    #[test]
+    fn only_nil_and_false_are_falsey() {
+        assert!(!is_truthy(Primitive::Nil));
+        assert!(!is_truthy(Primitive::Boolean(false)));
+
+        for value in [
+            Primitive::Boolean(true),
+            Primitive::Number(0.0),
+            Primitive::Number(f64::NAN),
+            Primitive::String(""),
+            LIST,
+        ] {
+            assert!(is_truthy(value), "{:?} should be truthy", value);
+        }
+    }
+
+    #[test]
+    fn values_of_different_types_are_never_equal() {
+        let values = [
+            Primitive::Nil,
+            Primitive::Boolean(false),
+            Primitive::Number(0.0),
+            Primitive::String(""),
+            LIST,
+        ];
+
+        for (i, left) in values.iter().enumerate() {
+            for (j, right) in values.iter().enumerate() {
+                assert_eq!(
+                    is_equal(*left, *right, || true),
+                    i == j,
+                    "{:?} == {:?}",
+                    left,
+                    right
+                );
+            }
+        }
+
+        assert!(!is_equal(
+            Primitive::Number(1.0),
+            Primitive::String("1"),
+            || true
+        ));
+    }
+
+    #[test]
+    fn numbers_follow_ieee_754_except_nan_equals_itself() {
+        assert!(numbers_equal(f64::NAN, f64::NAN));
+        assert!(numbers_equal(-0.0, 0.0));
+        assert!(numbers_equal(0.1 + 0.2, 0.1 + 0.2));
+        assert!(!numbers_equal(0.1 + 0.2, 0.3));
+        assert!(!numbers_equal(f64::NAN, 0.0));
+
+        let nan = Primitive::Number(f64::NAN);
+        assert!(is_equal(nan, nan, || false));
+        assert_eq!(
+            compare(Comparison::LessEqual, nan, nan),
+            Ok(false),
+            "ordering NaN is always false"
+        );
+    }
+
+    #[test]
+    fn objects_are_equal_only_to_themselves() {
+        assert!(is_equal(LIST, LIST, || true));
+        assert!(!is_equal(LIST, LIST, || false));
+        // Objects of different types aren't even asked about
+        assert!(!is_equal(LIST, Primitive::Object("instance"), || {
+            panic!("same_object called for different types")
+        }));
+    }
+
+    #[test]
+    fn only_numbers_can_be_ordered() {
+        let (one, two) = (Primitive::Number(1.0), Primitive::Number(2.0));
+        assert_eq!(compare(Comparison::Less, one, two), Ok(true));
+        assert_eq!(compare(Comparison::LessEqual, two, two), Ok(true));
+        assert_eq!(compare(Comparison::Greater, one, two), Ok(false));
+        assert_eq!(compare(Comparison::GreaterEqual, one, two), Ok(false));
+
+        assert_eq!(
+            compare(
+                Comparison::Less,
+                Primitive::Boolean(true),
+                Primitive::Boolean(false)
+            ),
+            Err("Operands must be numbers: left operand is boolean, right is boolean.".into())
+        );
+        assert_eq!(
+            compare(Comparison::Greater, Primitive::String("a"), Primitive::Nil),
+            Err("Operands must be numbers: left operand is string, right is nil.".into())
+        );
+        assert_eq!(
+            compare(Comparison::Less, one, LIST),
+            Err("Operands must be numbers: left operand is number, right is list.".into())
+        );
+        assert_eq!(
+            number_operand_error(Primitive::Object("function")),
+            "Operand must be a number, got function."
+        );
+    }
+
+    #[test]
+    fn values_print_like_print_shows_them() {
+        let printed = |value: Primitive| value.to_string();
+
+        assert_eq!(printed(Primitive::Nil), "nil");
+        assert_eq!(printed(Primitive::Boolean(true)), "true");
+        assert_eq!(printed(Primitive::Number(3.0)), "3");
+        assert_eq!(printed(Primitive::Number(-2.5)), "-2.5");
+        assert_eq!(printed(Primitive::Number(f64::INFINITY)), "inf");
+        assert_eq!(printed(Primitive::Number(f64::NEG_INFINITY)), "-inf");
+        assert_eq!(printed(Primitive::Number(f64::NAN)), "NaN");
+        assert_eq!(printed(Primitive::String("a b")), "a b");
+        assert_eq!(printed(LIST), "<list>");
+    }
+}
@@ -0,0 +1,100 @@
+//! Runs the examples of the README and the standard library conformance
+//! suite, as `ilox doctest` does, so a change to what a native does or prints
+//! fails the build instead of waiting for someone to run them by hand. The
+//! VM's natives can't be reached from Lox yet, so they're checked here too.
+
+use std::{fs, path::Path};
+
+use lox_rs::{
+    bytecode::{
+        assembler::Assembler,
+        vm::{Vm, VmConfig},
+    },
+    interpreter::{
+        diagnostic::{ColorChoice, DiagnosticRenderer, ErrorFormat},
+        doc_examples::DocExample,
+        interpreter::InterpreterConfig,
+        output::CaptureBuffer,
+        Lox,
+    },
+};
+
+/// Runs the Lox examples of `file`, relative to the crate, failing with every
+/// example that didn't print what it expected
+fn run_examples(file: &str) {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(file);
+    let markdown = fs::read_to_string(&path).expect("markdown file is readable");
+
+    let mut lox = Lox::new(
+        InterpreterConfig::default(),
+        DiagnosticRenderer::new(ColorChoice::Never, ErrorFormat::Human),
+    );
+    lox.load_prelude();
+    lox.set_source_name(Some(file.to_string()));
+
+    let examples = DocExample::extract(&markdown);
+    assert!(!examples.is_empty(), "{} has no examples", file);
+
+    let failures: Vec<String> = examples
+        .iter()
+        .filter_map(|example| {
+            let message = example.run(&lox).err()?;
+            Some(format!("{}:{}: {}", file, example.line, message))
+        })
+        .collect();
+
+    assert!(
+        failures.is_empty(),
+        "examples failed:\n{}",
+        failures.join("\n")
+    );
+}
+
+/// Assembles and runs `source` on a fresh VM, returning what it printed
+fn run_chunk(source: &str) -> Result<String, String> {
+    let chunk = Assembler::new(source).assemble().expect("chunk assembles");
+    let output = CaptureBuffer::new();
+
+    let mut vm = Vm::new(VmConfig {
+        debug: false,
+        trace_json: None,
+        stats: false,
+    })
+    .expect("VM starts");
+    vm.set_output(Box::new(output.clone()));
+    vm.run_chunk(chunk).map_err(|error| error.to_string())?;
+
+    Ok(output.contents())
+}
+
+#[test]
+fn stdlib_examples() {
+    run_examples("data/stdlib.md");
+}
+
+#[test]
+fn readme_examples() {
+    run_examples("README.md");
+}
+
+#[test]
+fn vm_clock_matches_the_interpreter() {
+    let printed = run_chunk("OP_GET_NATIVE clock\nOP_CALL 0\nOP_RETURN\n").unwrap();
+    let seconds: f64 = printed.trim().parse().expect("clock returns a number");
+
+    // Whole seconds since the epoch, like the interpreter's `clock()`
+    assert!(seconds > 1_600_000_000.0, "printed {}", printed);
+    assert_eq!(seconds.fract(), 0.0, "printed {}", printed);
+}
+
+#[test]
+fn vm_clock_checks_its_arity() {
+    let error = run_chunk("OP_GET_NATIVE clock\nOP_CONSTANT 1\nOP_CALL 1\nOP_RETURN\n")
+        .expect_err("calling clock with an argument fails");
+
+    assert!(
+        error.contains("Expected 0 arguments but got 1."),
+        "got {}",
+        error
+    );
+}
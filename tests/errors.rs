@@ -0,0 +1,83 @@
+//! Checks the messages of runtime errors, caught with `try` so the program
+//! can print them.
+
+use lox_rs::interpreter::{
+    diagnostic::{ColorChoice, DiagnosticRenderer, ErrorFormat},
+    interpreter::InterpreterConfig,
+    Lox,
+};
+
+/// The message of the error raised by the statement `failing`, run after
+/// `setup`
+fn message(setup: &str, failing: &str) -> String {
+    let mut lox = Lox::new(
+        InterpreterConfig::default(),
+        DiagnosticRenderer::new(ColorChoice::Never, ErrorFormat::Human),
+    );
+
+    let source = format!(
+        "{}\ntry {{ {} }} catch (error) {{ print error.message; }}",
+        setup, failing
+    );
+    let output = lox.capture_output(|lox| lox.run(source));
+    assert!(
+        !lox.had_error && !lox.had_runtime_error,
+        "failed running {}",
+        failing
+    );
+
+    output.trim_end().to_string()
+}
+
+const CHAIN: &str = "
+class Node {
+  next() { return this; }
+  end() { return nil; }
+}
+var a = Node();
+a.b = a;
+";
+
+#[test]
+fn access_path_stops_before_the_failing_access() {
+    assert_eq!(
+        message(CHAIN, "a.b.end().d();"),
+        "Only instances have properties (while accessing 'a.b.end()')."
+    );
+    assert_eq!(
+        message(CHAIN, "a.next().next().end().d();"),
+        "Only instances have properties (while accessing 'a.next().next().end()')."
+    );
+    assert_eq!(
+        message(CHAIN, "a.b.missing();"),
+        "Undefined property 'missing' (while accessing 'a.b')."
+    );
+}
+
+#[test]
+fn access_path_of_a_failing_call_is_its_callee() {
+    assert_eq!(
+        message(CHAIN, "a.b.end()();"),
+        "Can only call functions and classes (while accessing 'a.b.end()')."
+    );
+    assert_eq!(
+        message(CHAIN, "a.next().end()();"),
+        "Can only call functions and classes (while accessing 'a.next().end()')."
+    );
+}
+
+#[test]
+fn access_path_is_left_out_when_the_first_access_fails() {
+    assert_eq!(
+        message(CHAIN, "a.missing;"),
+        "Undefined property 'missing'."
+    );
+    assert_eq!(
+        message(CHAIN, "a.missing.b;"),
+        "Undefined property 'missing'."
+    );
+    assert_eq!(
+        message("var a = nil;", "a();"),
+        "Can only call functions and classes."
+    );
+}
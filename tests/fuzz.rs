@@ -0,0 +1,395 @@
+//! Runs generated and mangled programs through every phase, checking that no
+//! input makes the interpreter panic or overflow the stack, and does the same
+//! for the assembler, the optimization passes and the VM. Inputs come from a
+//! fixed seed, so a failure reproduces on every run and prints the program
+//! that caused it.
+
+use std::{
+    io,
+    panic::{self, AssertUnwindSafe},
+    thread,
+};
+
+use lox_rs::{
+    bytecode::{
+        assembler::Assembler,
+        chunk::{Chunk, OpCode},
+        eliminator::DeadCodeEliminator,
+        error::LoxError as VmError,
+        folder::ConstantFolder,
+        peephole::Peephole,
+        vm::{Vm, VmConfig},
+    },
+    interpreter::{
+        diagnostic::{ColorChoice, DiagnosticRenderer, ErrorFormat, Severity},
+        interpreter::InterpreterConfig,
+        strictness::Strictness,
+        Lox,
+    },
+};
+
+/// Same as the stack `ilox` runs programs on
+const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+/// Nesting far past the default limit, which overflowed the stack before the
+/// parser checked it
+const DEEP: usize = 3000;
+
+const CORPUS: &str = r#"
+class Shape {
+  init(name) { this.name = name; }
+  area() { return 0; }
+  describe() { return this.name; }
+}
+class Square < Shape {
+  init(side) { super.init("square"); this.side = side; }
+  area() { return this.side * this.side; }
+}
+print Square(3).describe();
+fun counter() {
+  var count = 0;
+  fun increment() { count = count + 1; return count; }
+  return increment;
+}
+var next = counter();
+print next();
+var total = 0;
+for (var i = 0; i < 10; i = i + 1) {
+  if (i == 5) total = total - 1; else total = total + i;
+}
+var xs = [3, 1, 2];
+for (var x in xs) print x * 2;
+var (first, second) = [1, 2];
+print match (total) { 0 -> "zero", n if n > 0 -> "positive", _ -> "negative" };
+try {
+  throw "oops";
+} catch (error) {
+  print error;
+} finally {
+  print "done";
+}
+fun *numbers() { yield 1; yield 2; }
+var i = 0;
+while (i < 3) { i = i + 1; }
+print !true or nil and -1 >= 2;
+print "a" + "b" == "ab";
+"#;
+
+/// Tokens random programs are made of, separated by whitespace
+const TOKENS: &str = r#"
+    ( ) { } [ ] , . - + ; / * ! != = == > >= < <= -> : ? a b f Shape "text" “smart”
+    1 2.5 1e3 0x1F and class else false for fun if in match nil or print return super
+    this true var while try catch finally throw yield delete import export using with
+    len sort map @ #
+"#;
+
+/// Instructions and operands random chunks are assembled from, one per line
+const ASSEMBLY: &str = "
+    OP_CONSTANT OP_ADD OP_SUBTRACT OP_MULTIPLY OP_DIVIDE OP_NEGATE OP_NIL OP_TRUE
+    OP_FALSE OP_NOT OP_EQUAL OP_GREATER OP_LESS OP_RETURN OP_GET_NATIVE OP_CALL
+    OP_JUMP OP_PUSH_HANDLER OP_POP_HANDLER OP_ADD_CONSTANT OP_CONSTANT_RETURN
+    0 1 2.5 -1 nan 255 clock done failed done: failed: ; op_nil
+";
+
+/// xorshift64*, enough to spread inputs around without a dependency
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+fn lox(strict: bool) -> Lox {
+    let mut config = InterpreterConfig::default();
+    config.limits.max_loop_iterations = Some(10_000);
+    if strict {
+        config.optimize = true;
+        config.type_check = Some(Severity::Warning);
+    }
+
+    let mut lox = Lox::new(
+        config,
+        DiagnosticRenderer::new(ColorChoice::Never, ErrorFormat::Human),
+    );
+    lox.interpreter.set_output(Box::new(io::sink()));
+    // Would wait for stdin
+    lox.interpreter
+        .remove_native("readLine")
+        .expect("readLine is defined");
+    if strict {
+        lox.set_strictness(Strictness::all());
+    }
+
+    lox
+}
+
+/// Runs `source` with default and with every optional check on, failing
+/// with the source if either panics. Returns whether it had errors.
+fn run(source: &str) -> bool {
+    let mut had_error = false;
+    for strict in [false, true] {
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            let mut lox = lox(strict);
+            lox.run(source.to_string());
+            lox.had_error
+        }));
+
+        match result {
+            Ok(error) => had_error |= error,
+            Err(_) => panic!("panicked running:\n{}", source),
+        }
+    }
+
+    had_error
+}
+
+/// Runs `test` on a thread with `ilox`'s stack, so debug builds get as far
+/// as the nesting limit
+fn with_stack(test: impl FnOnce() + Send + 'static) {
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(test)
+        .expect("failed to start test thread")
+        .join()
+        .expect("test thread panicked");
+}
+
+fn nested(open: &str, middle: &str, close: &str, depth: usize) -> String {
+    format!("{}{}{}", open.repeat(depth), middle, close.repeat(depth))
+}
+
+#[test]
+fn deep_nesting_is_a_parse_error() {
+    with_stack(|| {
+        let chain = |separator: &str| vec!["a"; DEEP].join(separator);
+        let programs = [
+            nested("{", "print 1;", "}", DEEP),
+            format!("print {};", nested("(", "1", ")", DEEP)),
+            format!("print {};", nested("[", "1", "]", DEEP)),
+            format!("print {}1;", "-".repeat(DEEP)),
+            format!("print {}true;", "!".repeat(DEEP)),
+            format!("var a; {}1;", "a = ".repeat(DEEP)),
+            format!("var a = 1; print {};", chain(" + ")),
+            format!("var a = true; print {};", chain(" or ")),
+            format!("var a; print a{};", ".b".repeat(DEEP)),
+            format!("fun a() {{ return a; }} a{};", "()".repeat(DEEP)),
+            format!("var a; {}print 1;", "if (a) ".repeat(DEEP)),
+            format!("var a; if (a) {{}}{}", " else if (a) {}".repeat(DEEP)),
+            nested("fun a() {", "", "}", DEEP),
+            format!("print {};", nested("match (1) { _ -> ", "1", " }", DEEP)),
+        ];
+
+        for program in programs {
+            assert!(run(&program), "expected a parse error for:\n{}", program);
+        }
+    });
+}
+
+#[test]
+fn nesting_below_the_limit_runs() {
+    with_stack(|| {
+        let programs = [
+            nested("{", "print 1;", "}", 200),
+            format!("print {};", nested("(", "1", ")", 200)),
+            format!("print {};", vec!["1"; 200].join(" + ")),
+        ];
+
+        for program in programs {
+            assert!(!run(&program), "expected no errors for:\n{}", program);
+        }
+    });
+}
+
+#[test]
+fn random_tokens() {
+    with_stack(|| {
+        let mut rng = Rng(0x5eed_1234_abcd_0001);
+        let tokens: Vec<&str> = TOKENS.split_whitespace().collect();
+        for _ in 0..3000 {
+            let length = 1 + rng.below(60);
+            let program: Vec<&str> = (0..length)
+                .map(|_| tokens[rng.below(tokens.len())])
+                .collect();
+
+            run(&program.join(" "));
+        }
+    });
+}
+
+#[test]
+fn mangled_programs() {
+    with_stack(|| {
+        let mut rng = Rng(0x5eed_1234_abcd_0002);
+        let corpus: Vec<char> = CORPUS.chars().collect();
+        let tokens: Vec<&str> = TOKENS.split_whitespace().collect();
+
+        for _ in 0..1000 {
+            let mut program = corpus.clone();
+            for _ in 0..1 + rng.below(4) {
+                let start = rng.below(program.len());
+                let end = (start + rng.below(40)).min(program.len());
+                match rng.below(4) {
+                    0 => {
+                        program.drain(start..end);
+                    }
+                    1 => {
+                        let copy: Vec<char> = program[start..end].to_vec();
+                        let at = rng.below(program.len());
+                        program.splice(at..at, copy);
+                    }
+                    2 => {
+                        let token = tokens[rng.below(tokens.len())];
+                        program.splice(start..start, token.chars());
+                    }
+                    _ => program.truncate(start),
+                }
+
+                if program.is_empty() {
+                    break;
+                }
+            }
+
+            run(&program.into_iter().collect::<String>());
+        }
+    });
+}
+
+#[test]
+fn random_characters() {
+    with_stack(|| {
+        let mut rng = Rng(0x5eed_1234_abcd_0003);
+        let alphabet: Vec<char> = "ab1.e0x\"'“”‘’ (){}[];+-*/=!<>\n\t\\#@é€\u{0}\u{feff}"
+            .chars()
+            .collect();
+
+        for _ in 0..3000 {
+            let program: String = (0..rng.below(80))
+                .map(|_| alphabet[rng.below(alphabet.len())])
+                .collect();
+
+            run(&program);
+        }
+    });
+}
+
+/// Runs `chunk` as is and optimized, failing with `shown` if either panics
+/// or fails with anything but an error about the chunk
+fn run_chunk(chunk: Chunk, shown: &str) {
+    let run = |chunk: Chunk| -> Result<(), VmError> {
+        let mut vm = Vm::new(VmConfig {
+            debug: false,
+            trace_json: None,
+            stats: true,
+        })?;
+        vm.set_output(Box::new(io::sink()));
+        vm.run_chunk(chunk)
+    };
+    let optimize = |chunk: &Chunk| -> Result<Chunk, VmError> {
+        let chunk = ConstantFolder::new(chunk).fold()?;
+        let chunk = Peephole::new(&chunk).optimize()?;
+        DeadCodeEliminator::new(&chunk).eliminate()
+    };
+
+    let results = panic::catch_unwind(AssertUnwindSafe(|| {
+        [run(chunk.clone()), optimize(&chunk).and_then(run)]
+    }));
+
+    let results = match results {
+        Ok(results) => results,
+        Err(_) => panic!("panicked running:\n{}", shown),
+    };
+    for result in results {
+        match result {
+            Ok(())
+            | Err(VmError::RuntimeError { .. })
+            | Err(VmError::Internal { .. })
+            | Err(VmError::OpCodeConversionError) => {}
+            Err(error) => panic!("{} running:\n{}", error, shown),
+        }
+    }
+}
+
+#[test]
+fn broken_chunks_are_errors() {
+    for source in [
+        "OP_RETURN",
+        "OP_ADD\nOP_RETURN",
+        "OP_CONSTANT 1",
+        "OP_JUMP done\ndone:",
+        &format!("{}OP_RETURN", "OP_NIL\n".repeat(300)),
+    ] {
+        let chunk = Assembler::new(source).assemble().expect("chunk assembles");
+        let mut vm = Vm::new(VmConfig {
+            debug: false,
+            trace_json: None,
+            stats: false,
+        })
+        .expect("VM starts");
+
+        match vm.run_chunk(chunk) {
+            Err(VmError::RuntimeError { .. }) | Err(VmError::Internal { .. }) => {}
+            result => panic!("expected an error running:\n{}\ngot {:?}", source, result),
+        }
+    }
+}
+
+#[test]
+fn random_assembly() {
+    let mut rng = Rng(0x5eed_1234_abcd_0004);
+    let words: Vec<&str> = ASSEMBLY.split_whitespace().collect();
+
+    for _ in 0..3000 {
+        let source: Vec<String> = (0..1 + rng.below(30))
+            .map(|_| {
+                let words: Vec<&str> = (0..1 + rng.below(2))
+                    .map(|_| words[rng.below(words.len())])
+                    .collect();
+                words.join(" ")
+            })
+            .collect();
+        let source = source.join("\n");
+
+        let assembled = panic::catch_unwind(|| Assembler::new(&source).assemble());
+        match assembled {
+            Ok(Ok(chunk)) => run_chunk(chunk, &source),
+            Ok(Err(_)) => {}
+            Err(_) => panic!("panicked assembling:\n{}", source),
+        }
+    }
+}
+
+#[test]
+fn random_bytes() {
+    let mut rng = Rng(0x5eed_1234_abcd_0005);
+    let opcodes = OpCode::ConstantReturn as usize + 1;
+
+    for _ in 0..3000 {
+        let mut chunk = Chunk::new();
+        for _ in 0..rng.below(4) {
+            chunk.add_constant((rng.below(10) as f64).into());
+        }
+
+        // Mostly opcodes, with small operands so constants and jumps are
+        // sometimes in range
+        let bytes: Vec<u8> = (0..rng.below(40))
+            .map(|_| match rng.below(4) {
+                0 => rng.below(8) as u8,
+                1 => rng.below(256) as u8,
+                _ => rng.below(opcodes) as u8,
+            })
+            .collect();
+        for byte in &bytes {
+            chunk.write(*byte, 1);
+        }
+
+        run_chunk(chunk, &format!("{:?}", bytes));
+    }
+}
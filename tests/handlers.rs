@@ -0,0 +1,206 @@
+//! Checks that the VM's error handlers behave like `try` and `catch` in the
+//! tree-walk interpreter. The VM can't compile Lox yet, so each case is
+//! written twice: as Lox, and as the assembly a compiler would emit for it.
+
+use std::{fs, path::Path};
+
+use lox_rs::{
+    bytecode::{
+        assembler::Assembler,
+        error::LoxError as VmError,
+        vm::{Vm, VmConfig},
+    },
+    interpreter::{
+        diagnostic::{ColorChoice, DiagnosticRenderer, ErrorFormat},
+        interpreter::InterpreterConfig,
+        output::CaptureBuffer,
+        Lox,
+    },
+};
+
+/// What the tree-walk interpreter prints running `source`
+fn tree_walk(source: &str) -> String {
+    let mut lox = Lox::new(
+        InterpreterConfig::default(),
+        DiagnosticRenderer::new(ColorChoice::Never, ErrorFormat::Human),
+    );
+
+    let output = lox.capture_output(|lox| lox.run(source.to_string()));
+    assert!(
+        !lox.had_error && !lox.had_runtime_error,
+        "failed running:\n{}",
+        source
+    );
+
+    output
+}
+
+/// What the VM prints running the chunk assembled from `source`, or the
+/// message of the runtime error that stopped it
+fn vm(source: &str) -> Result<String, String> {
+    let chunk = Assembler::new(source).assemble().expect("chunk assembles");
+    let output = CaptureBuffer::new();
+
+    let mut vm = Vm::new(VmConfig {
+        debug: false,
+        trace_json: None,
+        stats: false,
+    })
+    .expect("VM starts");
+    vm.set_output(Box::new(output.clone()));
+
+    match vm.run_chunk(chunk) {
+        Ok(()) => Ok(output.contents()),
+        Err(VmError::RuntimeError { message, .. }) => Err(message),
+        Err(error) => panic!("unexpected error: {}", error),
+    }
+}
+
+fn assert_same_output(lox: &str, assembly: &str) {
+    assert_eq!(vm(assembly), Ok(tree_walk(lox)), "for:\n{}", lox);
+}
+
+#[test]
+fn caught_error_continues_at_the_handler() {
+    let assembly =
+        fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("data/handler.bloxasm"))
+            .expect("example is readable");
+
+    assert_same_output(
+        "var x = 1;
+         try { var y = 2; -clock; x = x + y; } catch (error) { x = x + 10; }
+         print x;",
+        &assembly,
+    );
+}
+
+#[test]
+fn handler_is_skipped_without_an_error() {
+    assert_same_output(
+        "var x = 1;
+         try { x = x + 2; } catch (error) { x = x + 10; }
+         print x;",
+        "OP_CONSTANT 1
+         OP_PUSH_HANDLER failed
+         OP_CONSTANT 2
+         OP_ADD
+         OP_POP_HANDLER
+         OP_JUMP done
+         failed:
+         OP_CONSTANT 10
+         OP_ADD
+         done:
+         OP_RETURN",
+    );
+}
+
+#[test]
+fn innermost_handler_catches() {
+    assert_same_output(
+        "var x = 1;
+         try {
+           try { -clock; } catch (error) { x = x + 2; }
+         } catch (error) {
+           x = x + 100;
+         }
+         print x;",
+        "OP_CONSTANT 1
+         OP_PUSH_HANDLER outer
+         OP_PUSH_HANDLER inner
+         OP_GET_NATIVE clock
+         OP_NEGATE
+         OP_POP_HANDLER
+         OP_JUMP inner_done
+         inner:
+         OP_CONSTANT 2
+         OP_ADD
+         inner_done:
+         OP_POP_HANDLER
+         OP_JUMP done
+         outer:
+         OP_CONSTANT 100
+         OP_ADD
+         done:
+         OP_RETURN",
+    );
+}
+
+#[test]
+fn error_in_a_handler_goes_to_the_enclosing_one() {
+    assert_same_output(
+        "var x = 1;
+         try {
+           try { -clock; } catch (error) { -clock; }
+         } catch (error) {
+           x = x + 100;
+         }
+         print x;",
+        "OP_CONSTANT 1
+         OP_PUSH_HANDLER outer
+         OP_PUSH_HANDLER inner
+         OP_GET_NATIVE clock
+         OP_NEGATE
+         OP_POP_HANDLER
+         OP_JUMP inner_done
+         inner:
+         OP_GET_NATIVE clock
+         OP_NEGATE
+         inner_done:
+         OP_POP_HANDLER
+         OP_JUMP done
+         outer:
+         OP_CONSTANT 100
+         OP_ADD
+         done:
+         OP_RETURN",
+    );
+}
+
+#[test]
+fn native_errors_are_caught() {
+    assert_same_output(
+        "var x = 1;
+         try { clock(1); } catch (error) { x = x + 10; }
+         print x;",
+        "OP_CONSTANT 1
+         OP_PUSH_HANDLER failed
+         OP_GET_NATIVE clock
+         OP_CONSTANT 1
+         OP_CALL 1
+         OP_POP_HANDLER
+         OP_JUMP done
+         failed:
+         OP_CONSTANT 10
+         OP_ADD
+         done:
+         OP_RETURN",
+    );
+}
+
+#[test]
+fn errors_after_the_handler_is_popped_are_uncaught() {
+    let message = tree_walk(
+        "try { -clock; } catch (error) { print error.message; }
+         try { clock(1); } catch (error) { print error.message; }",
+    );
+
+    assert_eq!(
+        vm("OP_PUSH_HANDLER failed
+            OP_POP_HANDLER
+            failed:
+            OP_GET_NATIVE clock
+            OP_NEGATE
+            OP_RETURN"),
+        Err(message.lines().next().unwrap().to_string())
+    );
+    assert_eq!(
+        vm("OP_PUSH_HANDLER failed
+            OP_POP_HANDLER
+            failed:
+            OP_GET_NATIVE clock
+            OP_CONSTANT 1
+            OP_CALL 1
+            OP_RETURN"),
+        Err(message.lines().nth(1).unwrap().to_string())
+    );
+}
@@ -0,0 +1,134 @@
+//! Runs programs made of several files, written to a fresh directory for each
+//! test, to check how imports load and how cycles between them are reported.
+
+use std::{env, fs, path::PathBuf, process};
+
+use lox_rs::interpreter::{
+    diagnostic::{ColorChoice, DiagnosticRenderer, ErrorFormat},
+    interpreter::InterpreterConfig,
+    Lox,
+};
+
+/// Writes `files` to a directory named after `test`, returning the path of
+/// the first one, which is the one run
+fn write_program(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let directory = env::temp_dir().join(format!("lox-imports-{}-{}", process::id(), test));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).expect("temp directory is writable");
+
+    for (name, source) in files {
+        fs::write(directory.join(name), source).expect("temp directory is writable");
+    }
+
+    directory.join(files[0].0)
+}
+
+/// Runs the program, returning what it printed and whether it raised a
+/// runtime error
+fn run(entry: &PathBuf) -> (String, bool) {
+    let mut lox = Lox::new(
+        InterpreterConfig::default(),
+        DiagnosticRenderer::new(ColorChoice::Never, ErrorFormat::Human),
+    );
+
+    let output = lox.capture_output(|lox| lox.run_file(entry).expect("entry is readable"));
+    assert!(!lox.had_error, "program didn't compile");
+
+    (output, lox.had_runtime_error)
+}
+
+#[test]
+fn cycle_is_a_runtime_error_without_running_a_module_twice() {
+    let entry = write_program(
+        "cycle",
+        &[
+            (
+                "main.lox",
+                "print \"main\";\nimport \"a.lox\" as a;\nprint \"after\";\n",
+            ),
+            (
+                "a.lox",
+                "print \"a\";\nimport \"b.lox\" as b;\nexport var x = 1;\n",
+            ),
+            (
+                "b.lox",
+                "print \"b\";\nimport \"a.lox\" as a;\nexport var y = 2;\n",
+            ),
+        ],
+    );
+
+    let (output, had_runtime_error) = run(&entry);
+    assert!(had_runtime_error);
+    // Like other runtime errors, it only stops the top-level statement it's in
+    assert_eq!(output, "main\na\nb\nafter\n");
+}
+
+#[test]
+fn module_importing_itself_is_a_cycle() {
+    let entry = write_program(
+        "self",
+        &[
+            ("main.lox", "import \"a.lox\" as a;\n"),
+            ("a.lox", "print \"a\";\nimport \"a.lox\" as again;\n"),
+        ],
+    );
+
+    assert_eq!(run(&entry), ("a\n".to_string(), true));
+}
+
+#[test]
+fn shared_module_runs_once() {
+    let entry = write_program(
+        "diamond",
+        &[
+            (
+                "main.lox",
+                "import \"a.lox\" as a;\nimport \"b.lox\" as b;\nprint a.value + b.value;\n",
+            ),
+            (
+                "a.lox",
+                "import { base } from \"shared.lox\";\nexport var value = base + 1;\n",
+            ),
+            (
+                "b.lox",
+                "import { base } from \"shared.lox\";\nexport var value = base + 2;\n",
+            ),
+            ("shared.lox", "print \"shared\";\nexport var base = 10;\n"),
+        ],
+    );
+
+    assert_eq!(run(&entry), ("shared\n23\n".to_string(), false));
+}
+
+#[test]
+fn module_files_follow_the_whole_import_graph() {
+    let entry = write_program(
+        "files",
+        &[
+            (
+                "main.lox",
+                "import \"a.lox\" as a;\nimport \"b.lox\" as b;\n",
+            ),
+            (
+                "a.lox",
+                "import \"b.lox\" as b;\nimport \"missing.lox\" as m;\n",
+            ),
+            ("b.lox", "import \"main.lox\" as main;\n"),
+        ],
+    );
+    let directory = entry.parent().unwrap().canonicalize().unwrap();
+
+    let mut lox = Lox::new(
+        InterpreterConfig::default(),
+        DiagnosticRenderer::new(ColorChoice::Never, ErrorFormat::Human),
+    );
+    let mut files = lox.module_files(&entry);
+    files.sort();
+
+    let mut expected: Vec<PathBuf> = ["a.lox", "b.lox", "main.lox", "missing.lox"]
+        .iter()
+        .map(|name| directory.join(name))
+        .collect();
+    expected.sort();
+    assert_eq!(files, expected);
+}
@@ -0,0 +1,195 @@
+//! Runs the optimization passes on chunks with jumps and error handlers,
+//! checking what's left of the code and that it prints the same as before.
+
+use std::{fs, path::Path};
+
+use lox_rs::{
+    bytecode::{
+        assembler::Assembler,
+        chunk::Chunk,
+        eliminator::DeadCodeEliminator,
+        folder::ConstantFolder,
+        peephole::Peephole,
+        vm::{Vm, VmConfig},
+    },
+    interpreter::output::CaptureBuffer,
+};
+
+fn assemble(source: &str) -> Chunk {
+    Assembler::new(source).assemble().expect("chunk assembles")
+}
+
+/// Runs every pass `blox run -O` does
+fn optimize(chunk: &Chunk) -> Chunk {
+    let chunk = ConstantFolder::new(chunk).fold().expect("folds");
+    let chunk = Peephole::new(&chunk).optimize().expect("fuses");
+    DeadCodeEliminator::new(&chunk)
+        .eliminate()
+        .expect("eliminates")
+}
+
+/// The instructions of `chunk` as they'd be assembled, with constants and
+/// jump distances as numbers
+fn listing(chunk: &Chunk) -> Vec<String> {
+    chunk
+        .instructions()
+        .expect("chunk decodes")
+        .iter()
+        .map(|instruction| match instruction.operand {
+            Some(operand) => format!("{} {}", instruction.code, operand),
+            None => instruction.code.to_string(),
+        })
+        .collect()
+}
+
+fn run(chunk: Chunk) -> String {
+    let output = CaptureBuffer::new();
+    let mut vm = Vm::new(VmConfig {
+        debug: false,
+        trace_json: None,
+        stats: false,
+    })
+    .expect("VM starts");
+    vm.set_output(Box::new(output.clone()));
+    vm.run_chunk(chunk).expect("chunk runs");
+
+    output.contents()
+}
+
+/// Optimizes the chunk assembled from `source`, checking it prints the same
+/// and returning its listing
+fn optimized(source: &str) -> Vec<String> {
+    let chunk = assemble(source);
+    let optimized = optimize(&chunk);
+    assert_eq!(run(optimized.clone()), run(chunk), "for:\n{}", source);
+
+    listing(&optimized)
+}
+
+#[test]
+fn code_after_a_jump_is_removed() {
+    assert_eq!(
+        optimized(
+            "OP_JUMP done
+             OP_CONSTANT 1
+             OP_NEGATE
+             OP_RETURN
+             done:
+             OP_CONSTANT 2
+             OP_RETURN"
+        ),
+        ["OP_JUMP 0", "OP_CONSTANT_RETURN 0"]
+    );
+}
+
+#[test]
+fn handler_code_is_kept_and_its_jumps_remapped() {
+    let source =
+        fs::read_to_string(Path::new(env!("CARGO_MANIFEST_DIR")).join("data/handler.bloxasm"))
+            .expect("example is readable");
+
+    assert_eq!(
+        optimized(&source),
+        [
+            "OP_CONSTANT 0",
+            "OP_PUSH_HANDLER 8",
+            "OP_CONSTANT 1",
+            "OP_GET_NATIVE 0",
+            "OP_NEGATE",
+            "OP_POP_HANDLER",
+            "OP_JUMP 2",
+            "OP_ADD_CONSTANT 2",
+            "OP_RETURN",
+        ]
+    );
+}
+
+#[test]
+fn arithmetic_between_jumps_is_folded() {
+    assert_eq!(
+        optimized(
+            "OP_PUSH_HANDLER failed
+             OP_CONSTANT 1
+             OP_CONSTANT 2
+             OP_ADD
+             OP_CONSTANT 3
+             OP_MULTIPLY
+             OP_POP_HANDLER
+             OP_JUMP done
+             failed:
+             OP_CONSTANT 0
+             done:
+             OP_RETURN"
+        ),
+        [
+            "OP_PUSH_HANDLER 5",
+            "OP_CONSTANT 0",
+            "OP_POP_HANDLER",
+            "OP_JUMP 2",
+            "OP_CONSTANT 1",
+            "OP_RETURN",
+        ]
+    );
+}
+
+#[test]
+fn instructions_a_jump_lands_on_arent_fused() {
+    // Without the handler failing, `OP_ADD` runs without the 2 before it
+    let source = "OP_CONSTANT 1
+                  OP_PUSH_HANDLER failed
+                  OP_CONSTANT 5
+                  OP_POP_HANDLER
+                  OP_JUMP add
+                  failed:
+                  OP_CONSTANT 2
+                  add:
+                  OP_ADD
+                  OP_RETURN";
+    let listing = optimized(source);
+    assert!(listing.contains(&"OP_ADD".to_string()), "{:?}", listing);
+
+    let listing = optimized(&source.replace("OP_JUMP add", ""));
+    assert!(
+        listing.contains(&"OP_ADD_CONSTANT 2".to_string()),
+        "{:?}",
+        listing
+    );
+}
+
+#[test]
+fn instructions_a_jump_lands_on_arent_folded() {
+    // Folding the dead 1 + 2 would leave the jump nothing to add
+    let listing = optimized(
+        "OP_CONSTANT 3
+         OP_CONSTANT 4
+         OP_JUMP add
+         OP_CONSTANT 1
+         OP_CONSTANT 2
+         add:
+         OP_ADD
+         OP_RETURN",
+    );
+
+    assert_eq!(
+        listing,
+        [
+            "OP_CONSTANT 0",
+            "OP_CONSTANT 1",
+            "OP_JUMP 0",
+            "OP_ADD",
+            "OP_RETURN"
+        ]
+    );
+}
+
+#[test]
+fn jump_to_the_end_stays_at_the_end() {
+    let chunk = assemble(
+        "OP_CONSTANT 1
+         OP_JUMP end
+         OP_RETURN
+         end:",
+    );
+
+    assert_eq!(listing(&optimize(&chunk)), ["OP_CONSTANT 0", "OP_JUMP 0"]);
+}